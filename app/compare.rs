@@ -0,0 +1,80 @@
+//! Comparing two rendered frames, for reviewing config changes before deploying them.
+use image::{GenericImage, GrayImage, Rgb, RgbImage};
+
+/// The gap between the panels of the comparison.
+const GAP: u32 = 8;
+
+/// A comparison of two frames.
+pub struct Comparison {
+    /// The old frame, the new frame, and a heatmap of the differences, side by side.
+    pub img: RgbImage,
+    /// Pixels which differ, over the area both frames cover.
+    pub changed: u64,
+}
+
+/// Place `old`, `new`, and a heatmap of their differences side by side.
+///
+/// The heatmap is the new frame faded, with changed pixels in red, brighter the larger the
+/// change. Frames of different sizes are aligned at the top left, any area only one of them
+/// covers counts as changed.
+pub fn compare(old: &GrayImage, new: &GrayImage) -> Comparison {
+    let w = old.width().max(new.width());
+    let h = old.height().max(new.height());
+    let mut img = RgbImage::from_pixel(w * 3 + GAP * 2, h, Rgb([128, 128, 128]));
+
+    let rgb = |x: &GrayImage| image::DynamicImage::ImageLuma8(x.clone()).into_rgb8();
+    img.copy_from(&rgb(old), 0, 0).expect("fits");
+    img.copy_from(&rgb(new), w + GAP, 0).expect("fits");
+
+    let mut changed = 0;
+    let x0 = (w + GAP) * 2;
+    for y in 0..h {
+        for x in 0..w {
+            let a = old.get_pixel_checked(x, y).map(|p| p.0[0]);
+            let b = new.get_pixel_checked(x, y).map(|p| p.0[0]);
+            let px = match (a, b) {
+                (Some(a), Some(b)) if a == b => {
+                    let faded = 192 + b / 4;
+                    Rgb([faded; 3])
+                }
+                (Some(a), Some(b)) => {
+                    changed += 1;
+                    let fade = 160 - (a.abs_diff(b) as u16 * 160 / 255) as u8;
+                    Rgb([255, fade, fade])
+                }
+                _ => {
+                    changed += 1;
+                    Rgb([255, 0, 0])
+                }
+            };
+            img.put_pixel(x0 + x, y, px);
+        }
+    }
+
+    Comparison { img, changed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heatmap_marks_changes() {
+        let old = GrayImage::from_pixel(4, 2, image::Luma([255]));
+        let mut new = old.clone();
+        new.put_pixel(1, 1, image::Luma([0]));
+
+        let Comparison { img, changed } = compare(&old, &new);
+        assert_eq!(changed, 1);
+        assert_eq!(img.dimensions(), (4 * 3 + GAP * 2, 2));
+        let heat = |x, y| *img.get_pixel((4 + GAP) * 2 + x, y);
+        assert_eq!(heat(0, 0), Rgb([255; 3]));
+        assert_eq!(heat(1, 1), Rgb([255, 0, 0]));
+        // the new frame is in the middle
+        assert_eq!(*img.get_pixel(4 + GAP + 1, 1), Rgb([0; 3]));
+
+        // growing the frame changes the new area
+        let bigger = GrayImage::from_pixel(4, 3, image::Luma([255]));
+        assert_eq!(compare(&old, &bigger).changed, 4);
+    }
+}
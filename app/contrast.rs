@@ -0,0 +1,142 @@
+//! Boosting the contrast of mid-greys before the frame is reduced to the panel's levels.
+//!
+//! egui's subtle greys, such as weak text and widget fills, sit close together and wash out on
+//! e-ink. Equalising the histogram of the mid-greys spreads them over the range, while pure black
+//! and white (text and background) are left alone.
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Contrast {
+    /// How far towards the equalised histogram to remap, from 0 (unchanged) to 1.
+    pub strength: f32,
+    /// Leave pure black and white alone, and keep the mid-greys from becoming either.
+    pub keep_extremes: bool,
+}
+
+impl Default for Contrast {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            keep_extremes: true,
+        }
+    }
+}
+
+impl Contrast {
+    pub fn apply(self, img: &mut GrayImage) {
+        let lut = self.lut(img);
+        for px in img.pixels_mut() {
+            px.0[0] = lut[px.0[0] as usize];
+        }
+    }
+
+    /// The remapping of each grey level, from the image's histogram.
+    fn lut(self, img: &GrayImage) -> [u8; 256] {
+        let mut hist = [0u64; 256];
+        for px in img.pixels() {
+            hist[px.0[0] as usize] += 1;
+        }
+        let (lo, hi) = if self.keep_extremes {
+            (1, 254)
+        } else {
+            (0, 255)
+        };
+        let total = hist[lo..=hi].iter().sum::<u64>();
+
+        let mut lut = std::array::from_fn(|x| x as u8);
+        if total == 0 {
+            return lut;
+        }
+        let strength = self.strength.clamp(0.0, 1.0);
+        let range = (hi - lo) as f32;
+        let mut below = 0;
+        for v in lo..=hi {
+            // the middle of the level's share of the pixels, so a lone grey stays mid range
+            let cdf = (below as f32 + hist[v] as f32 / 2.0) / total as f32;
+            below += hist[v];
+            let eq = lo as f32 + cdf * range;
+            lut[v] = (v as f32 + (eq - v as f32) * strength).round() as u8;
+        }
+        lut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A horizontal gradient from `from` to `to`, with black and white bars at the ends.
+    fn gradient(from: u8, to: u8) -> GrayImage {
+        GrayImage::from_fn(68, 4, |x, _| match x {
+            0..=1 => image::Luma([0]),
+            66.. => image::Luma([255]),
+            x => image::Luma([from + ((to - from) as u32 * (x - 2) / 63) as u8]),
+        })
+    }
+
+    fn row(img: &GrayImage) -> Vec<u8> {
+        (0..img.width()).map(|x| img.get_pixel(x, 0).0[0]).collect()
+    }
+
+    #[test]
+    fn spreads_narrow_gradient() {
+        let mut img = gradient(150, 200);
+        Contrast {
+            strength: 1.0,
+            keep_extremes: true,
+        }
+        .apply(&mut img);
+        let xs = row(&img);
+
+        // the extremes are untouched, and the greys stay greys
+        assert_eq!((xs[0], xs[67]), (0, 255));
+        let greys = &xs[2..66];
+        assert!(greys.iter().all(|&x| (1..=254).contains(&x)), "{greys:?}");
+        // the greys now span most of the range, in the same order
+        assert!(greys[0] < 10 && greys[63] > 245, "{greys:?}");
+        assert!(greys.windows(2).all(|x| x[0] <= x[1]), "{greys:?}");
+    }
+
+    #[test]
+    fn strength_blends() {
+        let orig = gradient(100, 160);
+        let at = |strength| {
+            let mut img = orig.clone();
+            Contrast {
+                strength,
+                keep_extremes: true,
+            }
+            .apply(&mut img);
+            row(&img)
+        };
+        assert_eq!(at(0.0), row(&orig));
+
+        // half way between the original and fully equalised
+        let (half, full) = (at(0.5), at(1.0));
+        for ((o, h), f) in row(&orig).iter().zip(&half).zip(&full) {
+            let mid = (*o as f32 + *f as f32) / 2.0;
+            assert!((*h as f32 - mid).abs() <= 1.0, "{o} {h} {f}");
+        }
+    }
+
+    #[test]
+    fn extremes_can_be_remapped() {
+        // with only black and white, nothing changes either way
+        let mut bw = gradient(0, 0);
+        let orig = bw.clone();
+        Contrast::default().apply(&mut bw);
+        assert_eq!(bw, orig);
+
+        let mut img = gradient(150, 200);
+        Contrast {
+            strength: 1.0,
+            keep_extremes: false,
+        }
+        .apply(&mut img);
+        let xs = row(&img);
+        // black and white are part of the histogram, so shift inwards
+        assert!(xs[0] > 0 && xs[67] < 255, "{xs:?}");
+    }
+}
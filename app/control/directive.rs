@@ -0,0 +1,154 @@
+//! Display overrides driven by events in a designated control calendar.
+//!
+//! An event with a summary such as `pical: mode month` applies for the event's duration.
+//! Supported directives:
+//!
+//! - `mode <name>`: show a layout mode by name, eg `month`.
+//! - `theme light|dark`
+//! - `photo <path>`: show an image full screen, needs the `photo-mode` feature.
+//! - `guest`: hide event details, showing them as busy.
+//! - `note <text>`: show a note under the header.
+use crate::{
+    data::{cal::Calendar, Model},
+    layout::{
+        theme::Theme,
+        widgets::{Note, Position},
+        Layout,
+    },
+};
+use miette::*;
+#[cfg(feature = "photo-mode")]
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+const PREFIX: &str = "pical:";
+
+#[derive(Clone)]
+pub enum Directive {
+    /// The name of a mode, looked up in the layout's modes when applied.
+    Mode(String),
+    Theme(Theme),
+    #[cfg(feature = "photo-mode")]
+    Photo(PathBuf),
+    Guest,
+    Note(String),
+}
+
+impl Directive {
+    /// Parse an event summary, `None` if it is not a directive.
+    pub fn parse(summary: &str) -> Option<Result<Self>> {
+        let s = summary.trim();
+        let rest = s
+            .get(..PREFIX.len())
+            .filter(|x| x.eq_ignore_ascii_case(PREFIX))
+            .map(|_| s[PREFIX.len()..].trim())?;
+        let (cmd, arg) = rest.split_once(' ').unwrap_or((rest, ""));
+        let arg = arg.trim();
+        let d = match (cmd.to_ascii_lowercase().as_str(), arg) {
+            ("mode", name) if !name.is_empty() => Ok(Directive::Mode(name.to_string())),
+            ("theme", "light") => Ok(Directive::Theme(Theme::Light)),
+            ("theme", "dark") => Ok(Directive::Theme(Theme::Dark)),
+            #[cfg(feature = "photo-mode")]
+            ("photo", path) if !path.is_empty() => Ok(Directive::Photo(path.into())),
+            #[cfg(not(feature = "photo-mode"))]
+            ("photo", _) => Err(miette!(
+                help = "build with the `photo-mode` feature",
+                "pical was built without photo support"
+            )),
+            ("guest", "") => Ok(Directive::Guest),
+            ("note", text) if !text.is_empty() => Ok(Directive::Note(text.to_string())),
+            _ => Err(miette!("unknown directive '{rest}'")),
+        };
+        Some(d.wrap_err_with(|| format!("in control event '{summary}'")))
+    }
+}
+
+/// The directives of the events happening `now`. Invalid directives are logged and skipped.
+pub fn active(cal: &Calendar, now: OffsetDateTime) -> Vec<Directive> {
+    cal.iter()
+        .filter(|e| e.start <= now && now < e.end)
+        .filter_map(|e| Directive::parse(&e.summary))
+        .filter_map(|d| d.map_err(|e| log::warn!("{e:?}")).ok())
+        .collect()
+}
+
+/// Apply the directives of the layout's control calendar, which is removed from the model so
+/// it is not displayed.
+pub fn apply_overrides(layout: &mut Layout, model: &mut Model) {
+    let Some(name) = layout.control_calendar.as_deref() else {
+        return;
+    };
+    let Some(cal) = model.make_mut().cals.remove(name) else {
+        return;
+    };
+
+    for d in active(&cal, layout.now) {
+        match d {
+            Directive::Mode(name) => match layout.modes.get(&name) {
+                Some(mode) => layout.mode = mode,
+                None => log::warn!("unknown mode '{name}' in control event"),
+            },
+            Directive::Theme(theme) => layout.theme = theme,
+            #[cfg(feature = "photo-mode")]
+            Directive::Photo(path) => layout.photo = Some(path),
+            Directive::Note(text) => layout.notes.notes.push(Note {
+                text,
+                position: Position::Top,
+            }),
+            Directive::Guest => {
+                for ev in model.make_mut().cals.values_mut().flatten() {
+                    ev.summary = "Busy".to_string();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::cal::Event;
+    use time::macros::datetime;
+
+    #[test]
+    fn parse_directives() {
+        assert!(Directive::parse("Dinner").is_none());
+        assert!(matches!(
+            Directive::parse("pical: mode month"),
+            Some(Ok(Directive::Mode(m))) if m == "month"
+        ));
+        assert!(matches!(
+            Directive::parse("PICAL:theme dark"),
+            Some(Ok(Directive::Theme(Theme::Dark)))
+        ));
+        #[cfg(feature = "photo-mode")]
+        assert!(matches!(
+            Directive::parse("pical: photo ./party.png"),
+            Some(Ok(Directive::Photo(p))) if p.to_str() == Some("./party.png")
+        ));
+        assert!(matches!(
+            Directive::parse("pical: guest"),
+            Some(Ok(Directive::Guest))
+        ));
+        assert!(matches!(
+            Directive::parse("pical: note Welcome!"),
+            Some(Ok(Directive::Note(t))) if t == "Welcome!"
+        ));
+        assert!(matches!(Directive::parse("pical: mode"), Some(Err(_))));
+        assert!(matches!(Directive::parse("pical: note"), Some(Err(_))));
+    }
+
+    #[test]
+    fn active_during_event() {
+        let ev = |summary: &str| Event {
+            summary: summary.to_string(),
+            start: datetime!(2024-06-21 18:00 +10),
+            end: datetime!(2024-06-21 23:00 +10),
+            attendees: Vec::new(),
+        };
+        let cal = vec![ev("pical: guest"), ev("pical: bogus"), ev("Party")];
+        assert_eq!(active(&cal, datetime!(2024-06-21 17:59 +10)).len(), 0);
+        assert_eq!(active(&cal, datetime!(2024-06-21 18:00 +10)).len(), 1);
+        assert_eq!(active(&cal, datetime!(2024-06-21 23:00 +10)).len(), 0);
+    }
+}
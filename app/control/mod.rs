@@ -0,0 +1,326 @@
+//! Controlling a running pical over HTTP.
+//!
+//! This is a deliberately tiny HTTP/1.1 handler, commands are a `POST` to a path:
+//!
+//! - `POST /maintenance/on`: pause fetching and rendering, showing a maintenance screen.
+//! - `POST /maintenance/off`: resume.
+//! - `GET /status`: a plain text report of the data sources, failures, merged duplicates, and the
+//!   panel driver's status.
+//! - `GET /logs`: the most recent log lines.
+//! - `GET /metrics`: the dispatcher, panel, and driver counters in the Prometheus text format,
+//!   with the `metrics` feature.
+//! - `GET /api/frame-text`: the text of the current frame as JSON, for screen readers and tests.
+//! - `POST /api/events`: show events from a script until they end, the body is an [`Injection`].
+//!
+//! The same commands are available from the command line with `pical maintenance on|off`,
+//! `pical status`, `pical logs`, `pical metrics`, and `pical frame-text`. Events are only
+//! injected over HTTP.
+//!
+//! Listening for commands needs the `web-ui` feature, sending them does not.
+pub mod directive;
+
+use crate::data::injected::Injected;
+use miette::*;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "web-ui")]
+use {
+    std::{future::Future, time::Duration},
+    tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    },
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ControlConfig {
+    /// Address to listen on, keep this to localhost unless the network is trusted.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+}
+
+fn default_listen() -> String {
+    "127.0.0.1:8425".to_string()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Maintenance(bool),
+    Status,
+    Logs,
+    Metrics,
+    FrameText,
+    InjectEvents(Injection),
+}
+
+/// Events for the calendar `source`, such as `{"source": "scripts", "events": [{"summary":
+/// "Babysitter", "start": "2024-06-21T19:00:00+10:00", "end": "2024-06-21T22:00:00+10:00"}]}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Injection {
+    pub source: String,
+    pub events: Vec<Injected>,
+}
+
+/// The largest request body read, injected events are small.
+#[cfg(feature = "web-ui")]
+const MAX_BODY: usize = 64 * 1024;
+
+impl Command {
+    /// Parse from command line arguments (excluding the binary name).
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Result<Self> {
+        let args = args.iter().map(|x| x.as_ref()).collect::<Vec<_>>();
+        match args.as_slice() {
+            ["maintenance", "on"] => Ok(Command::Maintenance(true)),
+            ["maintenance", "off"] => Ok(Command::Maintenance(false)),
+            ["status"] => Ok(Command::Status),
+            ["logs"] => Ok(Command::Logs),
+            ["metrics"] => Ok(Command::Metrics),
+            ["frame-text"] => Ok(Command::FrameText),
+            _ => Err(miette!(
+                help = "usage: pical maintenance on|off, pical status, pical logs, \
+                        pical metrics, pical frame-text",
+                "unknown command: {}",
+                args.join(" ")
+            )),
+        }
+    }
+
+    /// The request path for the command.
+    pub fn path(&self) -> &'static str {
+        match self {
+            Command::Maintenance(true) => "/maintenance/on",
+            Command::Maintenance(false) => "/maintenance/off",
+            Command::Status => "/status",
+            Command::Logs => "/logs",
+            Command::Metrics => "/metrics",
+            Command::FrameText => "/api/frame-text",
+            Command::InjectEvents(_) => "/api/events",
+        }
+    }
+
+    #[cfg(feature = "web-ui")]
+    fn content_type(&self) -> &'static str {
+        match self {
+            Command::FrameText => "application/json",
+            Command::Metrics => "text/plain; version=0.0.4; charset=utf-8",
+            _ => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// The command for a request, `None` if there is none, or an error if the body is invalid.
+    #[cfg(feature = "web-ui")]
+    fn from_request(method: &str, path: &str, body: &str) -> Option<Result<Self>> {
+        let cmd = match (method, path) {
+            ("POST", "/maintenance/on") => Command::Maintenance(true),
+            ("POST", "/maintenance/off") => Command::Maintenance(false),
+            ("GET", "/status") => Command::Status,
+            ("GET", "/logs") => Command::Logs,
+            #[cfg(feature = "metrics")]
+            ("GET", "/metrics") => Command::Metrics,
+            ("GET", "/api/frame-text") => Command::FrameText,
+            ("POST", "/api/events") => {
+                return Some(
+                    serde_json::from_str(body)
+                        .map(Command::InjectEvents)
+                        .into_diagnostic()
+                        .wrap_err("invalid events"),
+                )
+            }
+            _ => return None,
+        };
+        Some(Ok(cmd))
+    }
+}
+
+/// Send a command to a running pical listening at `cfg`.
+pub async fn send<F: crate::fetch::Fetcher>(
+    client: &F,
+    cfg: &ControlConfig,
+    cmd: Command,
+) -> Result<String> {
+    let url = format!("http://{}{}", cfg.listen, cmd.path());
+    let (status, body) = match cmd {
+        // get errors on a failure status itself
+        Command::Status | Command::Logs | Command::Metrics | Command::FrameText => {
+            (200, client.get(&url, Vec::new()).await?)
+        }
+        Command::Maintenance(_) => client.post_form(&url, Vec::new()).await?,
+        Command::InjectEvents(_) => {
+            return Err(miette!(
+                help = "POST the events as JSON to {url}",
+                "events can't be injected from the command line"
+            ))
+        }
+    };
+    if status == 200 {
+        Ok(body)
+    } else {
+        Err(miette!("{}", body.trim())).wrap_err(format!("command failed with status {status}"))
+    }
+}
+
+/// Listen for commands, `on_command` returns the text to respond with.
+#[cfg(feature = "web-ui")]
+pub async fn serve<F, Fut>(cfg: &ControlConfig, mut on_command: F) -> Result<()>
+where
+    F: FnMut(Command) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let listener = TcpListener::bind(&cfg.listen)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}", cfg.listen))?;
+    log::info!("🎛 Listening for commands on {}", cfg.listen);
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((x, _)) => x,
+            Err(e) => {
+                log::warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let mut stream = BufReader::new(stream);
+        let req = tokio::time::timeout(Duration::from_secs(5), read_request(&mut stream)).await;
+        let plain = "text/plain; charset=utf-8";
+        let (status, content_type, body) = match req {
+            Ok(Ok((method, path, body))) => match Command::from_request(&method, &path, &body) {
+                Some(Ok(cmd)) => {
+                    log::info!("🎛 Received command {cmd:?}");
+                    let content_type = cmd.content_type();
+                    match on_command(cmd).await {
+                        Ok(x) => ("200 OK", content_type, x),
+                        Err(e) => ("500 Internal Server Error", plain, format!("{e:?}")),
+                    }
+                }
+                Some(Err(e)) => ("400 Bad Request", plain, format!("{e:?}")),
+                None => (
+                    "404 Not Found",
+                    plain,
+                    format!("no command for {method} {path}"),
+                ),
+            },
+            Ok(Err(e)) => ("400 Bad Request", plain, e.to_string()),
+            Err(_) => ("408 Request Timeout", plain, String::new()),
+        };
+        let resp = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = stream.get_mut().write_all(resp.as_bytes()).await;
+    }
+}
+
+/// Read the request line, headers, and body, returning the method, path, and body.
+#[cfg(feature = "web-ui")]
+pub(crate) async fn read_request<R: tokio::io::AsyncBufRead + Unpin>(
+    stream: &mut R,
+) -> Result<(String, String, String)> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.into_diagnostic()?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = parts
+        .next()
+        .zip(parts.next())
+        .ok_or_else(|| miette!("malformed request line"))?;
+    let (method, path) = (method.to_string(), path.to_string());
+
+    // only the body's length is needed from the headers
+    let mut len = 0;
+    loop {
+        line.clear();
+        let n = stream.read_line(&mut line).await.into_diagnostic()?;
+        if n == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                len = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| miette!("invalid Content-Length"))?;
+            }
+        }
+    }
+    if len > MAX_BODY {
+        return Err(miette!("body of {len} bytes is too large"));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.into_diagnostic()?;
+    let body = String::from_utf8(body).map_err(|_| miette!("body is not UTF-8"))?;
+
+    Ok((method, path, body))
+}
+
+#[cfg(all(test, feature = "web-ui"))]
+mod tests {
+    use super::*;
+
+    fn route(method: &str, path: &str) -> Option<Command> {
+        Command::from_request(method, path, "").map(|x| x.unwrap())
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            Command::from_args(&["maintenance", "on"]).unwrap(),
+            Command::Maintenance(true)
+        );
+        assert_eq!(
+            Command::from_args(&["maintenance", "off"]).unwrap(),
+            Command::Maintenance(false)
+        );
+        assert!(Command::from_args(&["maintenance"]).is_err());
+
+        for cmd in [Command::Maintenance(true), Command::Maintenance(false)] {
+            assert_eq!(route("POST", cmd.path()), Some(cmd.clone()));
+            assert_eq!(route("GET", cmd.path()), None);
+        }
+        assert_eq!(Command::from_args(&["status"]).unwrap(), Command::Status);
+        assert_eq!(route("GET", "/status"), Some(Command::Status));
+        assert_eq!(Command::from_args(&["logs"]).unwrap(), Command::Logs);
+        assert_eq!(route("GET", "/logs"), Some(Command::Logs));
+        assert_eq!(Command::from_args(&["metrics"]).unwrap(), Command::Metrics);
+        #[cfg(feature = "metrics")]
+        assert_eq!(route("GET", "/metrics"), Some(Command::Metrics));
+        assert_eq!(
+            Command::from_args(&["frame-text"]).unwrap(),
+            Command::FrameText
+        );
+        assert_eq!(route("GET", "/api/frame-text"), Some(Command::FrameText));
+    }
+
+    #[tokio::test]
+    async fn reads_request() {
+        let req = b"POST /maintenance/on HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n";
+        let (method, path, body) = read_request(&mut &req[..]).await.unwrap();
+        assert_eq!(method, "POST");
+        assert_eq!(path, "/maintenance/on");
+        assert_eq!(body, "");
+
+        let req = b"POST /api/events HTTP/1.1\r\ncontent-length: 4\r\n\r\n{}\r\nextra";
+        let (_, _, body) = read_request(&mut &req[..]).await.unwrap();
+        assert_eq!(body, "{}\r\n");
+    }
+
+    #[test]
+    fn inject_events() {
+        use time::macros::datetime;
+        let body = r#"{"source": "scripts", "events": [{"summary": "Babysitter",
+            "start": "2024-06-21T19:00:00+10:00", "end": "2024-06-21T22:00:00+10:00"}]}"#;
+        let cmd = Command::from_request("POST", "/api/events", body).map(|x| x.unwrap());
+        assert_eq!(
+            cmd,
+            Some(Command::InjectEvents(Injection {
+                source: "scripts".to_string(),
+                events: vec![Injected {
+                    summary: "Babysitter".to_string(),
+                    start: datetime!(2024-06-21 19:00 +10),
+                    end: datetime!(2024-06-21 22:00 +10),
+                }],
+            }))
+        );
+        assert!(Command::from_request("POST", "/api/events", "{}").is_some_and(|x| x.is_err()));
+        assert_eq!(route("GET", "/api/events"), None);
+    }
+}
@@ -0,0 +1,36 @@
+use super::source::Registry;
+use std::time::Instant;
+
+#[cfg(feature = "weather")]
+pub mod open_meteo;
+
+#[derive(Clone)]
+pub struct AirQuality {
+    pub last_update: Instant,
+    pub pm2_5: Option<f32>,
+    pub aqi: Option<f32>,
+}
+
+impl AirQuality {
+    /// A coarse category for the US AQI value.
+    pub fn category(&self) -> Option<&'static str> {
+        self.aqi.map(|x| match x as u32 {
+            0..=50 => "Good",
+            51..=100 => "Moderate",
+            101..=150 => "Sensitive",
+            151..=200 => "Unhealthy",
+            201..=300 => "Very unhealthy",
+            _ => "Hazardous",
+        })
+    }
+}
+
+/// Add the air quality to `sources`.
+#[cfg(feature = "weather")]
+pub fn register(sources: &mut Registry, coords: [f32; 2]) {
+    sources.register(open_meteo::OpenMeteoAir { coords });
+}
+
+/// Without the `weather` feature there is no air quality to add.
+#[cfg(not(feature = "weather"))]
+pub fn register(_: &mut Registry, _: [f32; 2]) {}
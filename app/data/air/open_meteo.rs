@@ -0,0 +1,74 @@
+//! The Open-Meteo air quality.
+use super::AirQuality;
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// The Open-Meteo air quality for a location.
+pub struct OpenMeteoAir {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for OpenMeteoAir {
+    fn name(&self) -> &str {
+        "air quality"
+    }
+
+    /// Only every 30 minutes, the model only updates hourly.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 30)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, _now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let [lat, long] = self.coords;
+            let url = url::Url::parse_with_params(
+                "https://air-quality-api.open-meteo.com/v1/air-quality?current=pm2_5,us_aqi",
+                &[
+                    ("latitude", lat.to_string()),
+                    ("longitude", long.to_string()),
+                ],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let resp = crate::fetch::json(client, url.as_str(), [], self.fresh_for()).await?;
+            let air = AirQuality {
+                last_update: resp.at,
+                ..AirQuality::from_open_meteo(resp.body)?
+            };
+            Ok(ModelPatch::new(|model| model.air = Some(air)).stale(resp.stale))
+        })
+    }
+}
+
+impl AirQuality {
+    pub fn from_open_meteo(payload: OpenMeteoAirPayload) -> Result<Self> {
+        let OpenMeteoAirCurrent { pm2_5, us_aqi } = payload.current;
+        if pm2_5.is_none() && us_aqi.is_none() {
+            return Err(miette!(
+                "air quality response contained no PM2.5 or AQI values"
+            ));
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            pm2_5,
+            aqi: us_aqi,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OpenMeteoAirPayload {
+    current: OpenMeteoAirCurrent,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoAirCurrent {
+    pm2_5: Option<f32>,
+    us_aqi: Option<f32>,
+}
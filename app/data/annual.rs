@@ -0,0 +1,217 @@
+//! Dates which recur every year, such as anniversaries and tax deadlines, from the config.
+use super::{
+    cal::{Calendar, Event},
+    source::{DataSource, FetchFuture, ModelPatch},
+};
+use crate::fetch::Client;
+use miette::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::Duration;
+use time::{Date, Month, OffsetDateTime};
+
+/// The calendar name the annual events are shown under.
+pub const CALENDAR: &str = "Annual";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Annual {
+    pub label: String,
+    /// The month and day, eg `"06-21"`. The 29th of February falls on the 28th in other years.
+    pub date: MonthDay,
+    /// Shown before the label, such as an emoji.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Show a banner in the header this many days before.
+    #[serde(default)]
+    pub notify_days: Option<u16>,
+}
+
+impl Annual {
+    /// The label with the icon.
+    pub fn title(&self) -> String {
+        match &self.icon {
+            Some(icon) => format!("{icon} {}", self.label),
+            None => self.label.clone(),
+        }
+    }
+
+    /// The next occurrence, on or after `today`.
+    pub fn next(&self, today: Date) -> Date {
+        let this = self.date.in_year(today.year());
+        if this >= today {
+            this
+        } else {
+            self.date.in_year(today.year() + 1)
+        }
+    }
+
+    /// The banner text if the date is within [`Annual::notify_days`].
+    pub fn banner(&self, today: Date) -> Option<String> {
+        let days = (self.next(today) - today).whole_days();
+        if days > self.notify_days?.into() {
+            return None;
+        }
+        let title = self.title();
+        Some(match days {
+            0 => format!("{title} today!"),
+            1 => format!("{title} tomorrow"),
+            n => format!("{title} in {n} days"),
+        })
+    }
+}
+
+/// A month and day, without a year.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MonthDay {
+    pub month: Month,
+    pub day: u8,
+}
+
+impl MonthDay {
+    pub fn in_year(self, year: i32) -> Date {
+        let day = self
+            .day
+            .min(time::util::days_in_year_month(year, self.month));
+        Date::from_calendar_date(year, self.month, day).expect("day is clamped to the month")
+    }
+}
+
+impl std::str::FromStr for MonthDay {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || {
+            let (m, d) = s.split_once('-')?;
+            let month = Month::try_from(m.parse::<u8>().ok()?).ok()?;
+            let day = d.parse::<u8>().ok()?;
+            // validate against a leap year so the 29th of February is allowed
+            Date::from_calendar_date(2024, month, day).ok()?;
+            Some(Self { month, day })
+        };
+        parse().ok_or_else(|| miette!("expecting a date such as '06-21', found '{s}'"))
+    }
+}
+
+impl Serialize for MonthDay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let m = self.month as u8;
+        serializer.serialize_str(&format!("{m:02}-{:02}", self.day))
+    }
+}
+
+impl<'de> Deserialize<'de> for MonthDay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e: Report| serde::de::Error::custom(e.to_string()))
+    }
+}
+
+/// Generates all day events for this and next year's occurrences.
+pub struct AnnualEvents(pub Vec<Annual>);
+
+impl AnnualEvents {
+    pub fn calendar(&self, now: OffsetDateTime) -> Calendar {
+        let offset = now.offset();
+        let mut evs = [now.year(), now.year() + 1]
+            .into_iter()
+            .flat_map(|year| {
+                self.0.iter().map(move |x| {
+                    let date = x.date.in_year(year);
+                    Event {
+                        summary: x.title(),
+                        start: date.midnight().assume_offset(offset),
+                        // all day events end at the next midnight, as from calendars
+                        end: date
+                            .next_day()
+                            .unwrap_or(date)
+                            .midnight()
+                            .assume_offset(offset),
+                        attendees: Vec::new(),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        evs.sort_by(|a, b| a.start.cmp(&b.start));
+        evs
+    }
+}
+
+impl DataSource for AnnualEvents {
+    fn name(&self) -> &str {
+        CALENDAR
+    }
+
+    /// Nothing is fetched, this only needs to roll over into the new year.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    fn fetch<'a>(&'a mut self, _client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        let cal = self.calendar(now);
+        Box::pin(async move {
+            // not recorded in `cals_updated`, generated events are never stale
+            Ok(ModelPatch::new(move |model| {
+                model.cals.insert(CALENDAR.to_string(), cal);
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    fn annual(date: &str, notify_days: Option<u16>) -> Annual {
+        Annual {
+            label: "Anniversary".to_string(),
+            date: date.parse().unwrap(),
+            icon: Some("💍".to_string()),
+            notify_days,
+        }
+    }
+
+    #[test]
+    fn month_day_parsing() {
+        let md = "02-29".parse::<MonthDay>().unwrap();
+        assert_eq!(md.in_year(2024), date!(2024 - 02 - 29));
+        assert_eq!(md.in_year(2025), date!(2025 - 02 - 28));
+        assert!("02-30".parse::<MonthDay>().is_err());
+        assert!("13-01".parse::<MonthDay>().is_err());
+        assert!("june 21".parse::<MonthDay>().is_err());
+    }
+
+    #[test]
+    fn events_are_all_day() {
+        let now = datetime!(2024-12-31 10:00 +10);
+        let cal = AnnualEvents(vec![annual("12-31", None)]).calendar(now);
+        assert_eq!(cal[0].start, datetime!(2024-12-31 00:00 +10));
+        assert_eq!(cal[0].end, datetime!(2025-01-01 00:00 +10));
+    }
+
+    #[test]
+    fn next_occurrence() {
+        let x = annual("06-21", None);
+        assert_eq!(x.next(date!(2024 - 06 - 21)), date!(2024 - 06 - 21));
+        assert_eq!(x.next(date!(2024 - 06 - 22)), date!(2025 - 06 - 21));
+    }
+
+    #[test]
+    fn banners() {
+        let x = annual("01-02", Some(3));
+        assert_eq!(x.banner(date!(2024 - 12 - 29)), None);
+        assert_eq!(
+            x.banner(date!(2024 - 12 - 30)).as_deref(),
+            Some("💍 Anniversary in 3 days")
+        );
+        assert_eq!(
+            x.banner(date!(2025 - 01 - 01)).as_deref(),
+            Some("💍 Anniversary tomorrow")
+        );
+        assert_eq!(
+            x.banner(date!(2025 - 01 - 02)).as_deref(),
+            Some("💍 Anniversary today!")
+        );
+        assert_eq!(annual("01-02", None).banner(date!(2025 - 01 - 02)), None);
+    }
+}
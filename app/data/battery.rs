@@ -0,0 +1,129 @@
+//! Battery level from a [PiSugar](https://github.com/PiSugar/pisugar-power-manager-rs) UPS.
+//!
+//! Queries the `pisugar-server` TCP API, which by default listens on `127.0.0.1:8423`.
+use super::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatteryConfig {
+    /// Address of the `pisugar-server` TCP API.
+    #[serde(default = "default_addr")]
+    pub addr: String,
+    /// Battery percentage to show a low battery warning at.
+    #[serde(default = "default_low")]
+    pub low: f32,
+}
+
+fn default_addr() -> String {
+    "127.0.0.1:8423".to_string()
+}
+
+fn default_low() -> f32 {
+    20.0
+}
+
+#[derive(Clone)]
+pub struct Battery {
+    pub last_update: Instant,
+    /// Percentage, 0–100.
+    pub level: f32,
+    pub charging: bool,
+    /// Level is below the configured threshold and not charging.
+    pub low: bool,
+}
+
+impl Battery {
+    pub async fn from_pisugar(cfg: &BatteryConfig) -> Result<Self> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let stream = TcpStream::connect(&cfg.addr)
+                .await
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to connect to pisugar-server at {}", cfg.addr))?;
+            let mut stream = BufReader::new(stream);
+
+            let level = query(&mut stream, "battery")
+                .await?
+                .parse::<f32>()
+                .into_diagnostic()
+                .wrap_err("battery level is not a number")?
+                .clamp(0.0, 100.0);
+            let charging = query(&mut stream, "battery_charging").await? == "true";
+
+            Ok(Self {
+                last_update: Instant::now(),
+                level,
+                charging,
+                low: !charging && level < cfg.low,
+            })
+        })
+        .await
+        .into_diagnostic()
+        .wrap_err("timed out querying pisugar-server")?
+    }
+}
+
+/// The battery is local, so is read every pass of the fetch loop.
+impl DataSource for BatteryConfig {
+    fn name(&self) -> &str {
+        "battery"
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn fetch<'a>(&'a mut self, _: &'a Client, _: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let b = Battery::from_pisugar(self).await?;
+            if b.low {
+                log::warn!("🪫 Battery low: {:.0}%", b.level);
+            }
+            Ok(ModelPatch::new(|model| model.battery = Some(b)))
+        })
+    }
+}
+
+/// Sends `get <key>` and returns the value of the `<key>: <value>` response.
+async fn query(stream: &mut BufReader<TcpStream>, key: &str) -> Result<String> {
+    stream
+        .get_mut()
+        .write_all(format!("get {key}\n").as_bytes())
+        .await
+        .into_diagnostic()?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await.into_diagnostic()?;
+    parse_response(&line, key)
+        .map(ToString::to_string)
+        .ok_or_else(|| miette!("unexpected pisugar-server response: {line}"))
+}
+
+fn parse_response<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.trim()
+        .split_once(':')
+        .filter(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pisugar_responses() {
+        assert_eq!(parse_response("battery: 87.5\n", "battery"), Some("87.5"));
+        assert_eq!(
+            parse_response("battery_charging: true\n", "battery_charging"),
+            Some("true")
+        );
+        assert_eq!(parse_response("battery_i: 0.1\n", "battery"), None);
+        assert_eq!(parse_response("Invalid request.\n", "battery"), None);
+    }
+}
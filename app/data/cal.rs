@@ -1,5 +1,10 @@
+use super::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
 use ical::{parser::ical::component::IcalEvent, property::Property};
 use miette::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use time::{
     format_description::well_known::iso8601, Date, OffsetDateTime, PrimitiveDateTime, Time,
     UtcOffset, Weekday,
@@ -16,6 +21,8 @@ pub struct Event {
     pub summary: String,
     pub start: OffsetDateTime,
     pub end: OffsetDateTime,
+    /// Who is attending, by name where given otherwise by email address.
+    pub attendees: Vec<String>,
 }
 
 impl Event {
@@ -28,6 +35,7 @@ pub type Calendar = Vec<Event>;
 
 /// The returned calendar is sorted by start date.
 pub fn parse_ical(data: &str, offset: UtcOffset, limit: OffsetDateTime) -> Result<Calendar> {
+    let data = strip_vendor_props(data);
     let parser = ical::IcalParser::new(data.as_bytes());
     let mut evs = Vec::new();
     for cal in parser {
@@ -44,6 +52,22 @@ pub fn parse_ical(data: &str, offset: UtcOffset, limit: OffsetDateTime) -> Resul
     Ok(evs)
 }
 
+/// Remove vendor `X-` properties, including their folded continuation lines.
+///
+/// None are used, and some exports put quoted parameters or URIs in them which the parser
+/// chokes on, failing the whole calendar.
+fn strip_vendor_props(data: &str) -> String {
+    let mut vendor = false;
+    data.lines()
+        .filter(|line| {
+            if !line.starts_with([' ', '\t']) {
+                vendor = line.starts_with("X-");
+            }
+            !vendor
+        })
+        .fold(String::with_capacity(data.len()), |s, x| s + x + "\n")
+}
+
 fn make_event(ev: IcalEvent, offset: UtcOffset) -> impl Iterator<Item = Event> {
     let props = PropParser(&ev.properties);
     let mut rrule = props.rrule().map(|x| x.to_offset(offset));
@@ -56,6 +80,7 @@ fn make_event(ev: IcalEvent, offset: UtcOffset) -> impl Iterator<Item = Event> {
             summary,
             start,
             end,
+            attendees: props.attendees(),
         })
     })();
 
@@ -65,8 +90,26 @@ fn make_event(ev: IcalEvent, offset: UtcOffset) -> impl Iterator<Item = Event> {
 struct PropParser<'a>(&'a [Property]);
 
 impl<'a> PropParser<'a> {
+    /// Find the property `name`. Some exports repeat properties, in which case the one with a
+    /// time zone is preferred, otherwise the first.
     fn find(&self, name: &str) -> Option<&Property> {
-        self.0.iter().find(|x| x.name == name)
+        let mut found = self.0.iter().filter(|x| x.name == name);
+        let first = found.next()?;
+        let rest = found.collect::<Vec<_>>();
+        if rest.is_empty() {
+            return Some(first);
+        }
+
+        let chosen = std::iter::once(first)
+            .chain(rest.iter().copied())
+            .find(|x| find_param(x, "TZID").is_some())
+            .unwrap_or(first);
+        for p in std::iter::once(first).chain(rest) {
+            if !std::ptr::eq(p, chosen) {
+                log::debug!("ignoring duplicate {name} in iCal: {p:?}");
+            }
+        }
+        Some(chosen)
     }
 
     fn parse<F, T>(&self, name: &str, f: F) -> Option<T>
@@ -93,6 +136,24 @@ impl<'a> PropParser<'a> {
         self.parse(name, |p| p.value.clone())
     }
 
+    /// The `ATTENDEE`s, by their `CN` otherwise the `mailto:` address.
+    fn attendees(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|x| x.name == "ATTENDEE")
+            .filter_map(|p| {
+                find_param(p, "CN").or_else(|| {
+                    let val = p.value.as_deref()?;
+                    let addr = match val.get(..7) {
+                        Some(x) if x.eq_ignore_ascii_case("mailto:") => &val[7..],
+                        _ => val,
+                    };
+                    Some(addr.to_string())
+                })
+            })
+            .collect()
+    }
+
     fn datetime(&self, name: &str, offset: UtcOffset) -> Option<OffsetDateTime> {
         self.parse(name, |p| {
             let val = p.value.as_ref()?;
@@ -252,6 +313,7 @@ impl RepeatRule {
                     summary: ev.summary.clone(),
                     start,
                     end,
+                    attendees: ev.attendees.clone(),
                 })
             }
             Freq::Weekly => {
@@ -268,6 +330,7 @@ impl RepeatRule {
                     summary: ev.summary.clone(),
                     start,
                     end,
+                    attendees: ev.attendees.clone(),
                 })
             }
             Freq::Monthly => {
@@ -303,6 +366,7 @@ impl RepeatRule {
                     summary: ev.summary.clone(),
                     start,
                     end,
+                    attendees: ev.attendees.clone(),
                 })
             }
             Freq::Yearly => {
@@ -317,6 +381,7 @@ impl RepeatRule {
                     summary: ev.summary.clone(),
                     start,
                     end,
+                    attendees: ev.attendees.clone(),
                 })
             }
         }
@@ -407,6 +472,115 @@ fn find_param(prop: &Property, name: &str) -> Option<String> {
         .cloned()
 }
 
+// ##### REWRITES ##############################################################
+
+/// A find and replace on event summaries, such as stripping the `[Team-X] ` prefix a work
+/// calendar adds, to keep the day cells readable.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RewriteConfig {
+    /// The calendar to rewrite, every calendar if not set.
+    #[serde(default)]
+    pub calendar: Option<String>,
+    /// A regular expression, matches anywhere in the summary unless anchored.
+    pub find: String,
+    /// What each match is replaced with, `$1` and `${name}` are capture groups.
+    #[serde(default)]
+    pub replace: String,
+}
+
+/// The rewrites of one calendar, applied in order as it is fetched.
+#[derive(Clone, Default)]
+pub struct Rewrites(Vec<(Regex, String)>);
+
+impl Rewrites {
+    /// The rewrites in `cfg` which apply to the calendar `name`.
+    pub fn for_calendar(cfg: &[RewriteConfig], name: &str) -> Result<Self> {
+        cfg.iter()
+            .filter(|x| x.calendar.is_none() || x.calendar.as_deref() == Some(name))
+            .map(|x| {
+                Regex::new(&x.find)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("invalid rewrite pattern '{}'", x.find))
+                    .map(|re| (re, x.replace.clone()))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    pub fn apply(&self, cal: &mut Calendar) {
+        for ev in cal {
+            for (find, replace) in &self.0 {
+                let x = find.replace_all(&ev.summary, replace.as_str());
+                if let std::borrow::Cow::Owned(x) = x {
+                    ev.summary = x;
+                }
+            }
+        }
+    }
+}
+
+// ##### SOURCE ################################################################
+
+/// Credentials for a calendar whose URL needs HTTP basic or digest auth, such as a self-hosted
+/// export.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CalendarAuth {
+    /// The name of the calendar in `calendars`.
+    pub calendar: String,
+    #[serde(flatten)]
+    pub credentials: crate::fetch::Credentials,
+}
+
+/// An iCal calendar fetched from a URL.
+pub struct IcalSource {
+    pub name: String,
+    pub url: String,
+    pub rewrites: Rewrites,
+    /// Answers the server's basic or digest auth challenges.
+    pub auth: Option<crate::fetch::auth::Auth>,
+}
+
+impl DataSource for IcalSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let fetched = match &self.auth {
+                Some(auth) => {
+                    crate::fetch::string(&auth.client(client), &self.url, [], Duration::ZERO)
+                        .await?
+                }
+                None => crate::fetch::string(client, &self.url, [], Duration::ZERO).await?,
+            };
+            let mut cal = parse_ical(&fetched.body, now.offset(), fetch_limit(now))?;
+            self.rewrites.apply(&mut cal);
+            Ok(calendar_patch(self.name.clone(), cal, fetched.at).stale(fetched.stale))
+        })
+    }
+}
+
+/// How far ahead calendars are fetched.
+pub fn fetch_limit(now: OffsetDateTime) -> OffsetDateTime {
+    std::iter::successors(Some(now.date()), |x| x.next_day())
+        .nth(60)
+        .map(|d| now.replace_date(d))
+        .unwrap_or(now)
+}
+
+/// Replace the calendar `name` in the model, as it was fetched `at`.
+pub fn calendar_patch(name: String, cal: Calendar, at: Instant) -> ModelPatch {
+    ModelPatch::new(move |model| {
+        model.cals_updated.insert(name.clone(), at);
+        model.cals.insert(name, cal);
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -418,6 +592,7 @@ mod tests {
                 summary: String::arbitrary(g),
                 start: crate::test::ArbitraryDateTime::arbitrary(g).0.assume_utc(),
                 end: crate::test::ArbitraryDateTime::arbitrary(g).0.assume_utc(),
+                attendees: Vec::arbitrary(g),
             }
         }
     }
@@ -433,6 +608,46 @@ mod tests {
         covers == ev.covers(date)
     }
 
+    fn parse_fixture(data: &str) -> Calendar {
+        use time::macros::datetime;
+        parse_ical(
+            data,
+            UtcOffset::from_hms(10, 0, 0).unwrap(),
+            datetime!(2024-03-01 0:00 +10),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn duplicate_properties_prefer_tzid() {
+        use time::macros::datetime;
+        let cal = parse_fixture(include_str!("fixtures/duplicate-dtstart.ics"));
+        assert_eq!(
+            cal,
+            vec![Event {
+                summary: "Swimming".to_string(),
+                start: datetime!(2024-02-10 7:00 +10),
+                end: datetime!(2024-02-10 8:00 +10),
+                attendees: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn vendor_properties_ignored() {
+        use time::macros::datetime;
+        let cal = parse_fixture(include_str!("fixtures/vendor-props.ics"));
+        assert_eq!(
+            cal.iter()
+                .map(|x| (x.summary.as_str(), x.start))
+                .collect::<Vec<_>>(),
+            [
+                ("Dinner", datetime!(2024-02-02 18:30 +10)),
+                ("Piano", datetime!(2024-02-05 16:00 +10)),
+            ]
+        );
+    }
+
     #[test]
     fn event_repetition() {
         use time::macros::datetime;
@@ -448,6 +663,8 @@ DTSTART;TZID=Australia/Brisbane:20240120T083000
 DTEND;TZID=Australia/Brisbane:20240120T093000
 RRULE:FREQ=WEEKLY;WKST=SU;BYDAY=SA
 SUMMARY:Test2
+ATTENDEE;CN=Sam;ROLE=REQ-PARTICIPANT:mailto:sam@example.com
+ATTENDEE:MAILTO:alex@example.com
 END:VEVENT
 END:VCALENDAR";
 
@@ -458,6 +675,8 @@ END:VCALENDAR";
         )
         .unwrap();
 
+        // repeats keep the attendees
+        let attendees = vec!["Sam".to_string(), "alex@example.com".to_string()];
         assert_eq!(
             cal,
             vec![
@@ -465,23 +684,67 @@ END:VCALENDAR";
                     summary: "Test".to_string(),
                     start: datetime!(2024-01-13 8:30 +10),
                     end: datetime!(2024-01-13 9:30 +10),
+                    attendees: Vec::new(),
                 },
                 Event {
                     summary: "Test2".to_string(),
                     start: datetime!(2024-01-20 8:30 +10),
                     end: datetime!(2024-01-20 9:30 +10),
+                    attendees: attendees.clone(),
                 },
                 Event {
                     summary: "Test2".to_string(),
                     start: datetime!(2024-01-27 8:30 +10),
                     end: datetime!(2024-01-27 9:30 +10),
+                    attendees: attendees.clone(),
                 },
                 Event {
                     summary: "Test2".to_string(),
                     start: datetime!(2024-02-03 8:30 +10),
                     end: datetime!(2024-02-03 9:30 +10),
+                    attendees: attendees.clone(),
                 }
             ]
         );
     }
+
+    #[test]
+    fn rewrite_summaries() {
+        use time::macros::datetime;
+
+        let rule = |calendar: Option<&str>, find: &str, replace: &str| RewriteConfig {
+            calendar: calendar.map(String::from),
+            find: find.to_string(),
+            replace: replace.to_string(),
+        };
+        let cfg = [
+            rule(Some("Work"), r"^\[Team-X\] ", ""),
+            rule(None, r"^Gymnastics Term \d+ Week \d+.*", "Gym"),
+            rule(None, r"(\w+)'s birthday", "🎂 $1"),
+        ];
+        let ev = |summary: &str| Event {
+            summary: summary.to_string(),
+            start: datetime!(2024-01-13 8:30 +10),
+            end: datetime!(2024-01-13 9:30 +10),
+            attendees: Vec::new(),
+        };
+        let summaries = |name: &str| {
+            let mut cal = vec![
+                ev("[Team-X] Standup"),
+                ev("Gymnastics Term 2 Week 3 (bring water)"),
+                ev("Sam's birthday"),
+                ev("Dentist"),
+            ];
+            Rewrites::for_calendar(&cfg, name).unwrap().apply(&mut cal);
+            cal.into_iter().map(|x| x.summary).collect::<Vec<_>>()
+        };
+
+        assert_eq!(summaries("Work"), ["Standup", "Gym", "🎂 Sam", "Dentist"]);
+        assert_eq!(
+            summaries("Family"),
+            ["[Team-X] Standup", "Gym", "🎂 Sam", "Dentist"]
+        );
+
+        assert!(Rewrites::for_calendar(&[rule(None, "(", "")], "Work").is_err());
+    }
 }
@@ -0,0 +1,215 @@
+//! Merging recurring series which appear twice, usually after a calendar is migrated between
+//! providers and the copy gets new UIDs.
+//!
+//! Occurrences are considered duplicates if they have the same summary (ignoring case) and
+//! overlap, so a copy which was shifted slightly is still caught. Only recurring series, with
+//! occurrences on more than one day, are merged, one-off events which happen to match are left
+//! alone.
+use super::cal::{Calendar, Event};
+use std::{collections::HashMap, fmt};
+use time::{Time, Weekday};
+
+/// A series which was merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Merge {
+    pub summary: String,
+    pub weekday: Weekday,
+    pub time: Time,
+    /// The calendars the series appeared in, the first is the one kept.
+    pub calendars: Vec<String>,
+    /// Number of duplicate occurrences removed.
+    pub removed: usize,
+}
+
+impl Merge {
+    /// Both merges are of the same series.
+    pub fn same_series(&self, other: &Merge) -> bool {
+        self.summary.trim().to_lowercase() == other.summary.trim().to_lowercase()
+            && self.weekday == other.weekday
+            && self.time == other.time
+    }
+}
+
+impl fmt::Display for Merge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            summary,
+            weekday,
+            time,
+            calendars,
+            removed,
+        } = self;
+        write!(
+            f,
+            "'{summary}' ({weekday} {:02}:{:02}) in {}: {removed} duplicates removed",
+            time.hour(),
+            time.minute(),
+            calendars.join(", ")
+        )
+    }
+}
+
+/// The occurrences overlap, or start at the same instant if either takes none.
+fn overlaps(a: &Event, b: &Event) -> bool {
+    a.start == b.start || (a.start < b.end && b.start < a.end)
+}
+
+/// Remove duplicate occurrences of recurring series across (and within) `cals`.
+///
+/// Calendars are visited in name order, so the same copy is kept as calendars are refetched.
+pub fn merge_duplicates(cals: &mut HashMap<String, Calendar>) -> Vec<Merge> {
+    let mut names = cals.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+
+    // summary -> the occurrences, as (calendar, index)
+    let mut series = HashMap::<String, Vec<(usize, usize)>>::new();
+    for (c, name) in names.iter().enumerate() {
+        for (i, ev) in cals[name].iter().enumerate() {
+            series
+                .entry(ev.summary.trim().to_lowercase())
+                .or_default()
+                .push((c, i));
+        }
+    }
+
+    let ev = |(c, i): (usize, usize)| &cals[&names[c]][i];
+    let mut remove = vec![Vec::new(); names.len()];
+    let mut merges = Vec::<Merge>::new();
+    for mut occurrences in series.into_values() {
+        let day = ev(occurrences[0]).start.date();
+        if occurrences.iter().all(|&x| ev(x).start.date() == day) {
+            continue;
+        }
+
+        occurrences.sort_by_key(|&(c, i)| (ev((c, i)).start, c, i));
+        let summary = &ev(occurrences[0]).summary;
+        let mut kept = Vec::<(usize, usize)>::new();
+        for occ in occurrences {
+            let Some(&k) = kept.iter().rev().find(|&&k| overlaps(ev(k), ev(occ))) else {
+                kept.push(occ);
+                continue;
+            };
+            remove[occ.0].push(occ.1);
+
+            let kept_cal = &names[k.0];
+            let k = ev(k);
+            let (weekday, time) = (k.start.weekday(), k.start.time());
+            let i = match merges
+                .iter()
+                .position(|x| &x.summary == summary && x.weekday == weekday && x.time == time)
+            {
+                Some(i) => i,
+                None => {
+                    merges.push(Merge {
+                        summary: summary.clone(),
+                        weekday,
+                        time,
+                        calendars: Vec::new(),
+                        removed: 0,
+                    });
+                    merges.len() - 1
+                }
+            };
+            let m = &mut merges[i];
+            // the kept copy's calendar first
+            for c in [kept_cal, &names[occ.0]] {
+                if !m.calendars.contains(c) {
+                    m.calendars.push(c.clone());
+                }
+            }
+            m.removed += 1;
+        }
+    }
+
+    for (name, mut idxs) in names.iter().zip(remove) {
+        if idxs.is_empty() {
+            continue;
+        }
+        idxs.sort_unstable();
+        let cal = cals.get_mut(name).expect("calendar exists");
+        let mut i = 0;
+        cal.retain(|_| {
+            let keep = idxs.binary_search(&i).is_err();
+            i += 1;
+            keep
+        });
+    }
+
+    merges.sort_by(|a, b| a.summary.cmp(&b.summary));
+    merges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{macros::datetime, Duration, OffsetDateTime};
+
+    fn weekly(summary: &str, first: OffsetDateTime, weeks: i64) -> Calendar {
+        (0..weeks)
+            .map(|w| Event {
+                summary: summary.to_string(),
+                start: first + Duration::weeks(w),
+                end: first + Duration::weeks(w) + Duration::minutes(15),
+                attendees: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_migrated_series() {
+        let mut cals = HashMap::new();
+        // the migrated copy only overlaps for the last 2 weeks of the original
+        let mut old = weekly("Standup", datetime!(2024-06-03 09:00 +10), 4);
+        old.extend(weekly("Dentist", datetime!(2024-06-05 15:00 +10), 1));
+        cals.insert("Old".to_string(), old);
+        let mut new = weekly("standup ", datetime!(2024-06-17 09:00 +10), 4);
+        new.extend(weekly("Dentist", datetime!(2024-06-05 15:00 +10), 1));
+        cals.insert("New".to_string(), new);
+
+        let merges = merge_duplicates(&mut cals);
+        assert_eq!(
+            merges,
+            [Merge {
+                summary: "Standup".to_string(),
+                weekday: Weekday::Monday,
+                time: time::macros::time!(09:00),
+                calendars: vec!["New".to_string(), "Old".to_string()],
+                removed: 2,
+            }]
+        );
+        // the one-off dentist appointments are left alone
+        assert_eq!(cals["New"].len(), 5);
+        assert_eq!(cals["Old"].len(), 3);
+        assert!(cals["Old"]
+            .iter()
+            .all(|e| e.summary == "Dentist" || e.start < datetime!(2024-06-17 09:00 +10)));
+    }
+
+    #[test]
+    fn merges_within_a_calendar() {
+        let mut cal = weekly("Yoga", datetime!(2024-06-04 18:00 +10), 3);
+        cal.extend(weekly("Yoga", datetime!(2024-06-04 18:00 +10), 3));
+        cal.sort_by(|a, b| a.start.cmp(&b.start));
+        let mut cals = HashMap::from([("Home".to_string(), cal)]);
+
+        let merges = merge_duplicates(&mut cals);
+        assert_eq!(merges.len(), 1);
+        assert_eq!(merges[0].removed, 3);
+        assert_eq!(cals["Home"].len(), 3);
+        assert!(merge_duplicates(&mut cals).is_empty());
+    }
+
+    #[test]
+    fn merges_shifted_copies() {
+        // the copy was moved a quarter hour, but still overlaps
+        let old = weekly("Swim", datetime!(2024-06-06 07:00 +10), 3);
+        let new = weekly("Swim", datetime!(2024-06-06 07:10 +10), 3);
+        let mut cals = HashMap::from([("Old".to_string(), old), ("New".to_string(), new)]);
+
+        let merges = merge_duplicates(&mut cals);
+        assert_eq!(merges.len(), 1);
+        assert_eq!(merges[0].calendars, ["Old", "New"]);
+        assert_eq!(merges[0].removed, 3);
+        assert!(cals["New"].is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! A made up but plausible model, for rendering layouts without fetching anything.
+//!
+//! Everything is relative to the given time, so a layout previewed on any day looks the same.
+use super::{
+    air::AirQuality,
+    battery::Battery,
+    cal::Event,
+    moon::{LunarCalendar, Moon, Phase},
+    weather::{Code, Ob, Weather},
+    Model,
+};
+use std::{collections::HashMap, time::Instant};
+use time::{Date, Duration, OffsetDateTime, Time};
+
+pub fn model(now: OffsetDateTime) -> Model {
+    let today = now.date();
+    let days = std::iter::successors(Some(today), |x| x.next_day())
+        .take(42)
+        .collect::<Vec<_>>();
+    let at = |day: Date, h, m| {
+        day.with_time(Time::from_hms(h, m, 0).expect("valid time"))
+            .assume_offset(now.offset())
+    };
+    let ev = |summary: &str, start: OffsetDateTime, mins| Event {
+        summary: summary.to_string(),
+        start,
+        end: start + Duration::minutes(mins),
+        attendees: Vec::new(),
+    };
+
+    let mut family = Vec::new();
+    let mut work = Vec::new();
+    for (i, &day) in days.iter().enumerate() {
+        match day.weekday() {
+            time::Weekday::Saturday => {
+                family.push(ev("Swimming lessons", at(day, 8, 30), 45));
+                if i % 14 < 7 {
+                    family.push(ev("Birthday party at the park", at(day, 13, 0), 180));
+                }
+            }
+            time::Weekday::Sunday => family.push(ev("Groceries", at(day, 10, 0), 60)),
+            _ => {
+                work.push(ev("Standup", at(day, 9, 0), 15));
+                if i % 3 == 0 {
+                    work.push(ev(
+                        "Design review with a rather long title",
+                        at(day, 14, 0),
+                        90,
+                    ));
+                }
+                if day.weekday() == time::Weekday::Wednesday {
+                    family.push(ev("Piano", at(day, 16, 0), 45));
+                }
+            }
+        }
+    }
+    if let Some(&day) = days.get(9) {
+        family.push(ev("Camping", at(day, 0, 0), 3 * 24 * 60));
+    }
+    family.sort_by(|a, b| a.start.cmp(&b.start));
+    work.sort_by(|a, b| a.start.cmp(&b.start));
+
+    let codes = [
+        Code::ClearSky,
+        Code::PartlyCloudy,
+        Code::Rain,
+        Code::Overcast,
+        Code::MainlyClear,
+        Code::Thuderstorm,
+        Code::Drizzle,
+    ];
+    let ob = |i: usize| Ob {
+        code: codes[i % codes.len()],
+        temperature: Some(22.0 + (i % 5) as f32),
+        humidity: Some(55.0 + (i % 4) as f32 * 5.0),
+        precipitation_prob: Some((i * 15 % 100) as f32),
+    };
+    let phases = [
+        Phase::NewMoon,
+        Phase::WaxingCrescent,
+        Phase::FirstQuarter,
+        Phase::WaxingGibbous,
+        Phase::FullMoon,
+        Phase::WaningGibbous,
+        Phase::ThirdQuarter,
+        Phase::WaningCrescent,
+    ];
+
+    let last_update = Instant::now();
+    let mut model = Model::default();
+    let m = model.make_mut();
+    m.cals = HashMap::from([("Family".to_string(), family), ("Work".to_string(), work)]);
+    m.cals_updated = m.cals.keys().map(|k| (k.clone(), last_update)).collect();
+    m.weather = Some(Weather {
+        last_update,
+        current: ob(0),
+        forecast: days
+            .iter()
+            .take(16)
+            .enumerate()
+            .map(|(i, &d)| (d, ob(i)))
+            .collect(),
+        nowcast: Vec::new(),
+        warnings: Vec::new(),
+    });
+    m.moon = Some(LunarCalendar {
+        last_update,
+        calendar: days
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| {
+                let phase = phases[i / 4 % phases.len()];
+                (d, Moon { phase })
+            })
+            .collect(),
+    });
+    m.air = Some(AirQuality {
+        last_update,
+        pm2_5: Some(6.0),
+        aqi: Some(24.0),
+    });
+    m.battery = Some(Battery {
+        last_update,
+        level: 76.0,
+        charging: false,
+        low: false,
+    });
+    model
+}
@@ -0,0 +1,351 @@
+//! Calendars from the Microsoft Graph API (Outlook/Microsoft 365).
+//!
+//! Uses the OAuth device code flow, see [`oauth`]: on first use a code is logged which must be
+//! entered at <https://microsoft.com/devicelogin>.
+use super::{
+    cal::{calendar_patch, fetch_limit, Calendar, Event, Rewrites},
+    source::{DataSource, FetchFuture, ModelPatch},
+};
+use crate::fetch::{oauth, Client, Fetcher};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use time::{
+    format_description::well_known::{Iso8601, Rfc3339},
+    Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset,
+};
+
+const SCOPE: &str = "offline_access Calendars.Read";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GraphConfig {
+    pub name: String,
+    /// The application (client) ID of an Azure app registration allowing public client flows.
+    pub client_id: String,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+}
+
+fn default_tenant() -> String {
+    "common".to_string()
+}
+
+/// Where the token for the calendar `name` is kept, in the working directory whatever the name.
+///
+/// Characters other than letters, digits, `-`, and `_` are replaced, with a hash of the name added
+/// so that names differing only in those don't share a token.
+fn token_path(name: &str) -> PathBuf {
+    let safe = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "-_".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>();
+    let name = match safe == name {
+        true => safe,
+        false => {
+            let hash = name
+                .bytes()
+                .fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193));
+            format!("{safe}-{hash:08x}")
+        }
+    };
+    PathBuf::from(format!("./graph-{name}.token.pical.json"))
+}
+
+/// An authorised session with the Graph API.
+pub struct Session {
+    pub cfg: GraphConfig,
+    pub rewrites: Rewrites,
+    auth: oauth::Session,
+}
+
+impl Session {
+    /// Create a session, loading any persisted token.
+    pub fn new(cfg: GraphConfig) -> Self {
+        let url = |endpoint: &str| {
+            format!(
+                "https://login.microsoftonline.com/{}/oauth2/v2.0/{endpoint}",
+                cfg.tenant
+            )
+        };
+        let provider = oauth::Provider {
+            device_code_url: url("devicecode"),
+            token_url: url("token"),
+            client_id: cfg.client_id.clone(),
+            client_secret: None,
+            scope: SCOPE.to_string(),
+        };
+        let token_path = token_path(&cfg.name);
+        let auth = oauth::Session::new(
+            format!("Graph calendar '{}'", cfg.name),
+            provider,
+            token_path,
+        );
+        Self {
+            cfg,
+            rewrites: Rewrites::default(),
+            auth,
+        }
+    }
+
+    /// Fetch the calendar view between `start` and `end`.
+    ///
+    /// Returns `None` while waiting on the user to authorise the device.
+    pub async fn calendar_view<F: Fetcher>(
+        &mut self,
+        client: &F,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        offset: UtcOffset,
+    ) -> Result<Option<(Calendar, Instant)>> {
+        let Some(auth) = self.auth.header(client).await? else {
+            return Ok(None);
+        };
+
+        let fmt = |x: OffsetDateTime| {
+            x.to_offset(UtcOffset::UTC)
+                .format(&Rfc3339)
+                .into_diagnostic()
+        };
+        let mut url = url::Url::parse_with_params(
+            "https://graph.microsoft.com/v1.0/me/calendarview",
+            &[
+                ("startDateTime", fmt(start)?),
+                ("endDateTime", fmt(end)?),
+                (
+                    "$select",
+                    "subject,start,end,isAllDay,isCancelled,attendees".to_string(),
+                ),
+                ("$top", "250".to_string()),
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("URL parse failed")?
+        .to_string();
+
+        let mut cal = Calendar::new();
+        let mut at = Instant::now();
+        loop {
+            let hdrs = [
+                auth.clone(),
+                ("Prefer", r#"outlook.timezone="UTC""#.to_string()),
+            ];
+            let resp =
+                crate::fetch::json::<CalendarViewPayload, _, _>(client, &url, hdrs, Duration::ZERO)
+                    .await?;
+            at = at.min(resp.at);
+            let payload = resp.body;
+            let next = payload.next_link.clone();
+            cal.extend(parse_calendar_view(payload, offset)?);
+            match next {
+                Some(x) => url = x,
+                None => break,
+            }
+        }
+
+        cal.sort_by(|a, b| a.start.cmp(&b.start));
+        Ok(Some((cal, at)))
+    }
+}
+
+impl DataSource for Session {
+    fn name(&self) -> &str {
+        &self.cfg.name
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let cal = self
+                .calendar_view(client, now, fetch_limit(now), now.offset())
+                .await?;
+            Ok(cal.map_or_else(ModelPatch::none, |(mut cal, at)| {
+                self.rewrites.apply(&mut cal);
+                calendar_patch(self.cfg.name.clone(), cal, at)
+            }))
+        })
+    }
+}
+
+/// Map a page of Graph events into calendar events.
+///
+/// Graph expands recurring events server side, so each occurrence is a separate event.
+pub fn parse_calendar_view(payload: CalendarViewPayload, offset: UtcOffset) -> Result<Calendar> {
+    payload
+        .value
+        .into_iter()
+        .filter(|x| !x.is_cancelled)
+        .map(|ev| {
+            let GraphEvent {
+                subject,
+                start,
+                end,
+                is_all_day,
+                is_cancelled: _,
+                attendees,
+            } = ev;
+            let (start, end) = if is_all_day {
+                // all day events are 'floating' and the end is exclusive
+                let start = start.date()?.with_time(Time::MIDNIGHT);
+                let end = end.date()?.with_time(Time::MIDNIGHT) - time::Duration::SECOND;
+                (start.assume_offset(offset), end.assume_offset(offset))
+            } else {
+                (start.datetime()?, end.datetime()?)
+            };
+            Ok(Event {
+                summary: subject.unwrap_or_default(),
+                start: start.to_offset(offset),
+                end: end.to_offset(offset),
+                attendees: attendees
+                    .into_iter()
+                    .filter_map(|x| x.email_address.name.or(x.email_address.address))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct CalendarViewPayload {
+    value: Vec<GraphEvent>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphEvent {
+    subject: Option<String>,
+    start: GraphDateTime,
+    end: GraphDateTime,
+    #[serde(default)]
+    is_all_day: bool,
+    #[serde(default)]
+    is_cancelled: bool,
+    #[serde(default)]
+    attendees: Vec<GraphAttendee>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphAttendee {
+    email_address: GraphEmailAddress,
+}
+
+#[derive(Deserialize)]
+struct GraphEmailAddress {
+    name: Option<String>,
+    address: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphDateTime {
+    date_time: String,
+    time_zone: String,
+}
+
+impl GraphDateTime {
+    fn primitive(&self) -> Result<PrimitiveDateTime> {
+        PrimitiveDateTime::parse(&self.date_time, &Iso8601::DEFAULT)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to parse Graph date time: {}", self.date_time))
+    }
+
+    fn date(&self) -> Result<Date> {
+        self.primitive().map(|x| x.date())
+    }
+
+    fn datetime(&self) -> Result<OffsetDateTime> {
+        if self.time_zone != "UTC" {
+            return Err(miette!(
+                "expecting Graph times in UTC, found {}",
+                self.time_zone
+            ));
+        }
+        self.primitive().map(|x| x.assume_utc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn calendar_view_parsing() {
+        let payload = r#"{
+    "@odata.context": "https://graph.microsoft.com/v1.0/$metadata#users('me')/calendarView",
+    "value": [
+        {
+            "subject": "Standup",
+            "isAllDay": false,
+            "isCancelled": false,
+            "attendees": [
+                { "emailAddress": { "name": "Sam", "address": "sam@example.com" } },
+                { "emailAddress": { "address": "alex@example.com" } }
+            ],
+            "start": { "dateTime": "2024-01-15T22:30:00.0000000", "timeZone": "UTC" },
+            "end": { "dateTime": "2024-01-15T22:45:00.0000000", "timeZone": "UTC" }
+        },
+        {
+            "subject": "Cancelled",
+            "isAllDay": false,
+            "isCancelled": true,
+            "start": { "dateTime": "2024-01-16T22:30:00.0000000", "timeZone": "UTC" },
+            "end": { "dateTime": "2024-01-16T22:45:00.0000000", "timeZone": "UTC" }
+        },
+        {
+            "subject": "Leave",
+            "isAllDay": true,
+            "isCancelled": false,
+            "start": { "dateTime": "2024-01-18T00:00:00.0000000", "timeZone": "AUS Eastern Standard Time" },
+            "end": { "dateTime": "2024-01-20T00:00:00.0000000", "timeZone": "AUS Eastern Standard Time" }
+        }
+    ]
+}"#;
+        let cal = parse_calendar_view(
+            serde_json::from_str(payload).unwrap(),
+            UtcOffset::from_hms(10, 0, 0).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            cal,
+            vec![
+                Event {
+                    summary: "Standup".to_string(),
+                    start: datetime!(2024-01-16 8:30 +10),
+                    end: datetime!(2024-01-16 8:45 +10),
+                    attendees: vec!["Sam".to_string(), "alex@example.com".to_string()],
+                },
+                Event {
+                    summary: "Leave".to_string(),
+                    start: datetime!(2024-01-18 0:00 +10),
+                    end: datetime!(2024-01-19 23:59:59 +10),
+                    attendees: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn token_paths_stay_in_the_working_directory() {
+        assert_eq!(
+            token_path("Work"),
+            PathBuf::from("./graph-Work.token.pical.json")
+        );
+        let path = token_path("../../etc/x");
+        assert_eq!(path.parent(), Some(std::path::Path::new(".")));
+        assert!(path.to_string_lossy().starts_with("./graph-______etc_x-"));
+        assert_ne!(token_path("a b"), token_path("a/b"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Public holidays from the [Nager.Date](https://date.nager.at) API.
+use super::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::{Client, Fetched};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{format_description::well_known::Iso8601, Date, OffsetDateTime};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HolidayConfig {
+    /// ISO 3166-1 alpha-2 country code, eg `AU`.
+    pub country: String,
+    /// ISO 3166-2 subdivision code, eg `AU-QLD`, to include regional holidays.
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Holidays {
+    pub last_update: Instant,
+    /// Holiday names by date.
+    pub days: HashMap<Date, Vec<String>>,
+}
+
+impl Holidays {
+    /// The holidays which apply to `region`. National holidays always apply.
+    pub fn from_nager(payload: Vec<NagerHoliday>, region: Option<&str>) -> Result<Self> {
+        let mut days = HashMap::<_, Vec<_>>::new();
+        for h in payload {
+            let applies = h.global
+                || h.counties
+                    .as_ref()
+                    .zip(region)
+                    .is_some_and(|(cs, r)| cs.iter().any(|c| c.eq_ignore_ascii_case(r)));
+            if !applies {
+                continue;
+            }
+            let date = Date::parse(&h.date, &Iso8601::DATE)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("date value: {}", h.date))?;
+            let names = days.entry(date).or_default();
+            if !names.contains(&h.local_name) {
+                names.push(h.local_name);
+            }
+        }
+        Ok(Self {
+            last_update: Instant::now(),
+            days,
+        })
+    }
+
+    pub fn on(&self, date: Date) -> &[String] {
+        self.days.get(&date).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Fetches this and next year's holidays, so views spanning the new year are covered.
+impl DataSource for HolidayConfig {
+    fn name(&self) -> &str {
+        "holidays"
+    }
+
+    /// Holidays are only announced months ahead, so daily is plenty.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 60 * 24)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let mut payload = Vec::new();
+            let mut stale = None;
+            for year in [now.year(), now.year() + 1] {
+                let url = format!(
+                    "https://date.nager.at/api/v3/PublicHolidays/{year}/{}",
+                    self.country
+                );
+                let resp: Fetched<Vec<NagerHoliday>> =
+                    crate::fetch::json(client, &url, [], self.fresh_for()).await?;
+                payload.extend(resp.body);
+                stale = stale.or(resp.stale);
+            }
+            let holidays = Holidays::from_nager(payload, self.region.as_deref())?;
+            Ok(ModelPatch::new(|model| model.holidays = Some(holidays)).stale(stale))
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NagerHoliday {
+    date: String,
+    local_name: String,
+    global: bool,
+    counties: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn regional_holidays() {
+        let payload = r#"[
+            {"date":"2024-01-01","localName":"New Year's Day","name":"New Year's Day","countryCode":"AU","fixed":false,"global":true,"counties":null,"launchYear":null,"types":["Public"]},
+            {"date":"2024-05-06","localName":"Labour Day","name":"Labour Day","countryCode":"AU","fixed":false,"global":false,"counties":["AU-QLD"],"launchYear":null,"types":["Public"]},
+            {"date":"2024-03-11","localName":"Labour Day","name":"Labour Day","countryCode":"AU","fixed":false,"global":false,"counties":["AU-VIC"],"launchYear":null,"types":["Public"]}
+        ]"#;
+        let h =
+            Holidays::from_nager(serde_json::from_str(payload).unwrap(), Some("au-qld")).unwrap();
+        assert_eq!(h.on(date!(2024 - 01 - 01)), ["New Year's Day"]);
+        assert_eq!(h.on(date!(2024 - 05 - 06)), ["Labour Day"]);
+        assert!(h.on(date!(2024 - 03 - 11)).is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Events injected by scripts, such as "Babysitter tonight", shown alongside the fetched
+//! calendars until they end.
+//!
+//! Each source is shown as a calendar of its own, see [`Model_::insert_events`]. The events are
+//! kept in a file so they survive restarts.
+use super::{
+    cal::{Calendar, Event},
+    Model_,
+};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use time::OffsetDateTime;
+
+/// An event as injected and saved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Injected {
+    pub summary: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+}
+
+impl From<Injected> for Event {
+    fn from(x: Injected) -> Self {
+        let Injected {
+            summary,
+            start,
+            end,
+        } = x;
+        Event {
+            summary,
+            start,
+            end,
+            attendees: Vec::new(),
+        }
+    }
+}
+
+impl From<&Event> for Injected {
+    fn from(x: &Event) -> Self {
+        Injected {
+            summary: x.summary.clone(),
+            start: x.start,
+            end: x.end,
+        }
+    }
+}
+
+impl Model_ {
+    /// Show `evs` in the calendar `source`, alongside any injected there before.
+    ///
+    /// Errors if `source` is the name of a fetched calendar, which would replace the events, or
+    /// if an event ends before it starts.
+    pub fn insert_events(&mut self, source: &str, evs: Vec<Event>) -> Result<()> {
+        if self.cals_updated.contains_key(source) {
+            return Err(miette!(
+                help = "inject into a source of its own, such as \"scripts\"",
+                "{source} is a fetched calendar"
+            ));
+        }
+        if let Some(x) = evs.iter().find(|x| x.end < x.start) {
+            return Err(miette!("{} ends before it starts", x.summary));
+        }
+        self.injected.insert(source.to_string());
+        let cal = self.cals.entry(source.to_string()).or_default();
+        cal.extend(evs);
+        cal.sort_by(|a, b| a.start.cmp(&b.start));
+        Ok(())
+    }
+
+    /// Remove the injected events which ended before `now`, and any sources left empty.
+    /// Returns whether any were removed.
+    pub fn expire_events(&mut self, now: OffsetDateTime) -> bool {
+        // a calendar since fetched under the same name has replaced the events
+        let fetched = &self.cals_updated;
+        self.injected.retain(|x| !fetched.contains_key(x));
+        let mut expired = false;
+        for source in &self.injected {
+            if let Some(cal) = self.cals.get_mut(source) {
+                let n = cal.len();
+                cal.retain(|x| x.end >= now);
+                expired |= cal.len() < n;
+            }
+        }
+        let cals = &mut self.cals;
+        self.injected.retain(|source| match cals.get(source) {
+            Some(cal) if cal.is_empty() => {
+                cals.remove(source);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        });
+        expired
+    }
+
+    /// Whether any injected events ended before `now`, to check before cloning the model to
+    /// expire them.
+    pub fn any_expired(&self, now: OffsetDateTime) -> bool {
+        self.injected_calendars()
+            .any(|(_, cal)| cal.iter().any(|x| x.end < now))
+    }
+
+    /// The calendars of injected events, by source.
+    pub fn injected_calendars(&self) -> impl Iterator<Item = (&str, &Calendar)> {
+        self.injected
+            .iter()
+            .filter(|x| !self.cals_updated.contains_key(*x))
+            .filter_map(|x| self.cals.get(x).map(|cal| (x.as_str(), cal)))
+    }
+}
+
+/// The injected events by source, as saved.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Saved(pub BTreeMap<String, Vec<Injected>>);
+
+impl Saved {
+    pub fn of(model: &Model_) -> Self {
+        Self(
+            model
+                .injected_calendars()
+                .map(|(source, cal)| (source.to_string(), cal.iter().map(Into::into).collect()))
+                .collect(),
+        )
+    }
+
+    /// Read the events saved at `path`, none if there are none.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(x) => serde_json::from_str(&x)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the events to `path`, via a temporary file so a power cut can't truncate them.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).into_diagnostic()?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to replace {}", path.display()))
+    }
+
+    /// Insert the saved events into `model`.
+    pub fn restore(self, model: &mut Model_) -> Result<()> {
+        for (source, evs) in self.0 {
+            model.insert_events(&source, evs.into_iter().map(Into::into).collect())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn ev(summary: &str, start: OffsetDateTime, end: OffsetDateTime) -> Event {
+        Injected {
+            summary: summary.to_string(),
+            start,
+            end,
+        }
+        .into()
+    }
+
+    #[tokio::test]
+    async fn inject_expire_and_restore() {
+        let mut model = Model_::default();
+        model
+            .cals_updated
+            .insert("Work".to_string(), std::time::Instant::now());
+        let babysitter = ev(
+            "Babysitter",
+            datetime!(2024-06-21 19:00 +10),
+            datetime!(2024-06-21 22:00 +10),
+        );
+        let bins = ev(
+            "Bins out",
+            datetime!(2024-06-21 17:00 +10),
+            datetime!(2024-06-21 18:00 +10),
+        );
+        assert!(model.insert_events("Work", vec![bins.clone()]).is_err());
+        let backwards = ev("Backwards", bins.end, bins.start);
+        assert!(model.insert_events("scripts", vec![backwards]).is_err());
+        assert!(!model.cals.contains_key("scripts"));
+        model
+            .insert_events("scripts", vec![babysitter.clone()])
+            .unwrap();
+        model.insert_events("scripts", vec![bins.clone()]).unwrap();
+        assert_eq!(model.cals["scripts"], [bins.clone(), babysitter.clone()]);
+
+        let dir = std::env::temp_dir().join(format!("pical-injected-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("injected.pical.json");
+        assert_eq!(Saved::load(&path).unwrap(), Saved::default());
+        Saved::of(&model).save(&path).await.unwrap();
+        let mut restored = Model_::default();
+        Saved::load(&path).unwrap().restore(&mut restored).unwrap();
+        assert_eq!(restored.cals, model.cals);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let now = datetime!(2024-06-21 18:30 +10);
+        assert!(model.any_expired(now));
+        assert!(model.expire_events(now));
+        assert!(!model.any_expired(now));
+        assert_eq!(model.cals["scripts"], [babysitter]);
+        assert!(model.expire_events(datetime!(2024-06-22 0:00 +10)));
+        assert!(!model.cals.contains_key("scripts"));
+        assert!(model.injected.is_empty());
+    }
+}
@@ -1,7 +1,24 @@
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Deref,
+    sync::Arc,
+    time::Instant,
+};
 
+pub mod air;
+pub mod annual;
+pub mod battery;
 pub mod cal;
+pub mod dedupe;
+pub mod fixture;
+pub mod graph;
+pub mod holiday;
+pub mod injected;
 pub mod moon;
+pub mod news;
+pub mod source;
+pub mod sun;
+pub mod tide;
 pub mod weather;
 
 #[derive(Clone, Default)]
@@ -10,8 +27,52 @@ pub struct Model(Arc<Model_>);
 #[derive(Default, Clone)]
 pub struct Model_ {
     pub cals: HashMap<String, cal::Calendar>,
+    /// When each calendar was last fetched.
+    pub cals_updated: HashMap<String, Instant>,
     pub weather: Option<weather::Weather>,
+    pub ensemble: Option<weather::Ensemble>,
     pub moon: Option<moon::LunarCalendar>,
+    /// Today's sunrise, sunset, and twilight.
+    pub sun: Option<sun::Daylight>,
+    pub tides: Option<tide::Tides>,
+    pub air: Option<air::AirQuality>,
+    pub battery: Option<battery::Battery>,
+    pub holidays: Option<holiday::Holidays>,
+    pub news: Option<news::News>,
+    /// Duplicate series merged from the calendars, see [`dedupe`].
+    pub merged: Vec<dedupe::Merge>,
+    /// The calendars of events injected by scripts, see [`injected`].
+    pub injected: BTreeSet<String>,
+    /// The data sources which have not loaded yet, and when they were first fetched.
+    pub unloaded: HashMap<String, Instant>,
+}
+
+impl Model_ {
+    /// The least recently updated data source and when it was updated.
+    ///
+    /// A source which has not loaded yet is as stale as the time since it was first fetched. Once
+    /// loaded, the lunar calendar and tides are not considered, they are only fetched twice a day.
+    pub fn stalest(&self) -> Option<(&str, Instant)> {
+        self.updated()
+            .chain(self.unloaded.iter().map(|(k, x)| (k.as_str(), *x)))
+            .min_by_key(|x| x.1)
+    }
+
+    /// When each data source was last updated, excluding the lunar calendar and tides.
+    pub fn updated(&self) -> impl Iterator<Item = (&str, Instant)> {
+        self.cals_updated
+            .iter()
+            .map(|(k, x)| (k.as_str(), *x))
+            .chain(self.weather.as_ref().map(|x| ("weather", x.last_update)))
+            .chain(
+                self.ensemble
+                    .as_ref()
+                    .map(|x| ("weather ensemble", x.last_update)),
+            )
+            .chain(self.air.as_ref().map(|x| ("air quality", x.last_update)))
+            .chain(self.battery.as_ref().map(|x| ("battery", x.last_update)))
+            .chain(self.news.as_ref().map(|x| ("news", x.last_update)))
+    }
 }
 
 impl Deref for Model {
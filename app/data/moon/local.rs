@@ -0,0 +1,25 @@
+//! The lunar calendar computed on the device.
+use super::LunarCalendar;
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// The lunar calendar computed locally, the default without a stormglass.io API key.
+pub struct Local;
+
+impl DataSource for Local {
+    fn name(&self) -> &str {
+        "lunar calendar"
+    }
+
+    fn interval(&self) -> Duration {
+        // only the dates shown move on
+        Duration::from_secs(60 * 60)
+    }
+
+    fn fetch<'a>(&'a mut self, _: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        let moon = LunarCalendar::compute(now.date(), 60, now.offset());
+        Box::pin(async move { Ok(ModelPatch::new(|model| model.moon = Some(moon))) })
+    }
+}
@@ -0,0 +1,153 @@
+//! Lunar phases, computed locally, or from stormglass.io if an API key is set.
+use super::source::Registry;
+use std::{collections::HashMap, time::Instant};
+use time::{Date, OffsetDateTime, UtcOffset};
+
+#[cfg(feature = "moon")]
+pub mod local;
+#[cfg(feature = "moon")]
+pub mod storm_glass;
+
+#[derive(Clone)]
+pub struct LunarCalendar {
+    pub last_update: Instant,
+    pub calendar: HashMap<Date, Moon>,
+}
+
+#[derive(Clone)]
+pub struct Moon {
+    pub phase: Phase,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    FullMoon,
+    WaningGibbous,
+    ThirdQuarter,
+    WaningCrescent,
+}
+
+impl LunarCalendar {
+    /// The phases of the `days` from `start` in `offset`, computed without a network call.
+    pub fn compute(start: Date, days: u16, offset: UtcOffset) -> Self {
+        let calendar = std::iter::successors(Some(start), |x| x.next_day())
+            .take(usize::from(days))
+            .map(|date| (date, Moon::on(date, offset)))
+            .collect();
+        Self {
+            last_update: Instant::now(),
+            calendar,
+        }
+    }
+}
+
+impl Moon {
+    /// The phase on `date` in `offset`.
+    ///
+    /// New, first quarter, full, and third quarter are instants, so only the day they happen
+    /// in has them, the days between are crescent or gibbous.
+    pub fn on(date: Date, offset: UtcOffset) -> Self {
+        use Phase::*;
+        let start = date.midnight().assume_offset(offset);
+        let from = elongation(start);
+        // the moon moves about 12° a day, so never wraps past the start again
+        let to = from + (elongation(start + time::Duration::DAY) - from).rem_euclid(360.0);
+        let quarter = (from / 90.0).ceil();
+        let phase = match (quarter * 90.0 < to).then_some(quarter as u8 % 4) {
+            Some(0) => NewMoon,
+            Some(1) => FirstQuarter,
+            Some(2) => FullMoon,
+            Some(_) => ThirdQuarter,
+            None => match (from / 90.0) as u8 {
+                0 => WaxingCrescent,
+                1 => WaxingGibbous,
+                2 => WaningGibbous,
+                _ => WaningCrescent,
+            },
+        };
+        Self { phase }
+    }
+}
+
+/// How far the moon is east of the sun at `t`, in degrees from 0 (new) through 180 (full).
+///
+/// The low precision solar and lunar longitudes with the largest lunar terms, within a degree,
+/// or a couple of hours of the phase.
+fn elongation(t: OffsetDateTime) -> f64 {
+    const J2000: OffsetDateTime = time::macros::datetime!(2000-01-01 12:00 UTC);
+    let d = (t - J2000).as_seconds_f64() / 86400.0;
+    let sin = |deg: f64| deg.to_radians().sin();
+
+    // the sun
+    let m = 357.529 + 0.98560028 * d;
+    let l = 280.459 + 0.98564736 * d;
+    let sun = l + 1.915 * sin(m) + 0.020 * sin(2.0 * m);
+
+    // the moon
+    let lm = 218.316 + 13.176396 * d;
+    let mm = 134.963 + 13.064993 * d;
+    let e = lm - l;
+    let moon = lm + 6.289 * sin(mm) + 1.274 * sin(2.0 * e - mm) + 0.658 * sin(2.0 * e)
+        - 0.186 * sin(m)
+        - 0.059 * sin(2.0 * e - 2.0 * mm)
+        - 0.057 * sin(2.0 * e - m - mm)
+        + 0.053 * sin(2.0 * e + mm);
+
+    (moon - sun).rem_euclid(360.0)
+}
+
+/// Add the lunar calendar to `sources`, from stormglass.io if `apikey` is set.
+#[cfg(feature = "moon")]
+pub fn register(sources: &mut Registry, coords: [f32; 2], apikey: String) {
+    if apikey.is_empty() {
+        sources.register(local::Local);
+    } else {
+        sources.register(storm_glass::StormGlass { coords, apikey });
+    }
+}
+
+/// Without the `moon` feature there is no lunar calendar to add.
+#[cfg(not(feature = "moon"))]
+pub fn register(_: &mut Registry, _: [f32; 2], _: String) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, offset};
+
+    #[test]
+    fn computed_phases() {
+        let phases = |start, offset| {
+            let cal = LunarCalendar::compute(start, 30, offset);
+            let mut days = cal.calendar.into_iter().collect::<Vec<_>>();
+            days.sort_by_key(|x| x.0);
+            days.into_iter()
+                .map(|(d, x)| (d, format!("{:?}", x.phase)))
+                .collect::<Vec<_>>()
+        };
+        let utc = phases(date!(2024 - 01 - 01), offset!(UTC));
+        let on = |phase: &str| {
+            utc.iter()
+                .filter(|x| x.1 == phase)
+                .map(|x| x.0)
+                .collect::<Vec<_>>()
+        };
+        // 11 Jan 11:57, 18 Jan 03:53, 25 Jan 17:54, and 4 Jan 03:30 UTC
+        assert_eq!(on("NewMoon"), [date!(2024 - 01 - 11)]);
+        assert_eq!(on("FirstQuarter"), [date!(2024 - 01 - 18)]);
+        assert_eq!(on("FullMoon"), [date!(2024 - 01 - 25)]);
+        assert_eq!(on("ThirdQuarter"), [date!(2024 - 01 - 04)]);
+        assert_eq!(on("WaxingCrescent").len(), 6);
+        assert_eq!(utc[11].1, "WaxingCrescent");
+        assert_eq!(utc[20].1, "WaxingGibbous");
+
+        // the full moon is the next morning in Brisbane
+        let bne = phases(date!(2024 - 01 - 01), offset!(+10));
+        assert_eq!(bne[24].1, "WaxingGibbous");
+        assert_eq!(bne[25].1, "FullMoon");
+    }
+}
@@ -0,0 +1,119 @@
+//! The lunar calendar from stormglass.io.
+use super::{LunarCalendar, Moon, Phase};
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{OffsetDateTime, UtcOffset};
+
+impl LunarCalendar {
+    pub fn from_storm_glass_io(payload: StormGlassPayload, offset: UtcOffset) -> Result<Self> {
+        let mut calendar = HashMap::default();
+        let fmt = time::format_description::well_known::Iso8601::PARSING;
+        for StormGlassData { time, moon_phase } in payload.data {
+            let date = OffsetDateTime::parse(&time, &fmt)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to parse time {time}"))?
+                .to_offset(offset)
+                .date();
+            let phase = Phase::from_storm_glass_io(&moon_phase.current.text)?;
+
+            calendar.insert(date, Moon { phase });
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            calendar,
+        })
+    }
+}
+
+/// The lunar calendar from stormglass.io.
+pub struct StormGlass {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+    pub apikey: String,
+}
+
+impl DataSource for StormGlass {
+    fn name(&self) -> &str {
+        "lunar calendar"
+    }
+
+    /// Only every half a day, avoids rate limits and the phases will not change.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 60 * 12)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let [lat, long] = self.coords;
+            let url = url::Url::parse_with_params(
+                "https://api.stormglass.io/v2/astronomy/point",
+                &[
+                    ("lat", lat.to_string()),
+                    ("lng", long.to_string()),
+                    ("start", now.date().to_string()),
+                    ("end", (now.date() + time::Duration::days(10)).to_string()),
+                ],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let resp = crate::fetch::json(
+                client,
+                url.as_str(),
+                [("Authorization", self.apikey.clone())],
+                self.fresh_for(),
+            )
+            .await?;
+            let moon = LunarCalendar {
+                last_update: resp.at,
+                ..LunarCalendar::from_storm_glass_io(resp.body, now.offset())?
+            };
+            Ok(ModelPatch::new(|model| model.moon = Some(moon)).stale(resp.stale))
+        })
+    }
+}
+
+impl Phase {
+    fn from_storm_glass_io(text: &str) -> Result<Self> {
+        use Phase::*;
+        match text {
+            "New moon" => Ok(NewMoon),
+            "Waxing crescent" => Ok(WaxingCrescent),
+            "First quarter" => Ok(FirstQuarter),
+            "Waxing gibbous" => Ok(WaxingGibbous),
+            "Full moon" => Ok(FullMoon),
+            "Waning gibbous" => Ok(WaningGibbous),
+            "Third quarter" => Ok(ThirdQuarter),
+            "Waning crescent" => Ok(WaningCrescent),
+            x => Err(miette!("unknown moon phase: {x}")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StormGlassPayload {
+    data: Vec<StormGlassData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StormGlassData {
+    time: String,
+    moon_phase: StormGlassMoonPhase,
+}
+
+#[derive(Deserialize)]
+struct StormGlassMoonPhase {
+    current: StormGlassMoonPhaseObj,
+}
+
+#[derive(Deserialize)]
+struct StormGlassMoonPhaseObj {
+    text: String,
+}
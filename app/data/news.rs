@@ -0,0 +1,194 @@
+//! Headlines from RSS and Atom feeds.
+use super::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::{rss::Feed, Client};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NewsConfig {
+    /// URLs of the RSS or Atom feeds.
+    pub feeds: Vec<String>,
+    /// How many of the newest headlines to rotate through.
+    #[serde(default = "default_top")]
+    pub top: usize,
+    /// How long each headline is shown.
+    #[serde(default = "default_rotate", with = "humantime_serde")]
+    pub rotate: Duration,
+}
+
+fn default_top() -> usize {
+    5
+}
+
+fn default_rotate() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Clone)]
+pub struct News {
+    pub last_update: Instant,
+    /// Newest first.
+    pub headlines: Vec<Headline>,
+    /// How long each headline is shown.
+    pub rotate: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headline {
+    pub title: String,
+    /// The title of the feed it is from.
+    pub source: Option<String>,
+    pub published: Option<OffsetDateTime>,
+}
+
+impl News {
+    /// The `top` newest headlines across the feeds.
+    ///
+    /// Headlines without a date follow those with one, in the order of their feed. A headline
+    /// carried by more than one feed is only kept once.
+    pub fn from_feeds(feeds: Vec<Feed>, top: usize, rotate: Duration) -> Self {
+        let mut headlines = feeds
+            .into_iter()
+            .flat_map(|feed| {
+                let source = feed.title;
+                feed.items.into_iter().map(move |x| Headline {
+                    title: x.title,
+                    source: source.clone(),
+                    published: x.published,
+                })
+            })
+            .collect::<Vec<_>>();
+        // stable, so undated headlines keep their feed's order
+        headlines.sort_by_key(|x| std::cmp::Reverse(x.published));
+        let mut seen = std::collections::HashSet::new();
+        headlines.retain(|x| seen.insert(x.title.to_lowercase()));
+        headlines.truncate(top);
+        Self {
+            last_update: Instant::now(),
+            headlines,
+            rotate,
+        }
+    }
+
+    /// The headline to show at `now`, each taking a turn of `rotate`.
+    pub fn current(&self, now: OffsetDateTime) -> Option<&Headline> {
+        let turn = self.rotate.as_secs().max(1) as i64;
+        let i = now.unix_timestamp().div_euclid(turn) as usize % self.headlines.len().max(1);
+        self.headlines.get(i)
+    }
+}
+
+impl DataSource for NewsConfig {
+    fn name(&self) -> &str {
+        "news"
+    }
+
+    /// Only every 30 minutes, headlines move slowly enough, and it is polite to the publishers.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 30)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, _now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let fresh_for = self.fresh_for();
+            let mut feeds = Vec::new();
+            let mut at = None;
+            let mut failed = Vec::new();
+            let mut stale = None;
+            for url in &self.feeds {
+                // one broken feed shouldn't take the others' headlines with it
+                let resp = match crate::fetch::rss::feed(client, url, fresh_for).await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        failed.push(e.wrap_err(format!("news feed {url} failed")));
+                        continue;
+                    }
+                };
+                feeds.push(resp.body);
+                stale = stale.or(resp.stale);
+                // the oldest, so a feed serving its cache is noticed as stale
+                at = Some(at.map_or(resp.at, |x: Instant| x.min(resp.at)));
+            }
+            let failures = failed.len();
+            if let Some(e) = failed.pop() {
+                if feeds.is_empty() {
+                    return Err(e.wrap_err("every news feed failed"));
+                }
+                let total = self.feeds.len();
+                stale = Some(e.wrap_err(format!(
+                    "{failures} of {total} news feeds failed, showing the others' headlines"
+                )));
+            }
+            let news = News {
+                last_update: at.unwrap_or_else(Instant::now),
+                ..News::from_feeds(feeds, self.top, self.rotate)
+            };
+            Ok(ModelPatch::new(move |model| {
+                // a failing feed's headlines are as old as the last fetch they were all in
+                let last = model.news.as_ref().map(|x| x.last_update);
+                let last_update = match last {
+                    Some(x) if failures > 0 => news.last_update.min(x),
+                    _ => news.last_update,
+                };
+                model.news = Some(News {
+                    last_update,
+                    ..news
+                });
+            })
+            .stale(stale))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::rss::Item;
+    use time::macros::datetime;
+
+    fn item(title: &str, published: Option<OffsetDateTime>) -> Item {
+        Item {
+            title: title.into(),
+            link: None,
+            published,
+        }
+    }
+
+    #[test]
+    fn newest_across_feeds() {
+        let feeds = vec![
+            Feed {
+                title: Some("A".into()),
+                items: vec![
+                    item("Undated", None),
+                    item("Older", Some(datetime!(2024-06-21 06:00 UTC))),
+                    item("Same story", Some(datetime!(2024-06-21 07:00 UTC))),
+                ],
+            },
+            Feed {
+                title: Some("B".into()),
+                items: vec![
+                    item("Newest", Some(datetime!(2024-06-21 09:00 UTC))),
+                    item("same STORY", Some(datetime!(2024-06-21 07:30 UTC))),
+                ],
+            },
+        ];
+        let news = News::from_feeds(feeds, 4, Duration::from_secs(60));
+        let titles = news
+            .headlines
+            .iter()
+            .map(|x| x.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["Newest", "same STORY", "Older", "Undated"]);
+        assert_eq!(news.headlines[0].source.as_deref(), Some("B"));
+
+        let now = datetime!(2024-06-21 10:00 UTC);
+        assert_eq!(news.current(now).unwrap().title, "Newest");
+        let next = now + time::Duration::minutes(1);
+        assert_eq!(news.current(next).unwrap().title, "same STORY");
+        let around = now + time::Duration::minutes(4);
+        assert_eq!(news.current(around).unwrap().title, "Newest");
+    }
+}
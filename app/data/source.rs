@@ -0,0 +1,195 @@
+//! Pluggable data sources.
+//!
+//! Each source fetches on its own interval and returns a [`ModelPatch`] which is applied to the
+//! model. New sources implement [`DataSource`] and are added to the [`Registry`], the fetch loop
+//! does not need to know about them.
+use super::Model_;
+use crate::fetch::Client;
+use miette::*;
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<ModelPatch>> + Send + 'a>>;
+
+/// Something which fetches data for the model, such as a calendar or the weather forecast.
+pub trait DataSource: Send {
+    /// A short name, used in logs and errors.
+    fn name(&self) -> &str;
+
+    /// How often to fetch. Failed fetches are retried on the next pass of the fetch loop.
+    fn interval(&self) -> Duration;
+
+    /// How long the source's cached responses are used for rather than fetching again, such as
+    /// after a restart. A minute short of the interval, so the response cached by the last
+    /// fetch isn't still fresh when the next is due.
+    fn fresh_for(&self) -> Duration {
+        self.interval().saturating_sub(Duration::from_secs(60))
+    }
+
+    /// Fetch the latest data. `now` is the local time.
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a>;
+}
+
+/// A change to the model, from a successful fetch.
+pub struct ModelPatch {
+    apply: Box<dyn FnOnce(&mut Model_) + Send>,
+    /// Why fetching failed, if the patch is of responses cached before.
+    stale: Option<Report>,
+}
+
+impl ModelPatch {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(&mut Model_) + Send + 'static,
+    {
+        Self {
+            apply: Box::new(f),
+            stale: None,
+        }
+    }
+
+    /// Mark the patch as partial or of responses cached before, if fetching failed with `e`. It
+    /// is still applied, but the failure is reported and the source fetched again on the next
+    /// pass.
+    pub fn stale(mut self, e: Option<Report>) -> Self {
+        self.stale = e.or(self.stale);
+        self
+    }
+
+    /// A patch which changes nothing, for when a fetch has nothing to report yet.
+    pub fn none() -> Self {
+        Self::new(|_| ())
+    }
+
+    pub fn apply(self, model: &mut Model_) {
+        (self.apply)(model)
+    }
+}
+
+/// The sources the fetch loop iterates.
+#[derive(Default)]
+pub struct Registry {
+    sources: Vec<Entry>,
+}
+
+struct Entry {
+    source: Box<dyn DataSource>,
+    last_fetch: Option<Instant>,
+    /// Patched the model, if only from the cache.
+    loaded: bool,
+}
+
+impl Registry {
+    pub fn register<S: DataSource + 'static>(&mut self, source: S) -> &mut Self {
+        self.sources.push(Entry {
+            source: Box::new(source),
+            last_fetch: None,
+            loaded: false,
+        });
+        self
+    }
+
+    /// The names of the registered sources.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().map(|x| x.source.name())
+    }
+
+    /// The names of the sources which have not been fetched yet, not even from the cache.
+    pub fn unfetched(&self) -> impl Iterator<Item = &str> {
+        self.sources
+            .iter()
+            .filter(|x| !x.loaded)
+            .map(|x| x.source.name())
+    }
+
+    /// Fetch the sources which are due, returning the patches of those which succeeded and the
+    /// errors of those which did not.
+    pub async fn fetch_due(
+        &mut self,
+        client: &Client,
+        now: OffsetDateTime,
+    ) -> (Vec<ModelPatch>, Vec<Report>) {
+        self.fetch_due_at(client, now, Instant::now()).await
+    }
+
+    /// [`Registry::fetch_due`] with `at` as the monotonic time, such as from a simulated clock.
+    pub async fn fetch_due_at(
+        &mut self,
+        client: &Client,
+        now: OffsetDateTime,
+        at: Instant,
+    ) -> (Vec<ModelPatch>, Vec<Report>) {
+        let mut patches = Vec::new();
+        let mut errs = Vec::new();
+        for entry in &mut self.sources {
+            if !entry.is_due(at) {
+                continue;
+            }
+            let name = entry.source.name().to_string();
+            match entry.source.fetch(client, now).await {
+                Ok(mut x) => {
+                    entry.loaded = true;
+                    match x.stale.take() {
+                        // left due, so it is tried again
+                        Some(e) => errs.push(e.wrap_err(format!("failed to fetch {name}"))),
+                        None => {
+                            entry.last_fetch = Some(at);
+                            log::info!("Fetched latest {name}");
+                        }
+                    }
+                    patches.push(x);
+                }
+                Err(e) => errs.push(e.wrap_err(format!("failed to fetch {name}"))),
+            }
+        }
+        (patches, errs)
+    }
+}
+
+impl Entry {
+    fn is_due(&self, now: Instant) -> bool {
+        match self.last_fetch {
+            Some(x) => now.duration_since(x) >= self.source.interval(),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed;
+
+    impl DataSource for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_secs(60)
+        }
+
+        fn fetch<'a>(&'a mut self, _: &'a Client, _: OffsetDateTime) -> FetchFuture<'a> {
+            Box::pin(async { Ok(ModelPatch::none()) })
+        }
+    }
+
+    #[test]
+    fn due_on_interval() {
+        let now = Instant::now();
+        let mut entry = Entry {
+            source: Box::new(Fixed),
+            last_fetch: None,
+            loaded: false,
+        };
+        assert!(entry.is_due(now));
+        entry.last_fetch = Some(now);
+        assert!(!entry.is_due(now + Duration::from_secs(59)));
+        assert!(entry.is_due(now + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,184 @@
+//! Sunrise, sunset, and civil twilight, computed locally with the NOAA solar equations.
+//!
+//! Accurate to a minute or two away from the poles, which is plenty for a calendar.
+use super::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use std::f64::consts::PI;
+use time::{Date, Duration, OffsetDateTime, Time, UtcOffset};
+
+/// The sun's movement on a day, and when it is light enough to see by.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Daylight {
+    pub sun: Sun,
+    /// Civil dawn and dusk, as the rise and set.
+    pub twilight: Sun,
+}
+
+impl Daylight {
+    /// Compute for `date` at `[latitude, longitude]`, with times in `offset`.
+    pub fn on(date: Date, coords: [f32; 2], offset: UtcOffset) -> Self {
+        Self {
+            sun: Sun::on(date, coords, offset),
+            twilight: Sun::civil(date, coords, offset),
+        }
+    }
+
+    /// Between civil dusk and dawn.
+    pub fn is_dark(&self, time: OffsetDateTime) -> bool {
+        !self.twilight.is_up(time)
+    }
+}
+
+/// Today's [`Daylight`] at the configured location, without any network call.
+pub struct Local {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for Local {
+    fn name(&self) -> &str {
+        "daylight"
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        // only the date moves it on
+        std::time::Duration::from_secs(60 * 10)
+    }
+
+    fn fetch<'a>(&'a mut self, _: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        let daylight = Daylight::on(now.date(), self.coords, now.offset());
+        Box::pin(async move { Ok(ModelPatch::new(move |model| model.sun = Some(daylight))) })
+    }
+}
+
+/// The sun's movement on a day.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sun {
+    RiseSet {
+        rise: OffsetDateTime,
+        set: OffsetDateTime,
+    },
+    /// Polar day.
+    AlwaysUp,
+    /// Polar night.
+    AlwaysDown,
+}
+
+impl Sun {
+    /// Compute for `date` at `[latitude, longitude]`, with times in `offset`.
+    pub fn on(date: Date, coords: [f32; 2], offset: UtcOffset) -> Self {
+        // 90.833° accounts for refraction and the size of the sun's disc
+        Self::at_zenith(date, coords, offset, 90.833)
+    }
+
+    /// Civil dawn and dusk, when the sun is 6° below the horizon, as the rise and set.
+    pub fn civil(date: Date, coords: [f32; 2], offset: UtcOffset) -> Self {
+        Self::at_zenith(date, coords, offset, 96.0)
+    }
+
+    /// When the sun crosses `zenith` degrees from overhead.
+    fn at_zenith(date: Date, coords: [f32; 2], offset: UtcOffset, zenith: f64) -> Self {
+        let [lat, long] = coords.map(|x| x as f64);
+        // fractional year at solar noon, in radians
+        let g = 2.0 * PI / 365.0 * (date.ordinal() as f64 - 1.0);
+        let eqtime = 229.18
+            * (0.000075 + 0.001868 * g.cos()
+                - 0.032077 * g.sin()
+                - 0.014615 * (2.0 * g).cos()
+                - 0.040849 * (2.0 * g).sin());
+        let decl = 0.006918 - 0.399912 * g.cos() + 0.070257 * g.sin() - 0.006758 * (2.0 * g).cos()
+            + 0.000907 * (2.0 * g).sin()
+            - 0.002697 * (3.0 * g).cos()
+            + 0.00148 * (3.0 * g).sin();
+
+        let lat = lat.to_radians();
+        let cos_ha = zenith.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+        if cos_ha > 1.0 {
+            return Sun::AlwaysDown;
+        }
+        if cos_ha < -1.0 {
+            return Sun::AlwaysUp;
+        }
+        let ha = cos_ha.acos().to_degrees();
+
+        // minutes from UTC midnight
+        let at = |mins: f64| {
+            (date.with_time(Time::MIDNIGHT).assume_utc()
+                + Duration::seconds((mins * 60.0).round() as i64))
+            .to_offset(offset)
+        };
+        Sun::RiseSet {
+            rise: at(720.0 - 4.0 * (long + ha) - eqtime),
+            set: at(720.0 - 4.0 * (long - ha) - eqtime),
+        }
+    }
+
+    /// The sun is up at `time`.
+    pub fn is_up(&self, time: OffsetDateTime) -> bool {
+        match self {
+            Sun::RiseSet { rise, set } => *rise <= time && time < *set,
+            Sun::AlwaysUp => true,
+            Sun::AlwaysDown => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime, offset};
+
+    fn assert_near(a: OffsetDateTime, b: OffsetDateTime) {
+        assert!((a - b).abs() < Duration::minutes(3), "{a} != {b}");
+    }
+
+    #[test]
+    fn brisbane_winter_solstice() {
+        let Sun::RiseSet { rise, set } =
+            Sun::on(date!(2024 - 06 - 21), [-27.4679, 153.0325], offset!(+10))
+        else {
+            panic!("expecting sunrise and sunset");
+        };
+        assert_near(rise, datetime!(2024-06-21 06:38 +10));
+        assert_near(set, datetime!(2024-06-21 17:02 +10));
+    }
+
+    #[test]
+    fn london_summer_solstice() {
+        let Sun::RiseSet { rise, set } =
+            Sun::on(date!(2024 - 06 - 21), [51.5072, -0.1276], offset!(+1))
+        else {
+            panic!("expecting sunrise and sunset");
+        };
+        assert_near(rise, datetime!(2024-06-21 04:43 +1));
+        assert_near(set, datetime!(2024-06-21 21:21 +1));
+    }
+
+    #[test]
+    fn brisbane_civil_twilight() {
+        let date = date!(2024 - 06 - 21);
+        let daylight = Daylight::on(date, [-27.4679, 153.0325], offset!(+10));
+        let Sun::RiseSet { rise, set } = daylight.twilight else {
+            panic!("expecting dawn and dusk");
+        };
+        assert_near(rise, datetime!(2024-06-21 06:12 +10));
+        assert_near(set, datetime!(2024-06-21 17:27 +10));
+        assert!(daylight.is_dark(datetime!(2024-06-21 06:00 +10)));
+        // after sunset, still light
+        assert!(!daylight.is_dark(datetime!(2024-06-21 17:10 +10)));
+        assert!(!daylight.sun.is_up(datetime!(2024-06-21 17:10 +10)));
+    }
+
+    #[test]
+    fn polar() {
+        let tromso = [69.6492, 18.9553];
+        assert_eq!(
+            Sun::on(date!(2024 - 12 - 21), tromso, offset!(+1)),
+            Sun::AlwaysDown
+        );
+        assert_eq!(
+            Sun::on(date!(2024 - 06 - 21), tromso, offset!(+2)),
+            Sun::AlwaysUp
+        );
+    }
+}
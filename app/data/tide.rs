@@ -0,0 +1,180 @@
+//! High and low tides from the [Storm Glass](https://stormglass.io) tide extremes API.
+use super::source::Registry;
+#[cfg(feature = "tides")]
+use super::source::{DataSource, FetchFuture, ModelPatch};
+#[cfg(feature = "tides")]
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+#[cfg(feature = "tides")]
+use std::time::Duration;
+use std::time::Instant;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+#[derive(Clone)]
+pub struct Tides {
+    pub last_update: Instant,
+    /// The high and low tides, in time order.
+    pub extremes: Vec<Extreme>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extreme {
+    pub time: OffsetDateTime,
+    /// Metres from mean sea level.
+    pub height: f32,
+    pub high: bool,
+}
+
+impl Tides {
+    pub fn from_storm_glass_io(payload: StormGlassTides, offset: UtcOffset) -> Result<Self> {
+        let mut extremes = payload
+            .data
+            .into_iter()
+            .map(|x| {
+                let time = OffsetDateTime::parse(&x.time, &Rfc3339)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("failed to parse time {}", x.time))?;
+                let high = match x.kind.as_str() {
+                    "high" => true,
+                    "low" => false,
+                    k => return Err(miette!("unknown tide type: {k}")),
+                };
+                Ok(Extreme {
+                    time: time.to_offset(offset),
+                    height: x.height,
+                    high,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        extremes.sort_by_key(|x| x.time);
+        Ok(Self {
+            last_update: Instant::now(),
+            extremes,
+        })
+    }
+
+    /// The tides after `now`, soonest first.
+    pub fn after(&self, now: OffsetDateTime) -> impl Iterator<Item = &Extreme> {
+        self.extremes.iter().filter(move |x| x.time > now)
+    }
+}
+
+/// Add the tides to `sources` if `apikey` to stormglass.io is set.
+#[cfg(feature = "tides")]
+pub fn register(sources: &mut Registry, coords: [f32; 2], apikey: String) {
+    if !apikey.is_empty() {
+        sources.register(StormGlass { coords, apikey });
+    }
+}
+
+/// Without the `tides` feature there are no tides to add.
+#[cfg(not(feature = "tides"))]
+pub fn register(_: &mut Registry, _: [f32; 2], _: String) {}
+
+/// The tides near a location from stormglass.io.
+#[cfg(feature = "tides")]
+pub struct StormGlass {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+    pub apikey: String,
+}
+
+#[cfg(feature = "tides")]
+impl DataSource for StormGlass {
+    fn name(&self) -> &str {
+        "tides"
+    }
+
+    /// Only every half a day, the free tier allows 10 requests a day and tides are
+    /// predicted well ahead.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 60 * 12)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let [lat, long] = self.coords;
+            let url = url::Url::parse_with_params(
+                "https://api.stormglass.io/v2/tide/extremes/point",
+                &[
+                    ("lat", lat.to_string()),
+                    ("lng", long.to_string()),
+                    ("start", now.date().to_string()),
+                    ("end", (now.date() + time::Duration::days(3)).to_string()),
+                ],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let resp = crate::fetch::json(
+                client,
+                url.as_str(),
+                [("Authorization", self.apikey.clone())],
+                self.fresh_for(),
+            )
+            .await?;
+            let tides = Tides {
+                last_update: resp.at,
+                ..Tides::from_storm_glass_io(resp.body, now.offset())?
+            };
+            Ok(ModelPatch::new(|model| model.tides = Some(tides)).stale(resp.stale))
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StormGlassTides {
+    data: Vec<StormGlassExtreme>,
+}
+
+#[derive(Deserialize)]
+struct StormGlassExtreme {
+    height: f32,
+    time: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, offset};
+
+    #[test]
+    fn storm_glass_extremes() {
+        let payload = r#"{"data":[
+            {"height":-0.62,"time":"2024-06-21T08:41:00+00:00","type":"low"},
+            {"height":0.71,"time":"2024-06-21T02:12:00+00:00","type":"high"},
+            {"height":0.55,"time":"2024-06-21T14:58:00+00:00","type":"high"}
+        ],"meta":{"cost":1}}"#;
+        let tides =
+            Tides::from_storm_glass_io(serde_json::from_str(payload).unwrap(), offset!(+10))
+                .unwrap();
+        let next = tides
+            .after(datetime!(2024-06-21 13:00 +10))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            next,
+            [
+                Extreme {
+                    time: datetime!(2024-06-21 18:41 +10),
+                    height: -0.62,
+                    high: false,
+                },
+                Extreme {
+                    time: datetime!(2024-06-22 00:58 +10),
+                    height: 0.55,
+                    high: true,
+                },
+            ]
+        );
+
+        let unknown =
+            r#"{"data":[{"height":0.1,"time":"2024-06-21T08:41:00+00:00","type":"slack"}]}"#;
+        assert!(
+            Tides::from_storm_glass_io(serde_json::from_str(unknown).unwrap(), offset!(+10))
+                .is_err()
+        );
+    }
+}
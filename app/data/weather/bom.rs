@@ -0,0 +1,260 @@
+//! The Australian Bureau of Meteorology's forecast and warnings, for locations in Australia.
+use super::{Code, Ob, Weather};
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{OffsetDateTime, UtcOffset};
+
+/// The BOM forecast and warnings for a location in Australia, from the API behind its app.
+pub struct Bom {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for Bom {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    /// Only every 10 minutes, to avoid making excessive API calls.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 10)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            // locations are 6 character geohashes, about 1 km across
+            let location = geohash(self.coords, 6);
+            let url = |x| format!("https://api.weather.bom.gov.au/v1/locations/{location}/{x}");
+            let every = self.fresh_for();
+            let hourly = crate::fetch::json(client, &url("forecasts/hourly"), [], every).await?;
+            let daily = crate::fetch::json(client, &url("forecasts/daily"), [], every).await?;
+            let warnings = crate::fetch::json(client, &url("warnings"), [], every).await?;
+            let weather = Weather {
+                last_update: hourly.at.min(daily.at).min(warnings.at),
+                ..Weather::from_bom(hourly.body, daily.body, warnings.body, now.offset())?
+            };
+            let stale = hourly.stale.or(daily.stale).or(warnings.stale);
+            Ok(ModelPatch::new(|model| model.weather = Some(weather)).stale(stale))
+        })
+    }
+}
+
+/// The geohash of `[latitude, longitude]` with `len` characters.
+fn geohash([lat, long]: [f32; 2], len: usize) -> String {
+    const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+    let (mut lat_range, mut long_range) = ([-90.0, 90.0], [-180.0, 180.0]);
+    let mut bits = (0..len * 5).map(|i| {
+        // bits alternate between longitude and latitude, starting with longitude
+        let (range, x) = match i % 2 {
+            0 => (&mut long_range, f64::from(long)),
+            _ => (&mut lat_range, f64::from(lat)),
+        };
+        let mid = (range[0] + range[1]) / 2.0;
+        let bit = x >= mid;
+        range[usize::from(!bit)] = mid;
+        u8::from(bit)
+    });
+    (0..len)
+        .map(|_| {
+            let i = bits.by_ref().take(5).fold(0, |acc, b| (acc << 1) | b);
+            char::from(BASE32[usize::from(i)])
+        })
+        .collect()
+}
+
+impl Weather {
+    /// The current conditions from the first `hourly` forecast, each day's from `daily`, and the
+    /// warnings which haven't been cancelled.
+    pub fn from_bom(
+        hourly: BomHourly,
+        daily: BomDaily,
+        warnings: BomWarnings,
+        offset: UtcOffset,
+    ) -> Result<Self> {
+        let now = hourly
+            .data
+            .first()
+            .ok_or_else(|| miette!("BOM hourly forecast is empty"))?;
+        let current = Ob {
+            code: Code::from_bom(&now.icon_descriptor),
+            temperature: now.temp,
+            humidity: now.relative_humidity,
+            precipitation_prob: None,
+        };
+
+        let mut forecast = HashMap::default();
+        for day in daily.data {
+            // the start of the day in UTC
+            let date =
+                OffsetDateTime::parse(&day.date, &time::format_description::well_known::Rfc3339)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("date value: {}", day.date))?
+                    .to_offset(offset)
+                    .date();
+            // days far ahead are only a range of temperatures
+            let Some(icon) = &day.icon_descriptor else {
+                continue;
+            };
+            let ob = Ob {
+                code: Code::from_bom(icon),
+                temperature: day.temp_max,
+                humidity: None,
+                precipitation_prob: day.rain.and_then(|x| x.chance),
+            };
+            forecast.insert(date, ob);
+        }
+
+        let warnings = warnings
+            .data
+            .into_iter()
+            .filter(|x| x.phase.as_deref() != Some("cancelled"))
+            .map(|x| x.short_title.unwrap_or(x.title))
+            .collect();
+
+        Ok(Self {
+            last_update: Instant::now(),
+            current,
+            forecast,
+            nowcast: Vec::new(),
+            warnings,
+        })
+    }
+}
+
+impl Code {
+    /// An icon descriptor such as `mostly_sunny`.
+    fn from_bom(icon: &str) -> Self {
+        use Code::*;
+        match icon {
+            "sunny" | "clear" | "frost" => ClearSky,
+            "mostly_sunny" => MainlyClear,
+            "partly_cloudy" => PartlyCloudy,
+            "cloudy" | "wind" | "windy" => Overcast,
+            "fog" | "haze" | "hazy" | "dust" | "dusty" => Fog,
+            "light_rain" | "light_shower" | "light_showers" => Drizzle,
+            "rain" | "shower" | "showers" | "heavy_shower" | "heavy_showers" => Rain,
+            "snow" => Snow,
+            "storm" | "storms" | "cyclone" | "tropicalcyclone" | "tropical_cyclone" => Thuderstorm,
+            x => Code::unknown("BOM", x),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BomHourly {
+    data: Vec<BomHour>,
+}
+
+#[derive(Deserialize)]
+struct BomHour {
+    temp: Option<f32>,
+    relative_humidity: Option<f32>,
+    icon_descriptor: String,
+}
+
+#[derive(Deserialize)]
+pub struct BomDaily {
+    data: Vec<BomDay>,
+}
+
+#[derive(Deserialize)]
+struct BomDay {
+    /// RFC 3339, the start of the day in UTC.
+    date: String,
+    temp_max: Option<f32>,
+    icon_descriptor: Option<String>,
+    rain: Option<BomRain>,
+}
+
+#[derive(Deserialize)]
+struct BomRain {
+    chance: Option<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct BomWarnings {
+    data: Vec<BomWarning>,
+}
+
+#[derive(Deserialize)]
+struct BomWarning {
+    title: String,
+    short_title: Option<String>,
+    phase: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn geohashes() {
+        // Brisbane, and the example from Wikipedia
+        assert_eq!(geohash([-27.4679, 153.0325], 6), "r7hgdr");
+        assert_eq!(geohash([42.6, -5.6], 5), "ezs42");
+    }
+
+    #[test]
+    fn bom_days_and_warnings() {
+        let hourly = r#"{"data":[
+            {"time":"2024-06-21T05:00:00Z","temp":21,"relative_humidity":64,
+                "icon_descriptor":"mostly_sunny"}
+        ]}"#;
+        let daily = r#"{"data":[
+            {"date":"2024-06-20T14:00:00Z","temp_max":23,"icon_descriptor":"storm",
+                "rain":{"chance":70}},
+            {"date":"2024-06-21T14:00:00Z","temp_max":null,"icon_descriptor":"light_showers",
+                "rain":{"chance":null}},
+            {"date":"2024-06-22T14:00:00Z","temp_max":20,"icon_descriptor":null}
+        ]}"#;
+        let warnings = r#"{"data":[
+            {"title":"Severe Thunderstorm Warning for South East Queensland",
+                "short_title":"Severe Thunderstorm Warning","phase":"new"},
+            {"title":"Flood Watch for Coastal Catchments","phase":"cancelled"},
+            {"title":"Marine Wind Warning","phase":"renewal"}
+        ]}"#;
+        let w = Weather::from_bom(
+            serde_json::from_str(hourly).unwrap(),
+            serde_json::from_str(daily).unwrap(),
+            serde_json::from_str(warnings).unwrap(),
+            UtcOffset::from_hms(10, 0, 0).unwrap(),
+        )
+        .unwrap();
+
+        assert!(matches!(w.current.code, Code::MainlyClear));
+        assert_eq!(w.current.temperature, Some(21.0));
+        let today = &w.forecast[&date!(2024 - 06 - 21)];
+        assert!(matches!(today.code, Code::Thuderstorm));
+        assert_eq!(today.temperature, Some(23.0));
+        assert_eq!(today.precipitation_prob, Some(70.0));
+        assert!(matches!(
+            w.forecast[&date!(2024 - 06 - 22)].code,
+            Code::Drizzle
+        ));
+        assert!(!w.forecast.contains_key(&date!(2024 - 06 - 23)));
+        assert_eq!(
+            w.warnings,
+            ["Severe Thunderstorm Warning", "Marine Wind Warning"]
+        );
+    }
+
+    #[test]
+    fn bom_descriptors() {
+        let code = |x| format!("{:?}", Code::from_bom(x));
+        assert_eq!(code("shower"), "Rain");
+        assert_eq!(code("heavy_shower"), "Rain");
+        assert_eq!(code("light_shower"), "Drizzle");
+        assert_eq!(code("windy"), "Overcast");
+        assert_eq!(code("storms"), "Thuderstorm");
+        assert_eq!(code("tropical_cyclone"), "Thuderstorm");
+        // a cloud rather than failing the forecast
+        assert_eq!(code("volcanic_ash"), "Overcast");
+    }
+}
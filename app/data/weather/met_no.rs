@@ -0,0 +1,268 @@
+//! The MET Norway (yr.no) locationforecast, free and keyless, and best in Scandinavia.
+use super::{user_agent, Code, Ob, Weather};
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{Date, OffsetDateTime, UtcOffset};
+
+/// The MET Norway (yr.no) locationforecast for a location, free and keyless.
+pub struct MetNo {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for MetNo {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    /// Only every 10 minutes, the forecast updates hourly at most.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 10)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            // the terms ask for no more than 4 decimals, so requests can be cached
+            let [lat, long] = self.coords.map(|x| format!("{x:.4}"));
+            let url = url::Url::parse_with_params(
+                "https://api.met.no/weatherapi/locationforecast/2.0/complete",
+                &[("lat", lat), ("lon", long)],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let hdrs = [user_agent()];
+            let resp = crate::fetch::json(client, url.as_str(), hdrs, self.fresh_for()).await?;
+            let weather = Weather {
+                last_update: resp.at,
+                ..Weather::from_met_no(resp.body, now.offset())?
+            };
+            Ok(ModelPatch::new(|model| model.weather = Some(weather)).stale(resp.stale))
+        })
+    }
+}
+
+impl Weather {
+    /// The forecast in the days of `offset`, with no nowcast as the locationforecast is hourly.
+    pub fn from_met_no(payload: MetNoPayload, offset: UtcOffset) -> Result<Self> {
+        let series = payload.properties.timeseries;
+        let first = series
+            .first()
+            .ok_or_else(|| miette!("MET Norway forecast has no times"))?;
+        let MetNoInstant {
+            air_temperature,
+            relative_humidity,
+        } = first.data.instant.details;
+        let current = Ob {
+            code: first
+                .data
+                .symbol()
+                .ok_or_else(|| miette!("no weather symbol for {}", first.time))
+                .map(Code::from_met_no)?,
+            temperature: air_temperature,
+            humidity: relative_humidity,
+            precipitation_prob: None,
+        };
+
+        let mut forecast = HashMap::<Date, Ob>::default();
+        // the symbol nearest midday stands for the day
+        let mut midday = HashMap::<Date, i64>::default();
+        for step in &series {
+            let t =
+                OffsetDateTime::parse(&step.time, &time::format_description::well_known::Rfc3339)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("time value: {}", step.time))?
+                    .to_offset(offset);
+            let date = t.date();
+            let ob = forecast.entry(date).or_insert(Ob {
+                code: current.code,
+                temperature: None,
+                humidity: None,
+                precipitation_prob: None,
+            });
+            let max = |a: Option<f32>, b: Option<f32>| match (a, b) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            };
+            ob.temperature = max(ob.temperature, step.data.instant.details.air_temperature);
+            ob.precipitation_prob = max(ob.precipitation_prob, step.data.precipitation_prob());
+            let from_midday = (i64::from(t.hour()) * 60 + i64::from(t.minute()) - 12 * 60).abs();
+            if let Some(symbol) = step.data.symbol() {
+                if !midday.get(&date).is_some_and(|&x| x <= from_midday) {
+                    midday.insert(date, from_midday);
+                    ob.code = Code::from_met_no(symbol);
+                }
+            }
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            current,
+            forecast,
+            nowcast: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl Code {
+    /// A symbol code such as `lightrainshowers_day`.
+    fn from_met_no(symbol: &str) -> Self {
+        use Code::*;
+        let code = symbol.split('_').next().unwrap_or(symbol);
+        match code {
+            "clearsky" => ClearSky,
+            "fair" => MainlyClear,
+            "partlycloudy" => PartlyCloudy,
+            "cloudy" => Overcast,
+            "fog" => Fog,
+            x if x.ends_with("andthunder") => Thuderstorm,
+            x if x.contains("snow") || x.contains("sleet") => Snow,
+            "lightrain" | "lightrainshowers" => Drizzle,
+            x if x.contains("rain") => Rain,
+            _ => Code::unknown("MET Norway", symbol),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MetNoPayload {
+    properties: MetNoProperties,
+}
+
+#[derive(Deserialize)]
+struct MetNoProperties {
+    timeseries: Vec<MetNoStep>,
+}
+
+#[derive(Deserialize)]
+struct MetNoStep {
+    /// RFC 3339, in UTC.
+    time: String,
+    data: MetNoData,
+}
+
+#[derive(Deserialize)]
+struct MetNoData {
+    instant: MetNoDetails<MetNoInstant>,
+    /// Hourly steps have the next hour, later ones only the next 6 and 12.
+    next_1_hours: Option<MetNoPeriod>,
+    next_6_hours: Option<MetNoPeriod>,
+    next_12_hours: Option<MetNoPeriod>,
+}
+
+impl MetNoData {
+    fn periods(&self) -> impl Iterator<Item = &MetNoPeriod> {
+        [&self.next_1_hours, &self.next_6_hours, &self.next_12_hours]
+            .into_iter()
+            .flatten()
+    }
+
+    /// The symbol of the shortest period ahead.
+    fn symbol(&self) -> Option<&str> {
+        self.periods()
+            .find_map(|x| x.summary.as_ref())
+            .map(|x| x.symbol_code.as_str())
+    }
+
+    fn precipitation_prob(&self) -> Option<f32> {
+        self.periods()
+            .find_map(|x| x.details.probability_of_precipitation)
+    }
+}
+
+#[derive(Deserialize)]
+struct MetNoDetails<T> {
+    details: T,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct MetNoInstant {
+    air_temperature: Option<f32>,
+    relative_humidity: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct MetNoPeriod {
+    summary: Option<MetNoSummary>,
+    #[serde(default)]
+    details: MetNoPeriodDetails,
+}
+
+#[derive(Deserialize)]
+struct MetNoSummary {
+    symbol_code: String,
+}
+
+#[derive(Deserialize, Default)]
+struct MetNoPeriodDetails {
+    probability_of_precipitation: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn met_no_days() {
+        let step = |time: &str, temp: f32, next: &str| {
+            format!(
+                r#"{{"time":"{time}","data":{{
+                    "instant":{{"details":{{"air_temperature":{temp},"relative_humidity":80.0}}}},
+                    {next}
+                }}}}"#
+            )
+        };
+        let hour = |symbol: &str, prob: f32| {
+            format!(
+                r#""next_1_hours":{{"summary":{{"symbol_code":"{symbol}"}},
+                    "details":{{"probability_of_precipitation":{prob}}}}}"#
+            )
+        };
+        let six = r#""next_6_hours":{"summary":{"symbol_code":"heavyrainandthunder"},
+            "details":{"probability_of_precipitation":90.0}}"#;
+        let series = [
+            step("2024-06-21T00:00:00Z", 14.0, &hour("partlycloudy_day", 0.0)),
+            step("2024-06-21T02:00:00Z", 19.5, &hour("fair_day", 10.0)),
+            step(
+                "2024-06-21T08:00:00Z",
+                12.0,
+                &hour("lightrainshowers_night", 40.0),
+            ),
+            // the next day in +10
+            step("2024-06-22T00:00:00Z", 16.0, six),
+            step(
+                "2024-06-22T06:00:00Z",
+                13.0,
+                r#""next_12_hours":{"summary":{"symbol_code":"cloudy"}}"#,
+            ),
+        ];
+        let payload = format!(
+            r#"{{"type":"Feature","properties":{{"timeseries":[{}]}}}}"#,
+            series.join(",")
+        );
+        let offset = UtcOffset::from_hms(10, 0, 0).unwrap();
+        let w = Weather::from_met_no(serde_json::from_str(&payload).unwrap(), offset).unwrap();
+
+        assert!(matches!(w.current.code, Code::PartlyCloudy));
+        assert_eq!(w.current.temperature, Some(14.0));
+        assert_eq!(w.current.humidity, Some(80.0));
+        assert!(w.nowcast.is_empty());
+
+        let day = &w.forecast[&date!(2024 - 06 - 21)];
+        // 12:00 local is nearest midday
+        assert!(matches!(day.code, Code::MainlyClear));
+        assert_eq!(day.temperature, Some(19.5));
+        assert_eq!(day.precipitation_prob, Some(40.0));
+        let day = &w.forecast[&date!(2024 - 06 - 22)];
+        assert!(matches!(day.code, Code::Thuderstorm));
+        assert_eq!(day.temperature, Some(16.0));
+        assert_eq!(day.precipitation_prob, Some(90.0));
+    }
+}
@@ -0,0 +1,154 @@
+//! The weather forecast, from the provider set in the config.
+//!
+//! Each provider is a module behind the `weather` feature, [`register`] adds the chosen one to
+//! the fetch loop.
+use super::source::Registry;
+use std::{collections::HashMap, time::Instant};
+use time::{Date, OffsetDateTime};
+
+#[cfg(feature = "weather")]
+pub mod bom;
+#[cfg(feature = "weather")]
+pub mod met_no;
+#[cfg(feature = "weather")]
+pub mod nws;
+#[cfg(feature = "weather")]
+pub mod open_meteo;
+
+#[derive(Clone)]
+pub struct Weather {
+    pub last_update: Instant,
+    pub current: Ob,
+    pub forecast: HashMap<Date, Ob>,
+    /// Precipitation over the 15 minutes ending at each time, in mm, for the next few hours.
+    ///
+    /// Empty where the forecast has no 15 minute data.
+    pub nowcast: Vec<(OffsetDateTime, f32)>,
+    /// Warnings in force, such as for a severe thunderstorm, where the provider issues them.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct Ob {
+    pub code: Code,
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub precipitation_prob: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Code {
+    ClearSky,
+    MainlyClear,
+    PartlyCloudy,
+    Overcast,
+    Fog,
+    Drizzle,
+    Rain,
+    Snow,
+    Thuderstorm,
+}
+
+#[cfg(feature = "weather")]
+impl Code {
+    /// A plain cloud for a `provider`'s condition which isn't mapped, such as one it added,
+    /// rather than failing the whole forecast.
+    fn unknown(provider: &str, condition: &str) -> Self {
+        log::warn!("{provider} weather condition {condition:?} is not handled, showing a cloud");
+        Code::Overcast
+    }
+}
+
+/// Where the forecast comes from, see `weather_provider` in INSTALL.md.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    #[default]
+    OpenMeteo,
+    /// MET Norway's locationforecast, best in Scandinavia.
+    MetNo,
+    /// The US National Weather Service, only in the US.
+    Nws,
+    /// The Australian Bureau of Meteorology, with its warnings, only in Australia.
+    Bom,
+}
+
+impl Weather {
+    /// A line about rain starting or stopping soon, such as `Rain in ~20 min, stopping by 15:40`.
+    ///
+    /// Worked out from the 15 minute precipitation at `now`, so it counts down between fetches.
+    /// `None` if no rain is on the way.
+    pub fn nowcast(&self, now: OffsetDateTime) -> Option<String> {
+        let slot = time::Duration::minutes(15);
+        let wet = |mm: f32| mm >= NOWCAST_MIN_MM;
+        // each amount is the 15 minutes ending at its time
+        let mut slots = self
+            .nowcast
+            .iter()
+            .filter(|(t, _)| *t > now)
+            .map(|&(t, mm)| (t - slot, t, wet(mm)));
+        let hhmm = |t: OffsetDateTime| {
+            let t = t.to_offset(now.offset());
+            format!("{:02}:{:02}", t.hour(), t.minute())
+        };
+
+        let (_, mut until, raining) = slots.next()?;
+        if raining {
+            for (start, end, wet) in slots {
+                if !wet {
+                    return Some(format!("Rain stopping by {}", hhmm(start)));
+                }
+                until = end;
+            }
+            // raining past the end of the nowcast
+            return Some(format!("Rain until at least {}", hhmm(until)));
+        }
+
+        let start = slots.find(|(_, _, wet)| *wet)?.0;
+        let mins = ((start - now).whole_minutes() as f32 / 5.0).round() as i64 * 5;
+        let starting = format!("Rain in ~{} min", mins.max(5));
+        Some(match slots.find(|(_, _, wet)| !wet) {
+            Some((stop, _, _)) => format!("{starting}, stopping by {}", hhmm(stop)),
+            None => starting,
+        })
+    }
+}
+
+/// The precipitation in 15 minutes which counts as rain, in mm.
+const NOWCAST_MIN_MM: f32 = 0.1;
+
+/// The spread of the daily maximum temperature across the members of an ensemble forecast.
+#[derive(Clone)]
+pub struct Ensemble {
+    pub last_update: Instant,
+    /// The 10th to 90th percentile of the members' maximum temperature.
+    pub max_temperature: HashMap<Date, [f32; 2]>,
+}
+
+/// Add the forecast from `provider` to `sources`, with the spread of the ensemble if `ensemble`.
+#[cfg(feature = "weather")]
+pub fn register(sources: &mut Registry, provider: Provider, coords: [f32; 2], ensemble: bool) {
+    match provider {
+        Provider::OpenMeteo => sources.register(open_meteo::OpenMeteo { coords }),
+        Provider::MetNo => sources.register(met_no::MetNo { coords }),
+        Provider::Nws => sources.register(nws::Nws { coords }),
+        Provider::Bom => sources.register(bom::Bom { coords }),
+    };
+    if ensemble {
+        sources.register(open_meteo::OpenMeteoEnsemble { coords });
+    }
+}
+
+/// Without the `weather` feature there are no forecasts to add.
+#[cfg(not(feature = "weather"))]
+pub fn register(_: &mut Registry, _: Provider, _: [f32; 2], _: bool) {}
+
+/// Government weather services refuse requests without one identifying the app.
+#[cfg(feature = "weather")]
+fn user_agent() -> (&'static str, String) {
+    let agent = format!(
+        "pical/{} github.com/kurtlawrence/pical",
+        env!("CARGO_PKG_VERSION")
+    );
+    ("User-Agent", agent)
+}
@@ -0,0 +1,269 @@
+//! The US National Weather Service's forecast, from api.weather.gov, for locations in the US.
+use super::{user_agent, Code, Ob, Weather};
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{OffsetDateTime, UtcOffset};
+
+/// The api.weather.gov forecast for a location in the US.
+pub struct Nws {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for Nws {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    /// Only every 10 minutes, the forecasts update hourly at most.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 10)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            // the point's forecast grid rarely changes, so is kept for a day
+            let [lat, long] = self.coords.map(|x| format!("{x:.4}"));
+            let url = format!("https://api.weather.gov/points/{lat},{long}");
+            let day = Duration::from_secs(60 * 60 * 24);
+            let points = crate::fetch::json::<NwsPoints, _, _>(client, &url, [user_agent()], day)
+                .await
+                .wrap_err("failed to find the NWS forecast for the location, is it in the US?")?;
+            let NwsPointsProperties {
+                forecast,
+                forecast_hourly,
+            } = points.body.properties;
+
+            let every = self.fresh_for();
+            let hourly =
+                crate::fetch::json(client, &forecast_hourly, [user_agent()], every).await?;
+            let daily = crate::fetch::json(client, &forecast, [user_agent()], every).await?;
+            let weather = Weather {
+                last_update: hourly.at.min(daily.at),
+                ..Weather::from_nws(hourly.body, daily.body, now.offset())?
+            };
+            let stale = points.stale.or(hourly.stale).or(daily.stale);
+            Ok(ModelPatch::new(|model| model.weather = Some(weather)).stale(stale))
+        })
+    }
+}
+
+impl Weather {
+    /// The current conditions from the first `hourly` period, and each day's from its daytime
+    /// period in `daily`, or the night if the day has passed.
+    pub fn from_nws(hourly: NwsForecast, daily: NwsForecast, offset: UtcOffset) -> Result<Self> {
+        let now = hourly
+            .properties
+            .periods
+            .first()
+            .ok_or_else(|| miette!("NWS hourly forecast has no periods"))?;
+        let current = Ob {
+            code: Code::from_nws(&now.icon),
+            temperature: now.celsius(),
+            humidity: now.relative_humidity.and_then(|x| x.value),
+            precipitation_prob: None,
+        };
+
+        let mut forecast = HashMap::default();
+        for period in &daily.properties.periods {
+            let start = OffsetDateTime::parse(
+                &period.start_time,
+                &time::format_description::well_known::Rfc3339,
+            )
+            .into_diagnostic()
+            .wrap_err_with(|| format!("time value: {}", period.start_time))?;
+            let date = start.to_offset(offset).date();
+            if !period.is_daytime && forecast.contains_key(&date) {
+                continue;
+            }
+            let ob = Ob {
+                code: Code::from_nws(&period.icon),
+                // the night's is the low
+                temperature: period.celsius().filter(|_| period.is_daytime),
+                humidity: None,
+                precipitation_prob: period.probability_of_precipitation.and_then(|x| x.value),
+            };
+            forecast.insert(date, ob);
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            current,
+            forecast,
+            nowcast: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl Code {
+    /// The condition in an icon URL, such as `https://api.weather.gov/icons/land/day/tsra,40`.
+    ///
+    /// An icon changing over the period has two conditions, the first is used.
+    fn from_nws(icon: &str) -> Self {
+        use Code::*;
+        let path = icon.split('?').next().unwrap_or_default();
+        let mut segments = path.split('/');
+        let Some(code) = segments
+            .find(|x| *x == "day" || *x == "night")
+            .and_then(|_| segments.next())
+            .map(|x| x.split(',').next().unwrap_or(x))
+        else {
+            return Code::unknown("NWS", icon);
+        };
+        match code {
+            "skc" | "wind_skc" | "hot" | "cold" => ClearSky,
+            "few" | "wind_few" => MainlyClear,
+            "sct" | "wind_sct" => PartlyCloudy,
+            "bkn" | "ovc" | "wind_bkn" | "wind_ovc" => Overcast,
+            "fog" | "haze" | "smoke" | "dust" => Fog,
+            "rain" | "rain_showers" | "rain_showers_hi" | "fzra" | "rain_fzra" => Rain,
+            "snow" | "rain_snow" | "rain_sleet" | "snow_sleet" | "sleet" | "snow_fzra"
+            | "blizzard" => Snow,
+            "tsra" | "tsra_sct" | "tsra_hi" | "tornado" | "hurricane" | "tropical_storm" => {
+                Thuderstorm
+            }
+            x => Code::unknown("NWS", x),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NwsPoints {
+    properties: NwsPointsProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NwsPointsProperties {
+    forecast: String,
+    forecast_hourly: String,
+}
+
+#[derive(Deserialize)]
+pub struct NwsForecast {
+    properties: NwsForecastProperties,
+}
+
+#[derive(Deserialize)]
+struct NwsForecastProperties {
+    periods: Vec<NwsPeriod>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NwsPeriod {
+    start_time: String,
+    is_daytime: bool,
+    temperature: Option<f32>,
+    /// `F` or `C`.
+    temperature_unit: String,
+    probability_of_precipitation: Option<NwsValue>,
+    relative_humidity: Option<NwsValue>,
+    icon: String,
+}
+
+impl NwsPeriod {
+    fn celsius(&self) -> Option<f32> {
+        let t = self.temperature?;
+        Some(match self.temperature_unit.as_str() {
+            "F" => (t - 32.0) * 5.0 / 9.0,
+            _ => t,
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct NwsValue {
+    value: Option<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn nws_conditions() {
+        let code = |x: &str| format!("{:?}", Code::from_nws(x));
+        let icons = "https://api.weather.gov/icons/land";
+        assert_eq!(code(&format!("{icons}/day/few?size=medium")), "MainlyClear");
+        assert_eq!(
+            code(&format!("{icons}/night/tsra,40/rain,60")),
+            "Thuderstorm"
+        );
+        assert_eq!(code(&format!("{icons}/day/rain_snow,20")), "Snow");
+        assert_eq!(code(&format!("{icons}/day/wind_ovc")), "Overcast");
+        // a cloud rather than failing the forecast
+        assert_eq!(code(&format!("{icons}/day/unknown")), "Overcast");
+        assert_eq!(code("https://api.weather.gov/icons"), "Overcast");
+    }
+
+    #[test]
+    fn nws_days() {
+        let period = |start: &str, day: bool, temp: f32, icon: &str, prob: &str| {
+            format!(
+                r#"{{"startTime":"{start}","isDaytime":{day},"temperature":{temp},
+                    "temperatureUnit":"F","probabilityOfPrecipitation":{{"value":{prob}}},
+                    "relativeHumidity":{{"value":55}},
+                    "icon":"https://api.weather.gov/icons/land/{icon}?size=medium"}}"#
+            )
+        };
+        let forecast = |periods: &[String]| {
+            let json = format!(r#"{{"properties":{{"periods":[{}]}}}}"#, periods.join(","));
+            serde_json::from_str(&json).unwrap()
+        };
+        let hourly = forecast(&[period(
+            "2024-06-21T19:00:00-04:00",
+            false,
+            77.0,
+            "night/sct",
+            "null",
+        )]);
+        let daily = forecast(&[
+            period(
+                "2024-06-21T18:00:00-04:00",
+                false,
+                60.0,
+                "night/rain,30",
+                "30",
+            ),
+            period(
+                "2024-06-22T06:00:00-04:00",
+                true,
+                86.0,
+                "day/tsra_hi,50",
+                "50",
+            ),
+            period(
+                "2024-06-22T18:00:00-04:00",
+                false,
+                65.0,
+                "night/skc",
+                "null",
+            ),
+        ]);
+        let offset = UtcOffset::from_hms(-4, 0, 0).unwrap();
+        let w = Weather::from_nws(hourly, daily, offset).unwrap();
+
+        assert!(matches!(w.current.code, Code::PartlyCloudy));
+        assert_eq!(w.current.temperature, Some(25.0));
+        assert_eq!(w.current.humidity, Some(55.0));
+
+        // tonight, without its low as the maximum
+        let today = &w.forecast[&date!(2024 - 06 - 21)];
+        assert!(matches!(today.code, Code::Rain));
+        assert_eq!(today.temperature, None);
+        assert_eq!(today.precipitation_prob, Some(30.0));
+        let tomorrow = &w.forecast[&date!(2024 - 06 - 22)];
+        assert!(matches!(tomorrow.code, Code::Thuderstorm));
+        assert_eq!(tomorrow.temperature, Some(30.0));
+        assert_eq!(tomorrow.precipitation_prob, Some(50.0));
+    }
+}
@@ -0,0 +1,357 @@
+//! The Open-Meteo forecast, with its ensemble and 15 minute nowcast.
+use super::{Code, Ensemble, Ob, Weather};
+use crate::data::source::{DataSource, FetchFuture, ModelPatch};
+use crate::fetch::Client;
+use miette::*;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+/// The Open-Meteo forecast for a location.
+pub struct OpenMeteo {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for OpenMeteo {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    /// Only every 10 minutes to avoid making excessive API calls.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 10)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let [lat, long] = self.coords;
+            let url = url::Url::parse_with_params(
+                "https://api.open-meteo.com/v1/forecast?\
+                    current=temperature_2m,relative_humidity_2m,precipitation,weather_code&\
+                    daily=weather_code,temperature_2m_max,precipitation_probability_max&\
+                    minutely_15=precipitation&forecast_minutely_15=12&\
+                    forecast_days=16",
+                &[
+                    ("latitude", lat.to_string()),
+                    ("longitude", long.to_string()),
+                    ("timezone", format!("GMT{:+}", now.offset().whole_hours())),
+                ],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let resp = crate::fetch::json(client, url.as_str(), [], self.fresh_for()).await?;
+            let weather = Weather {
+                last_update: resp.at,
+                ..Weather::from_open_meteo(resp.body)?
+            };
+            Ok(ModelPatch::new(|model| model.weather = Some(weather)).stale(resp.stale))
+        })
+    }
+}
+
+impl Weather {
+    pub fn from_open_meteo(payload: OpenMeteoPayload) -> Result<Self> {
+        let OpenMeteoPayload {
+            utc_offset_seconds,
+            current,
+            daily,
+            minutely_15,
+        } = payload;
+
+        let OpenMeteoCurrent {
+            temperature_2m,
+            relative_humidity_2m,
+            weather_code,
+        } = current;
+        let current = Ob {
+            code: Code::from_open_meteo(weather_code)?,
+            temperature: temperature_2m.into(),
+            humidity: Some(relative_humidity_2m),
+            precipitation_prob: None,
+        };
+
+        let OpenMeteoDaily {
+            time,
+            weather_code,
+            temperature_2m_max,
+            precipitation_probability_max,
+        } = daily;
+        let mut forecast = HashMap::default();
+        for (((date, code), temperature), precipitation_prob) in time
+            .into_iter()
+            .zip(weather_code)
+            .zip(temperature_2m_max)
+            .zip(precipitation_probability_max)
+        {
+            let date = Date::parse(&date, &time::format_description::well_known::Iso8601::DATE)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("date value: {date}"))?;
+            let code = code
+                .ok_or_else(|| miette!("no weather code for {date}"))
+                .and_then(Code::from_open_meteo)?;
+            let ob = Ob {
+                code,
+                temperature,
+                precipitation_prob,
+                humidity: None,
+            };
+
+            forecast.insert(date, ob);
+        }
+
+        let offset = UtcOffset::from_whole_seconds(utc_offset_seconds).into_diagnostic()?;
+        let mut nowcast = Vec::new();
+        for (t, mm) in minutely_15
+            .into_iter()
+            .flat_map(|x| x.time.into_iter().zip(x.precipitation))
+        {
+            let Some(mm) = mm else {
+                continue;
+            };
+            let t = PrimitiveDateTime::parse(
+                &t,
+                &time::format_description::well_known::Iso8601::DEFAULT,
+            )
+            .into_diagnostic()
+            .wrap_err_with(|| format!("time value: {t}"))?;
+            nowcast.push((t.assume_offset(offset), mm));
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            current,
+            forecast,
+            nowcast,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl Ensemble {
+    pub fn from_open_meteo(payload: OpenMeteoEnsemblePayload) -> Result<Self> {
+        let OpenMeteoEnsembleDaily { time, members } = payload.daily;
+        let members = members
+            .into_iter()
+            .filter(|(k, _)| k.starts_with("temperature_2m_max"))
+            .map(|(k, v)| {
+                serde_json::from_value::<Vec<Option<f32>>>(v)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("ensemble member {k}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut max_temperature = HashMap::default();
+        for (i, date) in time.iter().enumerate() {
+            let date = Date::parse(date, &time::format_description::well_known::Iso8601::DATE)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("date value: {date}"))?;
+            let mut ts = members
+                .iter()
+                .filter_map(|x| x.get(i).copied().flatten())
+                .collect::<Vec<_>>();
+            if let Some(range) = spread(&mut ts) {
+                max_temperature.insert(date, range);
+            }
+        }
+
+        Ok(Self {
+            last_update: Instant::now(),
+            max_temperature,
+        })
+    }
+}
+
+/// The 10th and 90th percentiles, so a single outlying member does not dominate.
+fn spread(xs: &mut [f32]) -> Option<[f32; 2]> {
+    if xs.is_empty() {
+        return None;
+    }
+    xs.sort_by(f32::total_cmp);
+    let at = |p: f32| xs[((xs.len() - 1) as f32 * p).round() as usize];
+    Some([at(0.1), at(0.9)])
+}
+
+/// The Open-Meteo ensemble forecast for a location.
+pub struct OpenMeteoEnsemble {
+    /// `[latitude, longitude]`
+    pub coords: [f32; 2],
+}
+
+impl DataSource for OpenMeteoEnsemble {
+    fn name(&self) -> &str {
+        "weather ensemble"
+    }
+
+    /// Ensembles only update a few times a day.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    fn fetch<'a>(&'a mut self, client: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let [lat, long] = self.coords;
+            let url = url::Url::parse_with_params(
+                "https://ensemble-api.open-meteo.com/v1/ensemble?\
+                    daily=temperature_2m_max&models=icon_seamless&forecast_days=14",
+                &[
+                    ("latitude", lat.to_string()),
+                    ("longitude", long.to_string()),
+                    ("timezone", format!("GMT{:+}", now.offset().whole_hours())),
+                ],
+            )
+            .into_diagnostic()
+            .wrap_err("URL parse failed")?;
+            let resp = crate::fetch::json(client, url.as_str(), [], self.fresh_for()).await?;
+            let ensemble = Ensemble {
+                last_update: resp.at,
+                ..Ensemble::from_open_meteo(resp.body)?
+            };
+            Ok(ModelPatch::new(|model| model.ensemble = Some(ensemble)).stale(resp.stale))
+        })
+    }
+}
+
+impl Code {
+    fn from_open_meteo(code: u32) -> Result<Self> {
+        use Code::*;
+        match code {
+            0 => Ok(ClearSky),
+            1 => Ok(MainlyClear),
+            2 => Ok(PartlyCloudy),
+            3 => Ok(Overcast),
+            45 | 48 => Ok(Fog),
+            51 | 53 | 55 | 56 | 57 => Ok(Drizzle),
+            61 | 63 | 65 | 66 | 67 | 80 | 81 | 82 => Ok(Rain),
+            71 | 73 | 75 | 77 | 85 | 86 => Ok(Snow),
+            95 | 96 | 99 => Ok(Thuderstorm),
+            x => Err(miette!("weather code {} is not handled", x)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OpenMeteoPayload {
+    #[serde(default)]
+    utc_offset_seconds: i32,
+    current: OpenMeteoCurrent,
+    daily: OpenMeteoDaily,
+    /// Only available in some regions.
+    #[serde(default)]
+    minutely_15: Option<OpenMeteoMinutely>,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    temperature_2m: f32,
+    relative_humidity_2m: f32,
+    weather_code: u32,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoDaily {
+    time: Vec<String>,
+    weather_code: Vec<Option<u32>>,
+    temperature_2m_max: Vec<Option<f32>>,
+    precipitation_probability_max: Vec<Option<f32>>,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoMinutely {
+    time: Vec<String>,
+    precipitation: Vec<Option<f32>>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenMeteoEnsemblePayload {
+    daily: OpenMeteoEnsembleDaily,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoEnsembleDaily {
+    time: Vec<String>,
+    /// `temperature_2m_max` and `temperature_2m_max_memberNN`.
+    #[serde(flatten)]
+    members: HashMap<String, serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    #[test]
+    fn nowcast_rain_starting_and_stopping() {
+        let payload = r#"{
+            "utc_offset_seconds":36000,
+            "current":{"temperature_2m":21.0,"relative_humidity_2m":60.0,"weather_code":61},
+            "daily":{"time":[],"weather_code":[],"temperature_2m_max":[],
+                "precipitation_probability_max":[]},
+            "minutely_15":{
+                "time":["2024-06-21T15:00","2024-06-21T15:15","2024-06-21T15:30",
+                    "2024-06-21T15:45","2024-06-21T16:00","2024-06-21T16:15"],
+                "precipitation":[0.0,0.0,0.4,1.2,0.0,null]
+            }
+        }"#;
+        let w = Weather::from_open_meteo(serde_json::from_str(payload).unwrap()).unwrap();
+        assert_eq!(w.nowcast.len(), 5);
+
+        let at = |now| w.nowcast(now);
+        // rain falls in the 15 minutes before 15:30 and 15:45
+        assert_eq!(
+            at(datetime!(2024-06-21 14:55 +10)).as_deref(),
+            Some("Rain in ~20 min, stopping by 15:45")
+        );
+        assert_eq!(
+            at(datetime!(2024-06-21 15:08 +10)).as_deref(),
+            Some("Rain in ~5 min, stopping by 15:45")
+        );
+        assert_eq!(
+            at(datetime!(2024-06-21 15:20 +10)).as_deref(),
+            Some("Rain stopping by 15:45")
+        );
+        assert_eq!(at(datetime!(2024-06-21 15:50 +10)), None);
+
+        // raining through to the end of the data
+        let wet = Weather {
+            nowcast: w.nowcast[1..4].to_vec(),
+            ..w.clone()
+        };
+        assert_eq!(
+            wet.nowcast(datetime!(2024-06-21 15:20 +10)).as_deref(),
+            Some("Rain until at least 15:45")
+        );
+
+        // no 15 minute data
+        let dry = Weather {
+            nowcast: Vec::new(),
+            ..w
+        };
+        assert_eq!(dry.nowcast(datetime!(2024-06-21 14:55 +10)), None);
+    }
+
+    #[test]
+    fn ensemble_spread() {
+        let payload = r#"{"daily":{
+            "time":["2024-06-21","2024-06-22"],
+            "temperature_2m_max":[22.0,null],
+            "temperature_2m_max_member01":[21.0,null],
+            "temperature_2m_max_member02":[27.0,null],
+            "temperature_2m_max_member03":[24.0,null]
+        }}"#;
+        let e = Ensemble::from_open_meteo(serde_json::from_str(payload).unwrap()).unwrap();
+        assert_eq!(e.max_temperature[&date!(2024 - 06 - 21)], [21.0, 27.0]);
+        assert!(!e.max_temperature.contains_key(&date!(2024 - 06 - 22)));
+    }
+
+    #[test]
+    fn spread_ignores_outliers() {
+        let mut xs = (0..=10).map(|x| x as f32).collect::<Vec<_>>();
+        xs.push(40.0);
+        assert_eq!(spread(&mut xs), Some([1.0, 10.0]));
+        assert_eq!(spread(&mut []), None);
+    }
+}
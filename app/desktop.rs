@@ -0,0 +1,211 @@
+//! Showing frames in a window, for desktop e-ink monitors (such as Dasung or Boox) over HDMI.
+//!
+//! E-ink monitors refresh whenever the picture changes, so the window is only redrawn when a
+//! frame arrives, no more often than `min_interval`. A full refresh can flash black then white
+//! first, clearing the ghosting as the panel's GC16 waveform does.
+//!
+//! The window needs the `desktop` feature.
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[cfg(feature = "desktop")]
+pub use minifb::Key;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DesktopConfig {
+    /// Borderless and scaled to fit the monitor, otherwise a window the size of the frame.
+    #[serde(default = "default_true")]
+    pub fullscreen: bool,
+    /// Flash black then white before a full refresh.
+    #[serde(default = "default_true")]
+    pub flash: bool,
+    /// The least time between redraws, frames arriving sooner are held back.
+    #[serde(default = "default_min_interval", with = "humantime_serde")]
+    pub min_interval: Duration,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_min_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// A frame for the window.
+#[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+struct Frame {
+    img: image::GrayImage,
+    full: bool,
+}
+
+/// A window showing frames, drawn on its own thread.
+pub struct Monitor {
+    tx: std::sync::mpsc::Sender<Frame>,
+    /// Keys pressed in the window.
+    #[cfg(feature = "desktop")]
+    keys: std::sync::Mutex<std::sync::mpsc::Receiver<Key>>,
+}
+
+impl Monitor {
+    /// Open a window for `[width, height]` frames.
+    #[cfg(feature = "desktop")]
+    pub fn open(cfg: DesktopConfig, [width, height]: [u32; 2]) -> Result<Self> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (keys_tx, keys) = std::sync::mpsc::channel();
+        let (opened_tx, opened) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("desktop".to_string())
+            .spawn(move || {
+                let win = window::open(&cfg, [width, height]);
+                match win {
+                    Ok(win) => {
+                        let _ = opened_tx.send(Ok(()));
+                        window::run(win, &cfg, [width, height], rx, keys_tx);
+                    }
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(e));
+                    }
+                }
+            })
+            .into_diagnostic()?;
+        opened
+            .recv()
+            .into_diagnostic()
+            .wrap_err("desktop window thread exited")??;
+        Ok(Self {
+            tx,
+            keys: keys.into(),
+        })
+    }
+
+    #[cfg(not(feature = "desktop"))]
+    pub fn open(_: DesktopConfig, _: [u32; 2]) -> Result<Self> {
+        Err(miette!(
+            help = "build with `--features desktop`",
+            "pical was built without desktop window support"
+        ))
+    }
+
+    /// Show the frame, a `full` refresh flashing first if configured.
+    pub fn show(&self, img: image::GrayImage, full: bool) -> Result<()> {
+        self.tx
+            .send(Frame { img, full })
+            .map_err(|_| miette!("desktop window was closed"))
+    }
+
+    /// The keys pressed since last called.
+    #[cfg(feature = "desktop")]
+    pub fn keys(&self) -> Result<Vec<Key>> {
+        use std::sync::mpsc::TryRecvError;
+
+        let rx = self.keys.lock().expect("keys lock poisoned");
+        let mut keys = Vec::new();
+        loop {
+            match rx.try_recv() {
+                Ok(x) => keys.push(x),
+                Err(TryRecvError::Empty) => return Ok(keys),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(miette!("desktop window was closed"))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "desktop")]
+mod window {
+    use super::*;
+    use minifb::{KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+    use std::{
+        sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+        time::Instant,
+    };
+
+    /// How often window events are handled between frames.
+    const POLL: Duration = Duration::from_millis(100);
+    /// How long each flash colour is shown.
+    const FLASH: Duration = Duration::from_millis(300);
+
+    pub fn open(cfg: &DesktopConfig, [width, height]: [u32; 2]) -> Result<Window> {
+        let opts = WindowOptions {
+            borderless: cfg.fullscreen,
+            title: !cfg.fullscreen,
+            topmost: cfg.fullscreen,
+            scale: if cfg.fullscreen {
+                Scale::FitScreen
+            } else {
+                Scale::X1
+            },
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        };
+        Window::new("pical", width as usize, height as usize, opts)
+            .into_diagnostic()
+            .wrap_err("failed to open desktop window")
+    }
+
+    pub fn run(
+        mut win: Window,
+        cfg: &DesktopConfig,
+        size: [u32; 2],
+        rx: Receiver<Frame>,
+        keys: Sender<Key>,
+    ) {
+        let mut pending: Option<Frame> = None;
+        let mut drawn = None::<Instant>;
+        while win.is_open() {
+            for key in win.get_keys_pressed(KeyRepeat::Yes) {
+                let _ = keys.send(key);
+            }
+            match rx.recv_timeout(POLL) {
+                Ok(frame) => {
+                    // a held back full refresh stays full
+                    let full = frame.full || pending.as_ref().is_some_and(|x| x.full);
+                    pending = Some(Frame { full, ..frame });
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            let too_soon = drawn.is_some_and(|at| at.elapsed() < cfg.min_interval);
+            let frame = match pending.take() {
+                Some(frame) if !too_soon => frame,
+                held => {
+                    pending = held;
+                    // handle events without redrawing, which would refresh the monitor
+                    win.update();
+                    continue;
+                }
+            };
+            if let Err(e) = draw(&mut win, cfg, size, frame) {
+                log::warn!("failed to draw desktop window: {e:?}");
+            }
+            drawn = Some(Instant::now());
+        }
+        log::warn!("desktop window closed");
+    }
+
+    fn draw(win: &mut Window, cfg: &DesktopConfig, [w, h]: [u32; 2], frame: Frame) -> Result<()> {
+        let (w, h) = (w as usize, h as usize);
+        let mut update = |buf: &[u32]| win.update_with_buffer(buf, w, h).into_diagnostic();
+        if frame.full && cfg.flash {
+            for colour in [0, 0xffffff] {
+                update(&vec![colour; w * h])?;
+                std::thread::sleep(FLASH);
+            }
+        }
+        let img = image::imageops::resize(
+            &frame.img,
+            w as u32,
+            h as u32,
+            image::imageops::FilterType::Nearest,
+        );
+        let buf = img
+            .pixels()
+            .map(|p| u32::from_be_bytes([0, p.0[0], p.0[0], p.0[0]]))
+            .collect::<Vec<_>>();
+        update(&buf)
+    }
+}
@@ -0,0 +1,122 @@
+//! Reducing frames to the panel's 16 grey levels.
+//!
+//! The driver packs pixels into 4 bits with a plain `/16`, which bands smooth shading. Dithering
+//! the frame first trades the bands for a fine pattern. Each output pixel is one of the 16 levels
+//! exactly (a multiple of 17), so the driver's packing is lossless.
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+const LEVELS: u8 = 16;
+/// The step between grey levels, `255 / 15`.
+const STEP: f32 = 17.0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    /// Round down to the nearest level, as the driver does.
+    #[default]
+    Quantize,
+    /// A 4×4 Bayer threshold pattern, stable between frames so partial refreshes stay small.
+    Ordered,
+    /// Floyd–Steinberg error diffusion, the smoothest gradients.
+    Diffusion,
+}
+
+impl Dither {
+    pub fn apply(self, img: &mut GrayImage) {
+        match self {
+            Dither::Quantize => quantize(img),
+            Dither::Ordered => ordered(img),
+            Dither::Diffusion => diffusion(img),
+        }
+    }
+}
+
+fn level(v: f32) -> u8 {
+    (v.round().clamp(0.0, (LEVELS - 1) as f32) * STEP) as u8
+}
+
+fn quantize(img: &mut GrayImage) {
+    for px in img.pixels_mut() {
+        px.0[0] = px.0[0] / LEVELS * STEP as u8;
+    }
+}
+
+fn ordered(img: &mut GrayImage) {
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+    for (x, y, px) in img.enumerate_pixels_mut() {
+        let t = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5;
+        px.0[0] = level(px.0[0] as f32 / STEP + t);
+    }
+}
+
+fn diffusion(img: &mut GrayImage) {
+    let w = img.width() as usize;
+    // the error carried into this row and the next, padded a pixel either side
+    let mut this = vec![0f32; w + 2];
+    let mut next = vec![0f32; w + 2];
+    for y in 0..img.height() {
+        for x in 0..w {
+            let px = img.get_pixel_mut(x as u32, y);
+            let v = px.0[0] as f32 + this[x + 1];
+            let out = level(v / STEP);
+            px.0[0] = out;
+            let err = v - out as f32;
+            this[x + 2] += err * 7.0 / 16.0;
+            next[x] += err * 3.0 / 16.0;
+            next[x + 1] += err * 5.0 / 16.0;
+            next[x + 2] += err * 1.0 / 16.0;
+        }
+        std::mem::swap(&mut this, &mut next);
+        next.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(v: u8) -> GrayImage {
+        GrayImage::from_pixel(32, 32, image::Luma([v]))
+    }
+
+    fn mean(img: &GrayImage) -> f32 {
+        img.pixels().map(|x| x.0[0] as f32).sum::<f32>() / (img.width() * img.height()) as f32
+    }
+
+    #[test]
+    fn outputs_panel_levels() {
+        for dither in [Dither::Quantize, Dither::Ordered, Dither::Diffusion] {
+            let mut img = GrayImage::from_fn(64, 4, |x, _| image::Luma([(x * 4) as u8]));
+            dither.apply(&mut img);
+            assert!(img.pixels().all(|x| x.0[0] % 17 == 0), "{dither:?}");
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_the_tone() {
+        // 120 sits between levels 7 (119) and 8 (136)
+        let mut q = flat(120);
+        Dither::Quantize.apply(&mut q);
+        assert_eq!(mean(&q), 119.0);
+
+        for v in [40, 120, 200] {
+            for dither in [Dither::Ordered, Dither::Diffusion] {
+                let mut img = flat(v);
+                dither.apply(&mut img);
+                let m = mean(&img);
+                assert!((m - v as f32).abs() < 3.0, "{dither:?} {v} -> {m}");
+            }
+        }
+
+        let mut img = flat(128);
+        Dither::Diffusion.apply(&mut img);
+        assert!(img.pixels().any(|x| x.0[0] == 119) && img.pixels().any(|x| x.0[0] == 136));
+        // black and white stay solid
+        for v in [0, 255] {
+            let mut img = flat(v);
+            Dither::Diffusion.apply(&mut img);
+            assert!(img.pixels().all(|x| x.0[0] == v));
+        }
+    }
+}
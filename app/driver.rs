@@ -0,0 +1,561 @@
+//! Talking to the it8951-driver over its stdin and stdout, a line of JSON each way.
+//!
+//! Every request carries an `id` which the driver's reply repeats, so a push is only counted
+//! once the driver says it was shown, and a failure comes back as an error rather than a hang:
+//!
+//! ```text
+//! > {"id":1,"cmd":"push","image":"./frame.pical.bmp","waveform":"du4","areas":[{"x":0,"y":0,"w":200,"h":60}]}
+//! < {"id":1,"ok":true}
+//! > {"id":2,"cmd":"status"}
+//! < {"id":2,"ok":true,"status":{"width":1872,"height":1404,"firmware":"...","lut":"...","power":"asleep","pushes":1,...}}
+//! ```
+//!
+//! A frame can be sent as raw pixels rather than a file, following its request's line:
+//!
+//! ```text
+//! > {"id":3,"cmd":"push-raw","width":1872,"height":1404,"bpp":4,"len":1314144,"waveform":"gc16","areas":[]}
+//! > <1314144 bytes of pixels>
+//! < {"id":3,"ok":true}
+//! ```
+use crate::{
+    policy::{Push, Waveform},
+    render::Region,
+};
+use image::GrayImage;
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, Lines};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Command {
+    /// Show the image saved at `image`, only the `areas` of it if any.
+    Push {
+        image: PathBuf,
+        waveform: Waveform,
+        areas: Vec<Region>,
+    },
+    /// Show the `width` by `height` image in `data`, packed `bpp` bits a pixel, only the `areas`
+    /// of it if any. `data` follows the request's line, so isn't serialised with it.
+    PushRaw {
+        width: u32,
+        height: u32,
+        bpp: u8,
+        len: usize,
+        waveform: Waveform,
+        areas: Vec<Region>,
+        #[serde(skip)]
+        data: Vec<u8>,
+    },
+    /// Show the image saved at `image` with its top left at `x`, `y`, refreshing only that area.
+    /// The area's x and width on the panel must be multiples of 4, see the driver's `push-area`.
+    PushArea {
+        image: PathBuf,
+        x: u16,
+        y: u16,
+        waveform: Waveform,
+    },
+    /// Clear the panel to white.
+    Clear,
+    /// Leave the panel powered down between requests, as it is by default.
+    Sleep,
+    /// Leave the panel in standby between requests, quicker to wake.
+    Standby,
+    /// Keep the panel running between requests, until `Sleep` or `Standby`.
+    Wake,
+    /// Reply with the panel's details.
+    Status,
+}
+
+impl Command {
+    pub fn push(image: &Path, push: &Push) -> Self {
+        Command::Push {
+            image: image.to_path_buf(),
+            waveform: push.waveform,
+            areas: push.regions.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Push the image as raw pixels, 4 bits each.
+    pub fn push_raw(img: &GrayImage, push: &Push) -> Self {
+        let data = pack_4bpp(img);
+        Command::PushRaw {
+            width: img.width(),
+            height: img.height(),
+            bpp: 4,
+            len: data.len(),
+            waveform: push.waveform,
+            areas: push.regions.clone().unwrap_or_default(),
+            data,
+        }
+    }
+}
+
+/// Two pixels to a byte, the first in the high bits, each row starting on a byte.
+fn pack_4bpp(img: &GrayImage) -> Vec<u8> {
+    img.rows()
+        .flat_map(|row| {
+            let row = row.map(|x| x.0[0] / 16).collect::<Vec<_>>();
+            row.chunks(2)
+                .map(|x| x[0] << 4 | x.get(1).copied().unwrap_or(0))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    #[serde(flatten)]
+    command: &'a Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Response {
+    /// `None` if the driver couldn't read the request.
+    pub id: Option<u64>,
+    pub ok: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub status: Option<Status>,
+    /// Such as a frame which isn't the panel's size, which the driver fitted to it.
+    #[serde(default)]
+    pub warning: Option<String>,
+    /// `recovered` if driving the panel failed and the driver got over it, or `fatal` if it gave
+    /// up on the panel and is exiting.
+    #[serde(default)]
+    pub recovery: Option<String>,
+    /// The frame wasn't shown, as a newer one came before the panel got to it.
+    #[serde(default)]
+    pub dropped: bool,
+}
+
+impl Response {
+    /// Whether the driver gave up on the panel, so needs starting again.
+    pub fn is_fatal(&self) -> bool {
+        self.recovery.as_deref() == Some("fatal")
+    }
+
+    /// The response, or the error the driver replied with, logging any warning or dropped frame.
+    pub fn into_result(self) -> Result<Self> {
+        if let Some(w) = &self.warning {
+            log::warn!("it8951-driver: {w}");
+        }
+        if self.recovery.as_deref() == Some("recovered") {
+            log::warn!("it8951-driver: recovered from failing to drive the panel");
+        }
+        if self.dropped {
+            log::info!("it8951-driver: dropped the frame for a newer one");
+        }
+        match (self.ok, &self.error) {
+            (true, _) => Ok(self),
+            (false, Some(e)) => Err(miette!("it8951-driver: {e}")),
+            (false, None) => Err(miette!("it8951-driver failed without saying why")),
+        }
+    }
+}
+
+/// The panel as the driver reports it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub width: u16,
+    pub height: u16,
+    pub firmware: String,
+    pub lut: String,
+    /// Degrees the driver turns frames clockwise, `width` and `height` are of a turned frame.
+    #[serde(default)]
+    pub rotate: u16,
+    /// `asleep`, `standby`, or `awake` between requests, or `lost` if the driver gave up on the
+    /// panel.
+    #[serde(default)]
+    pub power: String,
+    /// Pushes since the driver started.
+    pub pushes: u64,
+    /// Refreshes of the whole panel with GC16 since the driver started.
+    #[serde(default)]
+    pub full_refreshes: u64,
+    /// Requests which failed since the driver started.
+    #[serde(default)]
+    pub failures: u64,
+    /// Tries at driving the panel which failed and were tried again.
+    #[serde(default)]
+    pub retries: u64,
+    /// Resets of the controller after repeated failures.
+    #[serde(default)]
+    pub resets: u64,
+    /// Frames dropped for newer ones, pushed faster than the panel refreshes.
+    #[serde(default)]
+    pub dropped: u64,
+    #[serde(default)]
+    pub uptime_secs: u64,
+    /// The panel's temperature in °C, if the driver reads it.
+    #[serde(default)]
+    pub temperature: Option<i16>,
+}
+
+#[cfg(feature = "in-process-driver")]
+pub use in_process::InProcess;
+
+/// Sends requests to the driver and waits for their replies.
+pub struct Client<W, R> {
+    tx: W,
+    rx: Lines<R>,
+    next_id: u64,
+}
+
+impl<W, R> Client<W, R>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncBufRead + Unpin,
+{
+    /// A client writing requests to `tx`, the driver's stdin, and reading replies from `rx`, its
+    /// stdout.
+    pub fn new(tx: W, rx: R) -> Self {
+        Self {
+            tx,
+            rx: rx.lines(),
+            next_id: 0,
+        }
+    }
+
+    /// Send `command` and wait for its reply.
+    ///
+    /// Errors if the driver can't be written to or exits, but not if it replies with an error,
+    /// see [`Response::into_result`].
+    pub async fn call(&mut self, command: &Command) -> Result<Response> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut line = serde_json::to_string(&Request { id, command }).into_diagnostic()?;
+        line.push('\n');
+        let data = match command {
+            Command::PushRaw { data, .. } => &data[..],
+            _ => &[],
+        };
+        for x in [line.as_bytes(), data] {
+            self.tx
+                .write_all(x)
+                .await
+                .into_diagnostic()
+                .wrap_err("failed to write to it8951-driver")?;
+        }
+        self.tx.flush().await.into_diagnostic()?;
+
+        loop {
+            let line = self
+                .rx
+                .next_line()
+                .await
+                .into_diagnostic()
+                .wrap_err("failed to read from it8951-driver")?
+                .ok_or_else(|| miette!("it8951-driver exited"))?;
+            let Ok(res) = serde_json::from_str::<Response>(&line) else {
+                log::debug!("it8951-driver: {line}");
+                continue;
+            };
+            match res.id {
+                Some(x) if x == id => return Ok(res),
+                // a late reply to an earlier request
+                Some(_) => continue,
+                // only one request is in flight, so it was this one
+                None => return Ok(res),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "in-process-driver")]
+mod in_process {
+    use super::*;
+    use it8951_driver::{config::Hardware, protocol, Panel};
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    /// The longest a request is waited on, as the driver's watchdog does, since retrying
+    /// can't get over a call into the panel which never returns.
+    const HUNG_AFTER: Duration = Duration::from_secs(120);
+
+    /// The panel driven from pical's own process, through the driver's library.
+    ///
+    /// Requests are answered as the driver would, so a push which fails replies with an error
+    /// rather than failing the call.
+    pub struct InProcess {
+        hw: Hardware,
+        /// `None` after a request hung, until the panel is opened again.
+        panel: Mutex<Option<Arc<Mutex<Panel>>>>,
+    }
+
+    impl InProcess {
+        /// Open the panel set up in the driver's config file, as the driver would.
+        pub async fn open() -> Result<Self> {
+            let hw = Hardware::load(None)?;
+            let panel = open_panel(hw.clone()).await?;
+            Ok(Self {
+                hw,
+                panel: Mutex::new(Some(panel)),
+            })
+        }
+
+        /// Handle `command`, waiting for the panel to refresh.
+        ///
+        /// A panel given up on is opened again, as the driver would be restarted. So is one
+        /// which hangs for [`HUNG_AFTER`], the hung call left to free the panel's SPI device and
+        /// pins when it returns, until which opening it again fails.
+        pub async fn call(&self, command: &Command) -> Result<Response> {
+            let current = self.panel.lock().expect("panel lock poisoned").clone();
+            let panel = match current {
+                Some(x) => x,
+                None => {
+                    log::warn!("Reopening the panel after it hung");
+                    let panel = open_panel(self.hw.clone()).await?;
+                    *self.panel.lock().expect("panel lock poisoned") = Some(panel.clone());
+                    panel
+                }
+            };
+            let command = command.to_protocol();
+            let hw = self.hw.clone();
+            let task = tokio::task::spawn_blocking(move || {
+                let mut panel = panel.lock().expect("panel lock poisoned");
+                let res = panel.handle(0, command);
+                if panel.is_lost() {
+                    log::warn!("Reopening the panel after losing it");
+                    *panel = Panel::open(&hw)?;
+                }
+                Ok::<_, Report>(res)
+            });
+            let res = match tokio::time::timeout(HUNG_AFTER, task).await {
+                Ok(x) => x.into_diagnostic()??,
+                Err(_) => {
+                    // the blocking call can't be cancelled, so it keeps the panel it has
+                    *self.panel.lock().expect("panel lock poisoned") = None;
+                    return Err(miette!(
+                        "the panel hung handling a request for {}, it is opened again for the next",
+                        humantime::format_duration(HUNG_AFTER)
+                    ));
+                }
+            };
+            Ok(Response {
+                id: res.id,
+                ok: res.ok,
+                error: res.error,
+                status: res.status.map(Status::from),
+                warning: res.warning,
+                recovery: res.recovery.map(|x| {
+                    match x {
+                        protocol::Recovery::Recovered => "recovered",
+                        protocol::Recovery::Fatal => "fatal",
+                    }
+                    .to_string()
+                }),
+                dropped: res.dropped,
+            })
+        }
+    }
+
+    /// Open the panel, giving up after [`HUNG_AFTER`] as opening it waits on the busy pin too.
+    async fn open_panel(hw: Hardware) -> Result<Arc<Mutex<Panel>>> {
+        let task = tokio::task::spawn_blocking(move || Panel::open(&hw));
+        let panel = tokio::time::timeout(HUNG_AFTER, task)
+            .await
+            .map_err(|_| {
+                miette!(
+                    "opening the panel hung for {}",
+                    humantime::format_duration(HUNG_AFTER)
+                )
+            })?
+            .into_diagnostic()??;
+        Ok(Arc::new(Mutex::new(panel)))
+    }
+
+    impl Command {
+        fn to_protocol(&self) -> protocol::Command {
+            let u16 = |x: u32| u16::try_from(x).unwrap_or(u16::MAX);
+            let area = |r: &Region| protocol::Area {
+                x: u16(r.x),
+                y: u16(r.y),
+                w: u16(r.w),
+                h: u16(r.h),
+            };
+            match self {
+                Command::Push {
+                    image,
+                    waveform,
+                    areas,
+                } => protocol::Command::Push {
+                    image: image.clone(),
+                    waveform: (*waveform).into(),
+                    areas: areas.iter().map(area).collect(),
+                    rotate: None,
+                },
+                Command::PushRaw {
+                    width,
+                    height,
+                    bpp,
+                    len,
+                    waveform,
+                    areas,
+                    data,
+                } => protocol::Command::PushRaw {
+                    width: u16(*width),
+                    height: u16(*height),
+                    bpp: *bpp,
+                    len: *len,
+                    waveform: (*waveform).into(),
+                    areas: areas.iter().map(area).collect(),
+                    rotate: None,
+                    data: data.clone(),
+                },
+                Command::PushArea {
+                    image,
+                    x,
+                    y,
+                    waveform,
+                } => protocol::Command::PushArea {
+                    image: image.clone(),
+                    x: *x,
+                    y: *y,
+                    waveform: (*waveform).into(),
+                    rotate: None,
+                },
+                Command::Clear => protocol::Command::Clear {
+                    waveform: protocol::Waveform::Gc16,
+                },
+                Command::Sleep => protocol::Command::Sleep,
+                Command::Standby => protocol::Command::Standby,
+                Command::Wake => protocol::Command::Wake,
+                Command::Status => protocol::Command::Status,
+            }
+        }
+    }
+
+    impl From<Waveform> for protocol::Waveform {
+        fn from(x: Waveform) -> Self {
+            match x {
+                Waveform::A2 => Self::A2,
+                Waveform::Du => Self::Du,
+                Waveform::Du4 => Self::Du4,
+                Waveform::Gl16 => Self::Gl16,
+                Waveform::Gc16 => Self::Gc16,
+            }
+        }
+    }
+
+    impl From<protocol::Status> for Status {
+        fn from(x: protocol::Status) -> Self {
+            Self {
+                width: x.width,
+                height: x.height,
+                firmware: x.firmware,
+                lut: x.lut,
+                rotate: x.rotate,
+                power: match x.power {
+                    protocol::Power::Asleep => "asleep",
+                    protocol::Power::Standby => "standby",
+                    protocol::Power::Awake => "awake",
+                    protocol::Power::Lost => "lost",
+                }
+                .to_string(),
+                pushes: x.pushes,
+                full_refreshes: x.full_refreshes,
+                failures: x.failures,
+                retries: x.retries,
+                resets: x.resets,
+                dropped: x.dropped,
+                uptime_secs: x.uptime_secs,
+                temperature: x.temperature,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    #[tokio::test]
+    async fn replies_by_id() {
+        let (tx, mut driver_rx) = tokio::io::duplex(1024);
+        let (mut driver_tx, rx) = tokio::io::duplex(1024);
+        let mut client = Client::new(tx, BufReader::new(rx));
+
+        let push = Push {
+            waveform: Waveform::Du4,
+            regions: Some(vec![Region {
+                x: 0,
+                y: 8,
+                w: 200,
+                h: 60,
+            }]),
+        };
+        let driver = tokio::spawn(async move {
+            let mut lines = BufReader::new(&mut driver_rx).lines();
+            let req = lines.next_line().await.unwrap().unwrap();
+            // a stale reply, and a stray line, are skipped
+            let replies = "{\"id\":0,\"ok\":true}\nrefreshed\n{\"id\":1,\"ok\":true}\n";
+            driver_tx.write_all(replies.as_bytes()).await.unwrap();
+            let clear = lines.next_line().await.unwrap().unwrap();
+            let reply =
+                "{\"id\":2,\"ok\":false,\"error\":\"failed to display image buffer: Spi\"}\n";
+            driver_tx.write_all(reply.as_bytes()).await.unwrap();
+            (req, clear)
+        });
+
+        let res = client
+            .call(&Command::push(Path::new("./frame.pical.bmp"), &push))
+            .await
+            .unwrap();
+        assert!(res.into_result().is_ok());
+        let res = client.call(&Command::Clear).await.unwrap();
+        let e = res.into_result().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "it8951-driver: failed to display image buffer: Spi"
+        );
+
+        let (req, clear) = driver.await.unwrap();
+        assert_eq!(
+            req,
+            r#"{"id":1,"cmd":"push","image":"./frame.pical.bmp","waveform":"du4","areas":[{"x":0,"y":8,"w":200,"h":60}]}"#
+        );
+        assert_eq!(clear, r#"{"id":2,"cmd":"clear"}"#);
+
+        // the driver exited
+        assert!(client.call(&Command::Status).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn raw_pixels_follow_the_line() {
+        let (tx, mut driver_rx) = tokio::io::duplex(1024);
+        let (mut driver_tx, rx) = tokio::io::duplex(1024);
+        let mut client = Client::new(tx, BufReader::new(rx));
+
+        let img = GrayImage::from_fn(3, 2, |x, y| {
+            image::Luma([[0, 255, 119, 17, 34, 51][(y * 3 + x) as usize]])
+        });
+        let cmd = Command::push_raw(&img, &Push::FULL);
+        let driver = tokio::spawn(async move {
+            let mut rx = BufReader::new(&mut driver_rx);
+            let mut line = String::new();
+            rx.read_line(&mut line).await.unwrap();
+            let mut data = [0; 4];
+            rx.read_exact(&mut data).await.unwrap();
+            driver_tx
+                .write_all(b"{\"id\":1,\"ok\":true}\n")
+                .await
+                .unwrap();
+            (line, data)
+        });
+        assert!(client.call(&cmd).await.unwrap().into_result().is_ok());
+
+        let (line, data) = driver.await.unwrap();
+        assert_eq!(
+            line,
+            "{\"id\":1,\"cmd\":\"push-raw\",\"width\":3,\"height\":2,\"bpp\":4,\"len\":4,\"waveform\":\"gc16\",\"areas\":[]}\n"
+        );
+        assert_eq!(data, [0x0f, 0x70, 0x12, 0x30]);
+    }
+}
@@ -0,0 +1,416 @@
+//! HTTP basic and digest auth, for URLs which need credentials such as self-hosted calendar
+//! exports.
+//!
+//! The first request is sent without credentials, so they are only sent in the scheme the
+//! server asks for in its `WWW-Authenticate` challenge. The scheme is remembered, so later
+//! requests carry the credentials from the start, a digest server only being asked again when
+//! its nonce goes stale.
+use super::{Credentials, Fetcher, Response, Unauthorized};
+use miette::*;
+use std::{future::Future, sync::Mutex};
+
+/// Credentials, and how the server asked for them.
+pub struct Auth {
+    credentials: Credentials,
+    /// `None` until the server has challenged a request.
+    scheme: Mutex<Option<Scheme>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Scheme {
+    Basic,
+    /// The challenge, and the requests made with its nonce.
+    Digest(Digest, u32),
+}
+
+/// A `WWW-Authenticate: Digest` challenge.
+#[derive(Clone, Debug, PartialEq)]
+struct Digest {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    /// Whether the server takes `qop=auth`, otherwise the RFC 2069 digest is sent.
+    qop_auth: bool,
+    /// A request was refused only because its nonce was out of date.
+    stale: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Auth {
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            scheme: Mutex::new(None),
+        }
+    }
+
+    /// `client`, sending these credentials with its GETs.
+    pub fn client<'a, F: Fetcher + Sync>(&'a self, client: &'a F) -> Authed<'a, F> {
+        Authed { auth: self, client }
+    }
+
+    /// The `Authorization` header for a GET of `url`, if the server's scheme is known.
+    fn header(&self, url: &str) -> Result<Option<(String, String)>> {
+        let mut scheme = self.scheme.lock().expect("auth lock poisoned");
+        let value = match &mut *scheme {
+            None => return Ok(None),
+            Some(Scheme::Basic) => self.credentials.header()?.1,
+            Some(Scheme::Digest(digest, count)) => {
+                *count += 1;
+                let cnonce = cnonce(url, *count);
+                digest.header(&self.credentials, &uri(url), *count, &cnonce)?
+            }
+        };
+        Ok(Some(("Authorization".to_string(), value)))
+    }
+
+    /// Take up the scheme of the server's challenges, returning whether the request is worth
+    /// sending again with it.
+    fn challenged(&self, challenges: &[String]) -> bool {
+        let mut scheme = self.scheme.lock().expect("auth lock poisoned");
+        let digest = challenges.iter().find_map(|x| Digest::parse(x));
+        let basic = challenges
+            .iter()
+            .any(|x| x.trim_start().to_ascii_lowercase().starts_with("basic"));
+        let retry = match (&*scheme, &digest, basic) {
+            // already sent what it asks for, so the credentials are wrong
+            (Some(Scheme::Basic), None, true) => false,
+            (Some(Scheme::Digest(..)), Some(x), _) => x.stale,
+            (_, Some(_), _) | (_, None, true) => true,
+            (_, None, false) => false,
+        };
+        if let Some(x) = digest {
+            *scheme = Some(Scheme::Digest(x, 0));
+        } else if basic {
+            *scheme = Some(Scheme::Basic);
+        }
+        retry
+    }
+}
+
+/// A client which answers the server's challenges with the credentials.
+pub struct Authed<'a, F> {
+    auth: &'a Auth,
+    client: &'a F,
+}
+
+impl<F: Fetcher + Sync> Fetcher for Authed<'_, F> {
+    fn get(
+        &self,
+        url: &str,
+        hdrs: Vec<(String, String)>,
+    ) -> impl Future<Output = Result<Response>> + Send {
+        async move {
+            let with_auth = |auth: Option<(String, String)>| {
+                let mut hdrs = hdrs.clone();
+                hdrs.extend(auth);
+                hdrs
+            };
+            let e = match self
+                .client
+                .get(url, with_auth(self.auth.header(url)?))
+                .await
+            {
+                Ok(x) => return Ok(x),
+                Err(e) => e,
+            };
+            match e.downcast_ref::<Unauthorized>() {
+                Some(x) if self.auth.challenged(&x.challenges) => {
+                    log::debug!("Sending the credentials for {url} as challenged");
+                    self.client
+                        .get(url, with_auth(self.auth.header(url)?))
+                        .await
+                }
+                _ => Err(e),
+            }
+        }
+    }
+
+    fn post_form(
+        &self,
+        url: &str,
+        form: Vec<(String, String)>,
+    ) -> impl Future<Output = Result<(u16, String)>> + Send {
+        self.client.post_form(url, form)
+    }
+
+    fn authenticated(&self) -> bool {
+        true
+    }
+}
+
+impl Digest {
+    /// The digest challenge in a `WWW-Authenticate` value, if it has one.
+    fn parse(value: &str) -> Option<Self> {
+        let i = value.to_ascii_lowercase().find("digest ")?;
+        let params = params(&value[i + "digest ".len()..]);
+        let get = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+        };
+        let algorithm = match get("algorithm").map(|x| x.to_ascii_uppercase()).as_deref() {
+            None | Some("MD5") => Algorithm::Md5,
+            Some("MD5-SESS") => Algorithm::Md5Sess,
+            Some("SHA-256") => Algorithm::Sha256,
+            Some("SHA-256-SESS") => Algorithm::Sha256Sess,
+            Some(x) => {
+                log::warn!("Unsupported digest auth algorithm {x}");
+                return None;
+            }
+        };
+        Some(Self {
+            realm: get("realm").unwrap_or_default(),
+            nonce: get("nonce")?,
+            opaque: get("opaque"),
+            algorithm,
+            qop_auth: get("qop").is_some_and(|x| x.split(',').any(|x| x.trim() == "auth")),
+            stale: get("stale").is_some_and(|x| x.eq_ignore_ascii_case("true")),
+        })
+    }
+
+    /// The `Authorization` value for the `count`th request with this nonce, of `uri`.
+    fn header(&self, creds: &Credentials, uri: &str, count: u32, cnonce: &str) -> Result<String> {
+        let h = |x: String| self.algorithm.hash(&x);
+        let nc = format!("{count:08x}");
+        let mut ha1 = h(format!(
+            "{}:{}:{}",
+            creds.username,
+            self.realm,
+            creds.password()?
+        ));
+        if matches!(self.algorithm, Algorithm::Md5Sess | Algorithm::Sha256Sess) {
+            ha1 = h(format!("{ha1}:{}:{cnonce}", self.nonce));
+        }
+        let ha2 = h(format!("GET:{uri}"));
+        let response = match self.qop_auth {
+            true => h(format!("{ha1}:{}:{nc}:{cnonce}:auth:{ha2}", self.nonce)),
+            false => h(format!("{ha1}:{}:{ha2}", self.nonce)),
+        };
+
+        let quote = |x: &str| x.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, \
+            response=\"{response}\"",
+            quote(&creds.username),
+            quote(&self.realm),
+            quote(&self.nonce),
+            quote(uri),
+            self.algorithm.name(),
+        );
+        if self.qop_auth {
+            value.push_str(&format!(", qop=auth, nc={nc}, cnonce=\"{cnonce}\""));
+        }
+        if let Some(x) = &self.opaque {
+            value.push_str(&format!(", opaque=\"{}\"", quote(x)));
+        }
+        Ok(value)
+    }
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+            Self::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    /// The lower case hex digest of `s`.
+    fn hash(self, s: &str) -> String {
+        use md5::Digest as _;
+        let bytes = match self {
+            Self::Md5 | Self::Md5Sess => md5::Md5::digest(s).to_vec(),
+            Self::Sha256 | Self::Sha256Sess => sha2::Sha256::digest(s).to_vec(),
+        };
+        bytes.iter().map(|x| format!("{x:02x}")).collect()
+    }
+}
+
+/// The `key=value` and `key="quoted value"` parameters of a challenge, up to the next scheme.
+fn params(s: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        // a following challenge, such as `Basic realm=...`
+        if key.contains(char::is_whitespace) {
+            break;
+        }
+        rest = &rest[eq + 1..];
+        let value = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|x| x.1)),
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                rest = &quoted[end..];
+                value
+            }
+            None => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let value = rest[..end].trim().to_string();
+                rest = &rest[end..];
+                value
+            }
+        };
+        params.push((key.to_string(), value));
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+    }
+    params
+}
+
+/// The path and query of `url`, as the digest's `uri`.
+fn uri(url: &str) -> String {
+    url::Url::parse(url).map_or_else(
+        |_| url.to_string(),
+        |x| match x.query() {
+            Some(q) => format!("{}?{q}", x.path()),
+            None => x.path().to_string(),
+        },
+    )
+}
+
+/// A client nonce, which only needs to differ between requests.
+fn cnonce(url: &str, count: u32) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |x| x.as_nanos());
+    let seed = format!("{url}:{count}:{nanos}:{}", std::process::id());
+    Algorithm::Md5.hash(&seed)[..16].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mufasa() -> Credentials {
+        Credentials {
+            username: "Mufasa".into(),
+            password: Some("Circle Of Life".into()),
+            password_env: None,
+            password_file: None,
+        }
+    }
+
+    #[test]
+    fn rfc2617_digest() {
+        let challenge = r#"Basic realm="x", Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#;
+        let digest = Digest::parse(challenge).unwrap();
+        assert_eq!(
+            digest,
+            Digest {
+                realm: "testrealm@host.com".into(),
+                nonce: "dcd98b7102dd2f0e8b11d0f600bfb0c093".into(),
+                opaque: Some("5ccc069c403ebaf9f0171e9517f40e41".into()),
+                algorithm: Algorithm::Md5,
+                qop_auth: true,
+                stale: false,
+            }
+        );
+        let header = digest
+            .header(&mufasa(), "/dir/index.html", 1, "0a4f113b")
+            .unwrap();
+        assert_eq!(
+            header,
+            "Digest username=\"Mufasa\", realm=\"testrealm@host.com\", \
+            nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", uri=\"/dir/index.html\", algorithm=MD5, \
+            response=\"6629fae49393a05397450978507c4ef1\", qop=auth, nc=00000001, \
+            cnonce=\"0a4f113b\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""
+        );
+        assert_eq!(Digest::parse(r#"Basic realm="x""#), None);
+        assert_eq!(
+            uri("https://cal.example/dav/x.ics?export"),
+            "/dav/x.ics?export"
+        );
+    }
+
+    /// A server taking Mufasa's digest auth, with a nonce which goes stale every third request.
+    struct Server(Mutex<Vec<Vec<(String, String)>>>);
+
+    impl Fetcher for Server {
+        fn get(
+            &self,
+            url: &str,
+            hdrs: Vec<(String, String)>,
+        ) -> impl Future<Output = Result<Response>> + Send {
+            let mut requests = self.0.lock().unwrap();
+            let authorization = hdrs.iter().find(|x| x.0 == "Authorization").cloned();
+            requests.push(hdrs);
+            let nonce = requests.len() / 3;
+            let authorization = authorization.map(|x| x.1).unwrap_or_default();
+            let current = authorization.contains(&format!("nonce=\"n{nonce}\""));
+            let res = match authorization.contains("username=\"Mufasa\"") {
+                true if current => Ok(Response {
+                    body: "BEGIN:VCALENDAR".into(),
+                    headers: Vec::new(),
+                }),
+                stale => Err(Unauthorized {
+                    url: url.to_string(),
+                    challenges: vec![format!(
+                        "Digest realm=\"cal\", qop=\"auth\", nonce=\"n{nonce}\", stale={stale}"
+                    )],
+                }
+                .into()),
+            };
+            async move { res }
+        }
+
+        fn post_form(
+            &self,
+            _: &str,
+            _: Vec<(String, String)>,
+        ) -> impl Future<Output = Result<(u16, String)>> + Send {
+            async { Err(miette!("unused")) }
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_digest_challenges() {
+        let server = Server(Mutex::new(Vec::new()));
+        let auth = Auth::new(mufasa());
+        let client = auth.client(&server);
+        let url = "https://cal.example/x.ics";
+        // challenged, then answered
+        assert!(client.get(url, Vec::new()).await.is_ok());
+        // stale, then answered with the new nonce
+        assert!(client.get(url, Vec::new()).await.is_ok());
+        // answered from the start
+        assert!(client.get(url, Vec::new()).await.is_ok());
+
+        let requests = server.0.into_inner().unwrap();
+        let sent = |x: &Vec<(String, String)>| x.first().map(|x| x.1.clone());
+        assert_eq!(requests.len(), 5);
+        assert_eq!(sent(&requests[0]), None);
+        assert!(sent(&requests[1]).is_some_and(|x| x.contains("nc=00000001")));
+        assert!(sent(&requests[2]).is_some_and(|x| x.contains("nc=00000002")));
+        assert!(sent(&requests[4]).is_some_and(|x| x.contains("nonce=\"n1\", ")));
+
+        let wrong = Auth::new(Credentials {
+            username: "Scar".into(),
+            ..mufasa()
+        });
+        let server = Server(Mutex::new(Vec::new()));
+        assert!(wrong.client(&server).get(url, Vec::new()).await.is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! OAuth2 access tokens for sources behind a provider's login, such as Microsoft or Google.
+//!
+//! Uses the device code flow, made for devices without a browser: on first use a code is logged
+//! which must be entered at the provider's verification page. The tokens are then kept in a file
+//! only the owner can read, refreshed before they expire, and added to requests as a bearer
+//! `Authorization` header.
+use super::Fetcher;
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+/// The endpoints of an OAuth2 provider, and the app registered with it.
+#[derive(Clone, Debug)]
+pub struct Provider {
+    pub device_code_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    /// Required by some providers (Google) even though a device can't keep it secret.
+    pub client_secret: Option<String>,
+    /// Space separated, including any the provider needs for a refresh token.
+    pub scope: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    /// Unix timestamp.
+    expires_at: i64,
+}
+
+struct DeviceCode {
+    device_code: String,
+    expires_at: OffsetDateTime,
+}
+
+enum TokenResponse {
+    Token(String),
+    Pending,
+    /// Refused by the provider, with its OAuth error code, such as `invalid_grant`.
+    Denied {
+        error: String,
+        description: Option<String>,
+    },
+}
+
+impl TokenResponse {
+    fn denied(error: String, description: Option<String>) -> Report {
+        miette!(
+            "{}",
+            description.unwrap_or_else(|| "no description".to_string())
+        )
+        .wrap_err(format!("token request failed: {error}"))
+    }
+}
+
+/// The tokens for one account with a provider.
+pub struct Session {
+    /// Who the tokens are for, in the logged messages.
+    pub name: String,
+    provider: Provider,
+    token_path: PathBuf,
+    token: Option<Token>,
+    pending: Option<DeviceCode>,
+}
+
+impl Session {
+    /// Create a session, loading any token saved at `token_path`.
+    pub fn new(name: String, provider: Provider, token_path: PathBuf) -> Self {
+        let token = load_token(&token_path);
+        Self {
+            name,
+            provider,
+            token_path,
+            token,
+            pending: None,
+        }
+    }
+
+    /// The `Authorization` header for a request, or `None` while waiting on the user to
+    /// authorise the device.
+    pub async fn header<F: Fetcher>(
+        &mut self,
+        client: &F,
+    ) -> Result<Option<(&'static str, String)>> {
+        let token = self.access_token(client).await?;
+        Ok(token.map(|x| ("Authorization", format!("Bearer {x}"))))
+    }
+
+    /// A valid access token, refreshing or starting a device code flow as required.
+    ///
+    /// Returns `None` while waiting on the user to authorise the device.
+    pub async fn access_token<F: Fetcher>(&mut self, client: &F) -> Result<Option<String>> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        match &self.token {
+            // give a minute of leeway
+            Some(t) if t.expires_at > now + 60 => return Ok(Some(t.access_token.clone())),
+            Some(Token {
+                refresh_token: Some(refresh),
+                ..
+            }) => {
+                let form = self.form(&[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh),
+                    ("scope", &self.provider.scope),
+                ]);
+                // kept through failed requests, such as when offline, only dropped when refused
+                match self.request_token(client, form).await? {
+                    TokenResponse::Token(t) => return Ok(Some(t)),
+                    TokenResponse::Pending => (),
+                    TokenResponse::Denied { error, .. } if error == "invalid_grant" => {
+                        log::warn!("'{}' refresh token was refused, re-authorising", self.name)
+                    }
+                    TokenResponse::Denied { error, description } => {
+                        return Err(TokenResponse::denied(error, description))
+                    }
+                }
+                self.token = None;
+            }
+            _ => self.token = None,
+        }
+
+        // need to authorise via device code
+        let pending = match self.pending.take() {
+            Some(x) if x.expires_at > OffsetDateTime::now_utc() => x,
+            _ => self.start_device_flow(client).await?,
+        };
+
+        let form = self.form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("device_code", &pending.device_code),
+        ]);
+        match self.request_token(client, form).await? {
+            TokenResponse::Token(t) => {
+                log::info!("✅ '{}' authorised", self.name);
+                Ok(Some(t))
+            }
+            TokenResponse::Pending => {
+                self.pending = Some(pending);
+                Ok(None)
+            }
+            TokenResponse::Denied { error, description } => {
+                Err(TokenResponse::denied(error, description))
+            }
+        }
+    }
+
+    /// The fields of a request to the provider, with the app's ID and any secret.
+    fn form(&self, fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        let client = [("client_id", Some(&self.provider.client_id))]
+            .into_iter()
+            .chain([("client_secret", self.provider.client_secret.as_ref())])
+            .filter_map(|(k, v)| Some((k, v?.as_str())));
+        client
+            .chain(fields.iter().copied())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    async fn start_device_flow<F: Fetcher>(&self, client: &F) -> Result<DeviceCode> {
+        let form = self.form(&[("scope", &self.provider.scope)]);
+        let (status, body) = client
+            .post_form(&self.provider.device_code_url, form)
+            .await?;
+        if status != 200 {
+            return Err(miette!("{body}"))
+                .wrap_err(format!("device code request failed with {status}"));
+        }
+
+        #[derive(Deserialize)]
+        struct Resp {
+            device_code: String,
+            user_code: String,
+            /// Google calls it `verification_url`.
+            #[serde(alias = "verification_url")]
+            verification_uri: String,
+            expires_in: i64,
+            /// Only some providers (Microsoft) give instructions.
+            message: Option<String>,
+        }
+        let Resp {
+            device_code,
+            user_code,
+            verification_uri,
+            expires_in,
+            message,
+        } = serde_json::from_str(&body)
+            .into_diagnostic()
+            .wrap_err("failed to parse device code response")?;
+
+        let message = message.unwrap_or_else(|| {
+            format!("To sign in, visit {verification_uri} and enter {user_code}")
+        });
+        log::warn!("🔑 '{}': {message}", self.name);
+
+        Ok(DeviceCode {
+            device_code,
+            expires_at: OffsetDateTime::now_utc() + time::Duration::seconds(expires_in),
+        })
+    }
+
+    async fn request_token<F: Fetcher>(
+        &mut self,
+        client: &F,
+        form: Vec<(String, String)>,
+    ) -> Result<TokenResponse> {
+        let (status, body) = client.post_form(&self.provider.token_url, form).await?;
+
+        #[derive(Deserialize)]
+        struct Resp {
+            access_token: Option<String>,
+            refresh_token: Option<String>,
+            expires_in: Option<i64>,
+            error: Option<String>,
+            error_description: Option<String>,
+        }
+        let resp: Resp = serde_json::from_str(&body)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to parse token response ({status})"))?;
+
+        match resp {
+            Resp {
+                access_token: Some(access_token),
+                refresh_token,
+                expires_in,
+                ..
+            } => {
+                let token = Token {
+                    access_token: access_token.clone(),
+                    // refresh tokens are not always rotated, keep the old one
+                    refresh_token: refresh_token
+                        .or_else(|| self.token.as_mut().and_then(|x| x.refresh_token.take())),
+                    expires_at: OffsetDateTime::now_utc().unix_timestamp()
+                        + expires_in.unwrap_or(3600),
+                };
+                save_token(&self.token_path, &token)?;
+                self.token = Some(token);
+                Ok(TokenResponse::Token(access_token))
+            }
+            Resp { error: Some(e), .. } if e == "authorization_pending" || e == "slow_down" => {
+                Ok(TokenResponse::Pending)
+            }
+            Resp {
+                error: Some(error),
+                error_description,
+                ..
+            } => Ok(TokenResponse::Denied {
+                error,
+                description: error_description,
+            }),
+            Resp { .. } => {
+                Err(miette!("{body}")).wrap_err(format!("token request failed with {status}"))
+            }
+        }
+    }
+}
+
+fn load_token(path: &Path) -> Option<Token> {
+    let s = std::fs::read_to_string(path).ok()?;
+    // tokens saved before they were kept private
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let private = std::fs::Permissions::from_mode(0o600);
+        if let Err(e) = std::fs::set_permissions(path, private) {
+            log::warn!("failed to make {} private: {e}", path.display());
+        }
+    }
+    serde_json::from_str(&s).ok()
+}
+
+/// Save the token where only the owner can read it.
+fn save_token(path: &Path, token: &Token) -> Result<()> {
+    use std::io::Write;
+    let tmp = path.with_extension("tmp");
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    opts.open(&tmp)
+        .and_then(|mut f| f.write_all(&serde_json::to_vec(token).unwrap_or_default()))
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to save token to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::Response;
+    use std::{future::Future, sync::Mutex};
+
+    /// Replies to each POST with the next of its responses, recording the forms. A status of 0
+    /// fails as if offline.
+    #[derive(Default)]
+    struct Fake {
+        replies: Mutex<Vec<(u16, &'static str)>>,
+        forms: Mutex<Vec<(String, Vec<(String, String)>)>>,
+    }
+
+    impl Fetcher for Fake {
+        fn get(
+            &self,
+            _: &str,
+            _: Vec<(String, String)>,
+        ) -> impl Future<Output = Result<Response>> + Send {
+            async { Err(miette!("unused")) }
+        }
+
+        fn post_form(
+            &self,
+            url: &str,
+            form: Vec<(String, String)>,
+        ) -> impl Future<Output = Result<(u16, String)>> + Send {
+            self.forms.lock().unwrap().push((url.to_string(), form));
+            let reply = self.replies.lock().unwrap().remove(0);
+            async move {
+                match reply.0 {
+                    0 => Err(miette!("offline")),
+                    status => Ok((status, reply.1.to_string())),
+                }
+            }
+        }
+    }
+
+    fn field<'a>(form: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        form.iter().find(|x| x.0 == key).map(|x| x.1.as_str())
+    }
+
+    #[tokio::test]
+    async fn authorises_then_refreshes() {
+        let path = std::env::temp_dir().join(format!("pical-oauth-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let provider = Provider {
+            device_code_url: "https://auth.example/device".into(),
+            token_url: "https://auth.example/token".into(),
+            client_id: "app".into(),
+            client_secret: Some("shh".into()),
+            scope: "calendar".into(),
+        };
+        let fake = Fake::default();
+        *fake.replies.lock().unwrap() = vec![
+            (
+                200,
+                r#"{"device_code":"dev","user_code":"ABCD","verification_url":"https://auth.example/go","expires_in":900}"#,
+            ),
+            (400, r#"{"error":"authorization_pending"}"#),
+            (
+                200,
+                r#"{"access_token":"one","refresh_token":"again","expires_in":30}"#,
+            ),
+            (200, r#"{"access_token":"two","expires_in":3600}"#),
+        ];
+
+        let mut session = Session::new("Test".into(), provider.clone(), path.clone());
+        assert_eq!(session.header(&fake).await.unwrap(), None);
+        let header = session.header(&fake).await.unwrap();
+        assert_eq!(header, Some(("Authorization", "Bearer one".to_string())));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // expiring within the minute's leeway, so refreshed, keeping the refresh token
+        let mut session = Session::new("Test".into(), provider.clone(), path.clone());
+        assert_eq!(
+            session.access_token(&fake).await.unwrap().as_deref(),
+            Some("two")
+        );
+        assert_eq!(
+            session.access_token(&fake).await.unwrap().as_deref(),
+            Some("two")
+        );
+        let token = load_token(&path).unwrap();
+        assert_eq!(token.refresh_token.as_deref(), Some("again"));
+
+        let forms = fake.forms.lock().unwrap();
+        assert_eq!(forms.len(), 4);
+        assert_eq!(forms[0].0, "https://auth.example/device");
+        assert_eq!(field(&forms[1].1, "device_code"), Some("dev"));
+        assert_eq!(field(&forms[3].1, "grant_type"), Some("refresh_token"));
+        assert_eq!(field(&forms[3].1, "refresh_token"), Some("again"));
+        assert!(forms
+            .iter()
+            .all(|x| field(&x.1, "client_secret") == Some("shh")));
+        drop(forms);
+
+        // offline, so the refresh fails but the token is kept to try again
+        *fake.replies.lock().unwrap() = vec![
+            (0, ""),
+            (200, r#"{"access_token":"three","expires_in":3600}"#),
+            (400, r#"{"error":"invalid_grant"}"#),
+            (
+                200,
+                r#"{"device_code":"dev2","user_code":"EFGH","verification_uri":"https://auth.example/go","expires_in":900}"#,
+            ),
+            (400, r#"{"error":"authorization_pending"}"#),
+        ];
+        let expired = Token {
+            access_token: "two".into(),
+            refresh_token: Some("again".into()),
+            expires_at: 0,
+        };
+        save_token(&path, &expired).unwrap();
+        let mut session = Session::new("Test".into(), provider.clone(), path.clone());
+        assert!(session.access_token(&fake).await.is_err());
+        assert_eq!(
+            session.access_token(&fake).await.unwrap().as_deref(),
+            Some("three")
+        );
+        // refused, so authorised again from the start
+        save_token(&path, &expired).unwrap();
+        let mut session = Session::new("Test".into(), provider, path.clone());
+        assert_eq!(session.access_token(&fake).await.unwrap(), None);
+        assert!(fake.replies.lock().unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+//! RSS and Atom feeds, reduced to their headlines.
+//!
+//! Only the few elements a headline needs are read from the parsed document, by name and
+//! namespace, so extensions such as `media:title` are not confused with their plain namesakes.
+use super::{Fetched, Fetcher};
+use miette::*;
+use std::time::Duration;
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feed {
+    /// The channel's (RSS) or feed's (Atom) title.
+    pub title: Option<String>,
+    /// In the order of the feed, usually newest first.
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<OffsetDateTime>,
+}
+
+/// GET the feed at `url`, see [`super::string`] for the caching.
+pub async fn feed<F: Fetcher>(client: &F, url: &str, fresh_for: Duration) -> Result<Fetched<Feed>> {
+    let Fetched { body, at, stale } = super::string(client, url, [], fresh_for).await?;
+    let body = parse(&body)
+        .wrap_err_with(|| format!("URL: {url}"))
+        .wrap_err("feed failure")?;
+    Ok(Fetched { body, at, stale })
+}
+
+/// Parse an RSS 2.0, RSS 1.0, or Atom document.
+///
+/// Items without a title are skipped.
+pub fn parse(xml: &str) -> Result<Feed> {
+    // feeds rarely have a DTD, but it isn't worth failing on one
+    let opts = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let doc = roxmltree::Document::parse_with_options(xml, opts)
+        .into_diagnostic()
+        .wrap_err("invalid XML")?;
+    let root = doc.root_element();
+    let name = root.tag_name();
+    match (name.namespace(), name.name()) {
+        (None, "rss") => {
+            let channel =
+                child(root, None, "channel").ok_or_else(|| miette!("RSS feed has no <channel>"))?;
+            Ok(rss(channel, channel, None))
+        }
+        (Some(RDF), "RDF") => {
+            let channel = child(root, Some(RSS1), "channel")
+                .ok_or_else(|| miette!("RSS feed has no <channel>"))?;
+            // the items follow the channel rather than being in it
+            Ok(rss(channel, root, Some(RSS1)))
+        }
+        (Some(ATOM), "feed") => Ok(atom(root)),
+        _ => Err(miette!("not an RSS or Atom feed")),
+    }
+}
+
+const ATOM: &str = "http://www.w3.org/2005/Atom";
+const RSS1: &str = "http://purl.org/rss/1.0/";
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const DC: &str = "http://purl.org/dc/elements/1.1/";
+
+/// The `channel`'s title and the items in `items`, whose elements are in the namespace `ns`.
+fn rss(channel: Node, items: Node, ns: Option<&str>) -> Feed {
+    let items = children(items, ns, "item")
+        .filter_map(|item| {
+            let title = child(item, ns, "title")
+                .map(text)
+                .filter(|x| !x.is_empty())?;
+            let published = child(item, ns, "pubDate")
+                .and_then(|x| OffsetDateTime::parse(text(x).trim(), &Rfc2822).ok())
+                .or_else(|| {
+                    // RSS 1.0
+                    child(item, Some(DC), "date")
+                        .and_then(|x| OffsetDateTime::parse(text(x).trim(), &Rfc3339).ok())
+                });
+            let link = child(item, ns, "link").map(text).filter(|x| !x.is_empty());
+            Some(Item {
+                title,
+                link,
+                published,
+            })
+        })
+        .collect();
+    Feed {
+        title: child(channel, ns, "title").map(text),
+        items,
+    }
+}
+
+fn atom(feed: Node) -> Feed {
+    let items = children(feed, Some(ATOM), "entry")
+        .filter_map(|entry| {
+            let title = child(entry, Some(ATOM), "title")
+                .map(text)
+                .filter(|x| !x.is_empty())?;
+            let published = child(entry, Some(ATOM), "published")
+                .or_else(|| child(entry, Some(ATOM), "updated"))
+                .and_then(|x| OffsetDateTime::parse(text(x).trim(), &Rfc3339).ok());
+            Some(Item {
+                title,
+                link: atom_link(entry),
+                published,
+            })
+        })
+        .collect();
+    Feed {
+        title: child(feed, Some(ATOM), "title").map(text),
+        items,
+    }
+}
+
+type Node<'a, 'input> = roxmltree::Node<'a, 'input>;
+
+/// The child elements called `name` in the namespace `ns`, so `media:title` isn't taken for
+/// `title`.
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    ns: Option<&'a str>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |x| x.is_element() && x.tag_name().namespace() == ns)
+        .filter(move |x| x.tag_name().name() == name)
+}
+
+fn child<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    ns: Option<&'a str>,
+    name: &'a str,
+) -> Option<Node<'a, 'input>> {
+    children(node, ns, name).next()
+}
+
+/// The `href` of an Atom entry's alternate link, or its first link.
+fn atom_link(entry: Node) -> Option<String> {
+    let links = children(entry, Some(ATOM), "link").collect::<Vec<_>>();
+    links
+        .iter()
+        .find(|x| matches!(x.attribute("rel"), None | Some("alternate")))
+        .or(links.first())
+        .and_then(|x| x.attribute("href"))
+        .map(str::to_string)
+}
+
+/// The text of an element, dropping any markup escaped into it, and decoding the HTML entities
+/// escaped with it, as titles are often HTML.
+fn text(node: Node) -> String {
+    let raw = node
+        .descendants()
+        .filter(|x| x.is_text())
+        .filter_map(|x| x.text())
+        .collect::<String>();
+    let s = decode(&strip_tags(&raw));
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Drop the tags in `s`, a `<` which doesn't start one, such as in `x < y`, being kept.
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut in_tag = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '<' if !in_tag
+                && chars
+                    .peek()
+                    .is_some_and(|x| x.is_ascii_alphabetic() || *x == '/' || *x == '!') =>
+            {
+                in_tag = true
+            }
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Replace the XML entities, and the HTML ones common in headlines.
+fn decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let Some(end) = rest.find(';').filter(|x| *x < 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            "hellip" => Some('…'),
+            x => x
+                .strip_prefix("#x")
+                .or_else(|| x.strip_prefix("#X"))
+                .and_then(|x| u32::from_str_radix(x, 16).ok())
+                .or_else(|| x.strip_prefix('#').and_then(|x| x.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn rss_items() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/">
+<channel>
+  <title>ABC News</title>
+  <link>https://abc.net.au/news</link>
+  <item>
+    <title><![CDATA[Storms &amp; hail <b>lash</b> the coast]]></title>
+    <media:title>not this</media:title>
+    <link>https://abc.net.au/news/1</link>
+    <pubDate>Fri, 21 Jun 2024 08:00:00 +1000</pubDate>
+  </item>
+  <item>
+    <title>Rates on hold &#8212; for &quot;now&quot;</title>
+    <link/>
+  </item>
+  <item><description>no title</description></item>
+</channel>
+</rss>"#;
+        let feed = parse(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("ABC News"));
+        assert_eq!(
+            feed.items,
+            [
+                Item {
+                    title: "Storms & hail lash the coast".into(),
+                    link: Some("https://abc.net.au/news/1".into()),
+                    published: Some(datetime!(2024-06-21 08:00 +10)),
+                },
+                Item {
+                    title: "Rates on hold — for \"now\"".into(),
+                    link: None,
+                    published: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn atom_entries() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title type="text">Example</title>
+  <entry>
+    <title type="text">Tom &amp; Jerry</title>
+    <link rel="self" href="https://example.com/self"/>
+    <link rel="alternate" href="https://example.com/a?x=1&amp;y=2"/>
+    <updated>2024-06-20T22:00:00Z</updated>
+  </entry>
+</feed>"#;
+        let feed = parse(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example"));
+        assert_eq!(
+            feed.items,
+            [Item {
+                title: "Tom & Jerry".into(),
+                link: Some("https://example.com/a?x=1&y=2".into()),
+                published: Some(datetime!(2024-06-20 22:00 UTC)),
+            }]
+        );
+        assert!(parse("<html></html>").is_err());
+    }
+
+    #[test]
+    fn rdf_items() {
+        let xml = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+  xmlns="http://purl.org/rss/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel rdf:about="https://example.com/">
+    <title>Slashdot</title>
+  </channel>
+  <item rdf:about="https://example.com/1">
+    <title>Is x &lt; y?</title>
+    <link>https://example.com/1</link>
+    <dc:date>2024-06-21T06:00:00+00:00</dc:date>
+  </item>
+</rdf:RDF>"#;
+        let feed = parse(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Slashdot"));
+        assert_eq!(
+            feed.items,
+            [Item {
+                title: "Is x < y?".into(),
+                link: Some("https://example.com/1".into()),
+                published: Some(datetime!(2024-06-21 06:00 UTC)),
+            }]
+        );
+    }
+
+    #[test]
+    fn markup_in_text() {
+        assert_eq!(
+            strip_tags("<p>a <i>b</i></p> x < y <!-- c -->"),
+            "a b x < y "
+        );
+        assert!(parse("<rss><channel><title>unclosed</channel></rss>").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Layouts described in the config, rows of columns of named elements.
+//!
+//! ```toml
+//! [[layouts]]
+//! name = "kitchen"
+//! [[layouts.rows]]
+//! height = 0.1
+//! columns = [{ widget = "header" }]
+//! [[layouts.rows]]
+//! columns = [{ widget = "month", width = 2 }, { widget = "agenda" }]
+//! ```
+//!
+//! An element is `header`, `notes`, the name of a mode, or the id of a registered widget.
+//! Each template is registered as a mode under its name, so it is selected by `pages`.
+use super::{modes::Modes, registry::Registry, Layout};
+use crate::{data::Model, render::Render};
+use egui::{pos2, Rect, Ui};
+use miette::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub rows: Vec<Row>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Row {
+    /// Relative to the other rows.
+    #[serde(default = "one")]
+    pub height: f32,
+    pub columns: Vec<Cell>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cell {
+    pub widget: String,
+    /// Relative to the other columns in the row.
+    #[serde(default = "one")]
+    pub width: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+impl Template {
+    /// Check each element is known. Templates cannot nest other templates.
+    pub fn validate(&self, modes: &Modes, widgets: &Registry) -> Result<()> {
+        let Self { name, rows } = self;
+        if modes.get(name).is_some() {
+            return Err(miette!(
+                help = "give the layout a name of its own",
+                "layout '{name}' has the name of another mode, which it would replace"
+            ));
+        }
+        if rows.is_empty() {
+            return Err(miette!("layout '{name}' has no rows"));
+        }
+        for row in rows {
+            if row.columns.is_empty() || row.height <= 0.0 {
+                return Err(miette!(
+                    "layout '{name}' has an empty row, each needs a positive height and columns"
+                ));
+            }
+            for Cell { widget, width } in &row.columns {
+                let known = matches!(widget.as_str(), "header" | "notes")
+                    || modes.get(widget).is_some_and(|x| x.chrome())
+                    || widgets.get(widget).is_some();
+                if !known {
+                    return Err(miette!(
+                        help = format!(
+                            "elements are header, notes, a mode ({}), or a widget ({})",
+                            modes.names().collect::<Vec<_>>().join(", "),
+                            widgets.ids().collect::<Vec<_>>().join(", ")
+                        ),
+                        "layout '{name}' has an unknown element '{widget}'"
+                    ));
+                }
+                if *width <= 0.0 {
+                    return Err(miette!(
+                        "layout '{name}' element '{widget}' needs a positive width"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Divide `area` proportionally into each element's rect.
+    pub fn cells(&self, area: Rect) -> Vec<(Rect, &str)> {
+        let total = self.rows.iter().map(|r| r.height).sum::<f32>();
+        let mut top = area.top();
+        let mut cells = Vec::new();
+        for row in &self.rows {
+            let bottom = top + area.height() * row.height / total;
+            let total = row.columns.iter().map(|c| c.width).sum::<f32>();
+            let mut left = area.left();
+            for cell in &row.columns {
+                let right = left + area.width() * cell.width / total;
+                cells.push((
+                    Rect::from_min_max(pos2(left, top), pos2(right, bottom)),
+                    cell.widget.as_str(),
+                ));
+                left = right;
+            }
+            top = bottom;
+        }
+        cells
+    }
+}
+
+/// Renders a [`Template`] over the whole frame.
+pub struct Composed(pub Template);
+
+impl Render<(&Layout, Model)> for Composed {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let area = ui.available_rect_before_wrap();
+        for (rect, element) in self.0.cells(area) {
+            let mut ui = ui.child_ui(rect, egui::Layout::top_down(egui::Align::Min));
+            ui.set_clip_rect(rect.intersect(ui.clip_rect()));
+            match element {
+                "header" => layout.render_header(&mut ui, &model),
+                "notes" => layout.all_notes().render_block(&mut ui, layout.mode_zoom()),
+                x => {
+                    if let Some(mode) = layout.modes.get(x) {
+                        mode.render(&mut ui, (layout, model.clone()));
+                    } else if let Some(w) = layout.widgets.get(x) {
+                        ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
+                            w.render(ui, &model, layout)
+                        });
+                    }
+                }
+            }
+        }
+        ui.allocate_rect(area, egui::Sense::hover());
+    }
+}
+
+impl super::modes::View for Composed {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn inset(&self) -> bool {
+        false
+    }
+
+    fn chrome(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(toml: &str) -> Template {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn proportional_cells() {
+        let t = template(
+            r#"
+            name = "kitchen"
+            [[rows]]
+            columns = [{ widget = "header" }]
+            [[rows]]
+            height = 3
+            columns = [{ widget = "month", width = 3 }, { widget = "agenda" }]
+            "#,
+        );
+        let cells = t.cells(Rect::from_min_max(pos2(0.0, 0.0), pos2(800.0, 400.0)));
+        let cells = cells
+            .iter()
+            .map(|(r, w)| (*w, [r.left(), r.top(), r.right(), r.bottom()]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            [
+                ("header", [0.0, 0.0, 800.0, 100.0]),
+                ("month", [0.0, 100.0, 600.0, 400.0]),
+                ("agenda", [600.0, 100.0, 800.0, 400.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_elements() {
+        let mut modes = Modes::builtin();
+        let widgets = Registry::builtin();
+        let t = template(
+            r#"
+            name = "kitchen"
+            [[rows]]
+            columns = [{ widget = "header" }, { widget = "weather-strip" }]
+            [[rows]]
+            columns = [{ widget = "agenda" }, { widget = "notes" }]
+            "#,
+        );
+        assert!(t.validate(&modes, &widgets).is_ok());
+
+        let nested = template(
+            r#"
+            name = "hall"
+            [[rows]]
+            columns = [{ widget = "kitchen" }]
+            "#,
+        );
+        modes.register(Composed(t));
+        assert!(nested.validate(&modes, &widgets).is_err());
+
+        let zero = template(
+            r#"
+            name = "hall"
+            [[rows]]
+            height = 0
+            columns = [{ widget = "month" }]
+            "#,
+        );
+        assert!(zero.validate(&modes, &widgets).is_err());
+
+        let builtin = template(
+            r#"
+            name = "month"
+            [[rows]]
+            columns = [{ widget = "agenda" }]
+            "#,
+        );
+        assert!(builtin.validate(&modes, &widgets).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Drawing helpers which survive the trip to a 16 grey e-ink panel.
+//!
+//! Subtle greys and anti-aliased edges dither into noise, so these helpers build visual hierarchy
+//! out of solid black/white patterns instead, snapped to whole pixels.
+use egui::{pos2, vec2, Color32, Painter, Rect, Rounding, Shape, Stroke, Ui};
+
+/// A pattern to fill a region with.
+#[derive(Copy, Clone, Debug)]
+pub enum Fill {
+    Solid(Color32),
+    /// Diagonal lines, `spacing` points apart.
+    Hatch {
+        spacing: f32,
+        stroke: Stroke,
+    },
+    /// A grid of dots, `spacing` points apart, with alternate rows offset.
+    Stipple {
+        spacing: f32,
+        radius: f32,
+        colour: Color32,
+    },
+}
+
+impl Fill {
+    pub fn paint(self, painter: &Painter, rect: Rect) {
+        match self {
+            Fill::Solid(c) => painter.rect_filled(snap_rect(painter, rect), Rounding::ZERO, c),
+            Fill::Hatch { spacing, stroke } => hatch(painter, rect, spacing, stroke),
+            Fill::Stipple {
+                spacing,
+                radius,
+                colour,
+            } => stipple(painter, rect, spacing, radius, colour),
+        }
+    }
+}
+
+/// Fill `rect` with 45° lines, `spacing` points apart.
+pub fn hatch(painter: &Painter, rect: Rect, spacing: f32, stroke: impl Into<Stroke>) {
+    let lines = hatch_shapes(painter, rect, spacing, stroke);
+    painter
+        .with_clip_rect(rect.intersect(painter.clip_rect()))
+        .extend(lines);
+}
+
+/// The lines of [`hatch`], which overrun `rect` and need clipping.
+/// Useful to fill a placeholder shape once the size of a region is known.
+pub fn hatch_shapes(
+    painter: &Painter,
+    rect: Rect,
+    spacing: f32,
+    stroke: impl Into<Stroke>,
+) -> Vec<Shape> {
+    let stroke = snap_stroke(painter, stroke.into());
+    let spacing = spacing.max(stroke.width * 2.0);
+    let h = rect.height();
+    let mut x = rect.left() - h;
+    let mut lines = Vec::new();
+    while x < rect.right() {
+        lines.push(Shape::line_segment(
+            [pos2(x, rect.bottom()), pos2(x + h, rect.top())],
+            stroke,
+        ));
+        x += spacing;
+    }
+    lines
+}
+
+/// Fill `rect` with a dot pattern, `spacing` points apart.
+pub fn stipple(painter: &Painter, rect: Rect, spacing: f32, radius: f32, colour: Color32) {
+    let spacing = spacing.max(radius * 2.0 + 1.0);
+    let painter = painter.with_clip_rect(rect.intersect(painter.clip_rect()));
+    let mut y = rect.top() + spacing * 0.5;
+    let mut odd = false;
+    while y < rect.bottom() {
+        let mut x = rect.left() + if odd { spacing } else { spacing * 0.5 };
+        while x < rect.right() {
+            let c = painter.round_pos_to_pixels(pos2(x, y));
+            painter.circle_filled(c, radius, colour);
+            x += spacing;
+        }
+        y += spacing;
+        odd = !odd;
+    }
+}
+
+/// A rounded box with pixel snapped edges and a whole pixel stroke width, optionally filled.
+pub fn rounded_box(
+    painter: &Painter,
+    rect: Rect,
+    rounding: f32,
+    stroke: impl Into<Stroke>,
+    fill: Option<Fill>,
+) {
+    let rect = snap_rect(painter, rect);
+    let rounding = Rounding::same(painter.round_to_pixel(rounding));
+    if let Some(fill) = fill {
+        match fill {
+            Fill::Solid(c) => painter.rect_filled(rect, rounding, c),
+            // patterns do not follow the rounded corners, inset them a little
+            f => f.paint(painter, rect.shrink(rounding.nw * 0.5)),
+        }
+    }
+    painter.rect_stroke(rect, rounding, snap_stroke(painter, stroke.into()));
+}
+
+/// A full width horizontal line, taking up `thickness` plus some padding of vertical space.
+pub fn separator(ui: &mut Ui, thickness: f32, colour: Color32) {
+    let pad = thickness.max(1.0) * 2.0;
+    let (rect, _) = ui.allocate_exact_size(
+        vec2(ui.available_width(), thickness + pad * 2.0),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter();
+    let stroke = snap_stroke(painter, Stroke::new(thickness, colour));
+    let y = painter.round_to_pixel(rect.center().y);
+    painter.hline(rect.x_range(), y, stroke);
+}
+
+fn snap_rect(painter: &Painter, rect: Rect) -> Rect {
+    Rect::from_min_max(
+        painter.round_pos_to_pixels(rect.min),
+        painter.round_pos_to_pixels(rect.max),
+    )
+}
+
+fn snap_stroke(painter: &Painter, stroke: Stroke) -> Stroke {
+    let px = 1.0 / painter.ctx().pixels_per_point();
+    let width = (stroke.width / px).round().max(1.0) * px;
+    Stroke::new(width, stroke.color)
+}
@@ -0,0 +1,168 @@
+//! Golden image tests, rendering the modes with the fixture model and comparing against the
+//! reference PNGs in `golden/`, so layout regressions are caught before deploying.
+//!
+//! `PICAL_BLESS=1 cargo test golden` writes the references from the renders, for a new case or
+//! after an intended change, review them and check them in. A case without a reference is skipped
+//! with a note to bless it. A case which fails leaves a `<case>.diff.png` comparison beside its
+//! reference.
+use super::Layout;
+use crate::{
+    data::fixture,
+    render::{paint_gray, Render},
+};
+use image::GrayImage;
+use std::path::{Path, PathBuf};
+use time::macros::datetime;
+
+/// Pixels differing by more than this many grey levels count as changed, absorbing rounding
+/// differences between platforms.
+const LEVEL_TOLERANCE: u8 = 24;
+/// The fraction of pixels which may change before a case fails.
+const MAX_CHANGED: f64 = 0.001;
+
+struct Case {
+    name: &'static str,
+    mode: &'static str,
+    size: [u32; 2],
+    zoom: f32,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "twelve-day",
+        mode: "twelve-day",
+        size: [800, 600],
+        zoom: 1.0,
+    },
+    Case {
+        name: "month",
+        mode: "month",
+        size: [800, 600],
+        zoom: 1.0,
+    },
+    Case {
+        name: "agenda",
+        mode: "agenda",
+        size: [800, 600],
+        zoom: 1.0,
+    },
+    Case {
+        name: "timeline",
+        mode: "timeline",
+        size: [800, 600],
+        zoom: 1.0,
+    },
+    Case {
+        name: "busy",
+        mode: "busy",
+        size: [800, 600],
+        zoom: 1.0,
+    },
+    Case {
+        name: "month-portrait-zoomed",
+        mode: "month",
+        size: [600, 800],
+        zoom: 1.4,
+    },
+];
+
+fn dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("app/layout/golden")
+}
+
+fn render(case: &Case) -> GrayImage {
+    let now = datetime!(2024-06-21 9:30 +10);
+    let mut layout = Layout {
+        zoom: case.zoom,
+        now,
+        ..Default::default()
+    };
+    layout.mode = layout.modes.get(case.mode).expect("a built in mode");
+    let model = fixture::model(now);
+    let [w, h] = case.size;
+    paint_gray(w, h, 1.0, |ctx| {
+        ctx.set_visuals(egui::Visuals::light());
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(egui::Color32::WHITE))
+            .show(ctx, |ui| layout.render(ui, model));
+    })
+    .img
+}
+
+/// Compare the case against its reference, or write the reference if blessing.
+fn check(case: &Case, bless: bool) -> Result<(), String> {
+    let img = render(case);
+    let path = dir().join(format!("{}.png", case.name));
+    let diff = dir().join(format!("{}.diff.png", case.name));
+    let _ = std::fs::remove_file(&diff);
+
+    if bless {
+        std::fs::create_dir_all(dir()).map_err(|e| e.to_string())?;
+        return img.save(&path).map_err(|e| e.to_string());
+    }
+    if !path.exists() {
+        // the references are rendered on a desktop, so a fresh checkout may not have them yet
+        eprintln!(
+            "skipping {}: no golden image at {}, write it with PICAL_BLESS=1",
+            case.name,
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let golden = image::open(&path)
+        .map_err(|e| format!("{}: {e}", path.display()))?
+        .into_luma8();
+    let changed = changed(&golden, &img);
+    let total = f64::from(img.width()) * f64::from(img.height());
+    if changed as f64 <= total * MAX_CHANGED {
+        return Ok(());
+    }
+    crate::compare::compare(&golden, &img)
+        .img
+        .save(&diff)
+        .map_err(|e| e.to_string())?;
+    Err(format!(
+        "{}: {changed} pixels changed ({:.2}%), see {}",
+        case.name,
+        changed as f64 * 100.0 / total,
+        diff.display()
+    ))
+}
+
+/// Pixels which differ by more than the tolerance, those outside one of the images included.
+fn changed(a: &GrayImage, b: &GrayImage) -> u64 {
+    let w = a.width().max(b.width());
+    let h = a.height().max(b.height());
+    let px = |img: &GrayImage, x, y| img.get_pixel_checked(x, y).map(|p| p.0[0]);
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .filter(|&(x, y)| match (px(a, x, y), px(b, x, y)) {
+            (Some(a), Some(b)) => a.abs_diff(b) > LEVEL_TOLERANCE,
+            _ => true,
+        })
+        .count() as u64
+}
+
+#[test]
+fn golden_images() {
+    let bless = std::env::var_os("PICAL_BLESS").is_some();
+    let failures = CASES
+        .iter()
+        .filter_map(|x| check(x, bless).err())
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn tolerance() {
+    let a = GrayImage::from_pixel(10, 10, image::Luma([200]));
+    let mut b = a.clone();
+    b.put_pixel(0, 0, image::Luma([200 - LEVEL_TOLERANCE]));
+    assert_eq!(changed(&a, &b), 0);
+    b.put_pixel(1, 0, image::Luma([0]));
+    assert_eq!(changed(&a, &b), 1);
+    // the extra column counts as changed
+    let wider = GrayImage::from_pixel(11, 10, image::Luma([200]));
+    assert_eq!(changed(&a, &wider), 10);
+}
@@ -0,0 +1,177 @@
+//! Weather and moon icons painted from simple vector shapes.
+//!
+//! Emoji glyphs are anti-aliased colour bitmaps which dither into noise on a 16 grey panel, these
+//! are solid black and white shapes which stay crisp at any zoom.
+use crate::data::{moon::Phase, weather::Code};
+use egui::{pos2, vec2, Color32, Painter, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+use std::f32::consts::{PI, TAU};
+
+/// Allocate a square of `size` and paint with `f`, which gets the rect and a unit length
+/// (a 16th of the size).
+fn icon(ui: &mut Ui, size: f32, f: impl FnOnce(&Painter, Rect, f32)) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(size), Sense::hover());
+    f(ui.painter(), rect, size / 16.0)
+}
+
+pub fn weather(ui: &mut Ui, code: Code, size: f32) {
+    icon(ui, size, |p, rect, u| {
+        let c = rect.center();
+        let stroke = Stroke::new(u, Color32::BLACK);
+        match code {
+            Code::ClearSky => sun(p, c, 4.0 * u, u),
+            Code::MainlyClear => {
+                sun(p, c - vec2(u, u), 3.5 * u, u);
+                cloud(p, c + vec2(3.0 * u, 4.0 * u), 4.0 * u, u);
+            }
+            Code::PartlyCloudy => {
+                sun(p, c - vec2(2.5 * u, 2.5 * u), 3.0 * u, u);
+                cloud(p, c + vec2(u, 2.0 * u), 6.0 * u, u);
+            }
+            Code::Overcast => {
+                cloud(p, c + vec2(2.0 * u, -2.5 * u), 5.0 * u, u);
+                cloud(p, c + vec2(-u, 1.5 * u), 7.0 * u, u);
+            }
+            Code::Fog => {
+                cloud(p, c - vec2(0.0, 2.5 * u), 6.0 * u, u);
+                for (dy, inset) in [(3.0, 1.0), (5.0, 2.5), (7.0, 1.0)] {
+                    let y = c.y + dy * u;
+                    p.hline(
+                        (rect.left() + inset * u)..=(rect.right() - inset * u),
+                        y,
+                        stroke,
+                    );
+                }
+            }
+            Code::Drizzle => {
+                cloud(p, c - vec2(0.0, 2.5 * u), 6.0 * u, u);
+                for dx in [-3.0, 0.0, 3.0] {
+                    p.circle_filled(c + vec2(dx * u, 5.0 * u), 0.8 * u, Color32::BLACK);
+                }
+            }
+            Code::Rain => {
+                cloud(p, c - vec2(0.0, 2.5 * u), 6.0 * u, u);
+                for dx in [-3.0, 0.0, 3.0] {
+                    let top = c + vec2(dx * u, 3.0 * u);
+                    p.line_segment([top, top + vec2(-1.5 * u, 4.0 * u)], stroke);
+                }
+            }
+            Code::Snow => {
+                cloud(p, c - vec2(0.0, 2.5 * u), 6.0 * u, u);
+                for dx in [-3.5, 0.0, 3.5] {
+                    flake(p, c + vec2(dx * u, 5.5 * u), 1.5 * u, stroke);
+                }
+            }
+            Code::Thuderstorm => {
+                cloud(p, c - vec2(0.0, 2.5 * u), 6.0 * u, u);
+                let bolt = [(0.5, 2.0), (-1.5, 5.0), (0.5, 5.0), (-1.0, 8.0)]
+                    .map(|(x, y)| c + vec2(x * u, y * u))
+                    .to_vec();
+                p.add(Shape::line(bolt, Stroke::new(1.3 * u, Color32::BLACK)));
+            }
+        }
+    });
+}
+
+/// A filled disc with rays.
+fn sun(p: &Painter, c: Pos2, r: f32, u: f32) {
+    let stroke = Stroke::new(u, Color32::BLACK);
+    p.circle_filled(c, r * 0.6, Color32::BLACK);
+    for i in 0..8 {
+        let d = Vec2::angled(i as f32 * TAU / 8.0);
+        p.line_segment([c + d * r * 0.8, c + d * r * 1.2], stroke);
+    }
+}
+
+/// A white cloud outlined in black, `w` wide, with the base centred on `c`.
+fn cloud(p: &Painter, c: Pos2, w: f32, u: f32) {
+    let puffs = [
+        (-0.25, -0.05, 0.25),
+        (0.05, -0.25, 0.32),
+        (0.3, -0.05, 0.22),
+    ]
+    .map(|(x, y, r)| (c + vec2(x * w, y * w), r * w));
+    let base = Rect::from_min_max(c + vec2(-0.5 * w, -0.2 * w), c + vec2(0.5 * w, 0.15 * w));
+    // the outline is the union of the shapes painted larger in black, then filled in white
+    for (colour, grow) in [(Color32::BLACK, u), (Color32::WHITE, 0.0)] {
+        for (pc, r) in puffs {
+            p.circle_filled(pc, r + grow, colour);
+        }
+        p.rect_filled(base.expand(grow), 0.15 * w + grow, colour);
+    }
+}
+
+/// An asterisk snowflake.
+fn flake(p: &Painter, c: Pos2, r: f32, stroke: Stroke) {
+    for i in 0..3 {
+        let d = Vec2::angled(i as f32 * PI / 3.0 + PI / 2.0) * r;
+        p.line_segment([c - d, c + d], stroke);
+    }
+}
+
+pub fn moon(ui: &mut Ui, phase: Phase, size: f32) {
+    use Phase::*;
+    let (lit, waxing) = match phase {
+        NewMoon => (0.0, true),
+        WaxingCrescent => (0.25, true),
+        FirstQuarter => (0.5, true),
+        WaxingGibbous => (0.75, true),
+        FullMoon => (1.0, true),
+        WaningGibbous => (0.75, false),
+        ThirdQuarter => (0.5, false),
+        WaningCrescent => (0.25, false),
+    };
+    icon(ui, size, |p, rect, u| {
+        let c = rect.center();
+        let r = 7.0 * u;
+        // waxing moons are lit on the right, as seen from the northern hemisphere
+        let lit_side = if waxing { 1.0 } else { -1.0 };
+        // paint the convex part of the disc over the other, the lit part is convex once gibbous
+        let (bg, fg, side, bulge) = if lit <= 0.5 {
+            (Color32::WHITE, Color32::BLACK, -lit_side, 1.0 - 2.0 * lit)
+        } else {
+            (Color32::BLACK, Color32::WHITE, lit_side, 2.0 * lit - 1.0)
+        };
+        p.circle_filled(c, r, bg);
+        p.add(Shape::convex_polygon(
+            lune(c, r, side, bulge),
+            fg,
+            Stroke::NONE,
+        ));
+        p.circle_stroke(c, r, Stroke::new(u, Color32::BLACK));
+    });
+}
+
+/// The half disc on `side` (-1 left, 1 right), extended by a half ellipse `bulge` of the radius
+/// across to the other side. The result is convex.
+fn lune(c: Pos2, r: f32, side: f32, bulge: f32) -> Vec<Pos2> {
+    const N: usize = 24;
+    let limb = (0..=N).map(|i| {
+        let a = PI * i as f32 / N as f32 - PI / 2.0;
+        pos2(c.x + side * r * a.cos(), c.y + r * a.sin())
+    });
+    let terminator = (1..N).rev().map(|i| {
+        let a = PI * i as f32 / N as f32 - PI / 2.0;
+        pos2(c.x - side * bulge * r * a.cos(), c.y + r * a.sin())
+    });
+    limb.chain(terminator).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lune_extents() {
+        let c = pos2(10.0, 10.0);
+        let xs = |pts: Vec<Pos2>| {
+            let min = pts.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+            let max = pts.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+            (min.round(), max.round())
+        };
+        // a half disc on the right
+        assert_eq!(xs(lune(c, 5.0, 1.0, 0.0)), (10.0, 15.0));
+        // a crescent's dark side bulging halfway into the lit left
+        assert_eq!(xs(lune(c, 5.0, 1.0, 0.5)), (8.0, 15.0));
+        assert_eq!(xs(lune(c, 5.0, -1.0, 1.0)), (5.0, 15.0));
+    }
+}
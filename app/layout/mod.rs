@@ -0,0 +1,1546 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+pub mod composer;
+pub mod draw;
+#[cfg(test)]
+mod golden;
+pub mod icon;
+pub mod modes;
+pub mod registry;
+pub mod theme;
+pub mod widgets;
+
+pub use modes::Mode;
+
+use crate::{
+    data::{
+        annual, battery,
+        cal::{Calendar, Event},
+        Model,
+    },
+    render::Render,
+};
+use egui::{vec2, Align, Color32, Frame, Label, RichText, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date, OffsetDateTime, Weekday};
+
+fn size_fonts(styles: &mut BTreeMap<egui::TextStyle, egui::FontId>, zoom: f32) {
+    use egui::TextStyle::*;
+    let f = egui::FontId::proportional;
+
+    styles.insert(Small, f(9.0 * zoom));
+    styles.insert(Body, f(12.5 * zoom));
+    styles.insert(Monospace, f(12.0 * zoom));
+    styles.insert(Button, f(12.5 * zoom));
+    styles.insert(Heading, f(18.0 * zoom));
+}
+
+#[derive(Clone)]
+pub struct Layout {
+    pub zoom: f32,
+    pub now: OffsetDateTime,
+    pub mode: Mode,
+    /// The modes which can be selected by name, see [`modes::Modes`].
+    pub modes: modes::Modes,
+    /// A tiny annotation painted in the bottom right corner of the frame.
+    pub footer: Option<String>,
+    pub notes: widgets::Notes,
+    pub countdowns: Vec<widgets::Countdown>,
+    pub pictures: Vec<widgets::Picture>,
+    /// Data older than this gets a prominent warning.
+    pub stale_after: Duration,
+    /// The most recent background failure, shown in a banner.
+    pub failure: Option<Failure>,
+    pub widgets: registry::Registry,
+    /// Ids of the widgets shown on the right of the header, from the right.
+    pub header: Vec<String>,
+    /// Applied to the painted frame, see [`theme::Theme`].
+    pub theme: theme::Theme,
+    /// Calendar holding `pical:` control events, see [`crate::control::directive`].
+    pub control_calendar: Option<String>,
+    /// Shown full screen instead of the calendar, set by a control event.
+    pub photo: Option<PathBuf>,
+    /// Next month at a glance, shown in the modes which are not a month.
+    pub inset: Option<widgets::MiniMonth>,
+    /// Annual dates, which show a banner in the header as they approach.
+    pub annual: Vec<annual::Annual>,
+    /// The `[start, end]` hours of the timeline mode's axis.
+    pub timeline_hours: [u8; 2],
+    /// How event times are shown in the day cells.
+    pub event_times: EventTimes,
+}
+
+/// How an event's time is shown in a day cell.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventTimes {
+    /// `09:00`
+    #[default]
+    Start,
+    /// `09:00–10:30`
+    Range,
+    /// `09:00 1h30`
+    Duration,
+}
+
+impl EventTimes {
+    /// The time label of an event starting on the cell's day, falling back to the start for
+    /// events which run past the day.
+    pub fn label(self, start: OffsetDateTime, end: OffsetDateTime) -> String {
+        let hm = |t: OffsetDateTime| format!("{:02}:{:02}", t.hour(), t.minute());
+        let midnight = start.replace_time(time::Time::MIDNIGHT) + time::Duration::DAY;
+        let same_day = end.date() == start.date() || end == midnight;
+        match self {
+            _ if end <= start || !same_day => hm(start),
+            EventTimes::Start => hm(start),
+            EventTimes::Range => format!("{}–{}", hm(start), hm(end)),
+            EventTimes::Duration => {
+                let mins = (end - start).whole_minutes();
+                match (mins / 60, mins % 60) {
+                    (0, m) => format!("{} {m}m", hm(start)),
+                    (h, 0) => format!("{} {h}h", hm(start)),
+                    (h, m) => format!("{} {h}h{m:02}", hm(start)),
+                }
+            }
+        }
+    }
+}
+
+/// The default header widgets, of those built in.
+pub fn default_header() -> Vec<String> {
+    let widgets = registry::Registry::builtin();
+    ["battery", "weather", "air-quality", "moon"]
+        .into_iter()
+        .filter(|x| widgets.get(x).is_some())
+        .map(String::from)
+        .collect()
+}
+
+/// A failure in a background task, such as fetching or pushing a frame.
+#[derive(Clone)]
+pub struct Failure {
+    /// The task that failed, the failure is cleared when it next succeeds.
+    pub task: &'static str,
+    pub message: String,
+    pub at: Instant,
+}
+
+/// Data older than this gets a subtle indicator, it is well past any fetch cadence.
+const STALE_SUBTLE: Duration = Duration::from_secs(60 * 60);
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            now: OffsetDateTime::now_utc(),
+            mode: Mode::new(Month),
+            modes: modes::Modes::builtin(),
+            footer: None,
+            notes: Default::default(),
+            countdowns: Vec::new(),
+            pictures: Vec::new(),
+            stale_after: Duration::from_secs(3 * 60 * 60),
+            failure: None,
+            widgets: registry::Registry::builtin(),
+            header: default_header(),
+            theme: Default::default(),
+            control_calendar: None,
+            photo: None,
+            inset: None,
+            annual: Vec::new(),
+            timeline_hours: [7, 19],
+            event_times: EventTimes::Start,
+        }
+    }
+}
+
+impl Layout {
+    /// The zoom scaled to suit the current mode.
+    pub fn mode_zoom(&self) -> f32 {
+        self.zoom * self.mode.zoom()
+    }
+}
+
+/// The photo at `path` as a screen, only decoded again once the file changes.
+#[cfg(feature = "photo-mode")]
+fn photo(path: &std::path::Path) -> miette::Result<widgets::Screen> {
+    use miette::{IntoDiagnostic, WrapErr};
+    use std::{
+        sync::{Mutex, PoisonError},
+        time::SystemTime,
+    };
+
+    static CACHE: Mutex<Option<(PathBuf, SystemTime, widgets::Screen)>> = Mutex::new(None);
+    let modified = std::fs::metadata(path)
+        .and_then(|x| x.modified())
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to open image {}", path.display()))?;
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*cache {
+        Some((p, m, screen)) if p == path && *m == modified => Ok(screen.clone()),
+        _ => {
+            let screen = widgets::Screen::load(&widgets::ScreenConfig {
+                image: Some(path.to_path_buf()),
+                text: None,
+            })?;
+            *cache = Some((path.to_path_buf(), modified, screen.clone()));
+            Ok(screen)
+        }
+    }
+}
+
+impl Render<Model> for Layout {
+    fn render(&self, ui: &mut Ui, model: Model) {
+        let zoom = self.mode_zoom();
+        #[cfg(feature = "photo-mode")]
+        if let Some(path) = &self.photo {
+            match photo(path) {
+                Ok(screen) => return screen.render(ui, (self.zoom, &[])),
+                Err(e) => log::warn!("{e:?}"),
+            }
+        }
+        size_fonts(&mut ui.style_mut().text_styles, zoom);
+
+        // views which place their own header and notes are given the whole frame
+        let chrome = self.mode.chrome();
+        if chrome {
+            ui.set_height(20.0 * zoom);
+            self.render_header(ui, &model);
+        }
+
+        let notes = self.all_notes();
+        let stale = model
+            .stalest()
+            .map(|(src, at)| (src.to_string(), Instant::now().duration_since(at)))
+            .filter(|(_, age)| *age > STALE_SUBTLE);
+
+        use widgets::Position::{Bottom, Top};
+        if chrome {
+            notes.render_strip(ui, Top, zoom);
+        }
+        let body = ui.available_rect_before_wrap();
+        let bottom = if chrome {
+            notes.strip_height(Bottom, zoom)
+        } else {
+            0.0
+        };
+        ui.allocate_ui(vec2(body.width(), body.height() - bottom), |ui| {
+            self.mode.render(ui, (self, model.clone()));
+        });
+        if chrome {
+            notes.render_strip(ui, Bottom, zoom);
+            notes.paint_corners(ui, body, zoom);
+        }
+        if let Some(inset) = self.inset.as_ref().filter(|_| self.mode.inset()) {
+            let next = end_of_month(self.now.date())
+                .next_day()
+                .expect("not the end of time");
+            let area = body.with_max_y(body.bottom() - bottom);
+            let busy = |day| model.cals.values().flatten().any(|e| e.covers(day));
+            inset.paint(ui, area, next, busy, self.zoom);
+        }
+        for p in &self.pictures {
+            p.paint(ui);
+        }
+
+        if let Some(footer) = &self.footer {
+            paint_footer(ui, footer, self.zoom);
+        }
+        if let Some((src, age)) = stale {
+            paint_stale(ui, &src, age, age > self.stale_after, self.zoom);
+        }
+        if let Some(failure) = &self.failure {
+            paint_failure(ui, failure, self.zoom);
+        }
+    }
+}
+
+impl Layout {
+    /// The date, time, countdowns, and header widgets.
+    pub fn render_header(&self, ui: &mut Ui, model: &Model) {
+        let zoom = self.mode_zoom();
+        let header = ui.horizontal(|ui| {
+            // left
+            let date = self
+                .now
+                .format(format_description!(
+                    "[weekday] [day padding:none] [month repr:long] [year]"
+                ))
+                .unwrap_or_else(|_| "?".into());
+            ui.heading(date);
+
+            let ordinal = self
+                .now
+                .format(format_description!("[ordinal]"))
+                .unwrap_or_else(|_| "?".into());
+            ui.small(format!("Day {ordinal}"));
+
+            // center
+            ui.add_space(20. * zoom);
+            let time = self
+                .now
+                .format(format_description!("[hour repr:24]:[minute]"))
+                .unwrap_or_else(|_| "?".into());
+            ui.heading(time);
+
+            for c in self.countdowns.iter().filter(|x| x.position.is_none()) {
+                ui.add_space(20. * zoom);
+                c.render_header(ui, self.now.date());
+            }
+            for text in self.annual.iter().filter_map(|x| x.banner(self.now.date())) {
+                ui.add_space(20. * zoom);
+                ui.label(RichText::new(text).heading().strong());
+            }
+            if let Some(text) = model.weather.as_ref().and_then(|x| x.nowcast(self.now)) {
+                ui.add_space(20. * zoom);
+                ui.label(RichText::new(text).strong());
+            }
+            for text in model.weather.iter().flat_map(|x| &x.warnings) {
+                ui.add_space(20. * zoom);
+                ui.label(RichText::new(format!("⚠ {text}")).strong());
+            }
+
+            // right
+            ui.with_layout(egui::Layout::right_to_left(Align::BOTTOM), |ui| {
+                for w in self.header.iter().filter_map(|id| self.widgets.get(id)) {
+                    w.render(ui, model, self);
+                }
+            });
+        });
+        let rect = header.response.rect;
+        ui.ctx()
+            .data_mut(|d| d.insert_temp(egui::Id::new(HEADER_RECT), rect));
+    }
+
+    /// The notes, with the countdowns which are placed as notes.
+    pub fn all_notes(&self) -> widgets::Notes {
+        let mut notes = self.notes.clone();
+        let today = self.now.date();
+        notes
+            .notes
+            .extend(self.countdowns.iter().filter_map(|x| x.to_note(today)));
+        notes
+    }
+}
+
+const HEADER_RECT: &str = "pical-header-rect";
+
+/// Where the header was painted in the frame being run, if it was.
+///
+/// The rect is taken, the context is kept between frames and the next may not paint a header.
+pub fn take_header_rect(ctx: &egui::Context) -> Option<egui::Rect> {
+    let id = egui::Id::new(HEADER_RECT);
+    ctx.data_mut(|d| {
+        let rect = d.get_temp(id);
+        d.remove::<egui::Rect>(id);
+        rect
+    })
+}
+
+/// A boxed banner in the bottom left corner of the frame, with the failure message.
+fn paint_failure(ui: &mut Ui, failure: &Failure, zoom: f32) {
+    let Failure { task, message, at } = failure;
+    let painter = ui.painter();
+    let screen = ui.ctx().screen_rect();
+    let text = format!(
+        "⚠ {task} failed {}: {message}",
+        ago(Instant::now().duration_since(*at))
+    );
+    let galley = painter.layout(
+        text,
+        egui::FontId::proportional(9.0 * zoom),
+        Color32::BLACK,
+        screen.width() * 0.4,
+    );
+    let rect = egui::Align2::LEFT_BOTTOM
+        .anchor_rect(egui::Rect::from_min_size(
+            screen.left_bottom(),
+            galley.size(),
+        ))
+        .translate(vec2(4.0, -4.0) * zoom)
+        .expand(3.0 * zoom);
+    draw::rounded_box(
+        painter,
+        rect,
+        2.0 * zoom,
+        egui::Stroke::new(1.0 * zoom, Color32::BLACK),
+        Some(draw::Fill::Solid(Color32::WHITE)),
+    );
+    painter.galley(rect.shrink(3.0 * zoom).min, galley);
+}
+
+/// An indicator at the bottom of the frame of how old the data is.
+/// A `warn`ing is larger and inverted, and names the stale source.
+fn paint_stale(ui: &mut Ui, source: &str, age: Duration, warn: bool, zoom: f32) {
+    let painter = ui.painter();
+    let (text, size, fg, bg) = if warn {
+        let text = format!("⚠ {source} data from {}", ago(age));
+        (text, 12.0, Color32::WHITE, Color32::BLACK)
+    } else {
+        let text = format!("data from {}", ago(age));
+        (text, 8.0, Color32::DARK_GRAY, Color32::WHITE)
+    };
+    let galley = painter.layout_no_wrap(text, egui::FontId::proportional(size * zoom), fg);
+    let rect = egui::Align2::CENTER_BOTTOM
+        .anchor_rect(egui::Rect::from_min_size(
+            ui.ctx().screen_rect().center_bottom(),
+            galley.size(),
+        ))
+        .expand(2.0 * zoom);
+    painter.rect_filled(rect, 2.0 * zoom, bg);
+    painter.galley(rect.shrink(2.0 * zoom).min, galley);
+}
+
+/// A rough age, such as `3h ago`.
+pub fn ago(age: Duration) -> String {
+    let mins = age.as_secs() / 60;
+    match mins {
+        0..=59 => format!("{mins}m ago"),
+        60..=2879 => format!("{}h ago", mins / 60),
+        _ => format!("{}d ago", mins / (60 * 24)),
+    }
+}
+
+/// The badge of an event which started before `day`: when it ends if that is on `day`,
+/// arrows if it runs on past the day, or just the left arrow if it ends with the day.
+pub fn carried_label(end: OffsetDateTime, day: Date) -> String {
+    let midnight = time::Time::MIDNIGHT;
+    let ends_with_day = day.next_day() == Some(end.date()) && end.time() == midnight;
+    if end.date() == day && end.time() != midnight {
+        format!("→ {:02}:{:02}", end.hour(), end.minute())
+    } else if end.date() > day && !ends_with_day {
+        "⬅ ➡".to_string()
+    } else {
+        "⬅".to_string()
+    }
+}
+
+/// When an event starts relative to `now`, such as `in 45m`, or `now` while it is on.
+/// Events which have finished have no label.
+pub fn relative_start(
+    now: OffsetDateTime,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Option<String> {
+    if start <= now {
+        return (now < end).then(|| "now".to_string());
+    }
+    // round up, so an event a few seconds away is not `in 0m`
+    let mins = (start - now).whole_seconds().saturating_add(59) / 60;
+    Some(match mins {
+        0..=59 => format!("in {mins}m"),
+        _ => format!("in {}h", mins / 60),
+    })
+}
+
+fn paint_footer(ui: &mut Ui, text: &str, zoom: f32) {
+    let painter = ui.painter();
+    let galley = painter.layout_no_wrap(
+        text.to_string(),
+        egui::FontId::proportional(8.0 * zoom),
+        Color32::BLACK,
+    );
+    let rect = egui::Align2::RIGHT_BOTTOM
+        .anchor_rect(egui::Rect::from_min_size(
+            ui.ctx().screen_rect().right_bottom(),
+            galley.size(),
+        ))
+        .expand(1.0 * zoom);
+    painter.rect_filled(rect, 0.0, Color32::WHITE);
+    painter.galley(rect.shrink(1.0 * zoom).min, galley);
+}
+
+// ##### FORTNIGHT #############################################################
+
+#[derive(Default, Copy, Clone)]
+pub struct TwelveDay;
+
+impl modes::View for TwelveDay {
+    fn name(&self) -> &str {
+        "twelve-day"
+    }
+
+    fn zoom(&self) -> f32 {
+        2.0
+    }
+}
+
+impl Render<(&Layout, Model)> for TwelveDay {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let mut evs = model.cals.values().flatten().collect::<Vec<_>>();
+        evs.sort_by(|a, b| a.start.cmp(&b.start));
+
+        let zoom = layout.zoom;
+        ui.spacing_mut().item_spacing = Vec2::ZERO;
+
+        let rows = [3; 3];
+
+        let start = layout.now.date();
+        let mut days = std::iter::successors(Some(start), |x| x.next_day())
+            .take(rows.iter().sum())
+            .collect::<Vec<_>>()
+            .into_iter();
+        let days = days.by_ref();
+
+        let row_height = ui.available_height() / rows.len() as f32;
+        let mut evs = evs.as_slice();
+        for cols in rows {
+            ui.columns(cols, |cs| {
+                days.zip(cs).for_each(|(day, ui)| {
+                    // progressively shrink the slice
+                    evs = remove_earlier_events(evs, day);
+                    let cell = CellWidget {
+                        zoom: zoom * 1.6,
+                        display_weekday: true,
+                        is_today: day == layout.now.date(),
+                        is_past: false,
+                        pad: true,
+                        day,
+                        now: layout.now,
+                        event_times: layout.event_times,
+                        model: &model,
+                    };
+                    ui.allocate_ui(vec2(ui.available_width(), row_height), |ui| {
+                        cell.day_cell(ui, evs);
+                    });
+                });
+            });
+        }
+    }
+}
+
+// ##### MONTH #################################################################
+
+#[derive(Default, Copy, Clone)]
+pub struct Month;
+
+impl modes::View for Month {
+    fn name(&self) -> &str {
+        "month"
+    }
+
+    /// The inset would only repeat the grid.
+    fn inset(&self) -> bool {
+        false
+    }
+}
+
+impl Render<(&Layout, Model)> for Month {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let mut evs = model.cals.values().flatten().collect::<Vec<_>>();
+        evs.sort_by(|a, b| a.start.cmp(&b.start));
+
+        let zoom = layout.zoom;
+        ui.spacing_mut().item_spacing = Vec2::ZERO;
+
+        // day headers
+        ui.columns(7, |cs| {
+            std::iter::successors(Some(Weekday::Monday), |x| x.next().into())
+                .zip(cs)
+                .for_each(|(d, ui)| {
+                    Frame::none()
+                        .stroke((1. * zoom, Color32::BLACK))
+                        .inner_margin(2.0 * zoom)
+                        .show(ui, |ui| {
+                            ui.vertical_centered_justified(|ui| ui.label(d.to_string()))
+                        });
+                });
+        });
+
+        let start = week_start(layout.now.date().replace_day(1).unwrap());
+        let end = week_end(end_of_month(layout.now.date()));
+        let days = std::iter::successors(Some(start), |x| x.next_day())
+            .take_while(|x| x <= &end)
+            .collect::<Vec<_>>();
+        debug_assert_eq!(days.len() % 7, 0);
+
+        let weeks = days.chunks_exact(7);
+        let week_height = ui.available_height() / weeks.len() as f32;
+        let mut evs = evs.as_slice();
+        for week in weeks {
+            ui.columns(7, |cs| {
+                week.iter().copied().zip(cs).for_each(|(day, ui)| {
+                    // progressively shrink the slice
+                    evs = remove_earlier_events(evs, day);
+                    let cell = CellWidget {
+                        zoom,
+                        is_today: day == layout.now.date(),
+                        is_past: day < layout.now.date(),
+                        display_weekday: false,
+                        pad: true,
+                        day,
+                        now: layout.now,
+                        event_times: layout.event_times,
+                        model: &model,
+                    };
+                    ui.allocate_ui(vec2(ui.available_width(), week_height), |ui| {
+                        cell.day_cell(ui, evs);
+                    });
+                });
+            });
+        }
+    }
+}
+
+fn end_of_month(date: Date) -> Date {
+    date.replace_day(time::util::days_in_year_month(date.year(), date.month()))
+        .unwrap()
+}
+
+// ##### AGENDA ################################################################
+
+#[derive(Default, Copy, Clone)]
+pub struct Agenda;
+
+impl modes::View for Agenda {
+    fn name(&self) -> &str {
+        "agenda"
+    }
+
+    fn zoom(&self) -> f32 {
+        2.0
+    }
+}
+
+impl Render<(&Layout, Model)> for Agenda {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let mut evs = model.cals.values().flatten().collect::<Vec<_>>();
+        evs.sort_by(|a, b| a.start.cmp(&b.start));
+
+        let zoom = layout.zoom * 2.2;
+        ui.spacing_mut().item_spacing = Vec2::ZERO;
+
+        let mut evs = evs.as_slice();
+        let mut day = layout.now.date();
+        ui.columns(2, |cs| {
+            for ui in cs {
+                'col: loop {
+                    // progressively shrink the slice
+                    evs = remove_earlier_events(evs, day);
+                    CellWidget {
+                        zoom,
+                        is_today: day == layout.now.date(),
+                        is_past: false,
+                        display_weekday: true,
+                        pad: false,
+                        day,
+                        now: layout.now,
+                        event_times: layout.event_times,
+                        model: &model,
+                    }
+                    .day_cell(ui, evs);
+
+                    day = day.next_day().unwrap();
+
+                    if ui.available_height() <= 120.0 {
+                        break 'col;
+                    }
+                }
+            }
+        });
+    }
+}
+
+// ##### TIMELINE ##############################################################
+
+/// Today on a vertical time axis, with events drawn as blocks proportional to their duration.
+#[derive(Default, Copy, Clone)]
+pub struct Timeline;
+
+impl modes::View for Timeline {
+    fn name(&self) -> &str {
+        "timeline"
+    }
+
+    fn zoom(&self) -> f32 {
+        2.0
+    }
+}
+
+impl Render<(&Layout, Model)> for Timeline {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let zoom = layout.mode_zoom();
+        let now = layout.now;
+        let today = now.date();
+        let day_start = today.midnight().assume_offset(now.offset());
+        let day_end = day_start + time::Duration::DAY;
+
+        let mut evs = model
+            .cals
+            .values()
+            .flatten()
+            .filter(|e| e.start < day_end && e.end > day_start)
+            .collect::<Vec<_>>();
+        evs.sort_by(|a, b| a.start.cmp(&b.start));
+        let (all_day, evs): (Vec<_>, Vec<_>) = evs
+            .into_iter()
+            .partition(|e| e.start <= day_start && e.end >= day_end - time::Duration::MINUTE);
+
+        ui.spacing_mut().item_spacing = Vec2::ZERO;
+        if !all_day.is_empty() {
+            Frame::none()
+                .stroke((1. * zoom, Color32::BLACK))
+                .inner_margin(2.0 * zoom)
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal_wrapped(|ui| {
+                        ui.spacing_mut().item_spacing.x = 6.0 * zoom;
+                        for (i, e) in all_day.iter().enumerate() {
+                            if i > 0 {
+                                ui.label(RichText::new("•").strong());
+                            }
+                            ui.label(&e.summary);
+                        }
+                    });
+                });
+        }
+
+        let [start_hour, end_hour] = layout.timeline_hours.map(|x| x.min(24));
+        let end_hour = end_hour.max(start_hour + 1);
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let gutter = 28.0 * zoom;
+        let axis = rect
+            .with_min_x(rect.left() + gutter)
+            .shrink2(vec2(0.0, 6.0 * zoom));
+        let hour_height = axis.height() / (end_hour - start_hour) as f32;
+        let window_start = day_start + time::Duration::hours(start_hour.into());
+        let window_end = day_start + time::Duration::hours(end_hour.into());
+        let y_of = |t: OffsetDateTime| {
+            let t = t.clamp(window_start, window_end);
+            axis.top() + (t - window_start).as_seconds_f32() / 3600.0 * hour_height
+        };
+
+        let font = egui::FontId::proportional(9.0 * zoom);
+        for hour in start_hour..=end_hour {
+            let y = axis.top() + (hour - start_hour) as f32 * hour_height;
+            painter.hline(axis.x_range(), y, (0.5 * zoom, Color32::from_gray(160)));
+            painter.text(
+                egui::pos2(axis.left() - 4.0 * zoom, y),
+                egui::Align2::RIGHT_CENTER,
+                format!("{hour:02}:00"),
+                font.clone(),
+                Color32::BLACK,
+            );
+        }
+
+        let evs = evs
+            .into_iter()
+            .filter(|e| e.start < window_end && e.end > window_start)
+            .collect::<Vec<_>>();
+        let spans = evs.iter().map(|e| (e.start, e.end)).collect::<Vec<_>>();
+        for (e, (lane, lanes)) in evs.iter().zip(lanes(&spans)) {
+            let width = axis.width() / lanes as f32;
+            let block = egui::Rect::from_x_y_ranges(
+                axis.left() + lane as f32 * width..=axis.left() + (lane + 1) as f32 * width,
+                y_of(e.start)..=y_of(e.end),
+            )
+            .shrink(1.0 * zoom);
+            draw::rounded_box(
+                &painter,
+                block,
+                2.0 * zoom,
+                (1.0 * zoom, Color32::BLACK),
+                Some(draw::Fill::Solid(Color32::WHITE)),
+            );
+            let colour = if e.end <= now {
+                PAST_TEXT
+            } else {
+                Color32::BLACK
+            };
+            let time = e
+                .start
+                .format(format_description!("[hour repr:24]:[minute]"))
+                .unwrap_or_default();
+            let galley = painter.layout(
+                format!("{time} {}", e.summary),
+                egui::FontId::proportional(10.0 * zoom),
+                colour,
+                block.width() - 4.0 * zoom,
+            );
+            painter
+                .with_clip_rect(block.shrink(1.0 * zoom))
+                .galley(block.min + vec2(2.0, 1.0) * zoom, galley);
+        }
+
+        if (window_start..window_end).contains(&now) {
+            let y = y_of(now);
+            painter.hline(axis.x_range(), y, (2.0 * zoom, Color32::BLACK));
+            painter.circle_filled(egui::pos2(axis.left(), y), 3.0 * zoom, Color32::BLACK);
+        }
+    }
+}
+
+/// Assign overlapping spans to side by side lanes.
+/// Returns the lane of each span and the number of lanes in its group of overlapping spans.
+/// `spans` must be sorted by start.
+fn lanes<T: Ord + Copy>(spans: &[(T, T)]) -> Vec<(usize, usize)> {
+    let mut out = Vec::with_capacity(spans.len());
+    let mut group_start = 0;
+    let mut group_end = None;
+    // the end of the last span in each lane of the current group
+    let mut lane_ends: Vec<T> = Vec::new();
+    for (i, &(start, end)) in spans.iter().enumerate() {
+        if group_end.is_some_and(|x| start >= x) {
+            let n = lane_ends.len();
+            out[group_start..i]
+                .iter_mut()
+                .for_each(|x: &mut (usize, usize)| x.1 = n);
+            group_start = i;
+            lane_ends.clear();
+        }
+        group_end = Some(group_end.map_or(end, |x: T| x.max(end)));
+        let lane = match lane_ends.iter().position(|x| *x <= start) {
+            Some(lane) => {
+                lane_ends[lane] = end;
+                lane
+            }
+            None => {
+                lane_ends.push(end);
+                lane_ends.len() - 1
+            }
+        };
+        out.push((lane, 0));
+    }
+    let n = lane_ends.len();
+    out[group_start..].iter_mut().for_each(|x| x.1 = n);
+    out
+}
+
+// ##### BUSY ##################################################################
+
+/// The upcoming days as bars, filled by how many hours are booked.
+#[derive(Default, Copy, Clone)]
+pub struct Busy;
+
+impl modes::View for Busy {
+    fn name(&self) -> &str {
+        "busy"
+    }
+
+    fn zoom(&self) -> f32 {
+        1.5
+    }
+}
+
+/// The number of days shown.
+const BUSY_DAYS: usize = 14;
+/// Hours booked for a full bar.
+const BUSY_FULL: f32 = 10.0;
+
+impl Render<(&Layout, Model)> for Busy {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let zoom = layout.mode_zoom();
+        let today = layout.now.date();
+        let offset = layout.now.offset();
+
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let row = rect.height() / BUSY_DAYS as f32;
+        let label = 70.0 * zoom;
+        let hours_label = 40.0 * zoom;
+        let bar_width = rect.width() - label - hours_label;
+        let font = egui::FontId::proportional(11.0 * zoom);
+
+        let days = std::iter::successors(Some(today), |x| x.next_day()).take(BUSY_DAYS);
+        for (i, day) in days.enumerate() {
+            let top = rect.top() + i as f32 * row;
+            let start = day.midnight().assume_offset(offset);
+            let spans = model
+                .cals
+                .values()
+                .flatten()
+                .map(|e| (e.start, e.end))
+                // all day events do not book the day
+                .filter(|(s, e)| *e - *s < time::Duration::DAY - time::Duration::MINUTE);
+            let hours = booked_hours(spans, start, start + time::Duration::DAY);
+
+            let colour = if day.weekday() == Weekday::Saturday || day.weekday() == Weekday::Sunday {
+                Color32::DARK_GRAY
+            } else {
+                Color32::BLACK
+            };
+            let text = day
+                .format(format_description!(
+                    "[weekday repr:short] [day padding:none]"
+                ))
+                .unwrap_or_else(|_| "?".into());
+            painter.text(
+                egui::pos2(rect.left() + 4.0 * zoom, top + row * 0.5),
+                egui::Align2::LEFT_CENTER,
+                text,
+                font.clone(),
+                colour,
+            );
+
+            let track = egui::Rect::from_min_size(
+                egui::pos2(rect.left() + label, top),
+                vec2(bar_width, row),
+            )
+            .shrink2(vec2(0.0, row * 0.2));
+            draw::rounded_box(
+                &painter,
+                track,
+                2.0 * zoom,
+                (1.0 * zoom, Color32::BLACK),
+                None,
+            );
+            let filled = (hours / BUSY_FULL).clamp(0.0, 1.0);
+            if filled > 0.0 {
+                let bar = track.with_max_x(track.left() + track.width() * filled);
+                // heavier fills as the day gets busier, so busy days stand out at a glance
+                let fill = match hours {
+                    h if h < 3.0 => draw::Fill::Stipple {
+                        spacing: 4.0 * zoom,
+                        radius: 0.8 * zoom,
+                        colour: Color32::BLACK,
+                    },
+                    h if h < 6.0 => draw::Fill::Hatch {
+                        spacing: 4.0 * zoom,
+                        stroke: egui::Stroke::new(1.0 * zoom, Color32::BLACK),
+                    },
+                    _ => draw::Fill::Solid(Color32::BLACK),
+                };
+                fill.paint(&painter, bar.shrink(1.0 * zoom));
+            }
+
+            painter.text(
+                egui::pos2(rect.right() - 4.0 * zoom, top + row * 0.5),
+                egui::Align2::RIGHT_CENTER,
+                format!("{hours:.1}h"),
+                font.clone(),
+                colour,
+            );
+        }
+    }
+}
+
+/// The hours within `start..end` covered by the `spans`, overlapping spans are only counted once.
+fn booked_hours(
+    spans: impl Iterator<Item = (OffsetDateTime, OffsetDateTime)>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> f32 {
+    let mut spans = spans
+        .map(|(s, e)| (s.max(start), e.min(end)))
+        .filter(|(s, e)| s < e)
+        .collect::<Vec<_>>();
+    spans.sort();
+
+    let mut total = time::Duration::ZERO;
+    let mut covered = start;
+    for (s, e) in spans {
+        let s = s.max(covered);
+        if e > s {
+            total += e - s;
+            covered = e;
+        }
+    }
+    total.as_seconds_f32() / 3600.0
+}
+
+// ##### FAMILY ################################################################
+
+/// Someone with a column in the family mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: String,
+    /// Calendars whose events are all theirs.
+    #[serde(default)]
+    pub calendars: Vec<String>,
+    /// Names or email addresses they attend events as, ignoring case.
+    #[serde(default)]
+    pub attendees: Vec<String>,
+}
+
+impl Member {
+    fn owns(&self, calendar: &str, event: &Event) -> bool {
+        self.calendars.iter().any(|x| x == calendar)
+            || event
+                .attendees
+                .iter()
+                .any(|a| self.attendees.iter().any(|x| x.eq_ignore_ascii_case(a)))
+    }
+}
+
+/// The upcoming week with a column for each member, like a paper family organiser.
+#[derive(Clone, Default)]
+pub struct Family {
+    pub members: Vec<Member>,
+}
+
+impl modes::View for Family {
+    fn name(&self) -> &str {
+        "family"
+    }
+
+    fn zoom(&self) -> f32 {
+        1.5
+    }
+}
+
+/// The number of days shown.
+const FAMILY_DAYS: usize = 7;
+
+impl Render<(&Layout, Model)> for Family {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let zoom = layout.zoom * 1.4;
+        let today = layout.now.date();
+        let columns = family_columns(&self.members, &model.cals);
+        ui.spacing_mut().item_spacing = Vec2::ZERO;
+
+        // names over the member columns, the first column is the days
+        ui.columns(columns.len() + 1, |cs| {
+            for (ui, member) in cs[1..].iter_mut().zip(&self.members) {
+                ui.vertical_centered(|ui| ui.label(RichText::new(&member.name).strong()));
+            }
+        });
+
+        let row_height = ui.available_height() / FAMILY_DAYS as f32;
+        let days = std::iter::successors(Some(today), |x| x.next_day()).take(FAMILY_DAYS);
+        for day in days {
+            let cell = CellWidget {
+                zoom,
+                is_today: day == today,
+                is_past: false,
+                display_weekday: true,
+                pad: true,
+                day,
+                now: layout.now,
+                event_times: layout.event_times,
+                model: &model,
+            };
+            ui.columns(columns.len() + 1, |cs| {
+                let (days, members) = cs.split_first_mut().expect("the days column");
+                days.allocate_ui(vec2(days.available_width(), row_height), |ui| {
+                    cell.day_cell(ui, &[]);
+                });
+                for (ui, evs) in members.iter_mut().zip(&columns) {
+                    ui.allocate_ui(vec2(ui.available_width(), row_height), |ui| {
+                        Frame::none()
+                            .stroke((1.0 * zoom, Color32::BLACK))
+                            .inner_margin(2.0 * zoom)
+                            .show(ui, |ui| {
+                                cell.event_lines(ui, evs);
+                                ui.allocate_space(ui.available_size());
+                            });
+                    });
+                }
+            });
+        }
+    }
+}
+
+/// The events in each member's column, sorted by start. Events nobody owns, such as those of a
+/// shared household calendar, are in every column.
+fn family_columns<'a>(
+    members: &[Member],
+    cals: &'a HashMap<String, Calendar>,
+) -> Vec<Vec<&'a Event>> {
+    let mut columns = vec![Vec::new(); members.len()];
+    for (calendar, evs) in cals {
+        for e in evs {
+            let owners = members
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.owns(calendar, e))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if owners.is_empty() {
+                columns.iter_mut().for_each(|x| x.push(e));
+            }
+            for i in owners {
+                columns[i].push(e);
+            }
+        }
+    }
+    for x in &mut columns {
+        x.sort_by(|a, b| a.start.cmp(&b.start));
+    }
+    columns
+}
+
+// ##### AWAY ##################################################################
+
+/// A big date and the weather, shown while nobody is home, see [`crate::policy`].
+#[derive(Default, Copy, Clone)]
+pub struct Away;
+
+impl modes::View for Away {
+    fn name(&self) -> &str {
+        "away"
+    }
+
+    fn inset(&self) -> bool {
+        false
+    }
+
+    fn chrome(&self) -> bool {
+        false
+    }
+}
+
+impl Render<(&Layout, Model)> for Away {
+    fn render(&self, ui: &mut Ui, (layout, model): (&Layout, Model)) {
+        let zoom = layout.zoom;
+        let today = layout.now.date();
+        ui.vertical_centered(|ui| {
+            ui.add_space(ui.available_height() * 0.25);
+            ui.label(RichText::new(today.weekday().to_string()).size(40.0 * zoom));
+            ui.label(
+                RichText::new(format!("{} {}", today.day(), today.month()))
+                    .size(72.0 * zoom)
+                    .strong(),
+            );
+            ui.add_space(24.0 * zoom);
+            if let Some(weather) = model.weather.as_ref() {
+                let size = 48.0 * zoom;
+                let today = weather.forecast.get(&today).unwrap_or(&weather.current);
+                ui.horizontal(|ui| {
+                    // centre the icon and temperature as a pair
+                    ui.add_space((ui.available_width() - size * 3.0).max(0.0) / 2.0);
+                    icon::weather(ui, today.code, size);
+                    if let Some(t) = today.temperature {
+                        ui.label(RichText::new(format!("{t:.0}°C")).size(size * 0.8));
+                    }
+                });
+            }
+        });
+    }
+}
+
+// ##### COMMON ################################################################
+
+fn week_start(date: Date) -> Date {
+    if date.weekday() == Weekday::Monday {
+        date
+    } else {
+        date.prev_occurrence(Weekday::Monday)
+    }
+}
+
+fn week_end(date: Date) -> Date {
+    if date.weekday() == Weekday::Sunday {
+        date
+    } else {
+        date.next_occurrence(Weekday::Sunday)
+    }
+}
+
+fn remove_earlier_events<'a>(evs: &'a [&'a Event], before: Date) -> &'a [&'a Event] {
+    let i = evs
+        .iter()
+        .enumerate()
+        .find(|(_, e)| e.start.date() >= before || e.covers(before))
+        .map(|(i, _)| i)
+        .unwrap_or(evs.len());
+    &evs[i..]
+}
+
+/// Text colour of days which have passed.
+const PAST_TEXT: Color32 = Color32::from_gray(150);
+
+/// Room kept for an event's summary when choosing the time label.
+const MIN_SUMMARY_WIDTH: f32 = 40.0;
+
+struct CellWidget<'a> {
+    zoom: f32,
+    is_today: bool,
+    /// Rendered with reduced contrast and without the weather.
+    is_past: bool,
+    display_weekday: bool,
+    pad: bool,
+    day: Date,
+    now: OffsetDateTime,
+    event_times: EventTimes,
+    model: &'a Model,
+}
+
+impl<'a> CellWidget<'a> {
+    fn day_cell(&self, ui: &mut Ui, evs: &[&Event]) {
+        let Self {
+            zoom,
+            is_today: _,
+            is_past,
+            display_weekday: _,
+            pad,
+            day,
+            now: _,
+            event_times: _,
+            model,
+        } = *self;
+        let holidays = model
+            .holidays
+            .as_ref()
+            .map(|x| x.on(day))
+            .unwrap_or_default();
+        // the hatch goes under the cell, but its size is only known once filled
+        let background = ui.painter().add(egui::Shape::Noop);
+        let cell = Frame::none()
+            .stroke((1. * zoom, Color32::BLACK))
+            .inner_margin(2.0 * zoom)
+            .show(ui, |ui| {
+                if is_past {
+                    ui.visuals_mut().override_text_color = Some(PAST_TEXT);
+                }
+                self.day_header(ui);
+
+                for name in holidays {
+                    ui.add(Label::new(RichText::new(name).small().italics()).truncate(true));
+                }
+
+                self.event_lines(ui, evs);
+
+                if pad {
+                    ui.allocate_space(ui.available_size());
+                }
+            });
+
+        if !holidays.is_empty() {
+            let rect = cell.response.rect;
+            let painter = ui.painter().with_clip_rect(rect);
+            // a grey would dither into noise, so sparse black lines keep the text legible
+            let stroke = (1.0 * zoom, Color32::BLACK);
+            let lines = draw::hatch_shapes(&painter, rect, 12.0 * zoom, stroke);
+            painter.set(background, egui::Shape::Vec(lines));
+        }
+    }
+
+    fn day_header(&self, ui: &mut Ui) {
+        let Self {
+            zoom,
+            is_today,
+            is_past,
+            display_weekday,
+            pad: _,
+            day,
+            now: _,
+            event_times: _,
+            model,
+        } = *self;
+        let (frame, dark) = if is_today {
+            (Frame::none().fill(Color32::DARK_GRAY), true)
+        } else {
+            (Frame::none(), false)
+        };
+        frame.show(ui, |ui| {
+            if dark {
+                ui.visuals_mut().override_text_color = Some(Color32::WHITE);
+            }
+            ui.set_height(16.0 * zoom);
+            ui.horizontal_centered(|ui| {
+                ui.horizontal(|ui| {
+                    if display_weekday {
+                        ui.label(&day.weekday().to_string()[..3]);
+                        ui.add_space(2.0 * zoom);
+                    }
+                    ui.label(day.day().to_string());
+                });
+
+                ui.with_layout(egui::Layout::right_to_left(Align::Center), |ui| {
+                    if is_past {
+                        return;
+                    }
+                    if let Some(weather) = model.weather.as_ref().and_then(|x| x.forecast.get(&day))
+                    {
+                        if let Some(x) = weather.precipitation_prob {
+                            ui.label(RichText::new(format!("({x:.0}%)")).size(10.0 * zoom));
+                        }
+                        icon::weather(ui, weather.code, 14.0 * zoom);
+                        let range = model
+                            .ensemble
+                            .as_ref()
+                            .and_then(|x| x.max_temperature.get(&day));
+                        if let Some([lo, hi]) = range {
+                            ui.label(format!("{lo:.0}–{hi:.0}°"));
+                        } else if let Some(t) = weather.temperature {
+                            ui.label(format!("{t:.0}°C"));
+                        }
+                    }
+                    if let Some(moon) = model.moon.as_ref().and_then(|x| x.calendar.get(&day)) {
+                        icon::moon(ui, moon.phase, 14.0 * zoom);
+                    }
+                });
+            });
+        });
+    }
+
+    /// The events on the day, as many as fit with an overflow line for the rest.
+    fn event_lines(&self, ui: &mut Ui, evs: &[&Event]) {
+        let day = self.day;
+        let evs = evs
+            .iter()
+            .take_while(|x| x.start.date() <= day)
+            .filter(|x| x.covers(day))
+            .collect::<Vec<_>>();
+        ui.set_clip_rect(ui.max_rect().intersect(ui.clip_rect()));
+        let line_height = self.line_height(ui);
+        for (i, e) in evs.iter().enumerate() {
+            // leave room for the overflow line if this is not the last event
+            let left = evs.len() - i;
+            let needs = if left == 1 { 1.0 } else { 2.0 } * line_height;
+            if ui.available_height() < needs {
+                self.more_line(ui, left);
+                break;
+            }
+            self.event_line(ui, e);
+        }
+    }
+
+    fn line_height(&self, ui: &Ui) -> f32 {
+        let text = ui.text_style_height(&egui::TextStyle::Small);
+        text.max(10.0 * self.zoom) + ui.spacing().item_spacing.y
+    }
+
+    /// In place of the events that do not fit in the cell.
+    fn more_line(&self, ui: &mut Ui, n: usize) {
+        ui.horizontal(|ui| {
+            ui.set_height(10.0 * self.zoom);
+            ui.label(RichText::new(format!("+{n} more")).small().italics());
+        });
+    }
+
+    fn event_line(&self, ui: &mut Ui, event: &Event) {
+        let Self {
+            zoom,
+            is_today,
+            is_past: _,
+            display_weekday: _,
+            pad: _,
+            day,
+            now,
+            event_times,
+            model: _,
+        } = *self;
+        let Event {
+            summary,
+            start,
+            end,
+            attendees: _,
+        } = event;
+
+        ui.horizontal(|ui| {
+            ui.set_height(10.0 * zoom);
+            ui.spacing_mut().item_spacing.x = 2.0 * zoom;
+            let rt = if start.date() == day {
+                // fall back to the start if the longer label would squeeze out the summary
+                let label = event_times.label(*start, *end);
+                let width = |text: &str| {
+                    let font = egui::TextStyle::Small.resolve(ui.style());
+                    ui.fonts(|f| f.layout_no_wrap(text.to_string(), font, Color32::BLACK))
+                        .size()
+                        .x
+                };
+                let fits = event_times == EventTimes::Start
+                    || width(&label) + MIN_SUMMARY_WIDTH * zoom < ui.available_width();
+                if fits {
+                    RichText::new(label)
+                } else {
+                    RichText::new(EventTimes::Start.label(*start, *end))
+                }
+            } else {
+                RichText::new(carried_label(*end, day))
+            };
+            ui.label(rt.strong().small());
+            if let Some(x) = relative_start(now, *start, *end).filter(|_| is_today) {
+                ui.label(RichText::new(x).small().italics());
+            }
+            ui.add(Label::new(RichText::new(summary).small()).truncate(true));
+        });
+    }
+}
+
+fn battery_indicator(ui: &mut Ui, battery: &battery::Battery, size: f32) {
+    let stroke = egui::Stroke::new(size * 0.08, Color32::BLACK);
+    let (rect, _) = ui.allocate_exact_size(vec2(size * 1.2, size * 0.6), egui::Sense::hover());
+    let painter = ui.painter();
+    let nub = egui::Rect::from_center_size(
+        egui::pos2(rect.right() - size * 0.05, rect.center().y),
+        vec2(size * 0.1, size * 0.25),
+    );
+    painter.rect_filled(nub, 0.0, Color32::BLACK);
+    let body = rect.with_max_x(nub.left());
+    draw::rounded_box(painter, body, size * 0.08, stroke, None);
+    let inner = body.shrink(size * 0.12);
+    let fill_rect = inner.with_max_x(inner.left() + inner.width() * battery.level / 100.0);
+    let fill = if battery.low {
+        draw::Fill::Hatch {
+            spacing: size * 0.12,
+            stroke,
+        }
+    } else {
+        draw::Fill::Solid(Color32::BLACK)
+    };
+    fill.paint(painter, fill_rect);
+
+    let mut txt = RichText::new(format!("{:.0}%", battery.level)).size(size * 0.6);
+    if battery.low {
+        txt = txt
+            .strong()
+            .color(Color32::WHITE)
+            .background_color(Color32::BLACK);
+    }
+    ui.label(txt);
+    if battery.charging {
+        ui.label(RichText::new("⚡").size(size * 0.6));
+    }
+}
+
+#[cfg(feature = "weather")]
+fn air_quality(ui: &mut Ui, air: &crate::data::air::AirQuality, size: f32) {
+    if let Some(x) = air.aqi {
+        let cat = air.category().unwrap_or_default();
+        ui.label(RichText::new(cat).size(size * 0.5));
+        ui.label(RichText::new(format!("AQI {x:.0}")).size(size));
+    }
+    if let Some(x) = air.pm2_5 {
+        ui.label(RichText::new(format!("PM2.5 {x:.0}")).size(size * 0.5));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_lanes() {
+        // 9-10 and 9:30-11 overlap, 10-11 fits under the first, 12-13 is on its own
+        let spans = [(900, 1000), (930, 1100), (1000, 1100), (1200, 1300)];
+        assert_eq!(lanes(&spans), [(0, 2), (1, 2), (0, 2), (0, 1)]);
+        assert_eq!(lanes::<u32>(&[]), []);
+    }
+
+    #[test]
+    fn family_routing() {
+        use time::macros::datetime;
+        let ev = |summary: &str, attendees: &[&str]| Event {
+            summary: summary.to_string(),
+            start: datetime!(2024-06-21 9:00 +10),
+            end: datetime!(2024-06-21 10:00 +10),
+            attendees: attendees.iter().map(|x| x.to_string()).collect(),
+        };
+        let member = |name: &str, calendars: &[&str], attendees: &[&str]| Member {
+            name: name.to_string(),
+            calendars: calendars.iter().map(|x| x.to_string()).collect(),
+            attendees: attendees.iter().map(|x| x.to_string()).collect(),
+        };
+        let members = [
+            member("Sam", &["Work"], &["sam@example.com"]),
+            member("Alex", &[], &["Alex"]),
+        ];
+        let cals = HashMap::from([
+            ("Work".to_string(), vec![ev("Standup", &[])]),
+            (
+                "Home".to_string(),
+                vec![
+                    ev("Piano", &["alex"]),
+                    ev("Dentist", &["SAM@example.com", "Alex"]),
+                    ev("Groceries", &[]),
+                ],
+            ),
+        ]);
+
+        let columns = family_columns(&members, &cals)
+            .into_iter()
+            .map(|x| {
+                let mut x = x.iter().map(|e| e.summary.as_str()).collect::<Vec<_>>();
+                x.sort();
+                x
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            [
+                vec!["Dentist", "Groceries", "Standup"],
+                vec!["Dentist", "Groceries", "Piano"],
+            ]
+        );
+    }
+
+    #[test]
+    fn busy_hours() {
+        use time::macros::datetime;
+        let day = datetime!(2024-06-21 00:00 +10);
+        let spans = [
+            (
+                datetime!(2024-06-20 23:00 +10),
+                datetime!(2024-06-21 01:00 +10),
+            ),
+            (
+                datetime!(2024-06-21 09:00 +10),
+                datetime!(2024-06-21 11:00 +10),
+            ),
+            (
+                datetime!(2024-06-21 10:00 +10),
+                datetime!(2024-06-21 10:30 +10),
+            ),
+            (
+                datetime!(2024-06-21 10:30 +10),
+                datetime!(2024-06-21 12:00 +10),
+            ),
+            (
+                datetime!(2024-06-22 09:00 +10),
+                datetime!(2024-06-22 10:00 +10),
+            ),
+        ];
+        let hours = booked_hours(spans.into_iter(), day, day + time::Duration::DAY);
+        assert_eq!(hours, 4.0);
+    }
+
+    #[test]
+    fn event_time_labels() {
+        use time::macros::datetime;
+        let start = datetime!(2024-06-21 09:00 +10);
+        let label = |x: EventTimes, mins| x.label(start, start + time::Duration::minutes(mins));
+        assert_eq!(label(EventTimes::Start, 90), "09:00");
+        assert_eq!(label(EventTimes::Range, 90), "09:00–10:30");
+        assert_eq!(label(EventTimes::Duration, 90), "09:00 1h30");
+        assert_eq!(label(EventTimes::Duration, 120), "09:00 2h");
+        assert_eq!(label(EventTimes::Duration, 45), "09:00 45m");
+        // ending at midnight is still the same day, running on is not
+        assert_eq!(label(EventTimes::Range, 15 * 60), "09:00–00:00");
+        assert_eq!(label(EventTimes::Range, 16 * 60), "09:00");
+    }
+
+    #[test]
+    fn carried_labels() {
+        use time::macros::{date, datetime};
+        let day = date!(2024 - 06 - 21);
+        assert_eq!(
+            carried_label(datetime!(2024-06-21 14:00 +10), day),
+            "→ 14:00"
+        );
+        assert_eq!(carried_label(datetime!(2024-06-23 00:00 +10), day), "⬅ ➡");
+        // ending at the start or the end of the day
+        assert_eq!(carried_label(datetime!(2024-06-21 00:00 +10), day), "⬅");
+        assert_eq!(carried_label(datetime!(2024-06-22 00:00 +10), day), "⬅");
+    }
+
+    #[test]
+    fn relative_start_labels() {
+        use time::macros::datetime;
+        let now = datetime!(2024-06-21 09:15 +10);
+        let rel = |start, mins| relative_start(now, start, start + time::Duration::minutes(mins));
+        assert_eq!(
+            rel(datetime!(2024-06-21 10:00 +10), 30).as_deref(),
+            Some("in 45m")
+        );
+        assert_eq!(
+            rel(datetime!(2024-06-21 09:15:20 +10), 30).as_deref(),
+            Some("in 1m")
+        );
+        assert_eq!(
+            rel(datetime!(2024-06-21 11:45 +10), 30).as_deref(),
+            Some("in 2h")
+        );
+        assert_eq!(
+            rel(datetime!(2024-06-21 09:00 +10), 30).as_deref(),
+            Some("now")
+        );
+        assert_eq!(rel(datetime!(2024-06-21 08:00 +10), 30), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! Pluggable layout modes, selected by name from the config.
+//!
+//! A mode is any [`View`], which renders the body of the frame under the header. Modes are
+//! added to the layout's [`Modes`], the built in modes are registered by [`Modes::builtin`].
+use super::{Agenda, Busy, Layout, Month, Timeline, TwelveDay};
+use crate::{data::Model, render::Render};
+use egui::Ui;
+use std::sync::Arc;
+
+/// Renders the body of the frame, such as a month grid.
+pub trait View: for<'a> Render<(&'a Layout, Model)> + Send + Sync {
+    /// The name the config selects the mode by.
+    fn name(&self) -> &str;
+
+    /// Scales the layout's zoom, views with fewer days can afford larger text.
+    fn zoom(&self) -> f32 {
+        1.0
+    }
+
+    /// The next month inset may be painted over the view.
+    fn inset(&self) -> bool {
+        true
+    }
+
+    /// The layout renders the header and note strips around the view.
+    /// Views which place these themselves are given the whole frame.
+    fn chrome(&self) -> bool {
+        true
+    }
+}
+
+/// A shared handle to a [`View`].
+#[derive(Clone)]
+pub struct Mode(Arc<dyn View>);
+
+impl Mode {
+    pub fn new<V: View + 'static>(view: V) -> Self {
+        Self(Arc::new(view))
+    }
+
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.0.zoom()
+    }
+
+    pub fn inset(&self) -> bool {
+        self.0.inset()
+    }
+
+    pub fn chrome(&self) -> bool {
+        self.0.chrome()
+    }
+}
+
+impl Render<(&Layout, Model)> for Mode {
+    fn render(&self, ui: &mut Ui, ctx: (&Layout, Model)) {
+        self.0.render(ui, ctx)
+    }
+}
+
+/// Modes by name.
+#[derive(Clone, Default)]
+pub struct Modes {
+    modes: Vec<Mode>,
+}
+
+impl Modes {
+    /// A registry with the built in modes.
+    pub fn builtin() -> Self {
+        let mut x = Self::default();
+        x.register(TwelveDay)
+            .register(Month)
+            .register(Agenda)
+            .register(Timeline)
+            .register(Busy);
+        x
+    }
+
+    /// Register a mode, replacing any with the same name.
+    pub fn register<V: View + 'static>(&mut self, view: V) -> &mut Self {
+        self.modes.retain(|x| x.name() != view.name());
+        self.modes.push(Mode::new(view));
+        self
+    }
+
+    /// Lookup a mode by the name used in the config.
+    pub fn get(&self, name: &str) -> Option<Mode> {
+        self.modes.iter().find(|x| x.name() == name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.modes.iter().map(|x| x.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blank;
+
+    impl Render<(&Layout, Model)> for Blank {
+        fn render(&self, _: &mut Ui, _: (&Layout, Model)) {}
+    }
+
+    impl View for Blank {
+        fn name(&self) -> &str {
+            "month"
+        }
+    }
+
+    #[test]
+    fn register_replaces_by_name() {
+        let mut modes = Modes::builtin();
+        assert!(modes.get("month").is_some_and(|x| !x.inset()));
+        assert!(modes.get("photo").is_none());
+
+        modes.register(Blank);
+        assert!(modes.get("month").is_some_and(|x| x.inset()));
+        assert_eq!(modes.names().filter(|x| *x == "month").count(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+//! Pluggable widgets, referenced by id from the layout.
+//!
+//! Widgets implement [`Widget`] and are added to the layout's [`Registry`].
+//! The built in widgets are registered by [`Registry::builtin`].
+use super::{battery_indicator, Layout};
+use crate::data::Model;
+use egui::{vec2, Ui, Vec2};
+use std::sync::Arc;
+
+/// Something drawn on the frame from the model, such as the current weather.
+pub trait Widget: Send + Sync {
+    /// The id layouts reference the widget by.
+    fn id(&self) -> &str;
+
+    /// The size the widget would like, in points.
+    fn desired_size(&self, layout: &Layout) -> Vec2;
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout);
+}
+
+/// Widgets by id.
+#[derive(Clone, Default)]
+pub struct Registry {
+    widgets: Vec<Arc<dyn Widget>>,
+}
+
+impl Registry {
+    /// A registry with the built in widgets.
+    pub fn builtin() -> Self {
+        let mut x = Self::default();
+        x.register(Battery).register(Sun).register(News);
+        #[cfg(feature = "weather")]
+        x.register(Weather)
+            .register(AirQuality)
+            .register(WeatherStrip);
+        #[cfg(feature = "moon")]
+        x.register(Moon);
+        #[cfg(feature = "tides")]
+        x.register(Tide);
+        x
+    }
+
+    /// Register a widget, replacing any with the same id.
+    pub fn register<W: Widget + 'static>(&mut self, widget: W) -> &mut Self {
+        self.widgets.retain(|x| x.id() != widget.id());
+        self.widgets.push(Arc::new(widget));
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn Widget> {
+        self.widgets
+            .iter()
+            .find(|x| x.id() == id)
+            .map(|x| x.as_ref())
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.widgets.iter().map(|x| x.id())
+    }
+}
+
+/// Font size of the header widgets.
+fn header_size(layout: &Layout) -> f32 {
+    20.0 * layout.mode_zoom()
+}
+
+/// PiSugar battery level.
+pub struct Battery;
+
+impl Widget for Battery {
+    fn id(&self) -> &str {
+        "battery"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 3.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        if let Some(battery) = model.battery.as_ref() {
+            battery_indicator(ui, battery, header_size(layout));
+        }
+    }
+}
+
+/// Today's sunrise and sunset.
+pub struct Sun;
+
+impl Widget for Sun {
+    fn id(&self) -> &str {
+        "sun"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 7.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        use crate::data::sun::Sun::*;
+        let Some(daylight) = model.sun.as_ref() else {
+            return;
+        };
+        let hhmm = |t: time::OffsetDateTime| format!("{:02}:{:02}", t.hour(), t.minute());
+        let text = match daylight.sun {
+            RiseSet { rise, set } => format!("☀↑{} ↓{}", hhmm(rise), hhmm(set)),
+            AlwaysUp => "☀ all day".to_string(),
+            AlwaysDown => "☀ none today".to_string(),
+        };
+        ui.label(egui::RichText::new(text).size(header_size(layout)));
+    }
+}
+
+/// The next high and low tides.
+#[cfg(feature = "tides")]
+pub struct Tide;
+
+#[cfg(feature = "tides")]
+impl Widget for Tide {
+    fn id(&self) -> &str {
+        "tide"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 7.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        let Some(tides) = model.tides.as_ref() else {
+            return;
+        };
+        let text = tides
+            .after(layout.now)
+            .take(2)
+            .map(|x| {
+                let arrow = if x.high { '▲' } else { '▼' };
+                format!("{arrow}{:02}:{:02}", x.time.hour(), x.time.minute())
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        ui.label(egui::RichText::new(format!("🌊{text}")).size(header_size(layout)));
+    }
+}
+
+/// The newest headlines, one at a time.
+pub struct News;
+
+impl Widget for News {
+    fn id(&self) -> &str {
+        "news"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 24.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        let Some(headline) = model.news.as_ref().and_then(|x| x.current(layout.now)) else {
+            return;
+        };
+        let text = egui::RichText::new(format!("📰 {}", headline.title)).size(header_size(layout));
+        ui.add(egui::Label::new(text).truncate(true));
+    }
+}
+
+/// The current weather conditions.
+#[cfg(feature = "weather")]
+pub struct Weather;
+
+#[cfg(feature = "weather")]
+impl Widget for Weather {
+    fn id(&self) -> &str {
+        "weather"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 8.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        let fontsize = header_size(layout);
+        if let Some(weather) = model.weather.as_ref().map(|x| &x.current) {
+            if let Some(x) = weather.precipitation_prob {
+                ui.label(egui::RichText::new(format!("({x:.0}%)")).size(fontsize));
+            }
+            super::icon::weather(ui, weather.code, fontsize);
+            if let Some(x) = weather.humidity {
+                ui.label(egui::RichText::new(format!("💧{x:.0}%")).size(fontsize));
+            }
+            if let Some(t) = weather.temperature {
+                ui.label(egui::RichText::new(format!("{t:.0}°C")).size(fontsize));
+            }
+        }
+    }
+}
+
+/// The current air quality.
+#[cfg(feature = "weather")]
+pub struct AirQuality;
+
+#[cfg(feature = "weather")]
+impl Widget for AirQuality {
+    fn id(&self) -> &str {
+        "air-quality"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 6.0, size)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        if let Some(air) = model.air.as_ref() {
+            super::air_quality(ui, air, header_size(layout));
+        }
+    }
+}
+
+/// Today's moon phase.
+#[cfg(feature = "moon")]
+pub struct Moon;
+
+#[cfg(feature = "moon")]
+impl Widget for Moon {
+    fn id(&self) -> &str {
+        "moon"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        Vec2::splat(header_size(layout))
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        if let Some(moon) = model
+            .moon
+            .as_ref()
+            .and_then(|x| x.calendar.get(&layout.now.date()))
+        {
+            super::icon::moon(ui, moon.phase, header_size(layout));
+        }
+    }
+}
+
+/// The forecast for the coming week, a column per day.
+#[cfg(feature = "weather")]
+pub struct WeatherStrip;
+
+#[cfg(feature = "weather")]
+impl WeatherStrip {
+    const DAYS: usize = 7;
+}
+
+#[cfg(feature = "weather")]
+impl Widget for WeatherStrip {
+    fn id(&self) -> &str {
+        "weather-strip"
+    }
+
+    fn desired_size(&self, layout: &Layout) -> Vec2 {
+        let size = header_size(layout);
+        vec2(size * 3.0 * Self::DAYS as f32, size * 3.0)
+    }
+
+    fn render(&self, ui: &mut Ui, model: &Model, layout: &Layout) {
+        let Some(weather) = model.weather.as_ref() else {
+            return;
+        };
+        let size = header_size(layout);
+        let days = std::iter::successors(Some(layout.now.date()), |d| d.next_day());
+        ui.columns(Self::DAYS, |cols| {
+            for (ui, day) in cols.iter_mut().zip(days) {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    let weekday = day.weekday().to_string();
+                    ui.label(egui::RichText::new(&weekday[..3]).size(size * 0.6));
+                    if let Some(ob) = weather.forecast.get(&day) {
+                        super::icon::weather(ui, ob.code, size);
+                        if let Some(t) = ob.temperature {
+                            ui.label(egui::RichText::new(format!("{t:.0}°")).size(size * 0.6));
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,97 @@
+//! Light and dark themes, switched automatically at night.
+use crate::data::sun::Sun;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// Black on white.
+    #[default]
+    Light,
+    /// White on black, the frame is inverted.
+    Dark,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeMode {
+    #[default]
+    Light,
+    Dark,
+    /// Dark at night, light during the day.
+    Auto,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    #[serde(default)]
+    pub mode: ThemeMode,
+    /// For `auto`, the `[start, end]` hours of the night.
+    /// If not set, night is from sunset to sunrise.
+    #[serde(default)]
+    pub night_hours: Option<[u8; 2]>,
+}
+
+impl ThemeConfig {
+    /// The theme to use at `now`, for a frame at `[latitude, longitude]`.
+    pub fn theme_at(&self, now: OffsetDateTime, coords: [f32; 2]) -> Theme {
+        let night = match (self.mode, self.night_hours) {
+            (ThemeMode::Light, _) => false,
+            (ThemeMode::Dark, _) => true,
+            (ThemeMode::Auto, Some([start, end])) => {
+                let h = now.hour();
+                if start <= end {
+                    start <= h && h < end
+                } else {
+                    h >= start || h < end
+                }
+            }
+            (ThemeMode::Auto, None) => !Sun::on(now.date(), coords, now.offset()).is_up(now),
+        };
+        if night {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn auto_switching() {
+        let hours = ThemeConfig {
+            mode: ThemeMode::Auto,
+            night_hours: Some([19, 6]),
+        };
+        let brisbane = [-27.4679, 153.0325];
+        assert_eq!(
+            hours.theme_at(datetime!(2024-06-21 18:59 +10), brisbane),
+            Theme::Light
+        );
+        assert_eq!(
+            hours.theme_at(datetime!(2024-06-21 19:00 +10), brisbane),
+            Theme::Dark
+        );
+        assert_eq!(
+            hours.theme_at(datetime!(2024-06-21 05:59 +10), brisbane),
+            Theme::Dark
+        );
+
+        let sun = ThemeConfig {
+            mode: ThemeMode::Auto,
+            night_hours: None,
+        };
+        assert_eq!(
+            sun.theme_at(datetime!(2024-06-21 17:30 +10), brisbane),
+            Theme::Dark
+        );
+        assert_eq!(
+            sun.theme_at(datetime!(2024-06-21 07:00 +10), brisbane),
+            Theme::Light
+        );
+    }
+}
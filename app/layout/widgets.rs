@@ -0,0 +1,467 @@
+use super::draw;
+use crate::render::{callback_pixel_bounds, Render, SoftwareCallback};
+use egui::{vec2, Align, Align2, Color32, Frame, Label, Rect, Rgba, RichText, Ui};
+use image::GrayAlphaImage;
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use time::Date;
+
+// ##### NOTES #################################################################
+
+/// Where a widget should be placed on the frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Position {
+    /// A strip reserved under the header.
+    Top,
+    /// A strip reserved at the bottom of the frame.
+    #[default]
+    Bottom,
+    /// Overlaid on the top right corner of the frame, under the header.
+    TopRight,
+    /// Overlaid on the bottom left corner of the frame.
+    BottomLeft,
+    /// Overlaid on the bottom right corner of the frame.
+    BottomRight,
+}
+
+impl Position {
+    fn is_strip(self) -> bool {
+        matches!(self, Position::Top | Position::Bottom)
+    }
+
+    /// The alignment of an overlaid corner, `None` for strips.
+    pub fn corner(self) -> Option<Align2> {
+        match self {
+            Position::Top | Position::Bottom => None,
+            Position::TopRight => Some(Align2::RIGHT_TOP),
+            Position::BottomLeft => Some(Align2::LEFT_BOTTOM),
+            Position::BottomRight => Some(Align2::RIGHT_BOTTOM),
+        }
+    }
+}
+
+/// A static note block, such as "Bins: Tuesday".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Note {
+    pub text: String,
+    #[serde(default)]
+    pub position: Position,
+}
+
+#[derive(Clone, Default)]
+pub struct Notes {
+    pub notes: Vec<Note>,
+}
+
+impl Notes {
+    fn at(&self, pos: Position) -> impl Iterator<Item = &Note> {
+        self.notes.iter().filter(move |x| x.position == pos)
+    }
+
+    /// The height reserved for a strip at `pos`, zero if there are no notes there.
+    pub fn strip_height(&self, pos: Position, zoom: f32) -> f32 {
+        if pos.is_strip() && self.at(pos).next().is_some() {
+            16.0 * zoom
+        } else {
+            0.0
+        }
+    }
+
+    /// Render the notes in a strip at `pos`, taking up [`Notes::strip_height`].
+    pub fn render_strip(&self, ui: &mut Ui, pos: Position, zoom: f32) {
+        let height = self.strip_height(pos, zoom);
+        if height == 0.0 {
+            return;
+        }
+
+        ui.allocate_ui(vec2(ui.available_width(), height), |ui| {
+            Frame::none()
+                .stroke((1. * zoom, Color32::BLACK))
+                .inner_margin(2.0 * zoom)
+                .show(ui, |ui| {
+                    ui.set_height(height - 4.0 * zoom);
+                    ui.set_width(ui.available_width());
+                    ui.horizontal_centered(|ui| {
+                        ui.spacing_mut().item_spacing.x = 6.0 * zoom;
+                        for (i, note) in self.at(pos).enumerate() {
+                            if i > 0 {
+                                ui.label(RichText::new("•").strong());
+                            }
+                            ui.add(Label::new(&note.text).truncate(true));
+                        }
+                    });
+                });
+        });
+    }
+
+    /// Render every note as a list, wherever it is placed, for layouts which reserve room for
+    /// the notes.
+    pub fn render_block(&self, ui: &mut Ui, zoom: f32) {
+        Frame::none()
+            .stroke((1. * zoom, Color32::BLACK))
+            .inner_margin(4.0 * zoom)
+            .show(ui, |ui| {
+                ui.set_width(ui.available_width());
+                for note in &self.notes {
+                    ui.add(Label::new(&note.text).wrap(true));
+                }
+            });
+    }
+
+    /// Paint the notes which are placed in corners over the top of the frame.
+    /// `area` is the region (excluding the header) the corners are relative to.
+    pub fn paint_corners(&self, ui: &mut Ui, area: egui::Rect, zoom: f32) {
+        let corners = [
+            Position::TopRight,
+            Position::BottomLeft,
+            Position::BottomRight,
+        ];
+
+        for (pos, align) in corners.into_iter().filter_map(|x| Some((x, x.corner()?))) {
+            let text = self.at(pos).map(|x| x.text.as_str()).collect::<Vec<_>>();
+            if text.is_empty() {
+                continue;
+            }
+
+            let painter = ui.painter();
+            let galley = painter.layout(
+                text.join("\n"),
+                egui::TextStyle::Body.resolve(ui.style()),
+                Color32::BLACK,
+                area.width() * 0.4,
+            );
+            let margin = 4.0 * zoom;
+            let inner = align.align_size_within_rect(galley.size(), area.shrink(margin * 2.0));
+            let outer = inner.expand(margin);
+            draw::rounded_box(
+                painter,
+                outer,
+                3.0 * zoom,
+                (1.5 * zoom, Color32::BLACK),
+                Some(draw::Fill::Solid(Color32::WHITE)),
+            );
+            let x = match align.x() {
+                Align::Max => inner.right() - galley.size().x,
+                _ => inner.left(),
+            };
+            painter.galley(egui::pos2(x, inner.top()), galley);
+        }
+    }
+}
+
+// ##### COUNTDOWN #############################################################
+
+/// Counts the days down to a date, such as "14 days until school holidays".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Countdown {
+    pub label: String,
+    pub date: Date,
+    /// Where to place the countdown, defaults to the header.
+    #[serde(default)]
+    pub position: Option<Position>,
+}
+
+impl Countdown {
+    /// The countdown text, `None` once the date has passed.
+    pub fn text(&self, today: Date) -> Option<String> {
+        let Self { label, date, .. } = self;
+        match (*date - today).whole_days() {
+            ..=-1 => None,
+            0 => Some(format!("{label} today!")),
+            1 => Some(format!("1 day until {label}")),
+            n => Some(format!("{n} days until {label}")),
+        }
+    }
+
+    /// Render in the header.
+    pub fn render_header(&self, ui: &mut Ui, today: Date) {
+        if let Some(text) = self.text(today) {
+            ui.label(RichText::new(text).heading().strong());
+        }
+    }
+
+    /// The countdown as a note, if it is not placed in the header.
+    pub fn to_note(&self, today: Date) -> Option<Note> {
+        Some(Note {
+            position: self.position?,
+            text: self.text(today)?,
+        })
+    }
+}
+
+// ##### MINI MONTH ##########################################################
+
+/// A small "next month at a glance" calendar, with a dot under days with events.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MiniMonth {
+    /// The corner to place the inset in.
+    #[serde(default = "default_inset_position")]
+    pub position: Position,
+}
+
+fn default_inset_position() -> Position {
+    Position::BottomRight
+}
+
+impl Default for MiniMonth {
+    fn default() -> Self {
+        Self {
+            position: default_inset_position(),
+        }
+    }
+}
+
+impl MiniMonth {
+    /// Paint the month of `date` in a corner of `area`, `busy` is whether a day has events.
+    pub fn paint(
+        &self,
+        ui: &mut Ui,
+        area: Rect,
+        date: Date,
+        busy: impl Fn(Date) -> bool,
+        zoom: f32,
+    ) {
+        let Some(align) = self.position.corner() else {
+            return;
+        };
+        let first = date.replace_day(1).expect("valid day");
+        let start = super::week_start(first);
+        let end = super::week_end(super::end_of_month(first));
+        let weeks = ((end - start).whole_days() + 1) / 7;
+
+        let cell = vec2(14.0, 14.0) * zoom;
+        let margin = 4.0 * zoom;
+        // a title row and a weekday row above the weeks
+        let size = vec2(cell.x * 7.0, cell.y * (weeks as f32 + 2.0));
+        let inner = align.align_size_within_rect(size, area.shrink(margin * 2.0));
+        draw::rounded_box(
+            ui.painter(),
+            inner.expand(margin),
+            3.0 * zoom,
+            (1.5 * zoom, Color32::BLACK),
+            Some(draw::Fill::Solid(Color32::WHITE)),
+        );
+
+        let painter = ui.painter();
+        let font = egui::FontId::proportional(8.0 * zoom);
+        let at = |col: f32, row: f32| inner.min + vec2((col + 0.5) * cell.x, (row + 0.5) * cell.y);
+        let title = format!("{} {}", date.month(), date.year());
+        painter.text(
+            at(3.0, 0.0),
+            Align2::CENTER_CENTER,
+            title,
+            egui::FontId::proportional(9.0 * zoom),
+            Color32::BLACK,
+        );
+        for (col, d) in ["M", "T", "W", "T", "F", "S", "S"].into_iter().enumerate() {
+            painter.text(
+                at(col as f32, 1.0),
+                Align2::CENTER_CENTER,
+                d,
+                font.clone(),
+                Color32::DARK_GRAY,
+            );
+        }
+
+        let days = std::iter::successors(Some(start), |x| x.next_day()).take_while(|x| x <= &end);
+        for (i, day) in days.enumerate() {
+            if day.month() != first.month() {
+                continue;
+            }
+            let pos = at((i % 7) as f32, (i / 7) as f32 + 2.0);
+            painter.text(
+                pos - vec2(0.0, 1.5 * zoom),
+                Align2::CENTER_CENTER,
+                day.day().to_string(),
+                font.clone(),
+                Color32::BLACK,
+            );
+            if busy(day) {
+                painter.circle_filled(pos + vec2(0.0, 5.0 * zoom), 1.2 * zoom, Color32::BLACK);
+            }
+        }
+    }
+}
+
+// ##### PICTURE ###############################################################
+
+/// Config for a static image placed on the frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PictureConfig {
+    /// Path to a PNG/BMP image.
+    pub path: PathBuf,
+    /// Top left position, in points.
+    pub pos: [f32; 2],
+    /// Size in points, defaults to the image size.
+    #[serde(default)]
+    pub size: Option<[f32; 2]>,
+}
+
+/// A static image, such as a family crest or a seasonal picture, converted to grayscale.
+#[derive(Clone)]
+pub struct Picture {
+    pub rect: Rect,
+    img: Arc<GrayAlphaImage>,
+}
+
+impl Picture {
+    pub fn load(cfg: &PictureConfig) -> Result<Self> {
+        let PictureConfig { path, pos, size } = cfg;
+        let img = image::open(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to open image {}", path.display()))?
+            .into_luma_alpha8();
+        let size = size.unwrap_or([img.width() as f32, img.height() as f32]);
+        Ok(Self {
+            rect: Rect::from_min_size((*pos).into(), size.into()),
+            img: Arc::new(img),
+        })
+    }
+
+    /// Paint the image, drawn straight into the framebuffer with nearest neighbour scaling.
+    pub fn paint(&self, ui: &mut Ui) {
+        let img = self.img.clone();
+        let cb = SoftwareCallback::new(move |info, buf| {
+            let [x0, y0, x1, y1] = callback_pixel_bounds(info);
+            let vp = info.viewport;
+            let ppp = info.pixels_per_point;
+            let (iw, ih) = (img.width(), img.height());
+            for y in y0..y1 {
+                let v = (y as f32 + 0.5 - vp.top() * ppp) / (vp.height() * ppp);
+                let iy = ((v * ih as f32) as u32).min(ih - 1);
+                for x in x0..x1 {
+                    let u = (x as f32 + 0.5 - vp.left() * ppp) / (vp.width() * ppp);
+                    let ix = ((u * iw as f32) as u32).min(iw - 1);
+                    let [l, a] = img.get_pixel(ix, iy).0;
+                    let px = Rgba::from(Color32::from_rgba_unmultiplied(l, l, l, a));
+                    buf.blend(x, y, px);
+                }
+            }
+        });
+        ui.painter().add(cb.paint_callback(self.rect));
+    }
+}
+
+// ##### SCREENS ###############################################################
+
+/// A whole-frame screen, such as the startup splash or the shutdown farewell.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ScreenConfig {
+    /// Path to a PNG/BMP image, centred and scaled to fit.
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+    /// Text shown under the image. `{version}`, `{date}`, and `{time}` are substituted.
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Screen {
+    picture: Option<Picture>,
+    text: Option<String>,
+}
+
+impl Screen {
+    pub fn load(cfg: &ScreenConfig) -> Result<Self> {
+        let picture = cfg
+            .image
+            .as_ref()
+            .map(|path| {
+                Picture::load(&PictureConfig {
+                    path: path.clone(),
+                    pos: [0.0; 2],
+                    size: None,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            picture,
+            text: cfg.text.clone(),
+        })
+    }
+}
+
+/// Replace `{key}`s in `template` with their values.
+pub fn fill_template(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(template.to_string(), |s, (k, v)| {
+        s.replace(&format!("{{{k}}}"), v)
+    })
+}
+
+impl Render<(f32, &[(&str, String)])> for Screen {
+    fn render(&self, ui: &mut Ui, (zoom, vars): (f32, &[(&str, String)])) {
+        let screen = ui.max_rect();
+        let text_height = if self.text.is_some() {
+            60.0 * zoom
+        } else {
+            0.0
+        };
+        let mut text_pos = screen.center();
+
+        if let Some(pic) = &self.picture {
+            let mut avail = screen.shrink(20.0 * zoom);
+            avail.set_bottom(avail.bottom() - text_height);
+            let size = pic.rect.size();
+            let scale = (avail.width() / size.x).min(avail.height() / size.y);
+            let rect = Rect::from_center_size(avail.center(), size * scale);
+            text_pos = egui::pos2(screen.center().x, rect.bottom() + text_height * 0.5);
+            Picture {
+                rect,
+                img: pic.img.clone(),
+            }
+            .paint(ui);
+        }
+
+        if let Some(text) = &self.text {
+            ui.painter().text(
+                text_pos,
+                Align2::CENTER_CENTER,
+                fill_template(text, vars),
+                egui::FontId::proportional(24.0 * zoom),
+                Color32::BLACK,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn countdown_text() {
+        let c = Countdown {
+            label: "school holidays".to_string(),
+            date: date!(2024 - 04 - 05),
+            position: None,
+        };
+        assert_eq!(
+            c.text(date!(2024 - 03 - 22)).as_deref(),
+            Some("14 days until school holidays")
+        );
+        assert_eq!(
+            c.text(date!(2024 - 04 - 04)).as_deref(),
+            Some("1 day until school holidays")
+        );
+        assert_eq!(
+            c.text(date!(2024 - 04 - 05)).as_deref(),
+            Some("school holidays today!")
+        );
+        assert_eq!(c.text(date!(2024 - 04 - 06)), None);
+    }
+
+    #[test]
+    fn template() {
+        let vars = [
+            ("version", "0.1".to_string()),
+            ("date", "today".to_string()),
+        ];
+        assert_eq!(
+            fill_template("pical v{version} ({date}) {unknown}", &vars),
+            "pical v0.1 (today) {unknown}"
+        );
+    }
+}
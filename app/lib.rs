@@ -2,11 +2,28 @@
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
+pub mod compare;
+pub mod contrast;
+pub mod control;
 pub mod data;
+pub mod desktop;
+pub mod dither;
+pub mod driver;
 pub mod fetch;
 pub mod layout;
+pub mod logs;
+pub mod mqtt;
+pub mod policy;
+pub mod preview;
+pub mod remote;
 pub mod render;
+pub mod rotation;
+pub mod run;
+pub mod service;
+pub mod sink;
 pub mod state;
+pub mod tone;
+pub mod wear;
 
 #[cfg(test)]
 mod test {
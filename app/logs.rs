@@ -0,0 +1,149 @@
+//! An in-memory ring buffer of the most recent log records.
+//!
+//! Served at `/logs` by the control listener and written into crash reports, so a misbehaving
+//! frame can be debugged without SSHing in to tail the log file.
+use log::{LevelFilter, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use time::{macros::format_description, OffsetDateTime};
+
+/// The most recent lines, oldest first.
+pub struct Ring {
+    lines: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl Ring {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        })
+    }
+
+    /// The lines, even if a panic poisoned the lock, as they are wanted for the crash report.
+    fn lock(&self) -> MutexGuard<'_, VecDeque<String>> {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn push(&self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lock();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// The last `n` lines, oldest first.
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        let lines = self.lock();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// All the lines, joined.
+    pub fn dump(&self) -> String {
+        let lines = self.lock();
+        lines.iter().fold(String::new(), |s, x| s + x + "\n")
+    }
+}
+
+/// A logger which records into a [`Ring`], to be combined with the file and terminal loggers.
+pub struct RingLogger {
+    level: LevelFilter,
+    /// Only records with a target starting with this are kept.
+    target: &'static str,
+    ring: Arc<Ring>,
+}
+
+impl RingLogger {
+    pub fn new(level: LevelFilter, target: &'static str, ring: Arc<Ring>) -> Box<Self> {
+        Box::new(Self {
+            level,
+            target,
+            ring,
+        })
+    }
+}
+
+impl Log for RingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with(self.target)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let at = OffsetDateTime::now_utc()
+            .format(format_description!("[hour]:[minute]:[second]Z"))
+            .unwrap_or_default();
+        self.ring
+            .push(format!("{at} [{}] {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+impl simplelog::SharedLogger for RingLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&simplelog::Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_most_recent() {
+        let ring = Ring::new(3);
+        for i in 0..5 {
+            ring.push(i.to_string());
+        }
+        assert_eq!(ring.tail(10), ["2", "3", "4"]);
+        assert_eq!(ring.tail(2), ["3", "4"]);
+        assert_eq!(ring.dump(), "2\n3\n4\n");
+
+        let none = Ring::new(0);
+        none.push("x".to_string());
+        assert!(none.tail(1).is_empty());
+    }
+
+    #[test]
+    fn filters_records() {
+        let ring = Ring::new(10);
+        let logger = RingLogger::new(LevelFilter::Info, "pical", ring.clone());
+        let log = |target, level, msg| {
+            logger.log(
+                &Record::builder()
+                    .target(target)
+                    .level(level)
+                    .args(format_args!("{msg}"))
+                    .build(),
+            )
+        };
+        log("pical::data", log::Level::Warn, "kept");
+        log("pical", log::Level::Debug, "too verbose");
+        log("hyper", log::Level::Warn, "not ours");
+        let lines = ring.tail(10);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("[WARN] kept"));
+    }
+}
@@ -1,31 +1,75 @@
 use miette::*;
-use pical::state::Dispatch;
+use pical::{
+    policy::{Push, Waveform},
+    render::Region,
+    run::{
+        log_error, paint_frame, FetchLoop, Frame, PanelFuture, Passes, Policies, RenderLoop, State,
+        SystemClock, FRAME_PATH,
+    },
+    sink::{BmpFile, FrameSink, PngFile},
+    state::{Dispatch, Lane},
+};
 use serde::{Deserialize, Serialize};
 use std::{
     future::Future,
     path::{Path, PathBuf},
-    pin::Pin,
+    sync::{Arc, OnceLock},
     time::{Duration, Instant},
 };
 use time::{OffsetDateTime, UtcOffset};
 use tokio::{
-    io::AsyncWriteExt,
     sync::Mutex,
     time::{interval, MissedTickBehavior},
 };
 
+const CONFIG_PATH: &str = "./config.pical.toml";
+
 fn main() -> Result<()> {
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .into_diagnostic()?
-        .block_on(main_())
+    RuntimeConfig::read(CONFIG_PATH)?.build()?.block_on(main_())
 }
 
 async fn main_() -> Result<()> {
+    let cpath = CONFIG_PATH;
+
+    // a command for an already running pical, handled before logging as to not clobber its log
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|x| x == "diff-config") {
+        return diff_config(&args[1..]).await;
+    }
+    if args.first().is_some_and(|x| x == "--simulate") {
+        return simulate(&args[1..]).await;
+    }
+    if args.first().is_some_and(|x| x == "bench-render") {
+        return bench_render(&args[1..]).await;
+    }
+    if !args.is_empty() {
+        let cmd = pical::control::Command::from_args(&args)?;
+        let control = Config::read_or_default(cpath)
+            .await?
+            .control
+            .ok_or_else(|| miette!("no [control] section in {cpath}"))?;
+        let client = pical::fetch::client(Duration::from_secs(20), None)?;
+        let resp = pical::control::send(&client, &control, cmd).await?;
+        println!("{resp}");
+        return Ok(());
+    }
+
     init_logging()?;
 
-    let cpath = "./config.pical.toml";
+    let config = Config::read_or_default(cpath).await?;
+    log::info!("✅ read in config from {cpath}");
+
+    let annotation = format!(
+        "pical v{} ({}) cfg:{:08x}",
+        env!("CARGO_PKG_VERSION"),
+        env!("PICAL_GIT_HASH"),
+        config.fingerprint()
+    );
+    log::info!("ℹ {annotation}");
+    let annotation = config.annotate.then_some(annotation);
+    // checked once past the agent, which doesn't render
+    let layout = config.layout(annotation.clone());
+
     let Config {
         width,
         height,
@@ -34,22 +78,139 @@ async fn main_() -> Result<()> {
         display_refresh,
         timezone,
         calendars,
+        rewrites,
+        calendar_auth,
         coords,
         stormglassio_apikey,
-    } = Config::read_or_default(cpath).await?;
-    log::info!("✅ read in config from {cpath}");
+        air_quality,
+        tides_apikey,
+        weather_ensemble,
+        weather_provider,
+        pages: _,
+        annotate: _,
+        notes: _,
+        countdowns: _,
+        annual,
+        graph_calendars,
+        pictures: _,
+        battery,
+        remote,
+        agent,
+        desktop,
+        preview,
+        stale_after: _,
+        splash,
+        farewell,
+        control,
+        mqtt,
+        header: _,
+        holidays,
+        news,
+        theme,
+        control_calendar: _,
+        inset: _,
+        layouts: _,
+        timeline_hours: _,
+        event_times: _,
+        family: _,
+        vacation,
+        quiet,
+        cadences,
+        raw_frames,
+        contrast,
+        tone,
+        dither,
+        merge_duplicates,
+        driver_socket,
+        proxy,
+        runtime: _,
+    } = config;
+    let canvas = Canvas {
+        width,
+        height,
+        scaling,
+        zoom,
+        offset: timezone,
+    };
 
-    #[cfg(not(feature = "local"))]
-    start_it8951_driver().await?;
-    let state = State {
-        layout: pical::layout::Layout {
-            zoom,
-            mode: pical::layout::TwelveDay.into(),
-            ..Default::default()
-        },
-        push_bitmap: |img, old| Box::pin(async move { push_bitmap(&img, old.as_deref()).await }),
+    let [splash, farewell] = [splash, farewell].map(|x| {
+        x.as_ref()
+            .map(pical::layout::widgets::Screen::load)
+            .transpose()
+    });
+    let (splash, farewell) = (splash?, farewell?);
+    let show = move |screen: Option<pical::layout::widgets::Screen>| async move {
+        if let Some(screen) = screen {
+            if let Err(e) = show_screen(&screen, canvas).await {
+                log_error(e.wrap_err("failed to show screen"));
+            }
+        }
+    };
+
+    match pical::wear::PanelStats::load(Path::new(PANEL_STATS_PATH)) {
+        Ok(x) => *PANEL_STATS.lock().expect("panel stats lock poisoned") = x,
+        Err(e) => log_error(e.wrap_err("panel statistics start from zero")),
+    }
+
+    #[cfg(feature = "web-ui")]
+    if let Some(preview) = preview {
+        let latest = pical::preview::Latest::default();
+        let _ = PREVIEW.set(latest.clone());
+        tokio::spawn(async move {
+            if let Err(e) = pical::preview::serve(&preview, latest).await {
+                log_error(e.wrap_err("preview server failed"));
+            }
+        });
+    }
+
+    if let Some(agent) = agent {
+        start_it8951_driver(driver_socket).await?;
+        show(splash).await;
+        // the agent only waits for frames, so the watchdog can only check the runtime is alive
+        tokio::spawn(async {
+            let mut timer = interval(Duration::from_secs(60));
+            loop {
+                timer.tick().await;
+                pical::service::notify(pical::service::WATCHDOG);
+            }
+        });
+        pical::service::notify(pical::service::READY);
+        return until_shutdown(run_agent(&agent), show(farewell)).await;
+    }
+
+    let (layout, rotation) = layout?;
+
+    match (remote, desktop) {
+        (Some(remote), _) => {
+            if let Some(key) = &remote.signing_key {
+                let key = pical::remote::signing_key(key)?;
+                log::info!(
+                    "🔑 Signing frames, agent verify key: {}",
+                    pical::remote::verify_key_of(&key)
+                );
+            }
+            let _ = REMOTE.set(remote);
+        }
+        (None, Some(desktop)) => {
+            let monitor = pical::desktop::Monitor::open(desktop, [width, height])?;
+            log::info!("🖥 Showing frames in a desktop window");
+            let _ = DESKTOP.set(monitor);
+        }
+        #[cfg(not(feature = "local"))]
+        (None, None) => start_it8951_driver(driver_socket).await?,
+        #[cfg(feature = "local")]
+        (None, None) => (),
+    }
+    show(splash).await;
+    let mut state = State {
+        layout,
         ..Default::default()
     };
+    let injected = pical::data::injected::Saved::load(Path::new(INJECTED_PATH))
+        .and_then(|x| x.restore(state.model.make_mut()));
+    if let Err(e) = injected {
+        log_error(e.wrap_err("injected events were not restored"));
+    }
 
     let (dispatch, state_loop) = pical::state::dispatcher(state);
     tokio::spawn(state_loop);
@@ -58,23 +219,176 @@ async fn main_() -> Result<()> {
         dispatch.clone(),
         Duration::from_secs(31),
         timezone,
+        theme,
+        coords,
     ));
-    tokio::spawn(fetch_loop(
+    let mut sources = pical::data::source::Registry::default();
+    let rewrites_of = |name: &str| {
+        pical::data::cal::Rewrites::for_calendar(&rewrites, name)
+            .wrap_err("invalid rewrites in config")
+    };
+    if let Some(auth) = calendar_auth
+        .iter()
+        .find(|x| !calendars.iter().any(|(name, _)| *name == x.calendar))
+    {
+        return Err(miette!("no calendar named '{}'", auth.calendar))
+            .wrap_err("invalid calendar_auth in config");
+    }
+    for (name, url) in calendars {
+        let rewrites = rewrites_of(&name)?;
+        let auth = calendar_auth
+            .iter()
+            .find(|x| x.calendar == name)
+            .map(|x| pical::fetch::auth::Auth::new(x.credentials.clone()));
+        sources.register(pical::data::cal::IcalSource {
+            name,
+            url,
+            rewrites,
+            auth,
+        });
+    }
+    for cfg in graph_calendars {
+        let mut session = pical::data::graph::Session::new(cfg);
+        session.rewrites = rewrites_of(&session.cfg.name)?;
+        sources.register(session);
+    }
+    pical::data::weather::register(&mut sources, weather_provider, coords, weather_ensemble);
+    if air_quality {
+        pical::data::air::register(&mut sources, coords);
+    }
+    sources.register(pical::data::sun::Local { coords });
+    pical::data::tide::register(&mut sources, coords, tides_apikey);
+    pical::data::moon::register(&mut sources, coords, stormglassio_apikey);
+    if !annual.is_empty() {
+        sources.register(pical::data::annual::AnnualEvents(annual));
+    }
+    if let Some(cfg) = battery {
+        sources.register(cfg);
+    }
+    if let Some(cfg) = holidays {
+        sources.register(cfg);
+    }
+    if let Some(cfg) = news {
+        if cfg.feeds.is_empty() || cfg.top == 0 {
+            return Err(miette!("news needs at least one feed and a positive top"))
+                .wrap_err("invalid news in config");
+        }
+        sources.register(cfg);
+    }
+    log::info!(
+        "ℹ Data sources: {}",
+        sources.names().collect::<Vec<_>>().join(", ")
+    );
+    let client = pical::fetch::client(Duration::from_secs(20), proxy.as_ref())?;
+    let fetch = FetchLoop::new(
         dispatch.clone(),
-        coords,
-        calendars,
-        stormglassio_apikey,
-        Duration::from_secs(61),
-    )?);
-    render_loop(dispatch, display_refresh, width, height, scaling).await
+        SystemClock,
+        client,
+        sources,
+        merge_duplicates,
+    );
+    tokio::spawn(fetch.run(Duration::from_secs(61)));
+    #[cfg(feature = "web-ui")]
+    if let Some(control) = control {
+        tokio::spawn(control_loop(dispatch.clone(), control, canvas));
+    }
+    #[cfg(not(feature = "web-ui"))]
+    if control.is_some() || preview.is_some() {
+        log::warn!(
+            "pical was built without the web-ui feature, [control] and [preview] are ignored"
+        );
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = mqtt {
+        tokio::spawn(mqtt_loop(dispatch.clone(), mqtt, canvas));
+    }
+    #[cfg(not(feature = "mqtt"))]
+    if mqtt.is_some() {
+        log::warn!("pical was built without the mqtt feature, [mqtt] is ignored");
+    }
+    let mut sinks = Vec::<Box<dyn FrameSink + Send>>::new();
+    if let Some(preview) = PREVIEW.get() {
+        sinks.push(Box::new(preview.clone()));
+    }
+    if let Some(text) = annotation {
+        sinks.push(Box::new(PngFile::new("./frame.pical.png").annotated(text)));
+    }
+    let painting = pical::run::Painting {
+        rotation,
+        size: [width, height],
+        scaling,
+        passes: Passes {
+            contrast,
+            tone,
+            dither,
+        },
+        sinks,
+    };
+    if let Some(quiet) = &quiet {
+        quiet.validate().wrap_err("invalid [quiet] in config")?;
+    }
+    let policies = Policies {
+        vacation,
+        quiet,
+        raw_frames,
+        schedule: pical::policy::Schedule::new(if cadences.is_empty() {
+            pical::policy::Schedule::default_cadences()
+        } else {
+            cadences
+        }),
+    };
+    let render =
+        RenderLoop::new(dispatch, SystemClock, Output, painting, policies).run(display_refresh);
+    pical::service::notify(pical::service::READY);
+    until_shutdown(render, show(farewell)).await
+}
+
+/// Run `fut` until it finishes or a shutdown signal (Ctrl-C/SIGTERM) arrives, in which case
+/// `on_shutdown` is run.
+async fn until_shutdown(
+    fut: impl Future<Output = Result<()>>,
+    on_shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    tokio::select! {
+        x = fut => x,
+        x = shutdown_signal() => {
+            x?;
+            log::info!("👋 Shutting down");
+            pical::service::notify(pical::service::STOPPING);
+            on_shutdown.await;
+            Ok(())
+        }
+    }
 }
 
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).into_diagnostic()?;
+        tokio::select! {
+            x = tokio::signal::ctrl_c() => x.into_diagnostic(),
+            _ = term.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.into_diagnostic()
+}
+
+/// Log lines kept in memory, for `pical logs` and crash reports.
+const LOG_RING_LINES: usize = 500;
+
+static LOGS: OnceLock<Arc<pical::logs::Ring>> = OnceLock::new();
+
 fn init_logging() -> Result<()> {
     let lvl = log::LevelFilter::Debug;
     let config = simplelog::ConfigBuilder::default()
         .add_filter_allow_str("pical")
         .build();
+    let ring = LOGS.get_or_init(|| pical::logs::Ring::new(LOG_RING_LINES));
+    write_crash_reports();
     simplelog::CombinedLogger::init(vec![
+        pical::logs::RingLogger::new(lvl, "pical", ring.clone()),
         simplelog::WriteLogger::new(
             lvl,
             config.clone(),
@@ -91,6 +405,78 @@ fn init_logging() -> Result<()> {
     .wrap_err("initialising logging failed")
 }
 
+/// On a panic, write the panic and the recent log lines to a crash report.
+fn write_crash_reports() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let logs = LOGS.get().map(|x| x.dump()).unwrap_or_default();
+        let report = format!("pical v{} {info}\n\n{logs}", env!("CARGO_PKG_VERSION"));
+        let path = format!(
+            "crash-{}.pical.log",
+            OffsetDateTime::now_utc().unix_timestamp()
+        );
+        if let Err(e) = std::fs::write(&path, report) {
+            eprintln!("failed to write crash report {path}: {e}");
+        }
+        hook(info)
+    }));
+}
+
+/// The tokio runtime to use, read before anything else in the config.
+#[derive(Default, Serialize, Deserialize)]
+struct RuntimeConfig {
+    /// Use a multi-threaded runtime, rather than a single thread.
+    #[serde(default)]
+    multi_thread: bool,
+    /// Worker threads of the multi-threaded runtime, defaults to the number of cores.
+    #[serde(default)]
+    worker_threads: Option<usize>,
+    /// Limit the threads used for blocking work such as painting and file IO.
+    #[serde(default)]
+    max_blocking_threads: Option<usize>,
+}
+
+impl RuntimeConfig {
+    /// Read the `[runtime]` section of the config at `path`, if it exists.
+    fn read(path: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Partial {
+            #[serde(default)]
+            runtime: RuntimeConfig,
+        }
+
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let s = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {path}"))?;
+        toml::from_str::<Partial>(&s)
+            .map(|x| x.runtime)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to deserialize runtime config in {path}"))
+    }
+
+    fn build(&self) -> Result<tokio::runtime::Runtime> {
+        let mut b = if self.multi_thread {
+            let mut b = tokio::runtime::Builder::new_multi_thread();
+            if let Some(x) = self.worker_threads {
+                b.worker_threads(x);
+            }
+            b
+        } else {
+            tokio::runtime::Builder::new_current_thread()
+        };
+        if let Some(x) = self.max_blocking_threads {
+            b.max_blocking_threads(x);
+        }
+        b.enable_all()
+            .build()
+            .into_diagnostic()
+            .wrap_err("failed to build tokio runtime")
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct Config {
     width: u32,
@@ -101,8 +487,160 @@ struct Config {
     display_refresh: Duration,
     timezone: UtcOffset,
     calendars: Vec<(String, String)>,
+    /// Find and replace rules for event summaries, applied as calendars are fetched.
+    #[serde(default)]
+    rewrites: Vec<pical::data::cal::RewriteConfig>,
+    /// HTTP basic auth for calendars in `calendars`.
+    #[serde(default)]
+    calendar_auth: Vec<pical::data::cal::CalendarAuth>,
     coords: [f32; 2],
+    /// Fetch the lunar phases from stormglass.io rather than computing them.
+    #[serde(default)]
     stormglassio_apikey: String,
+    /// Fetch air quality (PM2.5/AQI) and show it in the header.
+    #[serde(default)]
+    air_quality: bool,
+    /// Fetch the high and low tides from stormglass.io, for the tide header widget. A key of its
+    /// own, so setting it doesn't also fetch the lunar phases.
+    #[serde(default)]
+    tides_apikey: String,
+    /// Show the daily maximum temperature as the range of an ensemble forecast.
+    #[serde(default)]
+    weather_ensemble: bool,
+    /// Where the forecast comes from, the ensemble and air quality are always Open-Meteo's.
+    #[serde(default)]
+    weather_provider: pical::data::weather::Provider,
+    /// The layout pages to cycle through.
+    #[serde(default = "default_pages")]
+    pages: Vec<pical::rotation::Page>,
+    /// Paint the version and config fingerprint on the frame, and save an annotated PNG.
+    #[serde(default)]
+    annotate: bool,
+    /// Static note blocks to display.
+    #[serde(default)]
+    notes: Vec<pical::layout::widgets::Note>,
+    /// Countdowns to dates.
+    #[serde(default)]
+    countdowns: Vec<pical::layout::widgets::Countdown>,
+    /// Dates which recur every year, such as anniversaries.
+    #[serde(default)]
+    annual: Vec<pical::data::annual::Annual>,
+    /// Outlook/Microsoft 365 calendars, authorised with a device code on first use.
+    #[serde(default)]
+    graph_calendars: Vec<pical::data::graph::GraphConfig>,
+    /// Static images placed on the frame.
+    #[serde(default)]
+    pictures: Vec<pical::layout::widgets::PictureConfig>,
+    /// Monitor a PiSugar battery.
+    #[serde(default)]
+    battery: Option<pical::data::battery::BatteryConfig>,
+    /// Push frames to an agent over the network instead of a local panel.
+    #[serde(default)]
+    remote: Option<pical::remote::RemoteConfig>,
+    /// Run as an agent, displaying frames pushed from a render server.
+    #[serde(default)]
+    agent: Option<pical::remote::AgentConfig>,
+    /// Show frames in a window on a desktop e-ink monitor instead of a local panel.
+    #[serde(default)]
+    desktop: Option<pical::desktop::DesktopConfig>,
+    /// Serve a live preview of the latest frame over HTTP.
+    #[serde(default)]
+    preview: Option<pical::preview::PreviewConfig>,
+    /// How old data can be before a prominent warning is shown.
+    #[serde(default = "default_stale_after", with = "humantime_serde")]
+    stale_after: Duration,
+    /// Screen shown at startup.
+    #[serde(default)]
+    splash: Option<pical::layout::widgets::ScreenConfig>,
+    /// Screen shown when shutting down.
+    #[serde(default)]
+    farewell: Option<pical::layout::widgets::ScreenConfig>,
+    /// Listen for commands such as `pical maintenance on`.
+    #[serde(default)]
+    control: Option<pical::control::ControlConfig>,
+    /// Take the same commands over MQTT, such as from a home automation hub.
+    #[serde(default)]
+    mqtt: Option<pical::mqtt::MqttConfig>,
+    /// Ids of the widgets in the header, from the right.
+    #[serde(default = "pical::layout::default_header")]
+    header: Vec<String>,
+    /// Highlight public holidays.
+    #[serde(default)]
+    holidays: Option<pical::data::holiday::HolidayConfig>,
+    /// Headlines from RSS or Atom feeds, for the news widget.
+    #[serde(default)]
+    news: Option<pical::data::news::NewsConfig>,
+    /// Light/dark theme, and when to switch automatically.
+    #[serde(default)]
+    theme: pical::layout::theme::ThemeConfig,
+    /// Name of a calendar whose `pical:` events override the display while they run.
+    #[serde(default)]
+    control_calendar: Option<String>,
+    /// A small calendar of next month, shown in the twelve-day and agenda modes.
+    #[serde(default)]
+    inset: Option<pical::layout::widgets::MiniMonth>,
+    /// Layouts composed of rows of widgets, selected by name in `pages`.
+    #[serde(default)]
+    layouts: Vec<pical::layout::composer::Template>,
+    /// The `[start, end]` hours shown in the timeline mode.
+    #[serde(default = "default_timeline_hours")]
+    timeline_hours: [u8; 2],
+    /// How event times are shown in the day cells.
+    #[serde(default)]
+    event_times: pical::layout::EventTimes,
+    /// The people with a column in the family mode, which is only available when set.
+    #[serde(default)]
+    family: Vec<pical::layout::Member>,
+    /// Refresh once a day while an all-day vacation event is on.
+    #[serde(default)]
+    vacation: Option<pical::policy::Vacation>,
+    /// Stop refreshing during these hours, with the panel asleep.
+    #[serde(default)]
+    quiet: Option<pical::policy::QuietHours>,
+    /// Send frames to the it8951-driver as raw pixels, rather than saving them to
+    /// `frame.pical.bmp` for it to read, sparing the SD card a write each push.
+    #[serde(default)]
+    raw_frames: bool,
+    /// How each area of the frame is refreshed, defaults to changes with DU4 and a periodic
+    /// full refresh.
+    #[serde(default)]
+    cadences: Vec<pical::policy::Cadence>,
+    /// Boost the contrast of mid-greys before reducing to the panel's levels.
+    #[serde(default)]
+    contrast: Option<pical::contrast::Contrast>,
+    /// Darken the mid-tones, which e-ink crushes, and stretch the contrast.
+    #[serde(default)]
+    tone: Option<pical::tone::ToneCurve>,
+    /// How frames are reduced to the panel's 16 grey levels.
+    #[serde(default)]
+    dither: pical::dither::Dither,
+    /// Merge recurring series which appear twice, such as after migrating a calendar.
+    #[serde(default)]
+    merge_duplicates: bool,
+    /// Connect to an it8951-driver listening on this Unix socket, rather than starting one.
+    #[serde(default)]
+    driver_socket: Option<PathBuf>,
+    /// Fetch through this proxy.
+    #[serde(default)]
+    proxy: Option<pical::fetch::Proxy>,
+    /// The async runtime, see [`RuntimeConfig`].
+    #[serde(default)]
+    runtime: RuntimeConfig,
+}
+
+fn default_stale_after() -> Duration {
+    Duration::from_secs(3 * 60 * 60)
+}
+
+fn default_timeline_hours() -> [u8; 2] {
+    [7, 19]
+}
+
+fn default_pages() -> Vec<pical::rotation::Page> {
+    vec![pical::rotation::Page {
+        mode: "twelve-day".to_string(),
+        dwell: Duration::from_secs(60 * 60),
+    }]
 }
 
 impl Default for Config {
@@ -118,8 +656,52 @@ impl Default for Config {
                 "Name".to_string(),
                 "https://calendar.google.com/calendar/ical/path-to-cal".to_string(),
             )],
+            rewrites: Vec::new(),
+            calendar_auth: Vec::new(),
             coords: [0.; 2],
             stormglassio_apikey: String::new(),
+            air_quality: false,
+            tides_apikey: String::new(),
+            weather_ensemble: false,
+            weather_provider: Default::default(),
+            pages: default_pages(),
+            annotate: false,
+            notes: Vec::new(),
+            countdowns: Vec::new(),
+            annual: Vec::new(),
+            graph_calendars: Vec::new(),
+            pictures: Vec::new(),
+            battery: None,
+            remote: None,
+            agent: None,
+            desktop: None,
+            preview: None,
+            stale_after: default_stale_after(),
+            splash: None,
+            farewell: None,
+            control: None,
+            mqtt: None,
+            header: pical::layout::default_header(),
+            holidays: None,
+            news: None,
+            theme: Default::default(),
+            control_calendar: None,
+            inset: None,
+            layouts: Vec::new(),
+            timeline_hours: default_timeline_hours(),
+            event_times: Default::default(),
+            family: Vec::new(),
+            vacation: None,
+            quiet: None,
+            raw_frames: false,
+            cadences: Vec::new(),
+            contrast: None,
+            tone: None,
+            dither: Default::default(),
+            merge_duplicates: false,
+            driver_socket: None,
+            proxy: None,
+            runtime: Default::default(),
         }
     }
 }
@@ -128,13 +710,7 @@ impl Config {
     async fn read_or_default(path: &str) -> Result<Self> {
         let path = Path::new(path);
         if path.exists() {
-            let s = tokio::fs::read_to_string(path)
-                .await
-                .into_diagnostic()
-                .wrap_err_with(|| format!("failed to read {}", path.display()))?;
-            toml::from_str(&s).into_diagnostic().wrap_err_with(|| {
-                format!("failed to deserialize config in {} to TOML", path.display())
-            })
+            Self::read(path).await
         } else {
             let cfg = Self::default();
             let toml = toml::to_string_pretty(&cfg).expect("should serialize just fine");
@@ -145,345 +721,1176 @@ impl Config {
             Ok(cfg)
         }
     }
-}
 
-struct State {
-    model: pical::data::Model,
-    layout: pical::layout::Layout,
-    push_bitmap: fn(PathBuf, Option<PathBuf>) -> Pin<Box<dyn Future<Output = Result<()>>>>,
-}
+    async fn read(path: &Path) -> Result<Self> {
+        let s = tokio::fs::read_to_string(path)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&s)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to deserialize config in {} to TOML", path.display()))
+    }
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            model: Default::default(),
-            layout: Default::default(),
-            push_bitmap: |_path, _old| {
-                Box::pin(async { Err(miette!("provide a push_bitmap function")) })
-            },
+    /// The layout and page rotation described by the config, checking the references between
+    /// pages, layouts, and widgets.
+    fn layout(
+        &self,
+        footer: Option<String>,
+    ) -> Result<(pical::layout::Layout, pical::rotation::Rotation)> {
+        let widgets = pical::layout::registry::Registry::builtin();
+        let mut modes = pical::layout::modes::Modes::builtin();
+        if !self.family.is_empty() {
+            modes.register(pical::layout::Family {
+                members: self.family.clone(),
+            });
+        }
+        for template in &self.layouts {
+            template
+                .validate(&modes, &widgets)
+                .wrap_err("invalid layouts in config")?;
+            modes.register(pical::layout::composer::Composed(template.clone()));
+        }
+        let rotation = pical::rotation::Rotation::new(&self.pages, &modes)
+            .wrap_err("invalid pages in config")?;
+        if let Some(id) = self.header.iter().find(|x| widgets.get(x).is_none()) {
+            let ids = widgets.ids().collect::<Vec<_>>().join(", ");
+            return Err(miette!(
+                help = format!("available widgets: {ids}"),
+                "unknown widget '{id}'"
+            ))
+            .wrap_err("invalid header in config");
+        }
+        if let Some(inset) = self
+            .inset
+            .as_ref()
+            .filter(|x| x.position.corner().is_none())
+        {
+            return Err(miette!(
+                help = "use one of: top-right, bottom-left, bottom-right",
+                "the inset must be in a corner, not {:?}",
+                inset.position
+            ))
+            .wrap_err("invalid inset in config");
+        }
+        let [start, end] = self.timeline_hours;
+        if start >= end || end > 24 {
+            return Err(miette!(
+                help = "give the hours as [start, end] within 0 to 24, such as [7, 19]",
+                "the timeline can't show the hours {start} to {end}"
+            ))
+            .wrap_err("invalid timeline_hours in config");
         }
+        let pictures = self
+            .pictures
+            .iter()
+            .map(pical::layout::widgets::Picture::load)
+            .collect::<Result<Vec<_>>>()?;
+
+        let layout = pical::layout::Layout {
+            zoom: self.zoom,
+            mode: rotation.current().clone(),
+            modes,
+            footer,
+            notes: pical::layout::widgets::Notes {
+                notes: self.notes.clone(),
+            },
+            countdowns: self.countdowns.clone(),
+            annual: self.annual.clone(),
+            pictures,
+            stale_after: self.stale_after,
+            widgets,
+            header: self.header.clone(),
+            control_calendar: self.control_calendar.clone(),
+            inset: self.inset.clone(),
+            timeline_hours: self.timeline_hours,
+            event_times: self.event_times,
+            ..Default::default()
+        };
+        Ok((layout, rotation))
     }
-}
 
-fn log_error(e: Report) {
-    let mut buf = String::new();
-    let _ = GraphicalReportHandler::new().render_report(&mut buf, e.as_ref());
-    log::error!("{}", buf);
+    /// A short hash of the config, to identify what a frame was rendered with.
+    fn fingerprint(&self) -> u32 {
+        // FNV-1a, stable across builds unlike the std hasher
+        toml::to_string(self)
+            .unwrap_or_default()
+            .bytes()
+            .fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
+    }
 }
 
-async fn render_loop(
-    dispatch: Dispatch<State>,
-    refresh: Duration,
+/// The frame dimensions, for painting outside the render loop.
+#[derive(Copy, Clone)]
+struct Canvas {
     width: u32,
     height: u32,
     scaling: f32,
-) -> Result<()> {
+    zoom: f32,
+    offset: UtcOffset,
+}
+
+/// Paint a whole-frame screen and push it with a full refresh.
+async fn show_screen(screen: &pical::layout::widgets::Screen, canvas: Canvas) -> Result<()> {
     use pical::render::Render;
+    use time::macros::format_description;
 
-    let mut timer = interval(refresh);
-    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let Canvas {
+        width,
+        height,
+        scaling,
+        zoom,
+        offset,
+    } = canvas;
+    let now = OffsetDateTime::now_utc().to_offset(offset);
+    let fmt = |f| now.format(f).unwrap_or_else(|_| "?".into());
+    let vars = [
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        (
+            "date",
+            fmt(format_description!(
+                "[weekday] [day padding:none] [month repr:long] [year]"
+            )),
+        ),
+        ("time", fmt(format_description!("[hour repr:24]:[minute]"))),
+    ];
+
+    let img = pical::render::paint_gray(width, height, scaling, |ctx| {
+        ctx.set_visuals(egui::Visuals::light());
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(egui::Color32::WHITE))
+            .show(ctx, |ui| screen.render(ui, (zoom, &vars)));
+    })
+    .img;
+    let mut bmp = BmpFile::new(FRAME_PATH);
+    bmp.put(&img)?;
+    if let Some(preview) = PREVIEW.get() {
+        preview.set(&img);
+    }
+    push_frame(&Frame::Saved(FRAME_PATH.into()), &Push::FULL).await
+}
+
+/// `pical diff-config old.toml new.toml [--out diff.png]`: render the first page of each config
+/// with the same made up data, and save them side by side with a heatmap of the differences.
+async fn diff_config(args: &[String]) -> Result<()> {
+    let usage = "usage: pical diff-config <old.toml> <new.toml> [--out <diff.png>]";
+    let (paths, out) = match args {
+        [old, new] => ([old, new], "diff.png"),
+        [old, new, flag, out] if flag == "--out" => ([old, new], out.as_str()),
+        _ => return Err(miette!("{usage}")),
+    };
+    let old = Config::read(Path::new(paths[0])).await?;
+    let new = Config::read(Path::new(paths[1])).await?;
+
+    // both are rendered at the same time, in the old config's timezone
+    let now = OffsetDateTime::now_utc().to_offset(old.timezone);
+    let model = pical::data::fixture::model(now);
+    let render = |cfg: Config, path: &str| {
+        let model = model.clone();
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            render_preview(&cfg, model, now).wrap_err_with(|| format!("failed to render {path}"))
+        })
+    };
+    let old_img = render(old, paths[0]).await.into_diagnostic()??;
+    let new_img = render(new, paths[1]).await.into_diagnostic()??;
+
+    let cmp = pical::compare::compare(&old_img, &new_img);
+    cmp.img
+        .save(out)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to save {out}"))?;
+    let total = u64::from(old_img.width().max(new_img.width()))
+        * u64::from(old_img.height().max(new_img.height()));
+    println!(
+        "{} of {total} pixels changed ({:.1}%), written to {out}",
+        cmp.changed,
+        cmp.changed as f64 * 100.0 / total.max(1) as f64
+    );
+    Ok(())
+}
+
+/// `pical --simulate [config.toml]`: render the config with made up data in a window, to work
+/// on layouts without a panel.
+///
+/// The config is read again whenever it is saved. `+` and `-` zoom (`0` resets), the left and
+/// right arrows switch modes, and `Esc` quits.
+#[cfg(feature = "simulator")]
+async fn simulate(args: &[String]) -> Result<()> {
+    use pical::desktop::Key;
+
+    let path = match args {
+        [] => Path::new(CONFIG_PATH),
+        [path] => Path::new(path),
+        _ => return Err(miette!("usage: pical --simulate [config.toml]")),
+    };
+    let modified = || std::fs::metadata(path).and_then(|x| x.modified()).ok();
+    let mut cfg = Config::read(path).await?;
+    let mut cfg_modified = modified();
+    let window = pical::desktop::DesktopConfig {
+        fullscreen: false,
+        flash: false,
+        min_interval: Duration::ZERO,
+    };
+    let monitor = pical::desktop::Monitor::open(window, [cfg.width, cfg.height])?;
+    println!(
+        "Simulating {}, + and - zoom, arrows switch modes",
+        path.display()
+    );
 
+    let mut zoom = 1.0;
+    let mut mode = None::<String>;
+    let mut drawn = None;
+    let mut renderer = pical::render::Renderer::default();
+    let mut timer = interval(Duration::from_millis(100));
     loop {
         timer.tick().await;
-        let (data, layout, push_bitmap) = dispatch
-            .run(|s| (s.model.clone(), s.layout.clone(), s.push_bitmap))
-            .await;
-
-        let now = std::time::Instant::now();
-        let img = pical::render::paint(width, height, scaling, |ctx| {
-            ctx.set_visuals(egui::Visuals::light());
-            egui::CentralPanel::default()
-                .frame(egui::Frame::none().fill(egui::Color32::WHITE))
-                .show(ctx, |ui| layout.render(ui, data));
-        });
-        let render_time = now.elapsed();
-        img.log_debug_timings();
-        let img = img.img;
+        let mut dirty = false;
+        let mut step = 0;
+        // closing the window quits too
+        let Ok(keys) = monitor.keys() else {
+            return Ok(());
+        };
+        for key in keys {
+            match key {
+                Key::Equal | Key::NumPadPlus => zoom *= 1.1,
+                Key::Minus | Key::NumPadMinus => zoom /= 1.1,
+                Key::Key0 | Key::NumPad0 => zoom = 1.0,
+                Key::Right => step += 1,
+                Key::Left => step -= 1,
+                Key::Escape => return Ok(()),
+                _ => continue,
+            }
+            dirty = true;
+        }
+        if modified() != cfg_modified {
+            cfg_modified = modified();
+            match Config::read(path).await {
+                Ok(x) => {
+                    cfg = x;
+                    dirty = true;
+                }
+                Err(e) => eprintln!("{e:?}"),
+            }
+        }
+        // redraw as the clock ticks over
+        let now = OffsetDateTime::now_utc().to_offset(cfg.timezone);
+        let minute = now.replace_second(0).ok();
+        if !dirty && drawn == minute {
+            continue;
+        }
+        drawn = minute;
 
-        let now = std::time::Instant::now();
-        let path = "./frame.pical.bmp";
-        let old = match save_img(img, path) {
+        let (mut layout, _) = match cfg.layout(None) {
             Ok(x) => x,
             Err(e) => {
-                log_error(e);
+                eprintln!("{e:?}");
                 continue;
             }
         };
-        let save_time = now.elapsed();
+        let names = layout.modes.names().map(String::from).collect::<Vec<_>>();
+        let current = mode.as_deref().unwrap_or(layout.mode.name());
+        let at = names.iter().position(|x| x == current).unwrap_or_default();
+        let at = (at as isize + step).rem_euclid(names.len().max(1) as isize) as usize;
+        if let Some(x) = names.get(at).and_then(|x| layout.modes.get(x)) {
+            mode = Some(x.name().to_string());
+            layout.mode = x;
+        }
+        layout.zoom *= zoom;
+        layout.now = now;
+        layout.theme = cfg.theme.theme_at(now, cfg.coords);
+        let passes = Passes {
+            contrast: cfg.contrast,
+            tone: cfg.tone,
+            dither: cfg.dither,
+        };
+        let model = pical::data::fixture::model(now);
+        let size = [cfg.width, cfg.height];
+        let (img, ..) = paint_frame(&mut renderer, &layout, model, size, cfg.scaling, passes);
+        monitor.show(img, false)?;
+        println!("{} at {:.0}% zoom", layout.mode.name(), zoom * 100.0);
+    }
+}
 
-        let now = std::time::Instant::now();
-        if let Err(e) = push_bitmap(path.into(), old)
-            .await
-            .wrap_err_with(|| format!("failed to push bitmap to {path}"))
-        {
-            log_error(e);
+#[cfg(not(feature = "simulator"))]
+async fn simulate(_: &[String]) -> Result<()> {
+    Err(miette!(
+        help = "build with `--features simulator`",
+        "pical was built without the simulator"
+    ))
+}
+
+/// `pical bench-render [config.toml] [--runs N]`: paint each mode of the config with made up
+/// data `N` times, and print percentiles of how long each stage took.
+///
+/// Run on the Pi itself, timings on a desktop say little about the panel's hardware.
+async fn bench_render(args: &[String]) -> Result<()> {
+    let usage = "usage: pical bench-render [config.toml] [--runs <N>]";
+    let (path, runs) = match args {
+        [] => (CONFIG_PATH, "20"),
+        [path] => (path.as_str(), "20"),
+        [flag, runs] if flag == "--runs" => (CONFIG_PATH, runs.as_str()),
+        [path, flag, runs] if flag == "--runs" => (path.as_str(), runs.as_str()),
+        _ => return Err(miette!("{usage}")),
+    };
+    let runs = runs
+        .parse::<usize>()
+        .ok()
+        .filter(|x| *x > 0)
+        .ok_or_else(|| miette!("{usage}"))
+        .wrap_err_with(|| format!("invalid number of runs: {runs}"))?;
+    let cfg = Config::read(Path::new(path)).await?;
+    let (mut layout, _) = cfg.layout(None)?;
+    let now = OffsetDateTime::now_utc().to_offset(cfg.timezone);
+    layout.now = now;
+    let model = pical::data::fixture::model(now);
+    let passes = Passes {
+        contrast: cfg.contrast,
+        tone: cfg.tone,
+        dither: cfg.dither,
+    };
+    let size = [cfg.width, cfg.height];
+    println!(
+        "{}x{} at {}x scaling, {runs} runs of each mode, {} threads",
+        cfg.width,
+        cfg.height,
+        cfg.scaling,
+        std::thread::available_parallelism().map_or(1, |x| x.get())
+    );
+
+    let names = layout.modes.names().map(String::from).collect::<Vec<_>>();
+    // kept across runs as the render loop does
+    let mut renderer = pical::render::Renderer::default();
+    for name in names {
+        let Some(mode) = layout.modes.get(&name) else {
             continue;
-        }
-        let push_time = now.elapsed();
+        };
+        layout.mode = mode;
+        // a warm up, so the first run's page faults and glyph uploads don't skew the slowest
+        // times
+        let mut paint = || {
+            paint_frame(
+                &mut renderer,
+                &layout,
+                model.clone(),
+                size,
+                cfg.scaling,
+                passes,
+            )
+            .2
+        };
+        paint();
+        let timings = (0..runs).map(|_| paint()).collect::<Vec<_>>();
 
-        log::info!(
-            "⏱ Render perf: rendering=>{} | save-bitmap=>{} | push-time=>{}",
-            humantime::Duration::from(render_time),
-            humantime::Duration::from(save_time),
-            humantime::Duration::from(push_time)
-        );
+        println!("\n{name}");
+        println!("  {:<14} {:>8} {:>8} {:>8}", "stage", "p50", "p90", "p99");
+        let stages: [(&str, fn(&pical::render::Timings) -> Duration); 5] = [
+            ("ui-gen", |x| x.ui_gen),
+            ("tessellation", |x| x.tessellation),
+            ("rasterisation", |x| x.rasterisation),
+            ("conversion", |x| x.conversion),
+            ("total", |x| {
+                x.ui_gen + x.tessellation + x.rasterisation + x.conversion
+            }),
+        ];
+        for (stage, f) in stages {
+            let mut xs = timings.iter().map(f).collect::<Vec<_>>();
+            xs.sort();
+            // nearest rank
+            let p = |q: f64| xs[((q * xs.len() as f64).ceil() as usize).clamp(1, xs.len()) - 1];
+            let ms = |x: Duration| format!("{:.1}ms", x.as_secs_f64() * 1000.0);
+            println!(
+                "  {stage:<14} {:>8} {:>8} {:>8}",
+                ms(p(0.5)),
+                ms(p(0.9)),
+                ms(p(0.99))
+            );
+        }
     }
+    Ok(())
 }
 
-/// Returns if an original file at `to` was renamed.
-fn save_img(img: impl Into<image::DynamicImage>, to: &str) -> Result<Option<PathBuf>> {
-    let to = Path::new(to);
-    let old = if to.exists() {
-        let mut o = format!(
-            "{}.old",
-            to.file_stem().and_then(|x| x.to_str()).unwrap_or_default()
-        );
-        if let Some(ext) = to.extension().and_then(|x| x.to_str()) {
-            o.push('.');
-            o.push_str(ext);
-        }
-        let o = to.with_file_name(o);
-        std::fs::rename(to, &o).into_diagnostic()?;
-        Some(o)
-    } else {
-        None
+/// Paint the config's first page with `model`, as it would be pushed to the panel.
+fn render_preview(
+    cfg: &Config,
+    model: pical::data::Model,
+    now: OffsetDateTime,
+) -> Result<image::GrayImage> {
+    let (mut layout, _) = cfg.layout(None)?;
+    layout.now = now;
+    layout.theme = cfg.theme.theme_at(now, cfg.coords);
+    let size = [cfg.width, cfg.height];
+    let passes = Passes {
+        contrast: cfg.contrast,
+        tone: cfg.tone,
+        dither: cfg.dither,
     };
+    let mut renderer = pical::render::Renderer::default();
+    Ok(paint_frame(&mut renderer, &layout, model, size, cfg.scaling, passes).0)
+}
 
-    let img = img.into().into_luma8();
-    img.save(to)
+/// The regions of `img` which differ from the `old` frame saved at that path.
+///
+/// `None` if the frames can't be compared, in which case a full refresh is done.
+fn changed_regions(old: &Path, img: &image::GrayImage) -> Option<Vec<Region>> {
+    let old = image::open(old)
         .into_diagnostic()
-        .wrap_err_with(|| format!("failed to save bitmap to {}", to.display()))?;
-    Ok(old)
+        .wrap_err_with(|| format!("failed to read previous frame {}", old.display()))
+        .map_err(log_error)
+        .ok()?
+        .into_luma8();
+    pical::render::dirty_regions(&old, img)
+}
+
+#[cfg(feature = "web-ui")]
+async fn control_loop(
+    dispatch: Dispatch<State>,
+    cfg: pical::control::ControlConfig,
+    canvas: Canvas,
+) {
+    let screen = maintenance_screen();
+    let res = pical::control::serve(&cfg, |cmd| {
+        run_command(dispatch.clone(), canvas, screen.clone(), cmd)
+    })
+    .await;
+    if let Err(e) = res {
+        log_error(e.wrap_err("control listener failed"));
+    }
+}
+
+#[cfg(feature = "mqtt")]
+async fn mqtt_loop(dispatch: Dispatch<State>, cfg: pical::mqtt::MqttConfig, canvas: Canvas) {
+    let screen = maintenance_screen();
+    pical::mqtt::serve(&cfg, move |cmd| {
+        run_command(dispatch.clone(), canvas, screen.clone(), cmd)
+    })
+    .await
+}
+
+/// Shown while in maintenance mode.
+#[cfg(feature = "web-ui")]
+fn maintenance_screen() -> pical::layout::widgets::Screen {
+    pical::layout::widgets::Screen::load(&pical::layout::widgets::ScreenConfig {
+        image: None,
+        text: Some("🔧 Maintenance\nupdates paused since {time}".to_string()),
+    })
+    .expect("no image to load")
+}
+
+/// Run a command from the control listener or MQTT, returning the text to respond with.
+#[cfg(feature = "web-ui")]
+async fn run_command(
+    dispatch: Dispatch<State>,
+    canvas: Canvas,
+    screen: pical::layout::widgets::Screen,
+    cmd: pical::control::Command,
+) -> Result<String> {
+    use pical::control::Command;
+
+    match cmd {
+        Command::Maintenance(on) => {
+            let was = dispatch
+                .run(move |s| std::mem::replace(&mut s.maintenance, on))
+                .await;
+            match (was, on) {
+                (false, true) => {
+                    log::info!("🔧 Entering maintenance mode");
+                    show_screen(&screen, canvas).await?;
+                    Ok("maintenance on, updates paused".to_string())
+                }
+                (true, false) => {
+                    log::info!("🔧 Leaving maintenance mode");
+                    Ok("maintenance off, updates resumed".to_string())
+                }
+                _ => Ok(format!(
+                    "maintenance already {}",
+                    if on { "on" } else { "off" }
+                )),
+            }
+        }
+        Command::Logs => Ok(LOGS.get().map(|x| x.dump()).unwrap_or_default()),
+        Command::Status => {
+            let stats = dispatch.stats();
+            let driver = call_driver(&pical::driver::Command::Status)
+                .await
+                .ok()
+                .and_then(|x| x.status);
+            Ok(dispatch.run(move |s| status_report(s, stats, driver)).await)
+        }
+        #[cfg(feature = "metrics")]
+        Command::Metrics => {
+            let driver = call_driver(&pical::driver::Command::Status)
+                .await
+                .ok()
+                .and_then(|x| x.status);
+            Ok(metrics_report(dispatch.stats(), driver))
+        }
+        // not routed to without the feature
+        #[cfg(not(feature = "metrics"))]
+        Command::Metrics => Err(miette!("pical was built without the metrics feature")),
+        Command::FrameText => frame_text(&dispatch, canvas).await,
+        Command::InjectEvents(x) => inject_events(&dispatch, x).await,
+    }
 }
 
-async fn clock_loop(dispatch: Dispatch<State>, every: Duration, offset: UtcOffset) {
+/// Show the events until they end, saving them in case pical restarts first.
+#[cfg(feature = "web-ui")]
+async fn inject_events(
+    dispatch: &Dispatch<State>,
+    injection: pical::control::Injection,
+) -> Result<String> {
+    let pical::control::Injection { source, events } = injection;
+    let reply = format!("injected {} events into {source}", events.len());
+    let _saving = SAVING_INJECTED.lock().await;
+    let saved = dispatch
+        .run(move |s| {
+            let model = s.model.make_mut();
+            let evs = events.into_iter().map(Into::into).collect();
+            model.insert_events(&source, evs)?;
+            Ok::<_, Report>(pical::data::injected::Saved::of(model))
+        })
+        .await?;
+    saved.save(Path::new(INJECTED_PATH)).await?;
+    log::info!("📌 {reply}");
+    Ok(reply)
+}
+
+/// The text of the frame as it would be rendered now, as JSON lines and positioned text.
+#[cfg(feature = "web-ui")]
+async fn frame_text(dispatch: &Dispatch<State>, canvas: Canvas) -> Result<String> {
+    use pical::render::Render;
+
+    let (mut data, mut layout) = dispatch.run(|s| (s.model.clone(), s.layout.clone())).await;
+    pical::control::directive::apply_overrides(&mut layout, &mut data);
+    let texts = tokio::task::spawn_blocking(move || {
+        pical::render::frame_text(canvas.width, canvas.height, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| layout.render(ui, data));
+        })
+    })
+    .await
+    .into_diagnostic()
+    .wrap_err("laying out the frame failed")?;
+    let json = serde_json::json!({
+        "lines": pical::render::text_lines(&texts),
+        "texts": texts,
+    });
+    Ok(json.to_string())
+}
+
+async fn clock_loop(
+    dispatch: Dispatch<State>,
+    every: Duration,
+    offset: UtcOffset,
+    theme: pical::layout::theme::ThemeConfig,
+    coords: [f32; 2],
+) {
     let mut timer = interval(every);
     timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     loop {
-        dispatch
-            .run(move |s| {
-                s.layout.now = OffsetDateTime::now_utc().to_offset(offset);
+        let now = OffsetDateTime::now_utc().to_offset(offset);
+        let theme = theme.theme_at(now, coords);
+        let saving = SAVING_INJECTED.lock().await;
+        // the high lane keeps the time accurate while fetched data is being applied
+        let expired = dispatch
+            .run_in(Lane::High, move |s| {
+                s.layout.now = now;
+                s.layout.theme = theme;
+                s.model.any_expired(now).then(|| {
+                    let model = s.model.make_mut();
+                    model.expire_events(now);
+                    pical::data::injected::Saved::of(model)
+                })
             })
             .await;
+        if let Some(saved) = expired {
+            if let Err(e) = saved.save(Path::new(INJECTED_PATH)).await {
+                log_error(e.wrap_err("failed to save injected events"));
+            }
+        }
+        drop(saving);
+        // pinged once the state was reached, so a stalled dispatcher gets the service restarted,
+        // however long the display refresh is
+        pical::service::notify(pical::service::WATCHDOG);
         timer.tick().await;
     }
 }
 
-fn fetch_loop(
-    dispatch: Dispatch<State>,
-    coords: [f32; 2],
-    cals: Vec<(String, String)>,
-    stormglassio_apikey: String,
-    every: Duration,
-) -> Result<impl Future<Output = ()>> {
-    let mut timer = interval(every);
-    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+/// The counters of the status report in the Prometheus text format, served by the control
+/// listener for scraping.
+#[cfg(feature = "metrics")]
+fn metrics_report(dispatch: pical::state::Stats, driver: Option<pical::driver::Status>) -> String {
+    use std::fmt::Write;
 
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(20))
-        .build()
-        .into_diagnostic()
-        .wrap_err("failed to build reqwest client")?;
-
-    Ok(async move {
-        loop {
-            if let Err(e) =
-                fetch_iteration(&dispatch, &client, &cals, coords, &stormglassio_apikey).await
-            {
-                log_error(e);
-            }
-            timer.tick().await;
+    let mut s = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = writeln!(s, "# HELP pical_{name} {help}");
+        let _ = writeln!(s, "# TYPE pical_{name} {kind}");
+        let _ = writeln!(s, "pical_{name} {value}");
+    };
+    let count = |x: u64| x as f64;
+
+    metric(
+        "dispatch_executed_total",
+        "counter",
+        "Closures run with the state.",
+        count(dispatch.executed),
+    );
+    metric(
+        "dispatch_slow_total",
+        "counter",
+        "Closures which held the state longer than the slow threshold.",
+        count(dispatch.slow),
+    );
+    metric(
+        "dispatch_queue_depth",
+        "gauge",
+        "Closures waiting to run, as of the last dispatch.",
+        dispatch.queue_depth as f64,
+    );
+    metric(
+        "dispatch_queue_depth_max",
+        "gauge",
+        "The most closures waiting to run.",
+        dispatch.max_queue_depth as f64,
+    );
+    metric(
+        "dispatch_execution_seconds_total",
+        "counter",
+        "Time closures held the state.",
+        dispatch.total_exec.as_secs_f64(),
+    );
+    metric(
+        "dispatch_execution_seconds_max",
+        "gauge",
+        "The longest a closure held the state.",
+        dispatch.max_exec.as_secs_f64(),
+    );
+
+    let panel = *PANEL_STATS.lock().expect("panel stats lock poisoned");
+    metric(
+        "panel_frames_total",
+        "counter",
+        "Frames pushed to the panel.",
+        count(panel.frames),
+    );
+    metric(
+        "panel_full_refreshes_total",
+        "counter",
+        "Frames pushed as full refreshes.",
+        count(panel.full_refreshes),
+    );
+    metric(
+        "panel_partial_refreshes_total",
+        "counter",
+        "Frames pushed as partial refreshes.",
+        count(panel.partial_refreshes),
+    );
+    metric(
+        "driver_restarts_total",
+        "counter",
+        "Times the driver was restarted.",
+        count(panel.driver_restarts),
+    );
+
+    if let Some(x) = driver {
+        metric(
+            "driver_pushes_total",
+            "counter",
+            "Pushes since the driver started.",
+            count(x.pushes),
+        );
+        metric(
+            "driver_full_refreshes_total",
+            "counter",
+            "Refreshes of the whole panel with GC16 since the driver started.",
+            count(x.full_refreshes),
+        );
+        metric(
+            "driver_failures_total",
+            "counter",
+            "Requests which failed since the driver started.",
+            count(x.failures),
+        );
+        metric(
+            "driver_retries_total",
+            "counter",
+            "Tries at driving the panel which failed and were tried again.",
+            count(x.retries),
+        );
+        metric(
+            "driver_resets_total",
+            "counter",
+            "Resets of the controller after repeated failures.",
+            count(x.resets),
+        );
+        metric(
+            "driver_dropped_total",
+            "counter",
+            "Frames dropped for newer ones.",
+            count(x.dropped),
+        );
+        metric(
+            "driver_uptime_seconds",
+            "gauge",
+            "Time since the driver started.",
+            count(x.uptime_secs),
+        );
+        if let Some(t) = x.temperature {
+            metric(
+                "panel_temperature_celsius",
+                "gauge",
+                "The panel's temperature.",
+                f64::from(t),
+            );
         }
-    })
+    }
+    s
 }
 
-async fn fetch_iteration(
-    dispatch: &Dispatch<State>,
-    client: &reqwest::Client,
-    calendars: &[(String, String)],
-    coords: [f32; 2],
-    stormglassio_apikey: &str,
-) -> Result<()> {
-    let (model, now) = dispatch
-        .run(|state| (state.model.clone(), state.layout.now))
-        .await;
-
-    // download the calendar(s)
-    let mut cals = Vec::with_capacity(calendars.len());
-    let limit = std::iter::successors(Some(now.date()), |x| x.next_day())
-        .nth(60)
-        .map(|d| now.replace_date(d))
-        .unwrap_or(now);
-    for (name, url) in calendars {
-        let ical = pical::fetch::string(client, url, [])
-            .await
-            .and_then(|x| pical::data::cal::parse_ical(&x, now.offset(), limit))?;
-        cals.push((name.clone(), ical));
-        log::info!("Fetched latest calendars");
-    }
-
-    // fetch the weather
-    // only do this every 10 minutes to avoid making execessive API calls
-    let mut weather = None;
-    if model
-        .weather
-        .as_ref()
-        .map(|x| Instant::now().duration_since(x.last_update) > Duration::from_secs(60 * 10))
-        .unwrap_or(true)
-    {
-        let [lat, long] = coords;
-        let tz = now.offset();
-        let url = reqwest::Url::parse_with_params(
-            "https://api.open-meteo.com/v1/forecast?\
-                current=temperature_2m,relative_humidity_2m,precipitation,weather_code&\
-                daily=weather_code,temperature_2m_max,precipitation_probability_max&\
-                forecast_days=16",
-            &[
-                ("latitude", lat.to_string()),
-                ("longitude", long.to_string()),
-                ("timezone", format!("GMT{:+}", tz.whole_hours())),
-            ],
-        )
-        .into_diagnostic()
-        .wrap_err("URL parse failed")?;
-        let url = url.as_str();
-        let resp = pical::fetch::json(client, url, []).await?;
-        weather = Some(pical::data::weather::Weather::from_open_meteo(resp)?);
-        log::info!("Fetched latest weather");
-    }
-
-    // fetch lunar calendar
-    // only do this every half a day -- avoids rate limits and will not change
-    let mut moon = None;
-    if model
-        .moon
-        .as_ref()
-        .map(|x| Instant::now().duration_since(x.last_update) > Duration::from_secs(60 * 60 * 12))
-        .unwrap_or(true)
-    {
-        let [lat, long] = coords;
-        let url = reqwest::Url::parse_with_params(
-            "https://api.stormglass.io/v2/astronomy/point",
-            &[
-                ("lat", lat.to_string()),
-                ("lng", long.to_string()),
-                ("start", now.date().to_string()),
-                ("end", (now.date() + time::Duration::days(10)).to_string()),
-            ],
-        )
+/// A plain text report of the running state, served by the control listener.
+#[cfg(feature = "web-ui")]
+fn status_report(
+    state: &mut State,
+    dispatch: pical::state::Stats,
+    driver: Option<pical::driver::Status>,
+) -> String {
+    use pical::layout::ago;
+    use std::fmt::Write;
+
+    let mut s = String::new();
+    let now = Instant::now();
+    let onoff = |x| if x { "on" } else { "off" };
+    let _ = writeln!(s, "pical v{}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(s, "maintenance: {}", onoff(state.maintenance));
+    let _ = writeln!(s, "page: {}", state.layout.mode.name());
+    if let Some(f) = &state.layout.failure {
+        let age = ago(now.duration_since(f.at));
+        let _ = writeln!(s, "failure: {} failed {age}: {}", f.task, f.message);
+    }
+
+    let mut updated = state.model.updated().collect::<Vec<_>>();
+    updated.sort();
+    let _ = writeln!(s, "\ndata:");
+    for (src, at) in updated {
+        let _ = writeln!(s, "  {src}: updated {}", ago(now.duration_since(at)));
+    }
+    let mut unloaded = state.model.unloaded.keys().collect::<Vec<_>>();
+    unloaded.sort();
+    for src in unloaded {
+        let _ = writeln!(s, "  {src}: not loaded yet");
+    }
+
+    let _ = writeln!(s, "\ndispatch:");
+    let _ = writeln!(
+        s,
+        "  executed: {}, slow: {}",
+        dispatch.executed, dispatch.slow
+    );
+    let _ = writeln!(
+        s,
+        "  queue depth: {} (max {})",
+        dispatch.queue_depth, dispatch.max_queue_depth
+    );
+    let _ = writeln!(
+        s,
+        "  execution: {:?} mean, {:?} max",
+        dispatch.mean_exec(),
+        dispatch.max_exec
+    );
+    if let Some(at) = dispatch.slowest {
+        let _ = writeln!(s, "  slowest from: {at}");
+    }
+
+    let injected = state.model.injected_calendars().collect::<Vec<_>>();
+    if !injected.is_empty() {
+        let _ = writeln!(s, "\ninjected:");
+        for (src, cal) in injected {
+            let _ = writeln!(s, "  {src}: {} events", cal.len());
+        }
+    }
+
+    if !state.model.merged.is_empty() {
+        let _ = writeln!(s, "\nmerged duplicates:");
+        for m in &state.model.merged {
+            let _ = writeln!(s, "  {m}");
+        }
+    }
+
+    let panel = *PANEL_STATS.lock().expect("panel stats lock poisoned");
+    let _ = writeln!(s, "\npanel:");
+    if let Some(since) = panel.since {
+        let _ = writeln!(s, "  since: {}", since.date());
+    }
+    let _ = writeln!(
+        s,
+        "  frames: {} ({} full, {} partial)",
+        panel.frames, panel.full_refreshes, panel.partial_refreshes
+    );
+    let _ = writeln!(s, "  driver restarts: {}", panel.driver_restarts);
+
+    if let Some(x) = driver {
+        let _ = writeln!(s, "\ndriver:");
+        let _ = writeln!(
+            s,
+            "  panel: {}x{} turned {}°, firmware {}, LUT {}",
+            x.width, x.height, x.rotate, x.firmware, x.lut
+        );
+        let _ = writeln!(s, "  power: {}", x.power);
+        if let Some(t) = x.temperature {
+            let _ = writeln!(s, "  temperature: {t}°C");
+        }
+        let _ = writeln!(
+            s,
+            "  pushes: {} ({} full), failures: {}",
+            x.pushes, x.full_refreshes, x.failures
+        );
+        let _ = writeln!(
+            s,
+            "  retries: {}, resets: {}, dropped: {}",
+            x.retries, x.resets, x.dropped
+        );
+        let up = humantime::Duration::from(Duration::from_secs(x.uptime_secs));
+        let _ = writeln!(s, "  up: {up}");
+    }
+
+    if let Some(logs) = LOGS.get() {
+        let _ = writeln!(s, "\nrecent log (see `pical logs`):");
+        for line in logs.tail(10) {
+            let _ = writeln!(s, "  {line}");
+        }
+    }
+    s
+}
+
+static REMOTE: OnceLock<pical::remote::RemoteConfig> = OnceLock::new();
+
+static DESKTOP: OnceLock<pical::desktop::Monitor> = OnceLock::new();
+
+static PREVIEW: OnceLock<pical::preview::Latest> = OnceLock::new();
+
+/// The panel, or the remote agent or desktop window standing in for it.
+struct Output;
+
+impl pical::run::Panel for Output {
+    fn push<'a>(&'a mut self, frame: &'a Frame, push: &'a Push) -> PanelFuture<'a> {
+        Box::pin(push_frame(frame, push))
+    }
+
+    fn power(&mut self, cmd: pical::driver::Command) -> PanelFuture<'_> {
+        Box::pin(set_panel_power(cmd))
+    }
+}
+
+/// Push a frame to the panel, or the remote agent if configured.
+///
+/// The agent works out its own changes, so only whether the push is full is sent to it.
+async fn push_frame(frame: &Frame, push: &Push) -> Result<()> {
+    if let Some(remote) = REMOTE.get() {
+        return push_remote(remote, &frame.save()?, push.regions.is_none()).await;
+    }
+    if let Some(monitor) = DESKTOP.get() {
+        return monitor.show(frame.image()?, push.regions.is_none());
+    }
+    push_bitmap(frame, push).await
+}
+
+/// Push a saved frame to the remote agent.
+async fn push_remote(cfg: &pical::remote::RemoteConfig, img: &Path, full: bool) -> Result<()> {
+    let data = tokio::fs::read(img)
+        .await
         .into_diagnostic()
-        .wrap_err("URL parse failed")?;
-        let url = url.as_str();
-        let resp = pical::fetch::json(
-            client,
-            url,
-            [("Authorization", stormglassio_apikey.to_string())],
-        )
-        .await?;
-        moon = Some(pical::data::moon::LunarCalendar::from_storm_glass_io(
-            resp,
-            now.offset(),
-        )?);
-        log::info!("Fetched latest lunar calendar");
-    }
-
-    drop(model); // drop ref count
-    dispatch
-        .run(|state| {
-            let model = state.model.make_mut();
-            for (key, cal) in cals {
-                model.cals.insert(key.to_string(), cal);
-            }
-            if let Some(w) = weather {
-                model.weather = Some(w);
-            }
-            if let Some(m) = moon {
-                model.moon = Some(m);
-            }
-        })
-        .await;
+        .wrap_err_with(|| format!("failed to read {}", img.display()))?;
+    pical::remote::push(cfg, data, full).await
+}
 
-    Ok(())
+/// Display frames pushed from a render server, rather than rendering them here.
+async fn run_agent(cfg: &pical::remote::AgentConfig) -> Result<()> {
+    pical::remote::serve(cfg, |frame| async move {
+        let img = image::load_from_memory(&frame.data)
+            .into_diagnostic()
+            .wrap_err("failed to decode pushed frame")?
+            .into_luma8();
+        let mut bmp = BmpFile::new(FRAME_PATH);
+        let old = bmp.path().exists().then(|| bmp.previous());
+        bmp.put(&img)?;
+        if let Some(preview) = PREVIEW.get() {
+            preview.set(&img);
+        }
+        let push = match old
+            .filter(|_| !frame.full)
+            .and_then(|x| changed_regions(&x, &img))
+        {
+            Some(x) if x.is_empty() => return Ok(()),
+            Some(regions) => Push {
+                waveform: Waveform::Du4,
+                regions: Some(pical::policy::arrange(regions, None)),
+            },
+            None => Push::FULL,
+        };
+        push_bitmap(&Frame::Saved(FRAME_PATH.into()), &push).await
+    })
+    .await
 }
 
 static DRIVER_PROCESS: Mutex<Option<ScreenDriver>> = Mutex::const_new(None);
 
+/// The panel driven in-process, if the driver isn't a separate process.
+#[cfg(feature = "in-process-driver")]
+static IN_PROCESS: OnceLock<pical::driver::InProcess> = OnceLock::new();
+
+const PANEL_STATS_PATH: &str = "./panel-stats.pical.json";
+
+/// Events injected by scripts, see [`pical::data::injected`].
+const INJECTED_PATH: &str = "./injected.pical.json";
+
+/// Held from taking the injected events to saving them, so an older snapshot can't be written
+/// over a newer one.
+static SAVING_INJECTED: Mutex<()> = Mutex::const_new(());
+
+/// Lifetime counts of what was pushed to the panel, see `pical status`.
+static PANEL_STATS: std::sync::Mutex<pical::wear::PanelStats> =
+    std::sync::Mutex::new(pical::wear::PanelStats::ZERO);
+
+type DriverClient = pical::driver::Client<
+    Box<dyn tokio::io::AsyncWrite + Send + Unpin>,
+    Box<dyn tokio::io::AsyncBufRead + Send + Unpin>,
+>;
+
 struct ScreenDriver {
-    process: tokio::process::Child,
-    count: u8,
-    reset_count: u16,
+    /// The driver listening on this socket, otherwise one started by pical.
+    socket: Option<PathBuf>,
+    /// The driver started by pical.
+    process: Option<tokio::process::Child>,
+    client: DriverClient,
+    /// Pushes since the driver was started.
+    pushes: u16,
 }
 
-async fn start_it8951_driver() -> Result<()> {
-    *DRIVER_PROCESS.lock().await = Some(ScreenDriver::start()?);
+/// A driver started by pical is restarted after this many pushes, resetting the controller, as
+/// long running ones have been seen to stop refreshing the panel.
+const RESTART_DRIVER_AFTER: u16 = 180;
+
+async fn start_it8951_driver(socket: Option<PathBuf>) -> Result<()> {
+    #[cfg(feature = "in-process-driver")]
+    if socket.is_none() {
+        let panel = pical::driver::InProcess::open().await?;
+        log::info!("🔌 Driving the panel in-process");
+        let status = panel.call(&pical::driver::Command::Status).await?;
+        log_panel_status(status.into_result()?);
+        let _ = IN_PROCESS.set(panel);
+        return Ok(());
+    }
+    // a driver run as its own service may not be listening yet
+    let mut driver = ScreenDriver::start(socket, WAIT_FOR_DRIVER).await?;
+    let status = driver.call(&pical::driver::Command::Status).await?;
+    log_panel_status(status);
+    *DRIVER_PROCESS.lock().await = Some(driver);
     Ok(())
 }
 
+fn log_panel_status(res: pical::driver::Response) {
+    if let Some(x) = res.status {
+        log::info!(
+            "🖥 Panel is {}x{} turned {}°, firmware {}, LUT {}",
+            x.width,
+            x.height,
+            x.rotate,
+            x.firmware,
+            x.lut
+        );
+        if let Some(t) = x.temperature {
+            log::info!("🌡 Panel is {t}°C");
+        }
+    }
+}
+
 impl ScreenDriver {
-    fn start() -> Result<Self> {
-        use tokio::process::*;
-        let child = Command::new("./it8951-driver")
-            .stdin(std::process::Stdio::piped())
+    /// Connect to the driver listening on `socket`, trying again for up to `wait`, or start one.
+    async fn start(socket: Option<PathBuf>, wait: Duration) -> Result<Self> {
+        use std::process::Stdio;
+        use tokio::{io::BufReader, process::*};
+
+        if let Some(path) = socket {
+            let client = connect_driver_within(&path, wait).await?;
+            log::info!("🔌 Connected to it8951-driver on {}", path.display());
+            return Ok(ScreenDriver {
+                socket: Some(path),
+                process: None,
+                client,
+                pushes: 0,
+            });
+        }
+        let mut process = Command::new("./it8951-driver")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .into_diagnostic()
             .wrap_err("failed to start ./it8951-driver")?;
+        let tx = process
+            .stdin
+            .take()
+            .ok_or_else(|| miette!("no stdin pipe for it8951-driver"))?;
+        let rx = process
+            .stdout
+            .take()
+            .ok_or_else(|| miette!("no stdout pipe for it8951-driver"))?;
         Ok(ScreenDriver {
-            process: child,
-            count: 0,
-            reset_count: 0,
+            socket: None,
+            process: Some(process),
+            client: pical::driver::Client::new(Box::new(tx), Box::new(BufReader::new(rx))),
+            pushes: 0,
         })
     }
-}
 
-/// Change this to suit the how to push a frame to the screen.
-async fn push_bitmap(img: &Path, old: Option<&Path>) -> Result<()> {
-    let mut child_ = DRIVER_PROCESS.lock().await;
-    let child = child_
-        .as_mut()
-        .ok_or_else(|| miette!("it8951-driver process not started"))?;
-    child.count += 1;
-    child.reset_count += 1;
-    let mut line = img.display().to_string();
-    if child.count > 10 {
-        // do high screen
-        child.count = 0;
-        line += " --high";
-    } else {
-        // add maybe diff
-        if let Some(diff) = old {
-            line += " --low ";
-            line += &diff.display().to_string();
+    /// Send `cmd` to the driver, erroring if it replies with one.
+    ///
+    /// A driver which has exited, or gave up on the panel, is restarted, or reconnected to if it
+    /// listens on a socket. The driver retries and resets the panel itself, and exits if it
+    /// hangs. One started by pical is also restarted every [`RESTART_DRIVER_AFTER`] pushes.
+    async fn call(&mut self, cmd: &pical::driver::Command) -> Result<pical::driver::Response> {
+        use pical::driver::Command;
+
+        let e = match self.client.call(cmd).await {
+            Ok(x) if !x.is_fatal() => {
+                if matches!(
+                    cmd,
+                    Command::Push { .. } | Command::PushRaw { .. } | Command::PushArea { .. }
+                ) {
+                    self.pushes += 1;
+                }
+                if self.process.is_some() && self.pushes >= RESTART_DRIVER_AFTER {
+                    log::info!("Restarting it8951-driver after {} pushes", self.pushes);
+                    // the frame was shown, a failed restart is tried again by the next push
+                    if let Err(e) = self.restart().await {
+                        log_error(e);
+                    }
+                }
+                return x.into_result();
+            }
+            Ok(x) => x
+                .into_result()
+                .err()
+                .unwrap_or_else(|| miette!("it8951-driver gave up on the panel")),
+            Err(e) => e,
+        };
+        match self.process {
+            Some(_) => log::warn!("Restarting it8951-driver processing"),
+            None => log::warn!("Reconnecting to it8951-driver"),
         }
+        // if reconnecting fails, the next push tries again
+        self.restart().await?;
+        Err(e)
     }
 
-    line.push('\n'); // new line to end
-
-    let x = tokio::time::timeout(Duration::from_secs(60), async {
-        match &mut child.process.stdin {
-            Some(child) => child.write_all(line.as_bytes()).await.into_diagnostic(),
-            None => Err(miette!("no stdin pipe for it8951-driver")),
+    /// Kill and start again a driver started by pical, or reconnect to one on a socket.
+    async fn restart(&mut self) -> Result<()> {
+        if let Some(process) = self.process.as_mut() {
+            let _ = process.kill().await;
+            PANEL_STATS
+                .lock()
+                .expect("panel stats lock poisoned")
+                .record_restart();
         }
-    })
-    .await;
+        *self = Self::start(self.socket.clone(), Duration::ZERO).await?;
+        Ok(())
+    }
+}
 
-    let reset = match x {
-        Ok(res) => {
-            res?;
-            false
-        }
-        // timed out
-        Err(e) => {
-            log::error!("{e}");
-            true
+/// How long pical tries connecting to a driver on `driver_socket` at startup.
+const WAIT_FOR_DRIVER: Duration = Duration::from_secs(60);
+
+/// Connect to the driver on `path`, backing off from a quarter second to 8 seconds between tries
+/// until `wait` has passed.
+async fn connect_driver_within(path: &Path, wait: Duration) -> Result<DriverClient> {
+    let started = Instant::now();
+    let mut backoff = Duration::from_millis(250);
+    loop {
+        match connect_driver(path).await {
+            Ok(x) => return Ok(x),
+            Err(e) if started.elapsed() + backoff > wait => return Err(e),
+            Err(e) => {
+                log::warn!(
+                    "{e:?}, trying again in {}",
+                    humantime::Duration::from(backoff)
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(8));
+            }
         }
+    }
+}
+
+#[cfg(unix)]
+async fn connect_driver(path: &Path) -> Result<DriverClient> {
+    let (rx, tx) = tokio::net::UnixStream::connect(path)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to connect to it8951-driver on {}", path.display()))?
+        .into_split();
+    Ok(pical::driver::Client::new(
+        Box::new(tx),
+        Box::new(tokio::io::BufReader::new(rx)),
+    ))
+}
+
+#[cfg(not(unix))]
+async fn connect_driver(_: &Path) -> Result<DriverClient> {
+    Err(miette!("it8951-driver sockets are only supported on Unix"))
+}
+
+/// Change this to suit the how to push a frame to the screen.
+///
+/// Only the push's regions are updated, or the whole screen if there are none.
+async fn push_bitmap(frame: &Frame, push: &Push) -> Result<()> {
+    let cmd = match frame {
+        Frame::Saved(path) => pical::driver::Command::push(path, push),
+        // no file for the driver to read, saving the SD card a write
+        Frame::Painted(img) => pical::driver::Command::push_raw(img, push),
     };
+    let res = call_driver(&cmd).await;
+    // a whole screen pushed with a faster waveform doesn't clear the ghosting
+    let full = push.regions.is_none() && push.waveform == Waveform::Gc16;
+    // a frame dropped for a newer one wasn't shown
+    if res.as_ref().is_ok_and(|x| !x.dropped) {
+        let now = OffsetDateTime::now_utc();
+        PANEL_STATS
+            .lock()
+            .expect("panel stats lock poisoned")
+            .record_push(full, now);
+    }
 
-    if child.reset_count > 180 || reset {
-        log::warn!("Restarting it8951-driver processing");
-        child.process.kill().await.into_diagnostic()?;
-        *child = ScreenDriver::start()?;
+    // saved alongside the full refreshes, rather than writing to the SD card every frame
+    if full || res.is_err() {
+        let stats = *PANEL_STATS.lock().expect("panel stats lock poisoned");
+        if let Err(e) = stats.save(Path::new(PANEL_STATS_PATH)).await {
+            log_error(e);
+        }
     }
 
-    Ok(())
+    res.map(|_| ())
+}
+
+/// Send `cmd` to the panel driven in-process, or else the it8951-driver.
+async fn call_driver(cmd: &pical::driver::Command) -> Result<pical::driver::Response> {
+    #[cfg(feature = "in-process-driver")]
+    if let Some(panel) = IN_PROCESS.get() {
+        return panel.call(cmd).await?.into_result();
+    }
+    DRIVER_PROCESS
+        .lock()
+        .await
+        .as_mut()
+        .ok_or_else(|| miette!("it8951-driver process not started"))?
+        .call(cmd)
+        .await
+}
+
+/// Set how the panel rests between pushes, if pical drives it.
+async fn set_panel_power(cmd: pical::driver::Command) -> Result<()> {
+    if cfg!(feature = "local") || REMOTE.get().is_some() || DESKTOP.get().is_some() {
+        return Ok(());
+    }
+    call_driver(&cmd).await.map(|_| ())
 }
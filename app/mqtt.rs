@@ -0,0 +1,112 @@
+//! Taking the control commands over MQTT, for home automation hubs.
+//!
+//! Commands are published to `<topic>/command` as they are given on the command line, such as
+//! `maintenance on` or `status`, and the response is published to `<topic>/response`, prefixed
+//! with `error: ` if the command failed.
+//!
+//! Subscribing needs the `mqtt` feature.
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// The broker's host name or address.
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Commands are read from `<topic>/command` and answered on `<topic>/response`.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_topic() -> String {
+    "pical".to_string()
+}
+
+#[cfg(feature = "mqtt")]
+pub use subscribe::serve;
+
+#[cfg(feature = "mqtt")]
+mod subscribe {
+    use super::MqttConfig;
+    use crate::control::Command;
+    use miette::*;
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use std::{future::Future, time::Duration};
+
+    /// Subscribe to commands, `on_command` returns the text to respond with.
+    ///
+    /// Each command is run in its own task, so a slow one doesn't hold up the connection. A lost
+    /// connection is made again, after a pause.
+    pub async fn serve<F, Fut>(cfg: &MqttConfig, on_command: F)
+    where
+        F: Fn(Command) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<String>> + Send,
+    {
+        let mut opts = MqttOptions::new("pical", &cfg.host, cfg.port);
+        opts.set_keep_alive(Duration::from_secs(30));
+        if let Some(user) = &cfg.username {
+            opts.set_credentials(user, cfg.password.as_deref().unwrap_or_default());
+        }
+        let commands = format!("{}/command", cfg.topic);
+        let responses = format!("{}/response", cfg.topic);
+        let (client, mut events) = AsyncClient::new(opts, 10);
+        log::info!(
+            "🎛 Subscribing to commands on {commands} at {}:{}",
+            cfg.host,
+            cfg.port
+        );
+
+        loop {
+            let publish = match events.poll().await {
+                // subscriptions don't survive a reconnect
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    if let Err(e) = client.try_subscribe(&commands, QoS::AtLeastOnce) {
+                        log::warn!("failed to subscribe to {commands}: {e}");
+                    }
+                    continue;
+                }
+                Ok(Event::Incoming(Packet::Publish(x))) => x,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("MQTT connection to {} failed: {e}", cfg.host);
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    continue;
+                }
+            };
+
+            let text = String::from_utf8_lossy(&publish.payload);
+            let args = text.split_whitespace().collect::<Vec<_>>();
+            let cmd = match Command::from_args(&args) {
+                Ok(x) => x,
+                Err(e) => {
+                    log::warn!("invalid MQTT command: {e}");
+                    continue;
+                }
+            };
+            log::info!("🎛 Received command {cmd:?} over MQTT");
+            let on_command = on_command.clone();
+            let client = client.clone();
+            let responses = responses.clone();
+            tokio::spawn(async move {
+                let resp = match on_command(cmd).await {
+                    Ok(x) => x,
+                    Err(e) => format!("error: {e:?}"),
+                };
+                if let Err(e) = client
+                    .publish(&responses, QoS::AtLeastOnce, false, resp)
+                    .await
+                {
+                    log::warn!("failed to publish to {responses}: {e}");
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,659 @@
+//! Rules which change how often the frame is refreshed, evaluated from the model.
+use crate::{
+    data::{cal::Event, sun::Daylight, Model},
+    render::{dirty_regions, Region},
+};
+use image::{GenericImage, GenericImageView, GrayImage};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use time::{OffsetDateTime, Time};
+
+/// How the frame should be refreshed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Refresh {
+    /// At the display refresh interval, rotating through the pages.
+    Normal,
+    /// Once a day, with the [`Away`](crate::layout::Away) layout.
+    Away,
+}
+
+/// Vacation mode: while an all-day event with the token in its summary is on, nobody is home
+/// so the frame is only refreshed once a day.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Vacation {
+    /// Matched against event summaries, ignoring case.
+    #[serde(default = "default_token")]
+    pub token: String,
+}
+
+fn default_token() -> String {
+    "Vacation".to_string()
+}
+
+impl Vacation {
+    pub fn evaluate(&self, model: &Model, now: OffsetDateTime) -> Refresh {
+        let token = self.token.to_lowercase();
+        let away = model.cals.values().flatten().any(|e| {
+            all_day(e) && e.start <= now && now < e.end && e.summary.to_lowercase().contains(&token)
+        });
+        if away {
+            Refresh::Away
+        } else {
+            Refresh::Normal
+        }
+    }
+}
+
+/// The event spans whole days.
+fn all_day(ev: &Event) -> bool {
+    ev.start.time() == Time::MIDNIGHT && ev.end.time() == Time::MIDNIGHT && ev.end > ev.start
+}
+
+/// Quiet hours: nobody looks at the frame overnight, so it isn't refreshed and the panel is
+/// left asleep.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// The `[start, end]` hours, which can wrap past midnight.
+    #[serde(default)]
+    pub hours: Option<[u8; 2]>,
+    /// Also quiet between civil dusk and dawn, which follow the seasons.
+    #[serde(default)]
+    pub after_dark: bool,
+}
+
+impl QuietHours {
+    /// Errors on hours past 23, or the same hour twice, which would be either never or always
+    /// quiet.
+    pub fn validate(&self) -> Result<()> {
+        match self.hours {
+            Some([start, end]) if start > 23 || end > 23 || start == end => Err(miette!(
+                help = "give the hours as [start, end] within 0 to 23, such as [23, 6]",
+                "quiet hours can't be {start} to {end}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `now` is quiet, with today's `daylight` if known.
+    pub fn contains(&self, now: OffsetDateTime, daylight: Option<&Daylight>) -> bool {
+        let dark = self.after_dark && daylight.is_some_and(|x| x.is_dark(now));
+        let Some([start, end]) = self.hours else {
+            return dark;
+        };
+        let h = now.hour();
+        dark || if start <= end {
+            start <= h && h < end
+        } else {
+            h >= start || h < end
+        }
+    }
+}
+
+/// A panel waveform, trading speed for how cleanly greys are drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Waveform {
+    /// Black and white only, the fastest with no flashing, suited to a clock.
+    A2,
+    /// Black and white only without flashing, slower than A2 but leaving less ghosting.
+    Du,
+    /// Four greys without flashing.
+    Du4,
+    /// All 16 greys without flashing, for greys on a light background.
+    Gl16,
+    /// All 16 greys, flashing to clear any ghosting.
+    Gc16,
+}
+
+/// A part of the frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Area {
+    Screen,
+    /// The date, clock, and header widgets.
+    Header,
+    /// Everything below the header, or the whole screen if there is none.
+    Body,
+}
+
+/// How an area of the frame is refreshed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cadence {
+    pub area: Area,
+    pub waveform: Waveform,
+    /// Push the whole area this often, otherwise only the regions which changed.
+    #[serde(default, with = "humantime_serde")]
+    pub every: Option<Duration>,
+}
+
+/// What to push to the panel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Push {
+    pub waveform: Waveform,
+    /// The regions to update, or the whole screen if `None`.
+    pub regions: Option<Vec<Region>>,
+}
+
+impl Push {
+    /// A full refresh, clearing any ghosting.
+    pub const FULL: Self = Self {
+        waveform: Waveform::Gc16,
+        regions: None,
+    };
+}
+
+/// Decides what each frame pushes to the panel, from the cadences.
+///
+/// Changes in an area without an on change cadence wait for the area's next periodic push.
+pub struct Schedule {
+    cadences: Vec<Cadence>,
+    /// When each cadence last pushed its whole area.
+    pushed: Vec<Option<Instant>>,
+    /// The frame as it is on the panel.
+    shown: Option<GrayImage>,
+}
+
+impl Schedule {
+    pub fn new(cadences: Vec<Cadence>) -> Self {
+        Self {
+            pushed: vec![None; cadences.len()],
+            cadences,
+            shown: None,
+        }
+    }
+
+    /// The cadences when none are configured: changes to the header, mostly the clock ticking
+    /// over, with A2, and other changes with DU4. Clearing the ghosting is left to the driver.
+    pub fn default_cadences() -> Vec<Cadence> {
+        vec![
+            Cadence {
+                area: Area::Header,
+                waveform: Waveform::A2,
+                every: None,
+            },
+            Cadence {
+                area: Area::Body,
+                waveform: Waveform::Du4,
+                every: None,
+            },
+        ]
+    }
+
+    /// Forget what is on the panel, such as after a failed push, so the next frame is full.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// The pushes which bring the panel up to `frame`, in order.
+    ///
+    /// `header` is where the header was painted, in pixels. A `full` refresh is done regardless
+    /// of the cadences, as is the first frame.
+    pub fn plan(
+        &mut self,
+        frame: &GrayImage,
+        header: Option<Region>,
+        full: bool,
+        now: Instant,
+    ) -> Vec<Push> {
+        let (w, h) = frame.dimensions();
+        let screen = Region { x: 0, y: 0, w, h };
+        let shown = match &mut self.shown {
+            Some(x) if !full && x.dimensions() == frame.dimensions() => x,
+            _ => {
+                // a full refresh covers every area
+                self.pushed.fill(Some(now));
+                self.shown = Some(frame.clone());
+                return vec![Push::FULL];
+            }
+        };
+        let area = |a| match (a, header) {
+            (Area::Screen, _) | (Area::Body, None) => Some(screen),
+            (Area::Header, header) => header,
+            (Area::Body, Some(header)) => {
+                let y = (header.y + header.h).min(h);
+                Some(Region {
+                    y,
+                    h: h - y,
+                    ..screen
+                })
+            }
+        };
+
+        // the periodic pushes, largest area first so an area already pushed is skipped
+        let mut periodic = (0..self.cadences.len())
+            .filter(|&i| self.cadences[i].every.is_some())
+            .collect::<Vec<_>>();
+        periodic.sort_by_key(|&i| std::cmp::Reverse(area(self.cadences[i].area).map(size)));
+        let mut pushed = Vec::<Region>::new();
+        let mut pushes = Vec::new();
+        for i in periodic {
+            let Cadence {
+                area: a,
+                waveform,
+                every,
+            } = self.cadences[i];
+            let every = every.expect("periodic cadence");
+            if self.pushed[i].is_some_and(|at| now.duration_since(at) < every) {
+                continue;
+            }
+            self.pushed[i] = Some(now);
+            let Some(r) = area(a).filter(|r| size(*r) > 0) else {
+                continue;
+            };
+            if pushed.iter().any(|x| contains(*x, r)) {
+                continue;
+            }
+            copy_region(frame, shown, r);
+            pushed.push(r);
+            pushes.push(Push {
+                waveform,
+                regions: (r != screen).then(|| vec![r]),
+            });
+        }
+
+        for c in self.cadences.iter().filter(|c| c.every.is_none()) {
+            let Some(r) = area(c.area).filter(|r| size(*r) > 0) else {
+                continue;
+            };
+            let regions = changed_within(shown, frame, r);
+            if regions.is_empty() {
+                continue;
+            }
+            let regions = arrange(regions, header);
+            for x in &regions {
+                copy_region(frame, shown, *x);
+            }
+            pushes.push(Push {
+                waveform: c.waveform,
+                regions: Some(regions),
+            });
+        }
+        // the header settles last, after the rest of the frame
+        pushes.sort_by_key(|p| match (&p.regions, header) {
+            (Some(rs), Some(header)) => rs.iter().all(|r| overlaps(*r, header)),
+            _ => false,
+        });
+        pushes
+    }
+}
+
+fn size(r: Region) -> u64 {
+    u64::from(r.w) * u64::from(r.h)
+}
+
+fn contains(outer: Region, r: Region) -> bool {
+    outer.x <= r.x
+        && outer.y <= r.y
+        && r.x + r.w <= outer.x + outer.w
+        && r.y + r.h <= outer.y + outer.h
+}
+
+fn overlaps(a: Region, b: Region) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+fn bounds(a: Region, b: Region) -> Region {
+    let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+    Region {
+        x,
+        y,
+        w: (a.x + a.w).max(b.x + b.w) - x,
+        h: (a.y + a.h).max(b.y + b.h) - y,
+    }
+}
+
+/// Each area pushed waits for the panel to finish, which takes about as long as pushing this
+/// many more pixels.
+const AREA_COST_PX: u64 = 96 * 96;
+
+/// Arrange the changed regions of a push to flash as little as possible.
+///
+/// Regions are merged where their bounding box adds fewer unchanged pixels than another area
+/// would cost. They are pushed top to bottom, except that those overlapping the `header` go
+/// last, as the clock is what catches the eye.
+pub fn arrange(mut regions: Vec<Region>, header: Option<Region>) -> Vec<Region> {
+    loop {
+        let mut best = None;
+        for i in 0..regions.len() {
+            for j in i + 1..regions.len() {
+                let (a, b) = (regions[i], regions[j]);
+                let waste = size(bounds(a, b)).saturating_sub(size(a) + size(b));
+                let better = match best {
+                    Some((_, _, w)) => waste < w,
+                    None => waste < AREA_COST_PX,
+                };
+                if better {
+                    best = Some((i, j, waste));
+                }
+            }
+        }
+        let Some((i, j, _)) = best else {
+            break;
+        };
+        let b = regions.swap_remove(j);
+        regions[i] = bounds(regions[i], b);
+    }
+    regions.sort_by_key(|r| (header.is_some_and(|h| overlaps(*r, h)), r.y, r.x));
+    regions
+}
+
+fn copy_region(from: &GrayImage, to: &mut GrayImage, r: Region) {
+    let view = from.view(r.x, r.y, r.w, r.h);
+    to.copy_from(&*view, r.x, r.y).expect("same sized frames");
+}
+
+/// The regions which changed within the area `r`.
+fn changed_within(old: &GrayImage, new: &GrayImage, r: Region) -> Vec<Region> {
+    let crop = |x: &GrayImage| x.view(r.x, r.y, r.w, r.h).to_image();
+    dirty_regions(&crop(old), &crop(new))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| Region {
+            x: x.x + r.x,
+            y: x.y + r.y,
+            ..x
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use time::macros::{date, datetime, offset};
+
+    #[test]
+    fn cadences_per_area() {
+        let header = Region {
+            x: 0,
+            y: 0,
+            w: 64,
+            h: 10,
+        };
+        let mut schedule = Schedule::new(vec![
+            Cadence {
+                area: Area::Header,
+                waveform: Waveform::A2,
+                every: Some(Duration::from_secs(60)),
+            },
+            Cadence {
+                area: Area::Body,
+                waveform: Waveform::Du4,
+                every: None,
+            },
+            Cadence {
+                area: Area::Screen,
+                waveform: Waveform::Gc16,
+                every: Some(Duration::from_secs(3600)),
+            },
+        ]);
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let mut frame = GrayImage::from_pixel(64, 40, image::Luma([255]));
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, t0),
+            vec![Push::FULL]
+        );
+
+        // the clock ticks over, but the header waits for its minute
+        frame.put_pixel(30, 5, image::Luma([0]));
+        assert_eq!(schedule.plan(&frame, Some(header), false, at(30)), vec![]);
+        // a cell changes, and is pushed straight away
+        frame.put_pixel(9, 20, image::Luma([0]));
+        let cell = Region {
+            x: 8,
+            y: 20,
+            w: 4,
+            h: 1,
+        };
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, at(40)),
+            vec![Push {
+                waveform: Waveform::Du4,
+                regions: Some(vec![cell]),
+            }]
+        );
+        // the header is pushed whole on the minute, changed or not
+        let header_push = Push {
+            waveform: Waveform::A2,
+            regions: Some(vec![header]),
+        };
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, at(60)),
+            vec![header_push.clone()]
+        );
+        assert_eq!(schedule.plan(&frame, Some(header), false, at(90)), vec![]);
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, at(125)),
+            vec![header_push]
+        );
+        // on the hour the whole screen is cleared, which covers the header too
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, at(3600)),
+            vec![Push {
+                waveform: Waveform::Gc16,
+                regions: None,
+            }]
+        );
+
+        // a forced refresh, or a failed push, is full
+        assert_eq!(
+            schedule.plan(&frame, Some(header), true, at(3601)),
+            vec![Push::FULL]
+        );
+        schedule.invalidate();
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, at(3602)),
+            vec![Push::FULL]
+        );
+    }
+
+    #[test]
+    fn default_cadences_tick_the_clock_with_a2() {
+        let header = Region {
+            x: 0,
+            y: 0,
+            w: 64,
+            h: 12,
+        };
+        let t0 = Instant::now();
+        let mut schedule = Schedule::new(Schedule::default_cadences());
+        let mut frame = GrayImage::from_pixel(64, 40, image::Luma([255]));
+        assert_eq!(
+            schedule.plan(&frame, Some(header), false, t0),
+            vec![Push::FULL]
+        );
+
+        frame.put_pixel(30, 5, image::Luma([0]));
+        frame.put_pixel(9, 20, image::Luma([0]));
+        let waveforms = schedule
+            .plan(&frame, Some(header), false, t0 + Duration::from_secs(60))
+            .into_iter()
+            .map(|x| x.waveform)
+            .collect::<Vec<_>>();
+        assert_eq!(waveforms, [Waveform::Du4, Waveform::A2]);
+
+        // no header, so everything is the body
+        frame.put_pixel(30, 5, image::Luma([255]));
+        let pushes = schedule.plan(&frame, None, false, t0 + Duration::from_secs(120));
+        assert_eq!(pushes.len(), 1);
+        assert_eq!(pushes[0].waveform, Waveform::Du4);
+        // nothing is pushed without a change, the driver clearing the ghosting
+        assert!(schedule
+            .plan(&frame, None, false, t0 + Duration::from_secs(3600))
+            .is_empty());
+    }
+
+    fn model(evs: Vec<Event>) -> Model {
+        let mut model = Model::default();
+        model.make_mut().cals = HashMap::from([("Home".to_string(), evs)]);
+        model
+    }
+
+    fn ev(summary: &str, start: OffsetDateTime, end: OffsetDateTime) -> Event {
+        Event {
+            summary: summary.to_string(),
+            start,
+            end,
+            attendees: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn away_during_all_day_vacation() {
+        let vacation = Vacation {
+            token: default_token(),
+        };
+        let model = model(vec![
+            ev(
+                "Beach vacation 🏖",
+                datetime!(2024-07-01 0:00 +10),
+                datetime!(2024-07-08 0:00 +10),
+            ),
+            // timed events don't count, even if they match
+            ev(
+                "Vacation planning",
+                datetime!(2024-06-20 19:00 +10),
+                datetime!(2024-06-20 20:00 +10),
+            ),
+        ]);
+
+        let at = |now| vacation.evaluate(&model, now);
+        assert_eq!(at(datetime!(2024-07-01 0:00 +10)), Refresh::Away);
+        assert_eq!(at(datetime!(2024-07-07 23:59 +10)), Refresh::Away);
+        assert_eq!(at(datetime!(2024-07-08 0:00 +10)), Refresh::Normal);
+        assert_eq!(at(datetime!(2024-06-20 19:30 +10)), Refresh::Normal);
+    }
+
+    #[test]
+    fn quiet_overnight() {
+        let quiet = QuietHours {
+            hours: Some([23, 6]),
+            after_dark: false,
+        };
+        let contains = |quiet: &QuietHours, now| quiet.contains(now, None);
+        assert!(!contains(&quiet, datetime!(2024-06-21 22:59 +10)));
+        assert!(contains(&quiet, datetime!(2024-06-21 23:00 +10)));
+        assert!(contains(&quiet, datetime!(2024-06-22 5:59 +10)));
+        assert!(!contains(&quiet, datetime!(2024-06-22 6:00 +10)));
+        let afternoon = QuietHours {
+            hours: Some([13, 15]),
+            after_dark: false,
+        };
+        assert!(contains(&afternoon, datetime!(2024-06-21 14:00 +10)));
+        assert!(!contains(&afternoon, datetime!(2024-06-21 15:00 +10)));
+
+        assert!(quiet.validate().is_ok());
+        let invalid = |hours| {
+            QuietHours {
+                hours: Some(hours),
+                after_dark: false,
+            }
+            .validate()
+            .is_err()
+        };
+        assert!(invalid([23, 24]));
+        assert!(invalid([6, 6]));
+    }
+
+    #[test]
+    fn quiet_after_dark() {
+        let daylight = Daylight::on(date!(2024 - 06 - 21), [-27.4679, 153.0325], offset!(+10));
+        let dark = QuietHours {
+            hours: None,
+            after_dark: true,
+        };
+        let at = |quiet: &QuietHours, now| quiet.contains(now, Some(&daylight));
+        assert!(at(&dark, datetime!(2024-06-21 5:00 +10)));
+        assert!(!at(&dark, datetime!(2024-06-21 12:00 +10)));
+        assert!(at(&dark, datetime!(2024-06-21 18:00 +10)));
+        // not yet worked out
+        assert!(!dark.contains(datetime!(2024-06-21 18:00 +10), None));
+        // or in the hours
+        let both = QuietHours {
+            hours: Some([13, 15]),
+            ..dark
+        };
+        assert!(at(&both, datetime!(2024-06-21 14:00 +10)));
+        assert!(at(&both, datetime!(2024-06-21 18:00 +10)));
+    }
+
+    /// A white frame with black blocks at each `[x, y, w, h]`.
+    fn blocks(size: [u32; 2], blocks: &[[u32; 4]]) -> GrayImage {
+        let mut img = GrayImage::from_pixel(size[0], size[1], image::Luma([255]));
+        for &[x, y, w, h] in blocks {
+            for (x, y) in (y..y + h).flat_map(|y| (x..x + w).map(move |x| (x, y))) {
+                img.put_pixel(x, y, image::Luma([0]));
+            }
+        }
+        img
+    }
+
+    fn region([x, y, w, h]: [u32; 4]) -> Region {
+        Region { x, y, w, h }
+    }
+
+    #[test]
+    fn arrange_coalesces_and_orders() {
+        let old = blocks([800, 600], &[]);
+        let header = region([0, 0, 800, 40]);
+        // the clock, two small nearby cells, and a cell far away
+        let new = blocks(
+            [800, 600],
+            &[
+                [700, 10, 60, 20],
+                [100, 200, 40, 12],
+                [100, 240, 40, 12],
+                [600, 500, 40, 12],
+            ],
+        );
+        let dirty = dirty_regions(&old, &new).unwrap();
+        assert_eq!(dirty.len(), 4);
+
+        assert_eq!(
+            arrange(dirty.clone(), Some(header)),
+            [
+                region([100, 200, 40, 52]),
+                region([600, 500, 40, 12]),
+                region([700, 10, 60, 20]),
+            ]
+        );
+        // without a header, top to bottom
+        assert_eq!(arrange(dirty, None)[0], region([700, 10, 60, 20]));
+
+        // large regions are not worth merging
+        let big = [region([0, 100, 400, 100]), region([400, 300, 400, 100])];
+        assert_eq!(arrange(big.to_vec(), None), big);
+        assert_eq!(arrange(Vec::new(), Some(header)), []);
+    }
+
+    #[test]
+    fn header_pushed_last() {
+        let header = region([0, 0, 64, 10]);
+        let mut schedule = Schedule::new(vec![
+            Cadence {
+                area: Area::Header,
+                waveform: Waveform::A2,
+                every: None,
+            },
+            Cadence {
+                area: Area::Body,
+                waveform: Waveform::Du4,
+                every: None,
+            },
+        ]);
+        let t0 = Instant::now();
+        let mut frame = GrayImage::from_pixel(64, 40, image::Luma([255]));
+        schedule.plan(&frame, Some(header), false, t0);
+
+        frame.put_pixel(30, 5, image::Luma([0]));
+        frame.put_pixel(9, 20, image::Luma([0]));
+        let waveforms = schedule
+            .plan(&frame, Some(header), false, t0)
+            .into_iter()
+            .map(|x| x.waveform)
+            .collect::<Vec<_>>();
+        assert_eq!(waveforms, [Waveform::Du4, Waveform::A2]);
+    }
+}
@@ -0,0 +1,204 @@
+//! A live preview of the latest frame over HTTP, to check the panel from a phone.
+//!
+//! - `GET /`: a page showing the frame, reloading itself every `refresh`.
+//! - `GET /frame.png`: the latest frame.
+//!
+//! Serving needs the `web-ui` feature.
+use crate::sink::FrameSink;
+use image::GrayImage;
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(feature = "web-ui")]
+use tokio::{io::AsyncWriteExt, io::BufReader, net::TcpListener};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PreviewConfig {
+    /// Address to listen on, anyone who can reach it can see the calendar.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// How often the page reloads the frame.
+    #[serde(default = "default_refresh", with = "humantime_serde")]
+    pub refresh: Duration,
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:8426".to_string()
+}
+
+fn default_refresh() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// The latest frame, shared between the render loop and the preview server.
+#[derive(Clone, Default)]
+pub struct Latest(Arc<Mutex<Option<Arc<GrayImage>>>>);
+
+impl Latest {
+    pub fn set(&self, frame: &GrayImage) {
+        *self.0.lock().expect("preview lock poisoned") = Some(Arc::new(frame.clone()));
+    }
+
+    #[cfg(feature = "web-ui")]
+    fn get(&self) -> Option<Arc<GrayImage>> {
+        self.0.lock().expect("preview lock poisoned").clone()
+    }
+}
+
+impl FrameSink for Latest {
+    fn put(&mut self, frame: &GrayImage) -> Result<()> {
+        self.set(frame);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "web-ui")]
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+#[cfg(feature = "web-ui")]
+impl Response {
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into().into_bytes(),
+        }
+    }
+}
+
+/// Serve the preview of `latest`.
+///
+/// Connections are handled one at a time, which is plenty for a phone or two.
+#[cfg(feature = "web-ui")]
+pub async fn serve(cfg: &PreviewConfig, latest: Latest) -> Result<()> {
+    let listener = TcpListener::bind(&cfg.listen)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}", cfg.listen))?;
+    log::info!("🖼 Serving a live preview on {}", cfg.listen);
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((x, _)) => x,
+            Err(e) => {
+                log::warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let mut stream = BufReader::new(stream);
+        let req = tokio::time::timeout(
+            Duration::from_secs(5),
+            crate::control::read_request(&mut stream),
+        )
+        .await;
+        let resp = match req {
+            Ok(Ok((method, path, _))) => respond(&method, &path, &latest, cfg.refresh),
+            Ok(Err(e)) => Response::text("400 Bad Request", e.to_string()),
+            Err(_) => Response::text("408 Request Timeout", ""),
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+            resp.status,
+            resp.content_type,
+            resp.body.len()
+        );
+        let stream = stream.get_mut();
+        let write = async {
+            stream.write_all(head.as_bytes()).await?;
+            stream.write_all(&resp.body).await
+        };
+        // don't let a stalled phone hold up everyone else
+        let _ = tokio::time::timeout(Duration::from_secs(30), write).await;
+    }
+}
+
+#[cfg(feature = "web-ui")]
+fn respond(method: &str, path: &str, latest: &Latest, refresh: Duration) -> Response {
+    // a query is only there to bust caches
+    let path = path.split('?').next().unwrap_or_default();
+    match (method, path) {
+        ("GET", "/") => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: page(refresh).into_bytes(),
+        },
+        ("GET", "/frame.png") => match latest.get().map(|x| png(&x)) {
+            Some(Ok(body)) => Response {
+                status: "200 OK",
+                content_type: "image/png",
+                body,
+            },
+            Some(Err(e)) => Response::text("500 Internal Server Error", format!("{e:?}")),
+            None => Response::text("503 Service Unavailable", "no frame rendered yet"),
+        },
+        _ => Response::text("404 Not Found", format!("nothing at {method} {path}")),
+    }
+}
+
+#[cfg(feature = "web-ui")]
+fn page(refresh: Duration) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+         <html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta http-equiv=\"refresh\" content=\"{}\"><title>pical</title></head>\
+         <body style=\"margin:0;background:#777\">\
+         <img src=\"/frame.png\" alt=\"The latest frame\" style=\"display:block;width:100%\">\
+         </body></html>\n",
+        refresh.as_secs().max(1)
+    )
+}
+
+#[cfg(feature = "web-ui")]
+fn png(frame: &GrayImage) -> Result<Vec<u8>> {
+    let mut buf = std::io::Cursor::new(Vec::new());
+    frame
+        .write_to(&mut buf, image::ImageOutputFormat::Png)
+        .into_diagnostic()
+        .wrap_err("failed to encode frame as PNG")?;
+    Ok(buf.into_inner())
+}
+
+#[cfg(all(test, feature = "web-ui"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes() {
+        let latest = Latest::default();
+        let refresh = Duration::from_secs(30);
+        let page = respond("GET", "/", &latest, refresh);
+        assert_eq!(page.status, "200 OK");
+        let html = String::from_utf8(page.body).unwrap();
+        assert!(html.contains("content=\"30\""), "{html}");
+        assert!(html.contains("src=\"/frame.png\""), "{html}");
+
+        assert_eq!(
+            respond("GET", "/frame.png", &latest, refresh).status,
+            "503 Service Unavailable"
+        );
+        let frame = GrayImage::from_fn(4, 3, |x, y| image::Luma([(x * 60 + y) as u8]));
+        latest.clone().put(&frame).unwrap();
+        let resp = respond("GET", "/frame.png?t=1", &latest, refresh);
+        assert_eq!((resp.status, resp.content_type), ("200 OK", "image/png"));
+        let decoded = image::load_from_memory(&resp.body).unwrap().into_luma8();
+        assert_eq!(decoded, frame);
+
+        assert_eq!(
+            respond("POST", "/frame.png", &latest, refresh).status,
+            "404 Not Found"
+        );
+        assert_eq!(
+            respond("GET", "/x", &latest, refresh).status,
+            "404 Not Found"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Pushing frames over the network, from a render server to an agent driving the panel.
+//!
+//! A push is a single JSON [`Header`] line followed by the frame bytes, answered by the agent
+//! with an `ok` or `error: <reason>` line.
+//! Every push carries a shared token, and can additionally be signed with an ed25519 key
+//! which the agent verifies before the frame goes anywhere near the display.
+//! The signature covers the timestamp, and the agent only accepts pushes newer than the last it
+//! accepted, so a captured push cannot be replayed.
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, sync::Arc, time::Duration};
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+/// Pushes older (or newer) than this are rejected.
+const MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+/// Upper bound on a frame's size, a 1872x1404 bitmap is ~2.6MB.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Render server side: where to push frames to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// The agent's address, eg `"pical.local:8424"`.
+    pub addr: String,
+    /// Shared secret, must match the agent's.
+    pub token: String,
+    /// Base64 ed25519 secret key (32 byte seed) to sign frames with.
+    #[serde(default)]
+    pub signing_key: Option<String>,
+}
+
+/// Agent side: listen for pushed frames and display them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Shared secret, must match the render server's.
+    pub token: String,
+    /// Base64 ed25519 public key. If set, only frames signed with the matching key are shown.
+    #[serde(default)]
+    pub verify_key: Option<String>,
+}
+
+fn default_listen() -> String {
+    "0.0.0.0:8424".to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    token: String,
+    /// Unix timestamp (milliseconds) the push was made, so pushes in the same second differ.
+    timestamp: i64,
+    /// Do a full refresh.
+    full: bool,
+    len: usize,
+    /// Base64 signature of [`signed_message`].
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+/// A frame received by the agent.
+pub struct Frame {
+    /// The encoded image, any format `image` can read.
+    pub data: Vec<u8>,
+    pub full: bool,
+}
+
+fn signed_message(timestamp: i64, full: bool, data: &[u8]) -> Vec<u8> {
+    let mut msg = b"pical-frame-v1".to_vec();
+    msg.extend_from_slice(&timestamp.to_be_bytes());
+    msg.push(full as u8);
+    msg.extend_from_slice(data);
+    msg
+}
+
+fn key_bytes(b64: &str) -> Result<[u8; 32]> {
+    B64.decode(b64.trim())
+        .into_diagnostic()?
+        .try_into()
+        .map_err(|_| miette!("expecting a 32 byte key"))
+}
+
+/// Parse a base64 ed25519 secret key.
+pub fn signing_key(b64: &str) -> Result<SigningKey> {
+    key_bytes(b64)
+        .map(|k| SigningKey::from_bytes(&k))
+        .wrap_err("invalid signing key")
+}
+
+/// The base64 public key to give the agent for a signing key.
+pub fn verify_key_of(key: &SigningKey) -> String {
+    B64.encode(key.verifying_key().as_bytes())
+}
+
+fn verifying_key(b64: &str) -> Result<VerifyingKey> {
+    key_bytes(b64)
+        .and_then(|k| VerifyingKey::from_bytes(&k).into_diagnostic())
+        .wrap_err("invalid verify key")
+}
+
+/// Constant time comparison, so the token cannot be guessed byte by byte.
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Push a frame to the agent described by `cfg`.
+pub async fn push(cfg: &RemoteConfig, data: Vec<u8>, full: bool) -> Result<()> {
+    let timestamp = now_ms();
+    let signature = match &cfg.signing_key {
+        Some(k) => {
+            let sig = signing_key(k)?.sign(&signed_message(timestamp, full, &data));
+            Some(B64.encode(sig.to_bytes()))
+        }
+        None => None,
+    };
+    let hdr = Header {
+        token: cfg.token.clone(),
+        timestamp,
+        full,
+        len: data.len(),
+        signature,
+    };
+    let mut line = serde_json::to_string(&hdr).into_diagnostic()?;
+    line.push('\n');
+
+    let addr = cfg.addr.as_str();
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to connect to agent at {addr}"))?;
+    stream.write_all(line.as_bytes()).await.into_diagnostic()?;
+    stream.write_all(&data).await.into_diagnostic()?;
+
+    let mut resp = String::new();
+    BufReader::new(stream)
+        .read_line(&mut resp)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("no response from agent at {addr}"))?;
+    match resp.trim() {
+        "ok" => Ok(()),
+        e => Err(miette!("{e}")).wrap_err_with(|| format!("agent at {addr} rejected frame")),
+    }
+}
+
+/// Listen for pushed frames, calling `on_frame` with each one that is authorised.
+///
+/// Each connection is handled in its own task, so one slow to send its frame doesn't hold up
+/// the rest, but frames are shown one at a time, in the order they were accepted.
+pub async fn serve<F, Fut>(cfg: &AgentConfig, on_frame: F) -> Result<()>
+where
+    F: FnMut(Frame) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    let verify = cfg.verify_key.as_deref().map(verifying_key).transpose()?;
+    let token: Arc<str> = cfg.token.as_str().into();
+    let shown = Arc::new(Mutex::new(Shown {
+        last: i64::MIN,
+        on_frame,
+    }));
+    let listener = TcpListener::bind(&cfg.listen)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}", cfg.listen))?;
+    log::info!("📡 Listening for frames on {}", cfg.listen);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let token = token.clone();
+        let shown = shown.clone();
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let res = show(&mut stream, &token, verify.as_ref(), &shown).await;
+            if let Err(e) = &res {
+                log::warn!("frame from {peer} failed: {e}");
+            }
+            let resp = match res {
+                Ok(()) => "ok\n".to_string(),
+                Err(e) => format!("error: {e}\n"),
+            };
+            let _ = stream.get_mut().write_all(resp.as_bytes()).await;
+        });
+    }
+}
+
+/// The frame handler, and the timestamp of the last push accepted.
+struct Shown<F> {
+    last: i64,
+    on_frame: F,
+}
+
+/// Receive a push and show it, if it is authorised and still the newest.
+async fn show<F, Fut>(
+    stream: &mut BufReader<TcpStream>,
+    token: &str,
+    verify: Option<&VerifyingKey>,
+    shown: &Mutex<Shown<F>>,
+) -> Result<()>
+where
+    F: FnMut(Frame) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let last = shown.lock().await.last;
+    let (frame, timestamp) = receive(stream, token, verify, last).await?;
+    let mut shown = shown.lock().await;
+    // a newer push may have been accepted while this one was being read
+    if timestamp <= shown.last {
+        return Err(miette!(
+            "push is no newer than the last, it may be replayed"
+        ));
+    }
+    shown.last = timestamp;
+    (shown.on_frame)(frame).await
+}
+
+/// Read a push, returning it with its timestamp if it is authorised, `last` being the timestamp
+/// of the last push accepted.
+async fn receive(
+    stream: &mut BufReader<TcpStream>,
+    token: &str,
+    verify: Option<&VerifyingKey>,
+    last: i64,
+) -> Result<(Frame, i64)> {
+    let mut line = String::new();
+    // bound the header so a garbage connection can't exhaust memory
+    let mut hdr_stream = (&mut *stream).take(4096);
+    tokio::time::timeout(Duration::from_secs(10), hdr_stream.read_line(&mut line))
+        .await
+        .into_diagnostic()
+        .and_then(|x| x.into_diagnostic())
+        .wrap_err("failed to read header")?;
+    let hdr: Header = serde_json::from_str(&line)
+        .into_diagnostic()
+        .wrap_err("invalid header")?;
+    check_header(&hdr, token, now_ms(), last)?;
+
+    let mut data = vec![0; hdr.len];
+    tokio::time::timeout(Duration::from_secs(60), stream.read_exact(&mut data))
+        .await
+        .into_diagnostic()
+        .and_then(|x| x.into_diagnostic())
+        .wrap_err("failed to read frame")?;
+
+    if let Some(key) = verify {
+        let sig = hdr
+            .signature
+            .as_deref()
+            .ok_or_else(|| miette!("frame is not signed"))?;
+        let sig = B64
+            .decode(sig)
+            .into_diagnostic()
+            .and_then(|x| Signature::from_slice(&x).into_diagnostic())
+            .wrap_err("invalid signature")?;
+        key.verify(&signed_message(hdr.timestamp, hdr.full, &data), &sig)
+            .map_err(|_| miette!("bad signature"))?;
+    }
+
+    let frame = Frame {
+        data,
+        full: hdr.full,
+    };
+    Ok((frame, hdr.timestamp))
+}
+
+fn now_ms() -> i64 {
+    (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// Checks done before reading the frame body, `last` being the timestamp of the last push
+/// accepted.
+fn check_header(hdr: &Header, token: &str, now: i64, last: i64) -> Result<()> {
+    if !token_eq(&hdr.token, token) {
+        return Err(miette!("bad token"));
+    }
+    if u128::from(hdr.timestamp.abs_diff(now)) > MAX_SKEW.as_millis() {
+        return Err(miette!("timestamp outside allowed skew, check clocks"));
+    }
+    if hdr.timestamp <= last {
+        return Err(miette!(
+            "push is no newer than the last, it may be replayed"
+        ));
+    }
+    if hdr.len > MAX_FRAME_LEN {
+        return Err(miette!("frame is too large ({} bytes)", hdr.len));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_checks() {
+        let hdr = |token: &str, timestamp, len| Header {
+            token: token.to_string(),
+            timestamp,
+            full: false,
+            len,
+            signature: None,
+        };
+        let [then, now] = [1_000_000, 1_010_000];
+        let ok = |h, last| check_header(&h, "secret", now, last).is_ok();
+        assert!(ok(hdr("secret", then, 10), i64::MIN));
+        assert!(!ok(hdr("secreT", then, 10), i64::MIN));
+        assert!(!ok(hdr("secret1", then, 10), i64::MIN));
+        assert!(check_header(&hdr("secret", then, 10), "secret", 2_000_000, i64::MIN).is_err());
+        assert!(!ok(hdr("secret", then, usize::MAX), i64::MIN));
+        // replayed, or older than the last accepted
+        assert!(!ok(hdr("secret", then, 10), then));
+        assert!(!ok(hdr("secret", then, 10), then + 1));
+        assert!(ok(hdr("secret", then + 1, 10), then));
+    }
+
+    #[test]
+    fn signatures() {
+        let key = signing_key(&B64.encode([7u8; 32])).unwrap();
+        let verify = verifying_key(&verify_key_of(&key)).unwrap();
+        let sig = key.sign(&signed_message(1000, true, b"frame"));
+        assert!(verify
+            .verify(&signed_message(1000, true, b"frame"), &sig)
+            .is_ok());
+        assert!(verify
+            .verify(&signed_message(1001, true, b"frame"), &sig)
+            .is_err());
+        assert!(verify
+            .verify(&signed_message(1000, false, b"frame"), &sig)
+            .is_err());
+        assert!(verify
+            .verify(&signed_message(1000, true, b"fraMe"), &sig)
+            .is_err());
+    }
+}
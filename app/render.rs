@@ -1,12 +1,16 @@
 use egui::{
-    epaint::ImageDelta, ClippedPrimitive, Color32, Context, ImageData, Pos2, Rect, Rgba, Ui, Vec2,
+    epaint::{PaintCallback, PaintCallbackInfo},
+    ClippedPrimitive, Color32, Context, ImageData, Pos2, Rect, Rgba, Ui, Vec2,
 };
 use euc::{Buffer2d, Empty, Pipeline, Sampler, Texture};
 use humantime::Duration;
-use image::RgbaImage;
+use image::{GrayImage, RgbaImage};
+use serde::Serialize;
 use std::{
     collections::HashMap,
+    marker::PhantomData,
     ops::{Add, Mul},
+    sync::Arc,
     time::Instant,
 };
 
@@ -14,92 +18,222 @@ pub trait Render<C> {
     fn render(&self, ui: &mut Ui, ctx: C);
 }
 
-pub struct Painted {
-    pub img: RgbaImage,
+/// A custom paint callback which draws directly into the software framebuffer.
+///
+/// Add it to a painter with [`SoftwareCallback::paint_callback`].
+/// The framebuffer is indexed in pixels with the origin at the top left, see [`Framebuffer`].
+/// Callbacks should only draw within the pixel bounds of the info's viewport and clip rect.
+pub struct SoftwareCallback(Box<SoftwareCallbackFn>);
+
+type SoftwareCallbackFn = dyn Fn(&PaintCallbackInfo, &mut dyn Framebuffer) + Send + Sync;
+
+impl SoftwareCallback {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&PaintCallbackInfo, &mut dyn Framebuffer) + Send + Sync + 'static,
+    {
+        Self(Box::new(f))
+    }
+
+    pub fn paint_callback(self, rect: Rect) -> PaintCallback {
+        PaintCallback {
+            rect,
+            callback: Arc::new(self),
+        }
+    }
+}
+
+/// A pixel of the framebuffer, which premultiplied fragments are blended over.
+pub trait Pixel: Copy + Send + Sync + 'static {
+    /// The framebuffer is cleared to this before painting.
+    const CLEAR: Self;
+
+    /// Blend the premultiplied `new` over this pixel.
+    fn blend(self, new: Rgba) -> Self;
+}
+
+impl Pixel for Rgba {
+    const CLEAR: Self = Rgba::TRANSPARENT;
+
+    fn blend(self, new: Rgba) -> Self {
+        new + self.multiply(1.0 - new.a())
+    }
+}
+
+/// Linear luminance, for frames which end up grey anyway. Skips carrying and converting the
+/// colour channels, which is a good part of the painting time on a Pi Zero.
+impl Pixel for f32 {
+    const CLEAR: Self = 0.0;
+
+    fn blend(self, new: Rgba) -> Self {
+        luminance(new) + self * (1.0 - new.a())
+    }
+}
+
+/// The linear luminance of a (premultiplied) linear colour, by the Rec. 709 weights.
+fn luminance(c: Rgba) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+/// What software callbacks draw into.
+pub trait Framebuffer {
+    /// Blend the premultiplied colour `px` over the pixel at `x`, `y`.
+    fn blend(&mut self, x: usize, y: usize, px: Rgba);
+}
+
+impl<P: Pixel> Framebuffer for Buffer2d<P> {
+    fn blend(&mut self, x: usize, y: usize, px: Rgba) {
+        let i = self.linear_index([x, y]);
+        let old = self.raw()[i];
+        self.raw_mut()[i] = old.blend(px);
+    }
+}
+
+pub struct Painted<I = RgbaImage> {
+    pub img: I,
     pub ui_gen: Duration,
     pub tessellation: Duration,
+    /// Rasterising the triangles into the framebuffer.
     pub rendering: Duration,
+    /// Converting the framebuffer to the image.
+    pub conversion: Duration,
     pub resizing: Option<Duration>,
 }
 
-impl Painted {
+/// How long each stage of painting a frame took.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Timings {
+    pub ui_gen: std::time::Duration,
+    pub tessellation: std::time::Duration,
+    pub rasterisation: std::time::Duration,
+    /// From the framebuffer to the frame pushed to the panel, including any resizing.
+    pub conversion: std::time::Duration,
+}
+
+impl<I> Painted<I> {
     pub fn log_debug_timings(&self) {
         let Self {
             img: _,
             ui_gen,
             tessellation,
             rendering,
+            conversion,
             resizing,
         } = self;
         log::debug!("⏱ UI Generation: {ui_gen}");
         log::debug!("⏱ Tessallation: {tessellation}");
         log::debug!("⏱ Rendering: {rendering}");
+        log::debug!("⏱ Conversion: {conversion}");
         if let Some(x) = resizing {
             log::debug!("⏱ Resizing: {x}");
         }
     }
+
+    pub fn timings(&self) -> Timings {
+        Timings {
+            ui_gen: *self.ui_gen,
+            tessellation: *self.tessellation,
+            rasterisation: *self.rendering,
+            conversion: *self.conversion + self.resizing.map(|x| *x).unwrap_or_default(),
+        }
+    }
 }
 
+/// Paint the UI to an RGBA image, with a fresh [`Renderer`].
 pub fn paint<F>(width_px: u32, height_px: u32, scaling: f32, run_ui: F) -> Painted
 where
     F: FnOnce(&Context),
 {
-    // define the draw pixels
-    let [width, height] = [width_px, height_px].map(|x| (x as f32 * scaling).floor() as u32);
-    // define screen size in _points_
-    let size = [width_px, height_px].map(|x| x as f32);
+    Renderer::default().paint(width_px, height_px, scaling, run_ui)
+}
 
-    // generate UI
-    let now = Instant::now();
-    let ctx = Context::default();
-    let input = egui::RawInput {
-        screen_rect: Rect::from_two_pos(Pos2::ZERO, size.into()).into(),
-        ..Default::default()
-    };
-    let output = ctx.run(input.clone(), run_ui);
-    let ui_gen = Duration::from(now.elapsed());
+/// Paint the UI to a greyscale image, with a fresh [`Renderer`].
+pub fn paint_gray<F>(width_px: u32, height_px: u32, scaling: f32, run_ui: F) -> Painted<GrayImage>
+where
+    F: FnOnce(&Context),
+{
+    Renderer::default().paint_gray(width_px, height_px, scaling, run_ui)
+}
 
-    // generate painting triangles
-    let now = Instant::now();
-    let meshes = ctx
-        .tessellate(output.shapes, output.pixels_per_point)
-        .into_iter()
-        .filter_map(|x| Mesh::from_clipped_prim(size, x))
-        .collect::<Vec<_>>();
-    let tessellation = Duration::from(now.elapsed());
+/// Paints frame after frame, keeping the egui context and its textures between them.
+///
+/// A fresh context lays out and uploads the whole font atlas every frame, which is a good part
+/// of the painting time on a Pi Zero. Kept, only the glyphs new to a frame are added.
+#[derive(Default)]
+pub struct Renderer {
+    ctx: Context,
+    txs: HashMap<egui::TextureId, RgbaTexture>,
+}
 
-    // populate the textures
-    let now = Instant::now();
-    let txs: HashMap<_, _> = output
-        .textures_delta
-        .set
-        .into_iter()
-        .map(|(id, delta)| (id, RgbaTexture::from(delta)))
-        .collect();
-
-    let mut colour_buf = Buffer2d::fill(
-        [width as usize, height as usize],
-        Rgba::from_black_alpha(0.),
-    );
-
-    for mut mesh in meshes {
-        let sampler = txs.get(&mesh.mesh.texture_id).map(|tx| tx.linear());
-        mesh.sampler = sampler;
-        mesh.render(
-            mesh.mesh
-                .indices
+impl Renderer {
+    /// Paint the UI to an RGBA image.
+    pub fn paint<F>(&mut self, width_px: u32, height_px: u32, scaling: f32, run_ui: F) -> Painted
+    where
+        F: FnOnce(&Context),
+    {
+        let (buf, painted) = self.raster::<Rgba, _>(width_px, height_px, scaling, bands(), run_ui);
+        finish(painted, width_px, height_px, || {
+            let [width, height] = buf.size;
+            let mut img = RgbaImage::new(width, height);
+            for (px, out) in buf.pxs.iter().zip(img.pixels_mut()) {
+                *out = Color32::from(*px).to_array().into();
+            }
+            img
+        })
+    }
+
+    /// Paint the UI to a greyscale image, rasterising luminance directly rather than converting
+    /// from RGBA. Colours are reduced to their luminance.
+    pub fn paint_gray<F>(
+        &mut self,
+        width_px: u32,
+        height_px: u32,
+        scaling: f32,
+        run_ui: F,
+    ) -> Painted<GrayImage>
+    where
+        F: FnOnce(&Context),
+    {
+        let (buf, painted) = self.raster::<f32, _>(width_px, height_px, scaling, bands(), run_ui);
+        finish(painted, width_px, height_px, || {
+            let [width, height] = buf.size;
+            let pxs = buf
+                .pxs
                 .iter()
-                .copied()
-                .map(|x| mesh.mesh.vertices[x as usize]),
-            &mut colour_buf,
-            &mut Empty::default(),
-        );
+                .map(|&x| egui::ecolor::gamma_u8_from_linear_f32(x))
+                .collect();
+            GrayImage::from_raw(width, height, pxs).expect("buffer is the image size")
+        })
     }
+}
 
-    // fill image
-    let i = buf_to_img(width, height, &colour_buf);
-    let rendering = Duration::from(now.elapsed());
-    let (img, resizing) = if scaling == 1.0 {
+/// Timings of the rasterisation, before the image is finished.
+struct Rastered {
+    ui_gen: Duration,
+    tessellation: Duration,
+    rendering: Duration,
+}
+
+/// Convert the framebuffer with `to_img`, and resize it if it was scaled.
+fn finish<P>(
+    rastered: Rastered,
+    width_px: u32,
+    height_px: u32,
+    to_img: impl FnOnce() -> image::ImageBuffer<P, Vec<P::Subpixel>>,
+) -> Painted<image::ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: image::Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    let Rastered {
+        ui_gen,
+        tessellation,
+        rendering,
+    } = rastered;
+    let now = Instant::now();
+    let i = to_img();
+    let conversion = Duration::from(now.elapsed());
+    let (img, resizing) = if (i.width(), i.height()) == (width_px, height_px) {
         (i, None)
     } else {
         let now = Instant::now();
@@ -117,61 +251,446 @@ where
         ui_gen,
         tessellation,
         rendering,
+        conversion,
         resizing,
     }
 }
 
-fn buf_to_img(width: u32, height: u32, buf: &Buffer2d<Rgba>) -> RgbaImage {
-    let mut img = RgbaImage::new(width, height);
-    let pxs = buf.raw();
+/// A rectangle of the frame, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
 
-    for x in 0..width {
-        for y in 0..height {
-            let px = pxs[buf.linear_index([x as usize, y as usize])];
-            img.put_pixel(x, y, Color32::from(px).to_array().into());
+impl Region {
+    /// Widened to 4 pixel boundaries horizontally, as the panel packs 4 pixels to a word, within a
+    /// frame `width` wide.
+    pub fn aligned(self, width: u32) -> Self {
+        let x = self.x - self.x % 4;
+        let x1 = (self.x + self.w).next_multiple_of(4).min(width);
+        Self {
+            x,
+            w: x1.saturating_sub(x),
+            ..self
         }
     }
+}
 
-    img
+impl std::fmt::Display for Region {
+    /// The `x,y,w,h` area format the driver reads.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Region { x, y, w, h } = self;
+        write!(f, "{x},{y},{w},{h}")
+    }
 }
 
-struct Mesh<'a> {
-    mesh: egui::Mesh,
-    sampler: Option<euc::Linear<&'a RgbaTexture>>,
-    half_size: Vec2,
+/// Changed rows closer than this are merged into one region, pushing a few unchanged rows is
+/// cheaper than another area.
+const REGION_GAP: u32 = 16;
+/// Past this many regions they are merged into their bounding box.
+const MAX_REGIONS: usize = 8;
+
+/// The regions of `new` which differ from `old`, or `None` if the frames aren't the same size.
+///
+/// Regions are aligned to 4 pixels horizontally, as the panel packs 4 pixels to a word.
+/// No regions means nothing changed.
+pub fn dirty_regions(old: &GrayImage, new: &GrayImage) -> Option<Vec<Region>> {
+    if old.dimensions() != new.dimensions() {
+        return None;
+    }
+
+    let mut regions = Vec::<Region>::new();
+    for (y, (a, b)) in (0..).zip(old.rows().zip(new.rows())) {
+        let mut xs = (0..)
+            .zip(a.zip(b))
+            .filter(|(_, (a, b))| a != b)
+            .map(|(x, _)| x);
+        let Some(x0) = xs.next() else {
+            continue;
+        };
+        let x1 = xs.last().unwrap_or(x0) + 1;
+        match regions.last_mut() {
+            Some(r) if y - (r.y + r.h) < REGION_GAP => {
+                let x = r.x.min(x0);
+                r.w = (r.x + r.w).max(x1) - x;
+                r.x = x;
+                r.h = y + 1 - r.y;
+            }
+            _ => regions.push(Region {
+                x: x0,
+                y,
+                w: x1 - x0,
+                h: 1,
+            }),
+        }
+    }
+
+    if regions.len() > MAX_REGIONS {
+        let bounds = regions.iter().skip(1).fold(regions[0], |a, b| {
+            let x = a.x.min(b.x);
+            Region {
+                x,
+                y: a.y,
+                w: (a.x + a.w).max(b.x + b.w) - x,
+                h: b.y + b.h - a.y,
+            }
+        });
+        regions = vec![bounds];
+    }
+
+    Some(
+        regions
+            .into_iter()
+            .map(|x| x.aligned(new.width()))
+            .collect(),
+    )
+}
+
+/// The number of bands the frame is split into, one per core.
+fn bands() -> usize {
+    std::thread::available_parallelism().map_or(1, |x| x.get())
+}
+
+/// A rasterised frame, row by row from the top left.
+struct Frame<P> {
+    size: [u32; 2],
+    pxs: Vec<P>,
+}
+
+impl Renderer {
+    /// Run the UI and rasterise it into a framebuffer of `P` pixels.
+    ///
+    /// The frame is split into `bands` horizontal bands which are rasterised on their own threads.
+    /// Every band draws every primitive in order, clipped to the band, so blending is the same as
+    /// drawing the whole frame at once.
+    fn raster<P: Pixel, F>(
+        &mut self,
+        width_px: u32,
+        height_px: u32,
+        scaling: f32,
+        bands: usize,
+        run_ui: F,
+    ) -> (Frame<P>, Rastered)
+    where
+        F: FnOnce(&Context),
+    {
+        // define the draw pixels
+        let [width, height] = [width_px, height_px].map(|x| (x as f32 * scaling).floor() as u32);
+        // define screen size in _points_
+        let size = [width_px, height_px].map(|x| x as f32);
+
+        // generate UI
+        let now = Instant::now();
+        let ctx = &self.ctx;
+        let input = egui::RawInput {
+            screen_rect: Rect::from_two_pos(Pos2::ZERO, size.into()).into(),
+            ..Default::default()
+        };
+        let output = ctx.run(input.clone(), run_ui);
+        let ui_gen = Duration::from(now.elapsed());
+
+        // generate painting triangles
+        let now = Instant::now();
+        let prims = ctx
+            .tessellate(output.shapes, output.pixels_per_point)
+            .into_iter()
+            .map(Prim::from)
+            .collect::<Vec<_>>();
+        let tessellation = Duration::from(now.elapsed());
+
+        // populate the textures
+        let now = Instant::now();
+        let txs = &mut self.txs;
+        for (id, delta) in output.textures_delta.set {
+            match (delta.pos, txs.get_mut(&id)) {
+                (Some(pos), Some(tx)) => RgbaTexture::patch(tx, pos, delta.image),
+                (Some(_), None) => log::warn!("partial update of unknown texture {id:?}, skipping"),
+                (None, _) => {
+                    txs.insert(id, RgbaTexture::from(delta.image));
+                }
+            }
+        }
+
+        let band_height = (height as usize).div_ceil(bands.max(1)).max(1);
+        let rows = (0..height as usize)
+            .step_by(band_height)
+            .map(|top| top..(top + band_height).min(height as usize));
+        let canvas = Canvas {
+            prims: &prims,
+            txs,
+            size_px: [width, height],
+            size,
+        };
+        let pxs = std::thread::scope(|scope| {
+            let bands = rows
+                .map(|rows| scope.spawn(|| canvas.band::<P>(rows)))
+                .collect::<Vec<_>>();
+            let mut pxs = Vec::with_capacity(width as usize * height as usize);
+            for band in bands {
+                pxs.extend_from_slice(band.join().expect("rasterising a band panicked").raw());
+            }
+            pxs
+        });
+        // freed after the frame which last used them
+        for id in output.textures_delta.free {
+            txs.remove(&id);
+        }
+
+        (
+            Frame {
+                size: [width, height],
+                pxs,
+            },
+            Rastered {
+                ui_gen,
+                tessellation,
+                rendering: Duration::from(now.elapsed()),
+            },
+        )
+    }
+}
+
+/// A piece of text painted in the frame, for reading the frame without its pixels.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameText {
+    pub text: String,
+    /// `[left, top, right, bottom]`, in points.
+    pub rect: [f32; 4],
+}
+
+/// Run the UI and collect the text it paints, top to bottom then left to right, skipping the
+/// rasterising.
+pub fn frame_text<F>(width_px: u32, height_px: u32, run_ui: F) -> Vec<FrameText>
+where
+    F: FnOnce(&Context),
+{
+    fn walk(shape: &egui::Shape, clip: Rect, out: &mut Vec<FrameText>) {
+        match shape {
+            egui::Shape::Vec(xs) => xs.iter().for_each(|x| walk(x, clip, out)),
+            egui::Shape::Text(t) => {
+                let rect = t.galley.rect.translate(t.pos.to_vec2());
+                let text = t.galley.text().trim();
+                if !text.is_empty() && clip.intersects(rect) {
+                    out.push(FrameText {
+                        text: text.to_string(),
+                        rect: [rect.left(), rect.top(), rect.right(), rect.bottom()],
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let size = [width_px, height_px].map(|x| x as f32);
+    let ctx = Context::default();
+    let input = egui::RawInput {
+        screen_rect: Rect::from_two_pos(Pos2::ZERO, size.into()).into(),
+        ..Default::default()
+    };
+    let output = ctx.run(input, run_ui);
+    let mut texts = Vec::new();
+    for shape in &output.shapes {
+        walk(&shape.shape, shape.clip_rect, &mut texts);
+    }
+    texts.sort_by(|a, b| {
+        a.rect[1]
+            .total_cmp(&b.rect[1])
+            .then(a.rect[0].total_cmp(&b.rect[0]))
+    });
+    texts
+}
+
+/// The text as plain lines, text side by side joined with a tab.
+///
+/// Text shares a line when it is vertically centred within the line's first text.
+pub fn text_lines(texts: &[FrameText]) -> Vec<String> {
+    let mut lines: Vec<(f32, Vec<&FrameText>)> = Vec::new();
+    for t in texts {
+        let mid = (t.rect[1] + t.rect[3]) / 2.0;
+        match lines.last_mut() {
+            Some((bottom, line)) if mid < *bottom => line.push(t),
+            _ => lines.push((t.rect[3], vec![t])),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(_, mut line)| {
+            line.sort_by(|a, b| a.rect[0].total_cmp(&b.rect[0]));
+            line.iter()
+                .map(|x| x.text.replace('\n', " "))
+                .collect::<Vec<_>>()
+                .join("\t")
+        })
+        .collect()
+}
+
+/// The pixel bounds `[x0, y0, x1, y1)` of a callback's viewport, clipped and limited to the
+/// buffer size.
+pub fn callback_pixel_bounds(info: &PaintCallbackInfo) -> [usize; 4] {
+    let r = info.viewport.intersect(info.clip_rect);
+    let [w, h] = info.screen_size_px;
+    let f = |x: f32, max: u32| {
+        ((x * info.pixels_per_point).round().max(0.0) as usize).min(max as usize)
+    };
+    [
+        f(r.left(), w),
+        f(r.top(), h),
+        f(r.right(), w),
+        f(r.bottom(), h),
+    ]
+}
+
+/// A grey box with a black border and cross, standing in for an unsupported callback.
+fn paint_placeholder(info: &PaintCallbackInfo, buf: &mut dyn Framebuffer) {
+    let [x0, y0, x1, y1] = callback_pixel_bounds(info);
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+
+    let (w, h) = ((x1 - x0) as f32, (y1 - y0) as f32);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (u, v) = ((x - x0) as f32 / w, (y - y0) as f32 / h);
+            let edge = x == x0 || y == y0 || x + 1 == x1 || y + 1 == y1;
+            let cross = (u - v).abs() * w.min(h) < 1.0 || (1.0 - u - v).abs() * w.min(h) < 1.0;
+            let px = if edge || cross {
+                Rgba::BLACK
+            } else {
+                Rgba::from_gray(0.8)
+            };
+            buf.blend(x, y, px);
+        }
+    }
+}
+
+/// What is shared between the threads rasterising the bands.
+#[derive(Copy, Clone)]
+struct Canvas<'a> {
+    prims: &'a [Prim],
+    txs: &'a HashMap<egui::TextureId, RgbaTexture>,
+    /// The framebuffer size, in pixels.
+    size_px: [u32; 2],
+    /// The screen size, in points.
+    size: [f32; 2],
+}
+
+impl<'a> Canvas<'a> {
+    /// Rasterise the pixel `rows` of the frame.
+    fn band<P: Pixel>(self, rows: std::ops::Range<usize>) -> Buffer2d<P> {
+        let [width, height] = self.size_px;
+        let mut buf = Buffer2d::fill([width as usize, rows.len()], P::CLEAR);
+        let pixels_per_point = width as f32 / self.size[0];
+        // rows are mapped to points by the height, which may be rounded differently
+        let rows_per_point = height as f32 / self.size[1];
+        let top = rows.start as f32 / rows_per_point;
+        let bottom = rows.end as f32 / rows_per_point;
+
+        for prim in self.prims {
+            match prim {
+                Prim::Mesh(mesh) => {
+                    let mesh = Mesh::<P> {
+                        mesh,
+                        sampler: self.txs.get(&mesh.texture_id).map(|tx| tx.linear()),
+                        origin: Vec2::new(0.0, top),
+                        half_size: Vec2::new(self.size[0], bottom - top) * 0.5,
+                        pixel: PhantomData,
+                    };
+                    mesh.render(
+                        mesh.mesh
+                            .indices
+                            .iter()
+                            .copied()
+                            .map(|x| mesh.mesh.vertices[x as usize]),
+                        &mut buf,
+                        &mut Empty::default(),
+                    );
+                }
+                Prim::Callback(cb, clip_rect) => {
+                    let info = PaintCallbackInfo {
+                        viewport: cb.rect,
+                        // callbacks lay out from the clip rect, so it is left whole and the
+                        // band drops the rows outside of it
+                        clip_rect: *clip_rect,
+                        pixels_per_point,
+                        screen_size_px: [width, height],
+                    };
+                    let mut band = Band {
+                        buf: &mut buf,
+                        rows: rows.clone(),
+                    };
+                    match cb.callback.downcast_ref::<SoftwareCallback>() {
+                        Some(SoftwareCallback(f)) => f(&info, &mut band),
+                        None => {
+                            // only warn once, rather than for every band
+                            if rows.start == 0 {
+                                log::warn!("unsupported paint callback, painting placeholder");
+                            }
+                            paint_placeholder(&info, &mut band);
+                        }
+                    }
+                }
+            }
+        }
+        buf
+    }
+}
+
+/// A band of the frame as a framebuffer for the callbacks, which draw in frame coordinates.
+struct Band<'a, P> {
+    buf: &'a mut Buffer2d<P>,
+    rows: std::ops::Range<usize>,
+}
+
+impl<'a, P: Pixel> Framebuffer for Band<'a, P> {
+    fn blend(&mut self, x: usize, y: usize, px: Rgba) {
+        if self.rows.contains(&y) {
+            self.buf.blend(x, y - self.rows.start, px);
+        }
+    }
 }
 
-impl<'a> Mesh<'a> {
-    fn from_clipped_prim(size: [f32; 2], prim: ClippedPrimitive) -> Option<Self> {
+enum Prim {
+    Mesh(egui::Mesh),
+    Callback(PaintCallback, Rect),
+}
+
+impl From<ClippedPrimitive> for Prim {
+    fn from(prim: ClippedPrimitive) -> Self {
         let ClippedPrimitive {
-            clip_rect: _,
+            clip_rect,
             primitive,
         } = prim;
-        let half_size = Vec2::from(size) * 0.5;
         match primitive {
-            egui::epaint::Primitive::Mesh(mesh) => Some(Mesh {
-                mesh,
-                sampler: None,
-                half_size,
-            }),
-            egui::epaint::Primitive::Callback(_) => {
-                log::warn!("custom primitive callback invoked");
-                None
-            }
+            egui::epaint::Primitive::Mesh(mesh) => Prim::Mesh(mesh),
+            egui::epaint::Primitive::Callback(cb) => Prim::Callback(cb, clip_rect),
         }
     }
 }
 
-impl<'a> Pipeline<'_> for Mesh<'a> {
+struct Mesh<'a, P> {
+    mesh: &'a egui::Mesh,
+    sampler: Option<euc::Linear<&'a RgbaTexture>>,
+    /// The top left of the band being drawn, in points.
+    origin: Vec2,
+    /// Half the band size, in points.
+    half_size: Vec2,
+    /// The framebuffer pixel blended into.
+    pixel: PhantomData<P>,
+}
+
+impl<'a, P: Pixel> Pipeline<'_> for Mesh<'a, P> {
     type Vertex = egui::epaint::Vertex;
     type VertexData = PipelineVertex;
     type Fragment = Rgba;
     type Primitives = euc::TriangleList;
-    type Pixel = Rgba;
+    type Pixel = P;
 
     fn vertex(&self, vertex: &Self::Vertex) -> ([f32; 4], Self::VertexData) {
         let egui::epaint::Vertex { pos, color, uv } = *vertex;
-        let Vec2 { x, y } = pos.to_vec2() / self.half_size - Vec2::splat(1.0);
+        let Vec2 { x, y } = (pos.to_vec2() - self.origin) / self.half_size - Vec2::splat(1.0);
         let vd = PipelineVertex {
             colour: Rgba::from(color),
             uv: uv.to_vec2(),
@@ -189,7 +708,7 @@ impl<'a> Pipeline<'_> for Mesh<'a> {
 
     fn blend(&self, old: Self::Pixel, new: Self::Fragment) -> Self::Pixel {
         // all old, new, and output are premultiplied
-        new + old.multiply(1.0 - new.a())
+        old.blend(new)
     }
 
     fn rasterizer_config(
@@ -234,12 +753,14 @@ struct RgbaTexture {
     pxs: Vec<Rgba>,
 }
 
-impl From<ImageDelta> for RgbaTexture {
-    fn from(delta: ImageDelta) -> Self {
-        assert!(delta.is_whole(), "assuming setting total texture each time");
-        let size = delta.image.size();
-        match delta.image {
-            ImageData::Color(_) => todo!(),
+impl From<ImageData> for RgbaTexture {
+    fn from(image: ImageData) -> Self {
+        let size = image.size();
+        match image {
+            ImageData::Color(img) => RgbaTexture {
+                size,
+                pxs: img.pixels.iter().copied().map(Into::into).collect(),
+            },
             ImageData::Font(font) => RgbaTexture {
                 size,
                 pxs: font.srgba_pixels(None).map(Into::into).collect(),
@@ -248,6 +769,21 @@ impl From<ImageDelta> for RgbaTexture {
     }
 }
 
+impl RgbaTexture {
+    /// Overwrite the region at `pos` with `image`, such as a font atlas growing.
+    ///
+    /// Any of the image outside the texture is dropped.
+    fn patch(&mut self, [x0, y0]: [usize; 2], image: ImageData) {
+        let patch = RgbaTexture::from(image);
+        let [w, h] = patch.size;
+        for y in 0..h.min(self.size[1].saturating_sub(y0)) {
+            let w = w.min(self.size[0].saturating_sub(x0));
+            let to = (y0 + y) * self.size[0] + x0;
+            self.pxs[to..to + w].copy_from_slice(&patch.pxs[y * patch.size[0]..][..w]);
+        }
+    }
+}
+
 impl Texture<2> for RgbaTexture {
     type Index = usize;
     type Texel = Rgba;
@@ -262,3 +798,200 @@ impl Texture<2> for RgbaTexture {
         self.pxs[i]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gray_blend_matches_rgba() {
+        let fragments = [
+            Rgba::from_gray(0.3),
+            Rgba::from(Color32::from_black_alpha(128)),
+            Rgba::from(Color32::from_white_alpha(64)),
+            Rgba::from(Color32::from_rgb(200, 40, 90)),
+        ];
+        let mut rgba = Rgba::WHITE;
+        let mut gray = 1.0f32;
+        for f in fragments {
+            rgba = rgba.blend(f);
+            gray = gray.blend(f);
+            assert!((luminance(rgba) - gray).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn colour_textures() {
+        let img = egui::ColorImage::new([3, 2], Color32::from_rgb(200, 40, 90));
+        let tx = RgbaTexture::from(ImageData::from(img));
+        assert_eq!(tx.size(), [3, 2]);
+        assert_eq!(tx.read([2, 1]), Rgba::from(Color32::from_rgb(200, 40, 90)));
+
+        // and painting an image widget doesn't panic
+        paint(32, 32, 1.0, |ctx| {
+            let tx = ctx.load_texture(
+                "swatch",
+                egui::ColorImage::new([4, 4], Color32::RED),
+                Default::default(),
+            );
+            egui::CentralPanel::default().show(ctx, |ui| ui.image((tx.id(), Vec2::splat(16.0))));
+        });
+    }
+
+    #[test]
+    fn renderer_keeps_textures() {
+        let ui = |text: &'static str| {
+            move |ctx: &Context| {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::none().fill(Color32::WHITE))
+                    .show(ctx, |ui| ui.heading(text));
+            }
+        };
+        let mut renderer = Renderer::default();
+        for text in ["Tuesday 21", "Wednesday 22", "Tuesday 21"] {
+            let kept = renderer.paint_gray(120, 40, 1.0, ui(text)).img;
+            assert!(kept == paint_gray(120, 40, 1.0, ui(text)).img, "{text}");
+        }
+
+        // a texture is freed once its handle is dropped
+        let mut swatch = None;
+        renderer.paint_gray(32, 32, 1.0, |ctx| {
+            swatch = Some(ctx.load_texture(
+                "swatch",
+                egui::ColorImage::new([4, 4], Color32::RED),
+                Default::default(),
+            ));
+        });
+        assert_eq!(renderer.txs.len(), 2);
+        drop(swatch);
+        renderer.paint_gray(32, 32, 1.0, |_| ());
+        assert_eq!(renderer.txs.len(), 1);
+    }
+
+    #[test]
+    fn frame_text_in_reading_order() {
+        let texts = frame_text(200, 100, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Tuesday 21");
+                    ui.label("09:41");
+                });
+                ui.label("Standup\nwith the team");
+                ui.label("   ");
+            });
+        });
+        let text = texts.iter().map(|x| x.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(text, ["Tuesday 21", "09:41", "Standup\nwith the team"]);
+        assert!(texts[0].rect[2] <= texts[1].rect[0]);
+        assert_eq!(
+            text_lines(&texts),
+            ["Tuesday 21\t09:41", "Standup with the team"]
+        );
+    }
+
+    #[test]
+    fn partial_texture_deltas() {
+        let mut tx = RgbaTexture::from(ImageData::from(egui::ColorImage::new(
+            [4, 3],
+            Color32::WHITE,
+        )));
+        let patch = egui::ColorImage::new([2, 2], Color32::BLACK);
+        tx.patch([1, 1], ImageData::from(patch.clone()));
+        let black = |tx: &RgbaTexture, x, y| tx.read([x, y]) == Rgba::BLACK;
+        assert!([(1, 1), (2, 1), (1, 2), (2, 2)]
+            .iter()
+            .all(|&(x, y)| black(&tx, x, y)));
+        assert!([(0, 1), (3, 1), (1, 0)]
+            .iter()
+            .all(|&(x, y)| !black(&tx, x, y)));
+
+        // hanging off the edge is clipped
+        tx.patch([3, 2], ImageData::from(patch));
+        assert!(black(&tx, 3, 2));
+        assert_eq!(tx.pxs.len(), 12);
+    }
+
+    #[test]
+    fn gray_paint_matches_rgba() {
+        let ui = |ctx: &Context| {
+            egui::CentralPanel::default()
+                .frame(egui::Frame::none().fill(Color32::WHITE))
+                .show(ctx, |ui| {
+                    ui.heading("Tuesday 21");
+                    ui.painter().rect_filled(
+                        Rect::from_min_size(Pos2::new(10.0, 40.0), Vec2::splat(20.0)),
+                        0.0,
+                        Color32::DARK_GRAY,
+                    );
+                });
+        };
+        let rgba = image::DynamicImage::from(paint(64, 64, 1.0, ui).img).into_luma8();
+        let gray = paint_gray(64, 64, 1.0, ui).img;
+        assert_eq!(rgba.dimensions(), gray.dimensions());
+        for (a, b) in rgba.pixels().zip(gray.pixels()) {
+            assert!(a.0[0].abs_diff(b.0[0]) <= 2, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn dirty_regions_cover_changes() {
+        let old = GrayImage::from_pixel(64, 100, image::Luma([255]));
+        assert_eq!(dirty_regions(&old, &old), Some(Vec::new()));
+        assert_eq!(dirty_regions(&old, &GrayImage::new(64, 99)), None);
+
+        let mut new = old.clone();
+        // a clock digit, and a dot far enough below to be its own region
+        for (x, y) in [(45, 2), (50, 5), (47, 9), (5, 60)] {
+            new.put_pixel(x, y, image::Luma([0]));
+        }
+        let regions = dirty_regions(&old, &new).unwrap();
+        let region = |x, y, w, h| Region { x, y, w, h };
+        assert_eq!(regions, vec![region(44, 2, 8, 8), region(4, 60, 4, 1)]);
+        assert_eq!(regions[0].to_string(), "44,2,8,8");
+
+        // many scattered changes are pushed as one area
+        let old = GrayImage::from_pixel(64, 200, image::Luma([255]));
+        let mut new = old.clone();
+        for y in (0..200).step_by(20) {
+            new.put_pixel(y % 64, y, image::Luma([0]));
+        }
+        assert_eq!(
+            dirty_regions(&old, &new).unwrap(),
+            vec![region(0, 0, 64, 181)]
+        );
+    }
+
+    #[test]
+    fn bands_match_whole_frame() {
+        let ui = |ctx: &Context| {
+            egui::CentralPanel::default()
+                .frame(egui::Frame::none().fill(Color32::WHITE))
+                .show(ctx, |ui| {
+                    ui.heading("Tuesday 21");
+                    let rect = Rect::from_min_size(Pos2::new(5.0, 3.0), Vec2::new(50.0, 57.0));
+                    // a translucent fill over the placeholder blends across the band edges
+                    ui.painter().add(egui::PaintCallback {
+                        rect,
+                        callback: Arc::new(()),
+                    });
+                    let fill = SoftwareCallback::new(|info, buf| {
+                        let [x0, y0, x1, y1] = callback_pixel_bounds(info);
+                        for y in y0..y1 {
+                            for x in x0..x1 {
+                                buf.blend(x, y, Rgba::from_black_alpha(0.5));
+                            }
+                        }
+                    });
+                    ui.painter().add(fill.paint_callback(rect));
+                });
+        };
+        let whole = Renderer::default().raster::<Rgba, _>(64, 61, 1.0, 1, ui).0;
+        for bands in [2, 3, 7, 61, 100] {
+            let banded = Renderer::default()
+                .raster::<Rgba, _>(64, 61, 1.0, bands, ui)
+                .0;
+            assert_eq!(banded.size, whole.size);
+            assert!(banded.pxs == whole.pxs, "{bands} bands");
+        }
+    }
+}
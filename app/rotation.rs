@@ -0,0 +1,108 @@
+use crate::layout::{modes::Modes, Mode};
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// A page in the rotation, displaying `mode` for `dwell` time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Page {
+    pub mode: String,
+    #[serde(with = "humantime_serde")]
+    pub dwell: Duration,
+}
+
+/// Cycles through a list of pages.
+///
+/// Rotation is driven by the render loop, which should push the frame following a rotation as a
+/// full refresh, since the whole screen changes anyway. That restarts the driver's count towards
+/// clearing the ghosting, but a rotation more often than the driver clears it adds full refreshes.
+pub struct Rotation {
+    pages: Vec<(Mode, Duration)>,
+    current: usize,
+    since: Instant,
+}
+
+impl Rotation {
+    /// Pages select their mode by name from `modes`.
+    pub fn new(pages: &[Page], modes: &Modes) -> Result<Self> {
+        let pages = pages
+            .iter()
+            .map(|Page { mode, dwell }| {
+                modes.get(mode).map(|m| (m, *dwell)).ok_or_else(|| {
+                    let names = modes.names().collect::<Vec<_>>().join(", ");
+                    miette!(
+                        help = format!("available modes: {names}"),
+                        "unknown layout mode '{mode}'"
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if pages.is_empty() {
+            return Err(miette!("at least one page must be specified"));
+        }
+
+        Ok(Self {
+            pages,
+            current: 0,
+            since: Instant::now(),
+        })
+    }
+
+    pub fn current(&self) -> &Mode {
+        &self.pages[self.current].0
+    }
+
+    /// Move to the next page if the current page's dwell time has elapsed.
+    /// Returns `true` if the page changed.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        if self.pages.len() < 2 || now.duration_since(self.since) < self.pages[self.current].1 {
+            return false;
+        }
+
+        self.current = (self.current + 1) % self.pages.len();
+        self.since = now;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modes() -> Modes {
+        Modes::builtin()
+    }
+
+    fn page(mode: &str, mins: u64) -> Page {
+        Page {
+            mode: mode.to_string(),
+            dwell: Duration::from_secs(mins * 60),
+        }
+    }
+
+    #[test]
+    fn rotates_after_dwell() {
+        let mut r = Rotation::new(&[page("month", 10), page("agenda", 2)], &modes()).unwrap();
+        let start = r.since;
+        assert_eq!(r.current().name(), "month");
+        assert!(!r.tick(start + Duration::from_secs(9 * 60)));
+        assert!(r.tick(start + Duration::from_secs(10 * 60)));
+        assert_eq!(r.current().name(), "agenda");
+        assert!(!r.tick(start + Duration::from_secs(11 * 60)));
+        assert!(r.tick(start + Duration::from_secs(12 * 60)));
+        assert_eq!(r.current().name(), "month");
+    }
+
+    #[test]
+    fn single_page_never_rotates() {
+        let mut r = Rotation::new(&[page("month", 0)], &modes()).unwrap();
+        assert!(!r.tick(Instant::now() + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn invalid_pages() {
+        assert!(Rotation::new(&[], &modes()).is_err());
+        assert!(Rotation::new(&[page("photo", 1)], &modes()).is_err());
+    }
+}
@@ -0,0 +1,576 @@
+//! The fetch and render loops.
+//!
+//! The clock, the panel, and where painted frames go are passed in, so the loops can be run
+//! against simulated ones, as `tests/soak.rs` does.
+use crate::{
+    contrast::Contrast,
+    data::{dedupe, source::Registry, Model},
+    dither::Dither,
+    driver::Command,
+    fetch::Client,
+    layout::{theme::Theme, Away, Failure, Layout, Mode},
+    policy::{Push, QuietHours, Refresh, Schedule, Vacation},
+    render::{Region, Render, Renderer, Timings},
+    rotation::Rotation,
+    sink::{BmpFile, FrameSink},
+    state::Dispatch,
+    tone::ToneCurve,
+};
+use image::GrayImage;
+use miette::*;
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use time::Date;
+use tokio::time::{interval, MissedTickBehavior};
+
+/// Where frames are saved for the driver to read, with the one before alongside.
+pub const FRAME_PATH: &str = "./frame.pical.bmp";
+
+#[derive(Default)]
+pub struct State {
+    pub model: Model,
+    pub layout: Layout,
+    /// Fetching and rendering is paused.
+    pub maintenance: bool,
+}
+
+/// The monotonic time the loops go by.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The system's clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub type PanelFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
+
+/// Where the render loop pushes frames, such as the IT8951 driver or a remote agent.
+pub trait Panel {
+    /// Push `frame`, only updating the push's regions, or the whole screen if there are none.
+    fn push<'a>(&'a mut self, frame: &'a Frame, push: &'a Push) -> PanelFuture<'a>;
+
+    /// Put the panel to sleep, or wake it, around quiet hours.
+    fn power(&mut self, cmd: Command) -> PanelFuture<'_>;
+}
+
+/// A painted frame to push, saved to a file, or only in memory with `raw_frames`.
+#[derive(Clone)]
+pub enum Frame {
+    Saved(PathBuf),
+    Painted(Arc<GrayImage>),
+}
+
+impl Frame {
+    /// The frame's file, saving it if it is only in memory.
+    pub fn save(&self) -> Result<PathBuf> {
+        match self {
+            Frame::Saved(path) => Ok(path.clone()),
+            Frame::Painted(img) => {
+                BmpFile::new(FRAME_PATH).put(img)?;
+                Ok(FRAME_PATH.into())
+            }
+        }
+    }
+
+    pub fn image(&self) -> Result<GrayImage> {
+        match self {
+            Frame::Saved(path) => Ok(image::open(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to read {}", path.display()))?
+                .into_luma8()),
+            Frame::Painted(img) => Ok(img.as_ref().clone()),
+        }
+    }
+}
+
+pub fn log_error(e: Report) {
+    let mut buf = String::new();
+    let _ = GraphicalReportHandler::new().render_report(&mut buf, e.as_ref());
+    log::error!("{}", buf);
+}
+
+/// Log the error, and show it on the frame until `task` next succeeds.
+pub async fn report_error(dispatch: &Dispatch<State>, task: &'static str, e: Report) {
+    let message = e
+        .chain()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(": ");
+    log_error(e);
+    let failure = Failure {
+        task,
+        message,
+        at: Instant::now(),
+    };
+    dispatch
+        .run(move |s| s.layout.failure = Some(failure))
+        .await;
+}
+
+pub async fn clear_error(dispatch: &Dispatch<State>, task: &'static str) {
+    dispatch
+        .run(move |s| {
+            if s.layout.failure.as_ref().is_some_and(|x| x.task == task) {
+                s.layout.failure = None;
+            }
+        })
+        .await;
+}
+
+// ##### FETCHING ##############################################################
+
+/// Fetches the data sources which are due into the model.
+pub struct FetchLoop<C> {
+    dispatch: Dispatch<State>,
+    clock: C,
+    client: Client,
+    sources: Registry,
+    merge_duplicates: bool,
+    /// When the sources were first fetched, which those not loaded yet are stale since.
+    started: Instant,
+}
+
+impl<C: Clock> FetchLoop<C> {
+    pub fn new(
+        dispatch: Dispatch<State>,
+        clock: C,
+        client: Client,
+        sources: Registry,
+        merge_duplicates: bool,
+    ) -> Self {
+        let started = clock.now();
+        Self {
+            dispatch,
+            clock,
+            client,
+            sources,
+            merge_duplicates,
+            started,
+        }
+    }
+
+    /// Fetch `every` so often, unless in maintenance.
+    pub async fn run(mut self, every: Duration) {
+        let mut timer = interval(every);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let paused = self.dispatch.run(|s| s.maintenance).await;
+            if !paused {
+                self.iteration().await;
+            }
+            timer.tick().await;
+        }
+    }
+
+    /// Fetch the sources which are due, showing any failures on the frame.
+    pub async fn iteration(&mut self) {
+        let Self {
+            dispatch,
+            clock,
+            client,
+            sources,
+            merge_duplicates,
+            started,
+        } = self;
+        let merge_duplicates = *merge_duplicates;
+        let started = *started;
+        let now = dispatch.run(|state| state.layout.now).await;
+        let (patches, errs) = sources.fetch_due_at(client, now, clock.now()).await;
+        let unloaded = sources.unfetched().map(str::to_string).collect::<Vec<_>>();
+
+        dispatch
+            .run(move |state| {
+                // sources only leave the unloaded by loading, with a patch
+                if patches.is_empty() && state.model.unloaded.len() == unloaded.len() {
+                    return;
+                }
+                let model = state.model.make_mut();
+                model.unloaded = unloaded.into_iter().map(|x| (x, started)).collect();
+                let fetched = model.cals_updated.clone();
+                for patch in patches {
+                    patch.apply(model);
+                }
+                if merge_duplicates {
+                    // only refetched calendars have duplicates to remove, so the merges of the
+                    // others stand, but those of a replaced or removed calendar are found again
+                    let replaced = |c: &String| {
+                        !model.cals.contains_key(c) || model.cals_updated.get(c) != fetched.get(c)
+                    };
+                    let (old, mut merged): (Vec<_>, Vec<_>) = std::mem::take(&mut model.merged)
+                        .into_iter()
+                        .partition(|m| m.calendars.iter().any(replaced));
+                    for m in dedupe::merge_duplicates(&mut model.cals) {
+                        if !old.iter().chain(&merged).any(|x| x.same_series(&m)) {
+                            log::info!("🔀 Merged duplicate series {m}");
+                        }
+                        match merged.iter_mut().find(|x| x.same_series(&m)) {
+                            Some(x) => *x = m,
+                            None => merged.push(m),
+                        }
+                    }
+                    model.merged = merged;
+                }
+            })
+            .await;
+
+        if errs.is_empty() {
+            clear_error(dispatch, "fetch").await;
+        }
+        for e in errs {
+            report_error(dispatch, "fetch", e).await;
+        }
+    }
+}
+
+// ##### RENDERING #############################################################
+
+/// The rules for when and how frames are pushed.
+pub struct Policies {
+    pub vacation: Option<Vacation>,
+    pub quiet: Option<QuietHours>,
+    /// Push frames from memory rather than saving them first.
+    pub raw_frames: bool,
+    pub schedule: Schedule,
+}
+
+/// The passes over a painted frame, in order, before it is pushed.
+#[derive(Copy, Clone)]
+pub struct Passes {
+    pub contrast: Option<Contrast>,
+    pub tone: Option<ToneCurve>,
+    pub dither: Dither,
+}
+
+/// What the render loop paints, and how.
+pub struct Painting {
+    pub rotation: Rotation,
+    /// The frame's `[width, height]` in pixels.
+    pub size: [u32; 2],
+    pub scaling: f32,
+    pub passes: Passes,
+    /// Given each painted frame besides the panel, such as the live preview. Failures are only
+    /// logged.
+    pub sinks: Vec<Box<dyn FrameSink + Send>>,
+}
+
+/// Paints the layout and pushes it to the panel.
+pub struct RenderLoop<C, P> {
+    dispatch: Dispatch<State>,
+    clock: C,
+    panel: P,
+    painting: Painting,
+    policies: Policies,
+    renderer: Renderer,
+    paused: bool,
+    quieted: bool,
+    last_theme: Option<Theme>,
+    /// The day the away frame was last pushed, while away.
+    away_on: Option<Date>,
+}
+
+impl<C: Clock, P: Panel> RenderLoop<C, P> {
+    pub fn new(
+        dispatch: Dispatch<State>,
+        clock: C,
+        panel: P,
+        painting: Painting,
+        policies: Policies,
+    ) -> Self {
+        Self {
+            dispatch,
+            clock,
+            panel,
+            painting,
+            policies,
+            // kept for the whole run, so its fonts and textures are reused between frames
+            renderer: Renderer::default(),
+            paused: false,
+            quieted: false,
+            last_theme: None,
+            away_on: None,
+        }
+    }
+
+    /// The panel, such as to check what a simulated one was pushed.
+    pub fn panel(&self) -> &P {
+        &self.panel
+    }
+
+    /// Render on every `refresh`.
+    pub async fn run(mut self, refresh: Duration) -> Result<()> {
+        let mut timer = interval(refresh);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            timer.tick().await;
+            self.iteration().await;
+        }
+    }
+
+    /// Paint a frame and push what the schedule has due.
+    ///
+    /// Failures are shown on the frame, and the next frame is pushed in full.
+    pub async fn iteration(&mut self) {
+        let dispatch = self.dispatch.clone();
+
+        if dispatch.run(|s| s.maintenance).await {
+            self.pause().await;
+            return;
+        }
+
+        if let Some(quiet) = &self.policies.quiet {
+            let (now, daylight) = dispatch.run(|s| (s.layout.now, s.model.sun)).await;
+            if quiet.contains(now, daylight.as_ref()) {
+                if !self.quieted {
+                    log::info!("🌙 Quiet hours, leaving the panel asleep");
+                    self.set_power(Command::Sleep).await;
+                    self.quieted = true;
+                }
+                return;
+            }
+        }
+        // the panel is left asleep between pushes, as it is by default, so it only needs a push
+        let woken = std::mem::take(&mut self.quieted);
+        if woken {
+            log::info!("☀ Quiet hours over");
+        }
+
+        let rotation = &mut self.painting.rotation;
+        let rotated = rotation.tick(self.clock.now());
+        if rotated {
+            let mode = rotation.current().clone();
+            log::info!("🔁 Rotating to {} page", mode.name());
+            dispatch.run(move |s| s.layout.mode = mode).await;
+        }
+
+        let (mut data, mut layout) = dispatch.run(|s| (s.model.clone(), s.layout.clone())).await;
+        crate::control::directive::apply_overrides(&mut layout, &mut data);
+
+        let policy = self
+            .policies
+            .vacation
+            .as_ref()
+            .map_or(Refresh::Normal, |x| x.evaluate(&data, layout.now));
+        let today = layout.now.date();
+        let away = policy == Refresh::Away;
+        if away {
+            if self.away_on == Some(today) {
+                return;
+            }
+            if self.away_on.is_none() {
+                log::info!("🏖 Vacation, refreshing once a day");
+            }
+            self.away_on = Some(today);
+            layout.mode = Mode::new(Away);
+        }
+        let back = !away && self.away_on.take().is_some();
+        if back {
+            log::info!("🏠 Back from vacation");
+        }
+        let theme = layout.theme;
+
+        // painting is CPU heavy, keep it off the runtime so the clock and fetching keep ticking
+        let now = std::time::Instant::now();
+        let mut renderer = std::mem::take(&mut self.renderer);
+        let ([width, height], scaling, passes) = (
+            self.painting.size,
+            self.painting.scaling,
+            self.painting.passes,
+        );
+        let painted = tokio::task::spawn_blocking(move || {
+            let painted = paint_frame(
+                &mut renderer,
+                &layout,
+                data,
+                [width, height],
+                scaling,
+                passes,
+            );
+            (renderer, painted)
+        })
+        .await;
+        let render_time = now.elapsed();
+        let (img, header, _) = match painted.into_diagnostic().wrap_err("painting failed") {
+            Ok((x, painted)) => {
+                self.renderer = x;
+                painted
+            }
+            Err(e) => {
+                // lost with the panicked paint, a new one is left in its place
+                report_error(&dispatch, "render", e).await;
+                return;
+            }
+        };
+
+        let now = std::time::Instant::now();
+        let img = Arc::new(img);
+        let frame = if self.policies.raw_frames {
+            Frame::Painted(img.clone())
+        } else if let Err(e) = BmpFile::new(FRAME_PATH).put(&img) {
+            report_error(&dispatch, "render", e).await;
+            return;
+        } else {
+            Frame::Saved(FRAME_PATH.into())
+        };
+        for sink in &mut self.painting.sinks {
+            if let Err(e) = sink.put(&img) {
+                log_error(e);
+            }
+        }
+        let save_time = now.elapsed();
+
+        // maintenance may have started while painting, don't draw over the banner
+        if dispatch.run(|s| s.maintenance).await {
+            self.pause().await;
+            return;
+        }
+
+        // besides the cadences, a full refresh is done when the page rotates, when the theme
+        // flips, or when coming out of maintenance, vacation, or quiet hours
+        let flipped = self.last_theme.replace(theme).is_some_and(|x| x != theme);
+        let full = rotated || flipped || self.paused || away || back || woken;
+        self.paused = false;
+        let schedule = &mut self.policies.schedule;
+        let pushes = schedule.plan(&img, header, full, self.clock.now());
+        if pushes.is_empty() {
+            log::debug!("no changes due, skipping push");
+            clear_error(&dispatch, "render").await;
+            return;
+        }
+
+        let now = std::time::Instant::now();
+        let mut failed = None;
+        for push in pushes {
+            if let Err(e) = self
+                .panel
+                .push(&frame, &push)
+                .await
+                .wrap_err("failed to push frame")
+            {
+                failed = Some(e);
+                break;
+            }
+        }
+        if let Some(e) = failed {
+            // the panel is out of step with the schedule, so start again from a full refresh
+            schedule.invalidate();
+            report_error(&dispatch, "push", e).await;
+            return;
+        }
+        let push_time = now.elapsed();
+        clear_error(&dispatch, "render").await;
+        clear_error(&dispatch, "push").await;
+
+        log::info!(
+            "⏱ Render perf: rendering=>{} | save-bitmap=>{} | push-time=>{}",
+            humantime::Duration::from(render_time),
+            humantime::Duration::from(save_time),
+            humantime::Duration::from(push_time)
+        );
+    }
+
+    /// Leave the panel asleep through maintenance.
+    async fn pause(&mut self) {
+        if !self.paused {
+            log::info!("🔧 Maintenance, leaving the panel asleep");
+            self.set_power(Command::Sleep).await;
+            self.paused = true;
+        }
+    }
+
+    async fn set_power(&mut self, cmd: Command) {
+        if let Err(e) = self.panel.power(cmd).await {
+            log_error(e.wrap_err("failed to set the panel's power"));
+        }
+    }
+}
+
+/// Paint the layout in its theme, reduced to the panel's grey levels.
+///
+/// Returns the frame, where the header was painted in pixels, and how long painting took.
+pub fn paint_frame(
+    renderer: &mut Renderer,
+    layout: &Layout,
+    model: Model,
+    [width, height]: [u32; 2],
+    scaling: f32,
+    passes: Passes,
+) -> (GrayImage, Option<Region>, Timings) {
+    let mut header = None;
+    let painted = renderer.paint_gray(width, height, scaling, |ctx| {
+        ctx.set_visuals(egui::Visuals::light());
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(egui::Color32::WHITE))
+            .show(ctx, |ui| layout.render(ui, model));
+        header = crate::layout::take_header_rect(ctx);
+    });
+    painted.log_debug_timings();
+    let mut timings = painted.timings();
+    let now = std::time::Instant::now();
+    let mut img = painted.img;
+    if layout.theme == Theme::Dark {
+        image::imageops::invert(&mut img);
+    }
+    let Passes {
+        contrast,
+        tone,
+        dither,
+    } = passes;
+    if let Some(contrast) = contrast {
+        contrast.apply(&mut img);
+    }
+    if let Some(tone) = tone {
+        tone.apply(&mut img);
+    }
+    dither.apply(&mut img);
+    timings.conversion += now.elapsed();
+
+    let header = header.map(|r| {
+        let px = |x: f32| (x * scaling).round().clamp(0.0, u32::MAX as f32) as u32;
+        let (x, y) = (px(r.min.x).min(img.width()), px(r.min.y).min(img.height()));
+        Region {
+            x,
+            y,
+            w: px(r.max.x).min(img.width()).saturating_sub(x),
+            h: px(r.max.y).min(img.height()).saturating_sub(y),
+        }
+        .aligned(img.width())
+    });
+    (img, header, timings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_only_from_its_frame() {
+        let mut renderer = Renderer::default();
+        let passes = Passes {
+            contrast: None,
+            tone: None,
+            dither: Dither::default(),
+        };
+        let mut layout = Layout::default();
+        let paint = |renderer: &mut Renderer, layout: &Layout| {
+            paint_frame(renderer, layout, Model::default(), [400, 300], 1.0, passes).1
+        };
+        let header = paint(&mut renderer, &layout).unwrap();
+        // aligned to the words the panel packs pixels into
+        assert_eq!((header.x % 4, header.w % 4), (0, 0));
+
+        // the away frame has no header, the last frame's isn't carried over
+        layout.mode = Mode::new(Away);
+        assert_eq!(paint(&mut renderer, &layout), None);
+    }
+}
@@ -0,0 +1,63 @@
+//! Telling systemd how the service is doing, see `os/pical.service`.
+//!
+//! This is the `sd_notify` protocol: datagrams to the socket in `NOTIFY_SOCKET`. Outside of a
+//! `Type=notify` service the variable isn't set and notifying does nothing.
+
+/// Startup has finished.
+pub const READY: &str = "READY=1";
+/// Still alive, sent at least every `WatchdogSec` or systemd restarts the service.
+pub const WATCHDOG: &str = "WATCHDOG=1";
+/// Shutting down.
+pub const STOPPING: &str = "STOPPING=1";
+
+/// Send `state` to systemd, if it is listening.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        log::warn!("failed to notify systemd of {state}: {e}");
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send(path: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    };
+
+    let sock = UnixDatagram::unbound()?;
+    // a leading @ is an abstract socket
+    let addr = match path.as_bytes() {
+        [b'@', name @ ..] => SocketAddr::from_abstract_name(name)?,
+        _ => SocketAddr::from_pathname(path)?,
+    };
+    sock.send_to_addr(state.as_bytes(), &addr).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send(_path: &std::ffi::OsStr, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_datagrams() {
+        let path = std::env::temp_dir().join(format!("pical-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), READY).unwrap();
+        let mut buf = [0; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+//! Where painted frames go, so display backends and tests can take a frame without the
+//! filesystem.
+use image::GrayImage;
+use miette::*;
+use std::path::{Path, PathBuf};
+
+pub trait FrameSink {
+    fn put(&mut self, frame: &GrayImage) -> Result<()>;
+}
+
+/// A BMP file, as the panel driver reads, keeping the previous frame alongside.
+pub struct BmpFile {
+    path: PathBuf,
+}
+
+impl BmpFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the frame before the last one is kept, such as `frame.pical.old.bmp`.
+    pub fn previous(&self) -> PathBuf {
+        let mut o = format!(
+            "{}.old",
+            self.path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default()
+        );
+        if let Some(ext) = self.path.extension().and_then(|x| x.to_str()) {
+            o.push('.');
+            o.push_str(ext);
+        }
+        self.path.with_file_name(o)
+    }
+}
+
+impl FrameSink for BmpFile {
+    fn put(&mut self, frame: &GrayImage) -> Result<()> {
+        if self.path.exists() {
+            std::fs::rename(&self.path, self.previous()).into_diagnostic()?;
+        }
+        frame
+            .save(&self.path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to save bitmap to {}", self.path.display()))
+    }
+}
+
+/// A PNG file, optionally with text embedded in the metadata.
+pub struct PngFile {
+    path: PathBuf,
+    text: Option<String>,
+}
+
+impl PngFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            text: None,
+        }
+    }
+
+    /// Embed `text` as the PNG's `Software` text.
+    pub fn annotated(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+}
+
+impl FrameSink for PngFile {
+    fn put(&mut self, frame: &GrayImage) -> Result<()> {
+        let to = self.path.display();
+        let file = std::fs::File::create(&self.path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to create {to}"))?;
+        let mut enc =
+            png::Encoder::new(std::io::BufWriter::new(file), frame.width(), frame.height());
+        enc.set_color(png::ColorType::Grayscale);
+        enc.set_depth(png::BitDepth::Eight);
+        if let Some(text) = &self.text {
+            enc.add_text_chunk("Software".to_string(), text.clone())
+                .into_diagnostic()?;
+        }
+        enc.write_header()
+            .and_then(|mut w| w.write_image_data(frame.as_raw()))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to save PNG to {to}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> GrayImage {
+        GrayImage::from_fn(3, 2, |x, y| image::Luma([(x * 100 + y * 17) as u8]))
+    }
+
+    #[test]
+    fn bmp_keeps_previous() {
+        let dir = std::env::temp_dir().join(format!("pical-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut bmp = BmpFile::new(dir.join("frame.pical.bmp"));
+        assert_eq!(bmp.previous(), dir.join("frame.pical.old.bmp"));
+
+        bmp.put(&GrayImage::new(3, 2)).unwrap();
+        assert!(!bmp.previous().exists());
+        bmp.put(&frame()).unwrap();
+        let read = |p: &Path| image::open(p).unwrap().into_luma8();
+        assert_eq!(read(bmp.path()), frame());
+        assert_eq!(read(&bmp.previous()), GrayImage::new(3, 2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,50 +1,227 @@
-use std::future::Future;
+use std::{
+    future::Future,
+    panic::Location,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use tokio::sync::{
     mpsc::{channel, Receiver, Sender},
     oneshot,
 };
 
-pub struct Dispatch<T>(Sender<Fun<T>>);
+/// Closures holding the state for longer than this are logged, they stall every other loop.
+const SLOW_CLOSURE: Duration = Duration::from_millis(100);
+
+pub struct Dispatch<T> {
+    tx: Sender<Job<T>>,
+    /// The high priority lane.
+    tx_high: Sender<Job<T>>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+/// Which queue a closure waits in. High priority closures run before any waiting normal ones,
+/// so small updates such as the clock tick are not held up behind bulk work.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lane {
+    High,
+    Normal,
+}
 
 type Fun<T> = Box<dyn FnOnce(&mut T) + Send>;
 
+struct Job<T> {
+    f: Fun<T>,
+    /// Where the closure was dispatched from.
+    caller: &'static Location<'static>,
+}
+
+/// Dispatcher instrumentation, for finding closures which stall the state.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stats {
+    /// Closures run.
+    pub executed: u64,
+    /// Closures waiting to run, as of the last dispatch.
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    pub total_exec: Duration,
+    pub max_exec: Duration,
+    /// Where the slowest closure was dispatched from.
+    pub slowest: Option<&'static Location<'static>>,
+    /// Closures which took longer than the slow threshold.
+    pub slow: u64,
+}
+
+impl Stats {
+    pub fn mean_exec(&self) -> Duration {
+        self.total_exec
+            .checked_div(self.executed.try_into().unwrap_or(u32::MAX))
+            .unwrap_or_default()
+    }
+}
+
 impl<T> Clone for Dispatch<T> {
     fn clone(&self) -> Self {
-        Dispatch(self.0.clone())
+        Dispatch {
+            tx: self.tx.clone(),
+            tx_high: self.tx_high.clone(),
+            stats: self.stats.clone(),
+        }
     }
 }
 
 impl<T> Dispatch<T> {
-    pub async fn run<F, O>(&self, f: F) -> O
+    /// Run `f` with the state, in the normal lane.
+    ///
+    /// The caller's location is recorded, so slow closures can be traced back.
+    #[track_caller]
+    pub fn run<F, O>(&self, f: F) -> impl Future<Output = O>
     where
         F: FnOnce(&mut T) -> O + Send + 'static,
         O: Send + 'static,
     {
-        let (tx, rx) = oneshot::channel();
+        self.run_in(Lane::Normal, f)
+    }
 
-        let cb = |state: &mut T| {
-            tx.send(f(state))
-                .map_err(|_| ())
-                .expect("oneshot send failed")
-        };
+    /// Run `f` with the state, in the given `lane`.
+    #[track_caller]
+    pub fn run_in<F, O>(&self, lane: Lane, f: F) -> impl Future<Output = O>
+    where
+        F: FnOnce(&mut T) -> O + Send + 'static,
+        O: Send + 'static,
+    {
+        let caller = Location::caller();
+        let this = self.clone();
+        async move {
+            let queue = match lane {
+                Lane::High => &this.tx_high,
+                Lane::Normal => &this.tx,
+            };
+            let (tx, rx) = oneshot::channel();
+
+            let cb = |state: &mut T| {
+                tx.send(f(state))
+                    .map_err(|_| ())
+                    .expect("oneshot send failed")
+            };
+
+            queue
+                .send(Job {
+                    f: Box::new(cb),
+                    caller,
+                })
+                .await
+                .expect("dispatch channel failure");
 
-        self.0
-            .send(Box::new(cb))
-            .await
-            .expect("dispatch channel failure");
+            {
+                let depth = [&this.tx, &this.tx_high]
+                    .map(|tx| tx.max_capacity() - tx.capacity())
+                    .iter()
+                    .sum();
+                let mut stats = this.stats.lock().expect("stats lock poisoned");
+                stats.queue_depth = depth;
+                stats.max_queue_depth = stats.max_queue_depth.max(depth);
+            }
+
+            rx.await.expect("should receive a value")
+        }
+    }
 
-        rx.await.expect("should receive a value")
+    /// A snapshot of the dispatcher's statistics.
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().expect("stats lock poisoned")
     }
 }
 
 pub fn dispatcher<T>(state: T) -> (Dispatch<T>, impl Future<Output = ()>) {
     let (tx, rx) = channel(1024);
-    (Dispatch(tx), recv_loop(rx, state))
+    let (tx_high, rx_high) = channel(64);
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    (
+        Dispatch {
+            tx,
+            tx_high,
+            stats: stats.clone(),
+        },
+        recv_loop(rx_high, rx, state, stats),
+    )
 }
 
-async fn recv_loop<T>(mut recv: Receiver<Fun<T>>, mut state: T) {
-    while let Some(f) = recv.recv().await {
+async fn recv_loop<T>(
+    mut high: Receiver<Job<T>>,
+    mut normal: Receiver<Job<T>>,
+    mut state: T,
+    stats: Arc<Mutex<Stats>>,
+) {
+    loop {
+        let job = tokio::select! {
+            biased;
+            Some(job) = high.recv() => job,
+            Some(job) = normal.recv() => job,
+            else => break,
+        };
+        let Job { f, caller } = job;
+        let start = Instant::now();
         f(&mut state);
+        let took = start.elapsed();
+
+        if took > SLOW_CLOSURE {
+            log::warn!("🐢 Dispatched closure from {caller} held the state for {took:?}");
+        }
+        let mut stats = stats.lock().expect("stats lock poisoned");
+        stats.executed += 1;
+        stats.total_exec += took;
+        if took > SLOW_CLOSURE {
+            stats.slow += 1;
+        }
+        if took > stats.max_exec {
+            stats.max_exec = took;
+            stats.slowest = Some(caller);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_slow_closures() {
+        let (dispatch, state_loop) = dispatcher(0u32);
+        tokio::spawn(state_loop);
+
+        dispatch.run(|x| *x += 1).await;
+        let line = line!() + 2;
+        dispatch
+            .run(|x| {
+                std::thread::sleep(SLOW_CLOSURE + Duration::from_millis(10));
+                *x += 1
+            })
+            .await;
+        assert_eq!(dispatch.run(|x| *x).await, 2);
+
+        let stats = dispatch.stats();
+        // the last closure's result is sent before its stats are recorded
+        assert!(stats.executed >= 2);
+        assert_eq!(stats.slow, 1);
+        assert!(stats.max_exec > SLOW_CLOSURE);
+        assert_eq!(stats.slowest.map(|x| x.line()), Some(line));
+    }
+
+    #[tokio::test]
+    async fn high_lane_runs_first() {
+        let (dispatch, state_loop) = dispatcher(Vec::new());
+
+        // queue up both lanes before the loop starts
+        let d = dispatch.clone();
+        tokio::spawn(async move { d.run(|x| x.push("normal")).await });
+        let d = dispatch.clone();
+        tokio::spawn(async move { d.run_in(Lane::High, |x| x.push("high")).await });
+        for _ in 0..3 {
+            tokio::task::yield_now().await;
+        }
+
+        tokio::spawn(state_loop);
+        assert_eq!(dispatch.run(|x| x.clone()).await, ["high", "normal"]);
     }
 }
@@ -0,0 +1,97 @@
+//! A tone curve applied to the frame before it is reduced to the panel's levels.
+//!
+//! E-ink panels crush the mid-tones, so light grey text that reads fine on a monitor all but
+//! disappears. A gamma above 1 darkens the mid-tones, and the black and white points stretch the
+//! contrast by clipping the ends of the range.
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneCurve {
+    /// Above 1 darkens the mid-tones, below 1 lightens them.
+    pub gamma: f32,
+    /// Greys this dark or darker become black.
+    pub black: u8,
+    /// Greys this light or lighter become white.
+    pub white: u8,
+}
+
+impl Default for ToneCurve {
+    fn default() -> Self {
+        Self {
+            gamma: 1.4,
+            black: 0,
+            white: 255,
+        }
+    }
+}
+
+impl ToneCurve {
+    pub fn apply(self, img: &mut GrayImage) {
+        let lut = self.lut();
+        for px in img.pixels_mut() {
+            px.0[0] = lut[px.0[0] as usize];
+        }
+    }
+
+    fn lut(self) -> [u8; 256] {
+        let Self {
+            gamma,
+            black,
+            white,
+        } = self;
+        let gamma = if gamma.is_finite() && gamma > 0.0 {
+            gamma
+        } else {
+            1.0
+        };
+        let (black, white) = (
+            f32::from(black),
+            f32::from(white.max(black.saturating_add(1))),
+        );
+        std::array::from_fn(|x| {
+            let v = ((x as f32 - black) / (white - black)).clamp(0.0, 1.0);
+            (v.powf(gamma) * 255.0).round() as u8
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity() {
+        let lut = ToneCurve {
+            gamma: 1.0,
+            black: 0,
+            white: 255,
+        }
+        .lut();
+        assert!(lut.iter().enumerate().all(|(i, &x)| i == x as usize));
+    }
+
+    #[test]
+    fn darkens_midtones_and_stretches() {
+        let lut = ToneCurve::default().lut();
+        assert_eq!((lut[0], lut[255]), (0, 255));
+        // light grey text and mid-greys get noticeably darker
+        assert!(
+            lut[200] < 190 && lut[128] < 100,
+            "{} {}",
+            lut[200],
+            lut[128]
+        );
+        assert!(lut.windows(2).all(|x| x[0] <= x[1]));
+
+        let lut = ToneCurve {
+            gamma: 1.0,
+            black: 40,
+            white: 215,
+        }
+        .lut();
+        assert_eq!((lut[20], lut[40], lut[215], lut[230]), (0, 0, 255, 255));
+        assert!((126..=130).contains(&lut[128]), "{}", lut[128]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Lifetime panel counters, persisted across restarts.
+//!
+//! E-ink panels are rated for a number of refreshes, the counters help estimate the wear and
+//! spot configurations which refresh far more often than intended.
+use miette::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use time::OffsetDateTime;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelStats {
+    /// Frames pushed to the panel.
+    pub frames: u64,
+    pub full_refreshes: u64,
+    pub partial_refreshes: u64,
+    /// Times the driver process was restarted.
+    pub driver_restarts: u64,
+    /// When the first frame was counted.
+    pub since: Option<OffsetDateTime>,
+}
+
+impl PanelStats {
+    pub const ZERO: Self = Self {
+        frames: 0,
+        full_refreshes: 0,
+        partial_refreshes: 0,
+        driver_restarts: 0,
+        since: None,
+    };
+
+    pub fn record_push(&mut self, full: bool, now: OffsetDateTime) {
+        self.since.get_or_insert(now);
+        self.frames += 1;
+        if full {
+            self.full_refreshes += 1;
+        } else {
+            self.partial_refreshes += 1;
+        }
+    }
+
+    pub fn record_restart(&mut self) {
+        self.driver_restarts += 1;
+    }
+
+    /// Read the counters saved at `path`, starting from zero if there are none.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(x) => serde_json::from_str(&x)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::ZERO),
+            Err(e) => Err(e)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Write the counters to `path`, via a temporary file so a power cut can't truncate them.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).into_diagnostic()?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[tokio::test]
+    async fn persists_counters() {
+        let dir = std::env::temp_dir().join(format!("pical-wear-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("panel-stats.pical.json");
+        assert_eq!(PanelStats::load(&path).unwrap(), PanelStats::ZERO);
+
+        let mut stats = PanelStats::ZERO;
+        stats.record_push(true, datetime!(2024-06-21 9:00 UTC));
+        stats.record_push(false, datetime!(2024-06-21 9:01 UTC));
+        stats.record_push(false, datetime!(2024-06-21 9:02 UTC));
+        stats.record_restart();
+        stats.save(&path).await.unwrap();
+
+        let loaded = PanelStats::load(&path).unwrap();
+        assert_eq!(loaded, stats);
+        assert_eq!(
+            (
+                loaded.frames,
+                loaded.full_refreshes,
+                loaded.partial_refreshes
+            ),
+            (3, 1, 2)
+        );
+        assert_eq!(loaded.since, Some(datetime!(2024-06-21 9:00 UTC)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+//! The wiring and panel settings, so other HATs and panels work without recompiling.
+//!
+//! Each is taken from its flag, then the `--config` file (or [`PATH`] if it exists), then the
+//! default, which suits the Waveshare 10.3" HAT:
+//!
+//! ```toml
+//! panel = "it8951"       # or "epd7in5-v2" for the Waveshare 7.5" V2
+//! spi = "/dev/spidev0.0"
+//! gpio = "/dev/gpiochip0"
+//! rst_pin = 17
+//! busy_pin = 24
+//! dc_pin = 25            # the data/command pin, only the epd7in5-v2 has one
+//! spi_hz = 12000000
+//! vcom = 1670            # printed on the panel's ribbon cable, -1.67V is 1670
+//! reset = true
+//! chunk_rows = 32        # rows loaded per SPI transfer
+//! full_every_pushes = 50 # partial refreshes between full ones, 0 for no limit
+//! full_every = "1h"      # the longest between full refreshes, "0s" for no limit
+//! rotate = 0             # or 90, 180, 270 clockwise for a panel hung differently
+//! fit = "scale"          # or "center" or "clip", for images which aren't the frame's size
+//! cold_below = 5         # refresh only with GC16 below this °C, unset to never
+//!
+//! [min_interval]         # the shortest between refreshes with each waveform
+//! a2 = "0s"
+//! du = "0s"
+//! du4 = "250ms"
+//! gl16 = "500ms"
+//! gc16 = "1s"
+//! ```
+use crate::protocol::{Rotation, Waveform};
+use miette::*;
+use serde::Deserialize;
+use std::{path::Path, time::Duration};
+
+/// Read if there is no `--config`, beside pical's own config.
+pub const PATH: &str = "./it8951.pical.toml";
+
+/// The kind of panel, or controller, the driver drives.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+pub enum Model {
+    /// A panel on an IT8951 controller, such as the Waveshare 10.3" HAT.
+    #[default]
+    #[serde(rename = "it8951")]
+    #[value(name = "it8951")]
+    It8951,
+    /// The Waveshare 7.5" V2, black and white on SPI without an IT8951.
+    #[serde(rename = "epd7in5-v2")]
+    #[value(name = "epd7in5-v2")]
+    Epd7in5V2,
+}
+
+/// How an image which isn't the size of a frame is fitted to it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fit {
+    /// Scaled to fit, keeping its aspect, and centred on white.
+    #[default]
+    Scale,
+    /// Centred on white as it is, cut off at the edges if it is larger.
+    Center,
+    /// Loaded at the top left as it is, cut off at the right and bottom if it is larger and
+    /// leaving the rest of the panel as it was if it is smaller.
+    Clip,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hardware {
+    pub panel: Model,
+    /// The SPI device path.
+    pub spi: String,
+    /// The GPIO device path.
+    pub gpio: String,
+    /// The GPIO line of the reset pin.
+    pub rst_pin: u32,
+    /// The GPIO line of the busy (HRDY) pin.
+    pub busy_pin: u32,
+    /// The GPIO line of the data/command pin, which panels without an IT8951 have.
+    pub dc_pin: u32,
+    /// The SPI clock's maximum speed, in Hz.
+    pub spi_hz: u32,
+    /// The panel's VCOM voltage, in millivolts without the sign.
+    pub vcom: u16,
+    /// Reset the controller on start, flashing the panel, otherwise attach to it as it is.
+    pub reset: bool,
+    /// The most rows of an image loaded in one transfer.
+    pub chunk_rows: u16,
+    /// Partial refreshes allowed before one is made full to clear the ghosting, 0 for no limit.
+    pub full_every_pushes: u32,
+    /// The longest allowed between full refreshes, zero for no limit.
+    #[serde(with = "humantime_serde")]
+    pub full_every: Duration,
+    /// How far the panel is turned clockwise, frames are pushed the way it is hung.
+    pub rotate: Rotation,
+    /// How images which aren't the size of a frame are fitted to it.
+    pub fit: Fit,
+    /// Below this temperature in °C every refresh is GC16, as the faster waveforms smear in
+    /// the cold. Only an IT8951 reports its panel's temperature.
+    pub cold_below: Option<i16>,
+    /// The shortest between refreshes, by the waveform of the next, so frames pushed sooner are
+    /// dropped for newer ones.
+    pub min_interval: Intervals,
+}
+
+/// A duration for each waveform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Intervals {
+    #[serde(with = "humantime_serde")]
+    pub a2: Duration,
+    #[serde(with = "humantime_serde")]
+    pub du: Duration,
+    #[serde(with = "humantime_serde")]
+    pub du4: Duration,
+    #[serde(with = "humantime_serde")]
+    pub gl16: Duration,
+    #[serde(with = "humantime_serde")]
+    pub gc16: Duration,
+}
+
+impl Default for Intervals {
+    fn default() -> Self {
+        // about as long as each takes to refresh the 10.3" panel
+        Self {
+            a2: Duration::ZERO,
+            du: Duration::ZERO,
+            du4: Duration::from_millis(250),
+            gl16: Duration::from_millis(500),
+            gc16: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Intervals {
+    pub fn of(&self, waveform: Waveform) -> Duration {
+        match waveform {
+            Waveform::A2 => self.a2,
+            Waveform::Du => self.du,
+            Waveform::Du4 => self.du4,
+            Waveform::Gl16 => self.gl16,
+            Waveform::Gc16 => self.gc16,
+        }
+    }
+}
+
+impl Default for Hardware {
+    fn default() -> Self {
+        Self {
+            panel: Model::It8951,
+            spi: "/dev/spidev0.0".to_string(),
+            gpio: "/dev/gpiochip0".to_string(),
+            rst_pin: 17,
+            busy_pin: 24,
+            dc_pin: 25,
+            spi_hz: 12_000_000,
+            vcom: 1670,
+            reset: true,
+            chunk_rows: 32,
+            full_every_pushes: 50,
+            full_every: Duration::from_secs(3600),
+            rotate: Rotation::R0,
+            fit: Fit::Scale,
+            cold_below: None,
+            min_interval: Intervals::default(),
+        }
+    }
+}
+
+impl Hardware {
+    /// Read the file at `path`, or at [`PATH`] if there is one, else the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let default = Path::new(PATH);
+        match path {
+            Some(path) => Self::read(path),
+            // pical starts the driver without flags, so the file is read where it runs
+            None if default.exists() => Self::read(default),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Read the settings in the TOML file at `path`, defaulting those not in it.
+    pub fn read(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&s).wrap_err_with(|| format!("invalid config in {}", path.display()))
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        let hw = toml::from_str::<Self>(s).into_diagnostic()?;
+        if hw.spi_hz == 0 {
+            return Err(miette!("spi_hz must be above 0"));
+        }
+        if hw.chunk_rows == 0 {
+            return Err(miette!("chunk_rows must be above 0"));
+        }
+        Ok(hw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_what_is_missing() {
+        let hw = Hardware::parse(
+            "busy_pin = 22\nvcom = 1500\nrotate = 90\nfull_every = \"30m\"\nfit = \"center\"\n\
+            cold_below = -5\n\
+            [min_interval]\ngc16 = \"3s\"",
+        )
+        .unwrap();
+        assert_eq!(
+            hw,
+            Hardware {
+                busy_pin: 22,
+                vcom: 1500,
+                rotate: Rotation::R90,
+                full_every: Duration::from_secs(1800),
+                fit: Fit::Center,
+                cold_below: Some(-5),
+                min_interval: Intervals {
+                    gc16: Duration::from_secs(3),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        );
+        let waveshare = Hardware::parse("panel = \"epd7in5-v2\"").unwrap();
+        assert_eq!(waveshare.panel, Model::Epd7in5V2);
+        assert!(Hardware::parse("panel = \"epd13in3\"").is_err());
+        assert!(Hardware::parse("vcom = -1670").is_err());
+        assert!(Hardware::parse("reset_pin = 5").is_err());
+        assert!(Hardware::parse("spi_hz = 0").is_err());
+        assert!(Hardware::parse("rotate = 45").is_err());
+        assert!(Hardware::parse("chunk_rows = 0").is_err());
+        assert!(Hardware::parse("fit = \"stretch\"").is_err());
+        assert!(Hardware::parse("[min_interval]\na3 = \"1s\"").is_err());
+    }
+}
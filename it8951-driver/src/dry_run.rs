@@ -0,0 +1,118 @@
+//! A stand in for the display controller, writing what the panel would show to numbered PNGs,
+//! so the whole pipeline can be tried on a machine without a panel.
+//!
+//! The packed pixels are unpacked into a framebuffer as the controller would, so the PNGs show
+//! the panel's 16 greys, the way up the panel is.
+use crate::{framebuffer::Framebuffer, DisplayBackend, Info};
+use it8951::{memory_converter_settings::MemoryConverterSetting, AreaImgInfo, WaveformMode};
+use miette::*;
+use std::path::{Path, PathBuf};
+
+/// The Waveshare 10.3" panel's.
+pub const SIZE: [u16; 2] = [1872, 1404];
+
+pub struct DryRun {
+    dir: PathBuf,
+    fb: Framebuffer,
+    frames: u64,
+}
+
+impl DryRun {
+    pub fn new(dir: &Path, size: [u16; 2]) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            fb: Framebuffer::new(size),
+            frames: 0,
+        })
+    }
+}
+
+impl DisplayBackend for DryRun {
+    fn info(&self) -> Info {
+        let [width, height] = self.fb.size();
+        Info {
+            width,
+            height,
+            memory_address: 0,
+            firmware: "dry-run".to_string(),
+            lut: "dry-run".to_string(),
+        }
+    }
+
+    fn load_image_area(
+        &mut self,
+        _: u32,
+        cnvtr: MemoryConverterSetting,
+        area: &AreaImgInfo,
+        data: &[u16],
+    ) -> Result<()> {
+        self.fb.load(cnvtr, area, data)
+    }
+
+    fn display(&mut self, area: Option<&AreaImgInfo>, mode: WaveformMode) -> Result<()> {
+        self.frames += 1;
+        let path = self.dir.join(format!("{:05}.png", self.frames));
+        self.fb
+            .shown()
+            .save(&path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+        match area {
+            Some(a) => eprintln!(
+                "🖼 Wrote {}, refreshing {}x{} at {},{} with {mode:?}",
+                path.display(),
+                a.area_w,
+                a.area_h,
+                a.area_x,
+                a.area_y
+            ),
+            None => eprintln!("🖼 Wrote {}, refreshing with {mode:?}", path.display()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protocol::Rotation, Driver};
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn writes_what_the_panel_shows() {
+        let dir = std::env::temp_dir().join(format!("it8951-dry-run-{}", std::process::id()));
+        let mut d = Driver {
+            chunk_rows: 3,
+            inner: DryRun::new(&dir, [16, 8]).unwrap(),
+        };
+        let frame = |n| {
+            image::open(dir.join(format!("{n:05}.png")))
+                .unwrap()
+                .into_luma8()
+        };
+        let mode = WaveformMode::GrayscaleClearing16;
+
+        // already the panel's 16 greys, so they come out as they went in
+        let img = GrayImage::from_fn(16, 8, |x, y| Luma([((x + y) % 16 * 17) as u8]));
+        d.push_image(&img, &[], mode, Rotation::R0).unwrap();
+        assert_eq!(frame(1), img);
+
+        // a portrait frame, for the panel turned clockwise
+        let portrait = GrayImage::from_fn(8, 16, |x, y| Luma([((x * 2 + y) % 16 * 17) as u8]));
+        d.push_image(&portrait, &[], mode, Rotation::R90).unwrap();
+        assert_eq!(frame(2), image::imageops::rotate270(&portrait));
+
+        // only the area is loaded, over what was there
+        let black = GrayImage::from_pixel(4, 2, Luma([0]));
+        d.load_at(&black, [4, 2], Rotation::R0).unwrap();
+        d.display(None, mode).unwrap();
+        let mut expected = image::imageops::rotate270(&portrait);
+        image::imageops::replace(&mut expected, &black, 4, 2);
+        assert_eq!(frame(3), expected);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
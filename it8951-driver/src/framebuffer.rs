@@ -0,0 +1,76 @@
+//! The IT8951's memory as pixels, for the backends which stand in for it.
+//!
+//! The packed pixels are unpacked as the controller would, rotating and quantising them to the
+//! 16 greys, so what was loaded can be read back the way up the panel shows it.
+use crate::protocol::Rotation;
+use image::{GrayImage, Luma};
+use it8951::{
+    memory_converter_settings::{MemoryConverterRotation, MemoryConverterSetting},
+    AreaImgInfo,
+};
+use miette::*;
+
+pub struct Framebuffer {
+    /// Mirrored from what the panel shows, as the IT8951's is.
+    memory: GrayImage,
+}
+
+impl Framebuffer {
+    /// A white framebuffer of `size`.
+    pub fn new([w, h]: [u16; 2]) -> Self {
+        Self {
+            memory: GrayImage::from_pixel(w.into(), h.into(), Luma([255])),
+        }
+    }
+
+    pub fn size(&self) -> [u16; 2] {
+        [self.memory.width() as u16, self.memory.height() as u16]
+    }
+
+    /// Write the packed pixels `data` to `area`, rotated by the converter setting.
+    pub fn load(
+        &mut self,
+        cnvtr: MemoryConverterSetting,
+        area: &AreaImgInfo,
+        data: &[u16],
+    ) -> Result<()> {
+        let rotate = match cnvtr.rotation {
+            MemoryConverterRotation::Rotate0 => Rotation::R0,
+            MemoryConverterRotation::Rotate90 => Rotation::R90,
+            MemoryConverterRotation::Rotate180 => Rotation::R180,
+            MemoryConverterRotation::Rotate270 => Rotation::R270,
+        };
+        let [fw, fh] = rotate.frame(self.size());
+        let &AreaImgInfo {
+            area_x,
+            area_y,
+            area_w,
+            area_h,
+        } = area;
+        if area_x + area_w > fw || area_y + area_h > fh {
+            return Err(miette!(
+                "area {area_w}x{area_h} at {area_x},{area_y} is outside the {fw}x{fh} frame"
+            ));
+        }
+        // 4 pixels to a word, the first in the lowest bits
+        let stride = usize::from(area_w).div_ceil(4);
+        if data.len() < stride * usize::from(area_h) {
+            return Err(miette!("too few pixels for a {area_w}x{area_h} area"));
+        }
+        for dy in 0..area_h {
+            for dx in 0..area_w {
+                let (row, i) = (usize::from(dy) * stride, usize::from(dx));
+                let grey = (data[row + i / 4] >> (i % 4 * 4)) & 0xf;
+                let [x, y, ..] = rotate.to_panel([area_x + dx, area_y + dy, 1, 1], [fw, fh]);
+                self.memory
+                    .put_pixel(x.into(), y.into(), Luma([grey as u8 * 17]));
+            }
+        }
+        Ok(())
+    }
+
+    /// What the panel shows, the memory unmirrored.
+    pub fn shown(&self) -> GrayImage {
+        image::imageops::flip_horizontal(&self.memory)
+    }
+}
@@ -0,0 +1,77 @@
+//! When to clear the ghosting which builds up with partial refreshes.
+//!
+//! Fast waveforms and area refreshes leave traces of what was shown before, which a full GC16
+//! refresh clears. The driver counts the partial refreshes since the last full one, and turns a
+//! push into a full refresh once there have been too many or it has been too long, whoever the
+//! client is.
+use std::time::{Duration, Instant};
+
+pub struct Ghosting {
+    /// Partial refreshes allowed between full ones, 0 for no limit.
+    every_pushes: u32,
+    /// The longest between full refreshes, if limited.
+    every: Option<Duration>,
+    partials: u32,
+    last_full: Instant,
+}
+
+impl Ghosting {
+    pub fn new(every_pushes: u32, every: Option<Duration>, now: Instant) -> Self {
+        Self {
+            every_pushes,
+            every,
+            partials: 0,
+            last_full: now,
+        }
+    }
+
+    /// Whether a partial refresh should be done as a full one instead.
+    pub fn due(&self, now: Instant) -> bool {
+        (self.every_pushes > 0 && self.partials + 1 > self.every_pushes)
+            || self.every.is_some_and(|x| now - self.last_full >= x)
+    }
+
+    /// Record a refresh once it has been done, so one which failed is still due.
+    pub fn refreshed(&mut self, full: bool, now: Instant) {
+        if full {
+            self.partials = 0;
+            self.last_full = now;
+        } else {
+            self.partials += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_refresh_after_partials_or_time() {
+        let start = Instant::now();
+        let mins = |x: u64| start + Duration::from_secs(x * 60);
+        let mut g = Ghosting::new(3, Some(Duration::from_secs(3600)), start);
+        let refresh = |g: &mut Ghosting, full: bool, now| {
+            let due = !full && g.due(now);
+            g.refreshed(full || due, now);
+            due
+        };
+        assert_eq!(
+            (0..4)
+                .map(|x| refresh(&mut g, false, mins(x)))
+                .collect::<Vec<_>>(),
+            [false, false, false, true]
+        );
+        // a full refresh from the client starts the count again
+        assert!(!refresh(&mut g, false, mins(5)));
+        assert!(!refresh(&mut g, true, mins(6)));
+        assert!(!refresh(&mut g, false, mins(7)));
+        assert!(!refresh(&mut g, false, mins(8)));
+        // a due refresh which failed isn't recorded, so is due again
+        assert!(g.due(mins(6 + 60)));
+        assert!(refresh(&mut g, false, mins(6 + 61)));
+
+        let mut g = Ghosting::new(0, None, start);
+        assert!((0..1000).all(|x| !refresh(&mut g, false, mins(x))));
+    }
+}
@@ -0,0 +1,1041 @@
+//! Driving e-ink panels, on an IT8951 controller or otherwise, for the it8951-driver binary or
+//! in-process.
+//!
+//! A [`Panel`] sleeps between requests, waking to handle each. It is driven through a
+//! [`DisplayBackend`], the IT8951 itself or one standing in for it, such as the Waveshare 7.5"
+//! V2 or a dry run writing PNGs.
+//!
+//! Driving the panel is retried when it fails, resetting the controller if it fails again, and
+//! only if that fails is the panel given up on as lost.
+use config::{Fit, Hardware, Model};
+use dry_run::DryRun;
+use ghosting::Ghosting;
+use image::GrayImage;
+use it8951::WaveformMode;
+use miette::*;
+use protocol::{Area, Command, Pattern, Power, Recovery, Response, Rotation, Status, Waveform};
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thermometer::{SharedPin, Thermometer};
+use waveshare::Epd7in5V2;
+
+pub mod config;
+pub mod dry_run;
+mod framebuffer;
+mod ghosting;
+pub mod pattern;
+pub mod protocol;
+pub mod queue;
+mod thermometer;
+pub mod waveshare;
+
+/// Tries at driving the panel for a request, the last after resetting the controller.
+const ATTEMPTS: u32 = 3;
+
+/// How long a reading of the panel's temperature is used before it is read again.
+const TEMPERATURE_FOR: Duration = Duration::from_secs(10 * 60);
+
+fn build_driver(hw: &Hardware) -> Result<(It8951<it8951::Run>, Thermometer)> {
+    if !hw.reset {
+        // the controller keeps its state while the Pi is powered, so only needs a reset after
+        // a power cycle
+        let (interface, thermometer) = interface(hw)?;
+        match it8951::IT8951::new(interface).attach() {
+            Ok(x) if x.get_dev_info().panel_width > 0 => {
+                eprintln!("✅ Attached to E-Ink Display:\n{:#?}", x.get_dev_info());
+                return Ok((x, thermometer));
+            }
+            Ok(_) => eprintln!("⚠ Display controller isn't initialised, resetting"),
+            Err(e) => eprintln!("⚠ Failed to attach to display ({e:?}), resetting"),
+        }
+    }
+    let (interface, thermometer) = interface(hw)?;
+    let x = it8951::IT8951::new(interface)
+        .init(hw.vcom)
+        .map_err(|e| miette!("failed to build it8951 driver: {:?}", e))?;
+    eprintln!("✅ Connected to E-Ink Display:\n{:#?}", x.get_dev_info());
+    Ok((x, thermometer))
+}
+
+type Interface = it8951::interface::IT8951SPIInterface<
+    linux_embedded_hal::Spidev,
+    SharedPin,
+    linux_embedded_hal::CdevPin,
+    linux_embedded_hal::Delay,
+>;
+
+/// Open the SPI device and GPIO pins the controller is wired to, for the it8951 crate and for
+/// reading the temperature beside it.
+fn interface(hw: &Hardware) -> Result<(Interface, Thermometer)> {
+    use linux_embedded_hal::{gpio_cdev::*, CdevPin, Delay};
+    let devspi = &hw.spi;
+    eprintln!("ℹ Connecting to {devspi}");
+    let spi = open_spi(hw)?;
+
+    let devgpio = &hw.gpio;
+    let mut chip = Chip::new(devgpio)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("gpio path: {devgpio}"))?;
+    let rst_output = chip
+        .get_line(hw.rst_pin)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("reset pin: {}", hw.rst_pin))?;
+    let rst_output_handle = rst_output
+        .request(LineRequestFlags::OUTPUT, 0, "meeting-room")
+        .into_diagnostic()?;
+    let rst = CdevPin::new(rst_output_handle).into_diagnostic()?;
+    let busy_input = chip
+        .get_line(hw.busy_pin)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("busy pin: {}", hw.busy_pin))?;
+    let busy_input_handle = busy_input
+        .request(LineRequestFlags::INPUT, 0, "meeting-room")
+        .into_diagnostic()?;
+    let busy = SharedPin(Arc::new(CdevPin::new(busy_input_handle).into_diagnostic()?));
+
+    let thermometer = Thermometer::new(open_spi(hw)?, busy.clone());
+    let interface = it8951::interface::IT8951SPIInterface::new(spi, busy, rst, Delay);
+    Ok((interface, thermometer))
+}
+
+fn open_spi(hw: &Hardware) -> Result<linux_embedded_hal::Spidev> {
+    use linux_embedded_hal::{spidev::*, Spidev};
+    let devspi = &hw.spi;
+    let mut spi = Spidev::open(devspi)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("spi path: {devspi}"))?;
+    let opts = SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(hw.spi_hz)
+        .mode(SpiModeFlags::SPI_MODE_0)
+        .build();
+    spi.configure(&opts).into_diagnostic()?;
+    Ok(spi)
+}
+
+/// The panel between requests, asleep unless told otherwise.
+pub struct Panel {
+    /// `None` if the panel was given up on, or waking or sleeping it failed, which consumes the
+    /// driver.
+    idle: Option<Idle>,
+    /// What to open again to reset the controller, `None` for a dry run.
+    reopen: Option<Hardware>,
+    /// How the last request got over failing, for its response.
+    recovery: Option<Recovery>,
+    /// How the panel is left after each request.
+    rest: Rest,
+    info: Info,
+    /// Used by pushes which don't give their own.
+    rotate: Rotation,
+    fit: Fit,
+    chunk_rows: u16,
+    ghosting: Ghosting,
+    pushes: u64,
+    /// Refreshes of the whole panel with GC16, by a push, clear, or to clear the ghosting.
+    full_refreshes: u64,
+    /// Requests which failed.
+    failures: u64,
+    /// Tries at driving the panel which failed and were tried again.
+    retries: u64,
+    /// Resets of the controller after repeated failures.
+    resets: u64,
+    started: Instant,
+    /// Reads the IT8951's temperature, `None` for other panels.
+    thermometer: Option<Thermometer>,
+    /// The panel's last temperature in °C, and when it was read.
+    temperature: Option<(i16, Instant)>,
+    /// Below this temperature in °C every refresh is GC16.
+    cold_below: Option<i16>,
+}
+
+/// How the panel is left between requests.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Rest {
+    /// Powered down, the slowest to wake but drawing the least.
+    #[default]
+    Asleep,
+    /// Quicker to wake, drawing a little more.
+    Standby,
+    /// Running, the quickest to refresh.
+    Awake,
+}
+
+/// The controller between requests.
+enum Idle {
+    It8951(It8951Idle),
+    Epd7in5V2(Epd7in5V2),
+    DryRun(DryRun),
+}
+
+/// The IT8951 in each of its resting states.
+enum It8951Idle {
+    Asleep(It8951<it8951::PowerDown>),
+    Standby(It8951<it8951::StandBy>),
+    Awake(It8951<it8951::Run>),
+}
+
+impl It8951Idle {
+    fn rest(x: It8951<it8951::Run>, rest: Rest) -> Result<Self> {
+        match rest {
+            Rest::Asleep => x.sleep().map(Self::Asleep),
+            Rest::Standby => x.standby().map(Self::Standby),
+            Rest::Awake => Ok(Self::Awake(x)),
+        }
+        .map_err(|e| miette!("failed to sleep device: {:?}", e))
+    }
+
+    fn wake(self) -> Result<It8951<it8951::Run>> {
+        match self {
+            Self::Asleep(x) => x.sys_run(),
+            Self::Standby(x) => x.sys_run(),
+            Self::Awake(x) => Ok(x),
+        }
+        .map_err(|e| miette!("failed to wake device: {:?}", e))
+    }
+}
+
+impl Panel {
+    /// Connect to the panel `hw` describes, resetting or attaching to it.
+    pub fn open(hw: &Hardware) -> Result<Self> {
+        match hw.panel {
+            Model::It8951 => {
+                let (driver, thermometer) = build_driver(hw)?;
+                Self::new(driver, thermometer, hw)
+            }
+            Model::Epd7in5V2 => Self::epd7in5_v2(hw),
+        }
+    }
+
+    fn new(
+        driver: It8951<it8951::Run>,
+        mut thermometer: Thermometer,
+        hw: &Hardware,
+    ) -> Result<Self> {
+        let info = driver.info();
+        // read while the controller is running, so the first refresh knows if it is cold
+        let temperature = read_temperature(&mut thermometer);
+        let idle = It8951Idle::rest(driver, Rest::Asleep)?;
+        let mut panel = Self::with(Idle::It8951(idle), info, hw);
+        panel.thermometer = Some(thermometer);
+        if let Some(t) = temperature {
+            panel.set_temperature(t);
+        }
+        Ok(panel)
+    }
+
+    /// A Waveshare 7.5" V2, initialised to check it is there.
+    fn epd7in5_v2(hw: &Hardware) -> Result<Self> {
+        let mut epd = Epd7in5V2::open(hw)?;
+        epd.wake()?;
+        epd.rest(Rest::Asleep)?;
+        let info = epd.info();
+        eprintln!("✅ Connected to E-Ink Display:\n{info:#?}");
+        Ok(Self::with(Idle::Epd7in5V2(epd), info, hw))
+    }
+
+    /// A panel of `size` which writes what it would show to `dir`.
+    pub fn dry_run(dir: &Path, size: [u16; 2], hw: &Hardware) -> Result<Self> {
+        let dry_run = DryRun::new(dir, size)?;
+        let info = dry_run.info();
+        eprintln!("ℹ Dry run, writing frames to {}", dir.display());
+        Ok(Self::with(Idle::DryRun(dry_run), info, hw))
+    }
+
+    fn with(idle: Idle, info: Info, hw: &Hardware) -> Self {
+        let every = Some(hw.full_every).filter(|x| !x.is_zero());
+        let reopen = match idle {
+            Idle::DryRun(_) => None,
+            _ => Some(hw.clone()),
+        };
+        Self {
+            idle: Some(idle),
+            reopen,
+            recovery: None,
+            rest: Rest::Asleep,
+            info,
+            rotate: hw.rotate,
+            fit: hw.fit,
+            chunk_rows: hw.chunk_rows,
+            // the controller was just reset or attached to, so the count starts from now
+            ghosting: Ghosting::new(hw.full_every_pushes, every, Instant::now()),
+            pushes: 0,
+            full_refreshes: 0,
+            failures: 0,
+            retries: 0,
+            resets: 0,
+            started: Instant::now(),
+            thermometer: None,
+            temperature: None,
+            cold_below: hw.cold_below,
+        }
+    }
+
+    /// Whether the panel was given up on, so it needs opening again.
+    pub fn is_lost(&self) -> bool {
+        self.idle.is_none()
+    }
+
+    /// Leave the panel in `rest` between requests from now on, such as asleep overnight.
+    pub fn rest(&mut self, rest: Rest) -> Result<()> {
+        if rest == self.rest {
+            return Ok(());
+        }
+        self.rest = rest;
+        self.awake(|_| Ok(()))
+    }
+
+    /// Answer a request, with the error if it failed.
+    ///
+    /// The response says if driving the panel failed and was recovered from, or was given up on.
+    pub fn handle(&mut self, id: u64, command: Command) -> Response {
+        self.recovery = None;
+        let res = self.answer(id, command).unwrap_or_else(|e| {
+            self.failures += 1;
+            Response::error(Some(id), &e)
+        });
+        Response {
+            recovery: self.recovery,
+            ..res
+        }
+    }
+
+    fn answer(&mut self, id: u64, command: Command) -> Result<Response> {
+        let warning = match command {
+            Command::Push {
+                image,
+                waveform,
+                areas,
+                rotate,
+            } => self.push(&read_image(image)?, &areas, waveform, rotate)?,
+            Command::PushRaw {
+                width,
+                height,
+                bpp,
+                waveform,
+                areas,
+                rotate,
+                data,
+                ..
+            } => {
+                let img = unpack_raw([width, height], bpp, data)?;
+                self.push(&img, &areas, waveform, rotate)?
+            }
+            Command::PushArea {
+                image,
+                x,
+                y,
+                waveform,
+                rotate,
+            } => {
+                let img = read_image(image)?;
+                let rotate = rotate.unwrap_or(self.rotate);
+                let [w, h] = rotate.frame([self.info.width, self.info.height]);
+                if x >= w || y >= h {
+                    return Err(miette!("the image at {x},{y} is off the {w}x{h} frame"));
+                }
+                // the panel packs 4 pixels to a word along its own rows, which are the frame's
+                // columns when it is turned 90° or 270°
+                let size = [img.width(), img.height()].map(|x| x.try_into().unwrap_or(u16::MAX));
+                let [px, _, pw, _] = area_on_panel([x, y], size, [w, h], rotate);
+                if px % 4 != 0 || pw % 4 != 0 {
+                    return Err(miette!(
+                        "the area's x ({px}) and width ({pw}) on the panel must be multiples of 4, \
+                         turned {}°",
+                        rotate.degrees()
+                    ));
+                }
+                let full = self.ghosting.due(Instant::now());
+                if full {
+                    eprintln!("ℹ Refreshing the whole panel to clear the ghosting");
+                }
+                let mode = self.waveform(waveform);
+                self.awake(|d| {
+                    let area = d.load_at(&img, [x, y], rotate)?;
+                    match full {
+                        true => d.display(None, WaveformMode::GrayscaleClearing16),
+                        false => d.display(Some(&area), mode),
+                    }
+                })?;
+                self.ghosting.refreshed(full, Instant::now());
+                self.pushes += 1;
+                self.full_refreshes += u64::from(full);
+                None
+            }
+            Command::Clear { waveform } => {
+                self.clear(waveform)?;
+                None
+            }
+            Command::Test {
+                pattern,
+                step,
+                waveform,
+            } => {
+                self.test_pattern(pattern, step, waveform)?;
+                None
+            }
+            Command::Sleep => {
+                self.rest(Rest::Asleep)?;
+                None
+            }
+            Command::Standby => {
+                self.rest(Rest::Standby)?;
+                None
+            }
+            Command::Wake => {
+                self.rest(Rest::Awake)?;
+                None
+            }
+            Command::Status => {
+                return Ok(Response {
+                    status: Some(self.status()),
+                    ..Response::ok(id)
+                });
+            }
+        };
+        Ok(Response {
+            warning,
+            ..Response::ok(id)
+        })
+    }
+
+    /// Show the `areas` of the image, or all of it if there are none, turned by `rotate` or the
+    /// driver's rotation.
+    ///
+    /// An image which isn't the size of a frame is fitted to it, the whole of it shown unless it
+    /// is clipped, returning a warning saying so.
+    pub fn push(
+        &mut self,
+        img: &GrayImage,
+        areas: &[Area],
+        waveform: Waveform,
+        rotate: Option<Rotation>,
+    ) -> Result<Option<String>> {
+        let rotate = rotate.unwrap_or(self.rotate);
+        let [w, h] = rotate.frame([self.info.width, self.info.height]);
+        let warning = if img.dimensions() == (w.into(), h.into()) {
+            self.show(img, areas, waveform, rotate)?;
+            None
+        } else {
+            let how = match self.fit {
+                Fit::Scale => "scaled to fit",
+                Fit::Center => "centred in",
+                Fit::Clip => "clipped to",
+            };
+            let warning = format!(
+                "the {}x{} image was {how} the {w}x{h} frame",
+                img.width(),
+                img.height()
+            );
+            eprintln!("⚠ {warning}");
+            match fit_image(img, [w, h], self.fit) {
+                Some(img) => self.show(&img, &[], waveform, rotate)?,
+                None => self.show(img, areas, waveform, rotate)?,
+            }
+            Some(warning)
+        };
+        self.pushes += 1;
+        eprintln!("✅ Display refreshed, you should see your image now!");
+        Ok(warning)
+    }
+
+    /// Fill the panel with white, refreshing with `waveform`, such as before unmounting it.
+    pub fn clear(&mut self, waveform: Waveform) -> Result<()> {
+        let [w, h] = self.frame();
+        let white = GrayImage::from_pixel(w.into(), h.into(), image::Luma([255]));
+        self.show(&white, &[], waveform, self.rotate)?;
+        eprintln!("✅ Display cleared");
+        Ok(())
+    }
+
+    /// Show the `pattern` drawn to fill a frame, the `step`th of a crawl.
+    pub fn test_pattern(&mut self, pattern: Pattern, step: u32, waveform: Waveform) -> Result<()> {
+        let img = pattern::draw(pattern, self.frame(), step);
+        self.show(&img, &[], waveform, self.rotate)?;
+        eprintln!("✅ Showing the {pattern:?} test pattern");
+        Ok(())
+    }
+
+    /// Load and refresh the image, as a full refresh if one is due to clear the ghosting.
+    fn show(
+        &mut self,
+        img: &GrayImage,
+        areas: &[Area],
+        waveform: Waveform,
+        rotate: Rotation,
+    ) -> Result<()> {
+        let full = areas.is_empty() && waveform == Waveform::Gc16;
+        let due = !full && self.ghosting.due(Instant::now());
+        let (areas, mode) = match due {
+            true => {
+                eprintln!("ℹ Refreshing the whole panel to clear the ghosting");
+                (&[][..], WaveformMode::GrayscaleClearing16)
+            }
+            false => (areas, self.waveform(waveform)),
+        };
+        self.awake(|d| d.push_image(img, areas, mode, rotate))?;
+        self.ghosting.refreshed(full || due, Instant::now());
+        self.full_refreshes += u64::from(full || due);
+        Ok(())
+    }
+
+    /// The mode to refresh with `waveform` in, GC16 if the panel is below `cold_below`, as the
+    /// faster waveforms leave it smeared in the cold.
+    fn waveform(&self, waveform: Waveform) -> WaveformMode {
+        match self.is_cold() {
+            true => WaveformMode::GrayscaleClearing16,
+            false => waveform.into(),
+        }
+    }
+
+    fn is_cold(&self) -> bool {
+        matches!(
+            (self.temperature, self.cold_below),
+            (Some((t, _)), Some(below)) if t < below
+        )
+    }
+
+    fn set_temperature(&mut self, t: i16) {
+        let was_cold = self.is_cold();
+        self.temperature = Some((t, Instant::now()));
+        if self.is_cold() && !was_cold {
+            eprintln!("ℹ The panel is {t}°C, refreshing with GC16 until it warms");
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        let [width, height] = self.frame();
+        Status {
+            width,
+            height,
+            firmware: self.info.firmware.clone(),
+            lut: self.info.lut.clone(),
+            rotate: self.rotate.degrees(),
+            power: match (self.is_lost(), self.rest) {
+                (true, _) => Power::Lost,
+                (false, Rest::Asleep) => Power::Asleep,
+                (false, Rest::Standby) => Power::Standby,
+                (false, Rest::Awake) => Power::Awake,
+            },
+            pushes: self.pushes,
+            full_refreshes: self.full_refreshes,
+            failures: self.failures,
+            retries: self.retries,
+            resets: self.resets,
+            // counted by the queue in front of the panel, if there is one
+            dropped: 0,
+            uptime_secs: self.started.elapsed().as_secs(),
+            temperature: self.temperature.map(|x| x.0),
+        }
+    }
+
+    /// The size of a frame in the driver's rotation.
+    fn frame(&self) -> [u16; 2] {
+        self.rotate.frame([self.info.width, self.info.height])
+    }
+
+    /// Wake the panel for `f`, leaving it to rest after.
+    ///
+    /// If driving the panel fails it is tried again, after resetting the controller if it was
+    /// lost or fails again, up to [`ATTEMPTS`] times before the panel is given up on.
+    fn awake(
+        &mut self,
+        mut f: impl FnMut(&mut Driver<dyn DisplayBackend + '_>) -> Result<()>,
+    ) -> Result<()> {
+        let mut failed = 0;
+        loop {
+            let e = match self.try_awake(&mut f) {
+                Ok(()) => {
+                    if failed > 0 {
+                        eprintln!("✅ Recovered after {failed} failed attempts");
+                        self.recovery = Some(Recovery::Recovered);
+                    }
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            failed += 1;
+            // a dry run has no controller to reset
+            let Some(hw) = self.reopen.clone() else {
+                return Err(e);
+            };
+            let reset = failed > 1 || self.is_lost();
+            let e = match failed < ATTEMPTS {
+                true if reset => {
+                    eprintln!("⚠ {e:?}\nℹ Resetting the display controller and trying again");
+                    self.reset(&hw)
+                        .wrap_err("failed to reset the display controller")
+                        .err()
+                }
+                true => {
+                    eprintln!("⚠ {e:?}\nℹ Trying again");
+                    None
+                }
+                false => Some(e.wrap_err(format!("failed {ATTEMPTS} times driving the panel"))),
+            };
+            if let Some(e) = e {
+                eprintln!("❌ Giving up on the panel");
+                self.idle = None;
+                self.recovery = Some(Recovery::Fatal);
+                return Err(e);
+            }
+            self.retries += 1;
+        }
+    }
+
+    /// Open the controller again as the driver does when it starts, resetting it.
+    fn reset(&mut self, hw: &Hardware) -> Result<()> {
+        // dropped first, freeing its SPI device and pins
+        self.idle = None;
+        self.thermometer = None;
+        let hw = Hardware {
+            reset: true,
+            ..hw.clone()
+        };
+        let panel = Self::open(&hw)?;
+        self.idle = panel.idle;
+        self.thermometer = panel.thermometer;
+        self.temperature = panel.temperature.or(self.temperature);
+        self.resets += 1;
+        Ok(())
+    }
+
+    fn try_awake(
+        &mut self,
+        f: &mut impl FnMut(&mut Driver<dyn DisplayBackend + '_>) -> Result<()>,
+    ) -> Result<()> {
+        let (chunk_rows, rest) = (self.chunk_rows, self.rest);
+        match self.idle.take().ok_or_else(|| miette!("panel was lost"))? {
+            Idle::It8951(x) => {
+                let inner = x.wake()?;
+                let stale = self
+                    .temperature
+                    .map_or(true, |x| x.1.elapsed() >= TEMPERATURE_FOR);
+                if let Some(thermometer) = self.thermometer.as_mut().filter(|_| stale) {
+                    if let Some(t) = read_temperature(thermometer) {
+                        self.set_temperature(t);
+                    }
+                }
+                let mut d = Driver { chunk_rows, inner };
+                let res = f(&mut d);
+                self.idle = Some(Idle::It8951(It8951Idle::rest(d.inner, rest)?));
+                res
+            }
+            Idle::Epd7in5V2(mut inner) => {
+                inner.wake()?;
+                let mut d = Driver { chunk_rows, inner };
+                let res = f(&mut d);
+                d.inner.rest(rest)?;
+                self.idle = Some(Idle::Epd7in5V2(d.inner));
+                res
+            }
+            Idle::DryRun(inner) => {
+                let mut d = Driver { chunk_rows, inner };
+                let res = f(&mut d);
+                self.idle = Some(Idle::DryRun(d.inner));
+                res
+            }
+        }
+    }
+}
+
+type It8951<State> = it8951::IT8951<Interface, State>;
+
+/// The panel's temperature in °C, read while the controller is running.
+///
+/// Failing to is only logged, as the panel can still be driven without it.
+fn read_temperature(thermometer: &mut Thermometer) -> Option<i16> {
+    thermometer
+        .read()
+        .map_err(|e| eprintln!("⚠ Failed to read the panel's temperature: {e:?}"))
+        .ok()
+}
+
+/// The panel's details, as its controller reports them.
+#[derive(Clone, Debug)]
+pub struct Info {
+    pub width: u16,
+    pub height: u16,
+    pub memory_address: u32,
+    pub firmware: String,
+    pub lut: String,
+}
+
+/// What the driver needs of the display controller, so other controllers and a dry run can
+/// stand in for it.
+///
+/// Images are loaded as they would be into the IT8951's memory, packed and rotated by its
+/// memory converter, then displayed.
+pub trait DisplayBackend {
+    fn info(&self) -> Info;
+
+    /// Write the packed pixels `data` to the `area` of the controller's memory at `addr`.
+    fn load_image_area(
+        &mut self,
+        addr: u32,
+        cnvtr: it8951::memory_converter_settings::MemoryConverterSetting,
+        area: &it8951::AreaImgInfo,
+        data: &[u16],
+    ) -> Result<()>;
+
+    /// Display the loaded `area` of the panel, or all of it.
+    fn display(&mut self, area: Option<&it8951::AreaImgInfo>, mode: WaveformMode) -> Result<()>;
+}
+
+impl DisplayBackend for It8951<it8951::Run> {
+    fn info(&self) -> Info {
+        let it8951::DevInfo {
+            panel_width,
+            panel_height,
+            memory_address,
+            firmware_version,
+            lut_version,
+            ..
+        } = self.get_dev_info();
+        Info {
+            width: panel_width,
+            height: panel_height,
+            memory_address,
+            firmware: firmware_version,
+            lut: lut_version,
+        }
+    }
+
+    fn load_image_area(
+        &mut self,
+        addr: u32,
+        cnvtr: it8951::memory_converter_settings::MemoryConverterSetting,
+        area: &it8951::AreaImgInfo,
+        data: &[u16],
+    ) -> Result<()> {
+        it8951::IT8951::load_image_area(self, addr, cnvtr, area, data)
+            .map_err(|e| miette!("failed to write image rows to memory: {:?}", e))
+    }
+
+    fn display(&mut self, area: Option<&it8951::AreaImgInfo>, mode: WaveformMode) -> Result<()> {
+        match area {
+            Some(area) => self
+                .display_area(area, mode)
+                .map_err(|e| miette!("failed to display image area: {:?}", e)),
+            None => it8951::IT8951::display(self, mode)
+                .map_err(|e| miette!("failed to display image buffer: {:?}", e)),
+        }
+    }
+}
+
+/// Loads images into the controller's memory, and displays them.
+struct Driver<C: ?Sized> {
+    /// The most rows loaded in one transfer.
+    chunk_rows: u16,
+    inner: C,
+}
+
+impl<C: DisplayBackend + ?Sized> Driver<C> {
+    /// Load the `areas` of the image and display them, or the whole image if there are none.
+    fn push_image(
+        &mut self,
+        img: &GrayImage,
+        areas: &[Area],
+        mode: WaveformMode,
+        rotate: Rotation,
+    ) -> Result<()> {
+        let [w, h] = self.frame(rotate);
+        eprintln!(
+            "ℹ Pushing {}x{} image to display buffer",
+            img.width(),
+            img.height()
+        );
+
+        let whole = [Area { x: 0, y: 0, w, h }];
+        let partial = !areas.is_empty();
+        let areas = if partial { areas } else { &whole };
+
+        let mut shown = Vec::with_capacity(areas.len());
+        for area in areas {
+            shown.extend(self.load_area(img, area, [area.x, area.y], rotate)?);
+        }
+        eprintln!("✅ Buffer updated!");
+
+        if !partial {
+            return self.display(None, mode);
+        }
+        for area in &shown {
+            self.display(Some(area), mode)?;
+        }
+        Ok(())
+    }
+
+    /// Load the image with its top left at `x`, `y` on the panel, returning the panel's area
+    /// written.
+    fn load_at(
+        &mut self,
+        img: &GrayImage,
+        [x, y]: [u16; 2],
+        rotate: Rotation,
+    ) -> Result<it8951::AreaImgInfo> {
+        let whole = Area {
+            x: 0,
+            y: 0,
+            w: img.width().try_into().unwrap_or(u16::MAX),
+            h: img.height().try_into().unwrap_or(u16::MAX),
+        };
+        eprintln!(
+            "ℹ Pushing {}x{} image to display buffer at {x},{y}",
+            img.width(),
+            img.height()
+        );
+        self.load_area(img, &whole, [x, y], rotate)?
+            .ok_or_else(|| miette!("the image at {x},{y} is off the panel"))
+    }
+
+    /// Display the loaded `area` of the panel, or all of it.
+    fn display(&mut self, area: Option<&it8951::AreaImgInfo>, mode: WaveformMode) -> Result<()> {
+        self.inner.display(area, mode)
+    }
+
+    /// The size of a frame for the panel turned by `rotate`.
+    fn frame(&self, rotate: Rotation) -> [u16; 2] {
+        let info = self.inner.info();
+        rotate.frame([info.width, info.height])
+    }
+
+    /// Load the `src` area of the image into the display buffer with its top left at `at`,
+    /// clipped to the image and the frame, which the controller rotates by `rotate` as it loads.
+    /// Returns the panel's area written, if any.
+    fn load_area(
+        &mut self,
+        img: &GrayImage,
+        src: &Area,
+        [x, y]: [u16; 2],
+        rotate: Rotation,
+    ) -> Result<Option<it8951::AreaImgInfo>> {
+        use it8951::memory_converter_settings::*;
+        let memory_address = self.inner.info().memory_address;
+        let [frame_w, frame_h] = self.frame(rotate);
+        let cnvtr = || MemoryConverterSetting {
+            endianness: MemoryConverterEndianness::LittleEndian,
+            bit_per_pixel: MemoryConverterBitPerPixel::BitsPerPixel4,
+            rotation: rotate.into(),
+        };
+
+        // clip to the image and the frame
+        let clip = |len: u16, from: u16, size: u32, at: u16, panel: u16| {
+            let len = u32::from(len)
+                .min(size.saturating_sub(from.into()))
+                .min(panel.saturating_sub(at).into());
+            len as u16
+        };
+        let w = clip(src.w, src.x, img.width(), x, frame_w);
+        let h = clip(src.h, src.y, img.height(), y, frame_h);
+        if w == 0 || h == 0 {
+            return Ok(None);
+        }
+        // rows are packed reversed as the panel is mirrored, so the area is mirrored too
+        let area = it8951::AreaImgInfo {
+            area_x: frame_w - (x + w),
+            area_y: y,
+            area_w: w,
+            area_h: h,
+        };
+        // a transfer per chunk of rows rather than per row, which each cost a command and a wait
+        // on the busy pin
+        for dy in (0..h).step_by(self.chunk_rows.into()) {
+            let rows = self.chunk_rows.min(h - dy);
+            let chunk = it8951::AreaImgInfo {
+                area_y: y + dy,
+                area_h: rows,
+                ..area
+            };
+            let pxs = pack_rows(img, [src.x, src.y + dy], [w, rows]);
+            self.inner
+                .load_image_area(memory_address, cnvtr(), &chunk, &pxs)?;
+        }
+        // the display is refreshed in the panel's own coordinates
+        let [area_x, area_y, area_w, area_h] =
+            rotate.to_panel([area.area_x, y, w, h], [frame_w, frame_h]);
+        Ok(Some(it8951::AreaImgInfo {
+            area_x,
+            area_y,
+            area_w,
+            area_h,
+        }))
+    }
+}
+
+/// The `[x, y, w, h]` of the panel an image of `size` at `x`, `y` of a `frame` sized frame is
+/// refreshed in, clipped to the frame and mirrored as [`Driver::load_area`] loads it.
+fn area_on_panel(
+    [x, y]: [u16; 2],
+    [w, h]: [u16; 2],
+    frame: [u16; 2],
+    rotate: Rotation,
+) -> [u16; 4] {
+    let w = w.min(frame[0].saturating_sub(x));
+    let h = h.min(frame[1].saturating_sub(y));
+    rotate.to_panel([frame[0] - (x + w), y, w, h], frame)
+}
+
+/// The image fitted to a `w` by `h` frame on white, or `None` if it is clipped as it is.
+fn fit_image(img: &GrayImage, [w, h]: [u16; 2], fit: Fit) -> Option<GrayImage> {
+    use image::imageops::{self, FilterType};
+    let (w, h) = (u32::from(w), u32::from(h));
+    let img = match fit {
+        Fit::Scale => {
+            let scale = f64::min(
+                f64::from(w) / f64::from(img.width()),
+                f64::from(h) / f64::from(img.height()),
+            );
+            let size = |x: u32, max: u32| ((f64::from(x) * scale).round() as u32).clamp(1, max);
+            let (sw, sh) = (size(img.width(), w), size(img.height(), h));
+            imageops::resize(img, sw, sh, FilterType::Lanczos3)
+        }
+        Fit::Center => img.clone(),
+        Fit::Clip => return None,
+    };
+    let mut frame = GrayImage::from_pixel(w, h, image::Luma([255]));
+    // negative offsets cut off the edges of a larger image
+    let x = (i64::from(w) - i64::from(img.width())) / 2;
+    let y = (i64::from(h) - i64::from(img.height())) / 2;
+    imageops::overlay(&mut frame, &img, x, y);
+    Some(frame)
+}
+
+fn read_image(file: impl AsRef<Path>) -> Result<GrayImage> {
+    let file = file.as_ref();
+    image::open(file)
+        .into_diagnostic()
+        .wrap_err_with(|| miette!("image path: {}", file.display()))
+        .map(|x| x.into_luma8())
+}
+
+/// A `size` image from pixels packed `bpp` bits each, as a push-raw request sends them.
+fn unpack_raw([w, h]: [u16; 2], bpp: u8, data: Vec<u8>) -> Result<GrayImage> {
+    let (w, h) = (u32::from(w), u32::from(h));
+    let stride = match bpp {
+        4 => w.div_ceil(2),
+        8 => w,
+        _ => return Err(miette!("bpp must be 4 or 8, not {bpp}")),
+    };
+    let len = (stride * h) as usize;
+    if data.len() != len {
+        return Err(miette!(
+            "a {w}x{h} image at {bpp} bpp is {len} bytes, not {}",
+            data.len()
+        ));
+    }
+    if bpp == 8 {
+        return GrayImage::from_raw(w, h, data).ok_or_else(|| miette!("invalid image size"));
+    }
+    Ok(GrayImage::from_fn(w, h, |x, y| {
+        let byte = data[(y * stride + x / 2) as usize];
+        let grey = if x % 2 == 0 { byte >> 4 } else { byte & 0xf };
+        image::Luma([grey * 17])
+    }))
+}
+
+/// The `size` area of the image at `at`, packed a row after another.
+fn pack_rows(img: &GrayImage, [x, y]: [u16; 2], [w, h]: [u16; 2]) -> Vec<u16> {
+    (y..y + h)
+        .flat_map(|y| {
+            let row = (x..x + w).map(move |x| *img.get_pixel(x.into(), y.into()));
+            luma8_pxs_into_packed_u16_vec(row)
+        })
+        .collect()
+}
+
+fn luma8_pxs_into_packed_u16_vec(pxs: impl Iterator<Item = image::Luma<u8>>) -> Vec<u16> {
+    let mut pxs = pxs.collect::<Vec<_>>();
+    pxs.reverse();
+    pxs.chunks(4)
+        .map(|run| {
+            run.iter()
+                .rev()
+                .map(|x| x.0[0] / 16)
+                .fold(0u16, |d, x| d << 4 | x as u16)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpacks_raw_pixels() {
+        let img = unpack_raw([3, 2], 4, vec![0x0f, 0x70, 0x12, 0x30]).unwrap();
+        let px = |x, y| img.get_pixel(x, y).0[0];
+        assert_eq!([px(0, 0), px(1, 0), px(2, 0)], [0, 255, 119]);
+        assert_eq!([px(0, 1), px(1, 1), px(2, 1)], [17, 34, 51]);
+        // repacked as the panel takes them, the 16 greys come out as they went in
+        let packed =
+            luma8_pxs_into_packed_u16_vec([0, 15, 7].map(|x| image::Luma([x * 17])).into_iter());
+        assert_eq!(pack_rows(&img, [0, 0], [3, 1]), packed);
+
+        let img = unpack_raw([2, 1], 8, vec![10, 200]).unwrap();
+        assert_eq!(img.into_raw(), [10, 200]);
+        assert!(unpack_raw([3, 2], 4, vec![0; 3]).is_err());
+        assert!(unpack_raw([3, 2], 2, vec![0; 2]).is_err());
+    }
+
+    #[test]
+    fn fits_images_to_the_frame() {
+        let black = |w, h| GrayImage::from_pixel(w, h, image::Luma([0]));
+        let px = |img: &GrayImage, x, y| img.get_pixel(x, y).0[0];
+
+        // half the height, so letterboxed top and bottom
+        let img = fit_image(&black(4, 2), [8, 8], Fit::Scale).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(
+            [px(&img, 0, 1), px(&img, 4, 4), px(&img, 7, 6)],
+            [255, 0, 255]
+        );
+
+        let img = fit_image(&black(4, 2), [8, 8], Fit::Center).unwrap();
+        assert_eq!(
+            [
+                px(&img, 1, 3),
+                px(&img, 2, 3),
+                px(&img, 5, 4),
+                px(&img, 6, 4)
+            ],
+            [255, 0, 0, 255]
+        );
+        // larger, so cut off around the edges
+        let img = fit_image(&black(10, 10), [8, 4], Fit::Center).unwrap();
+        assert_eq!(img, black(8, 4));
+
+        assert_eq!(fit_image(&black(4, 2), [8, 8], Fit::Clip), None);
+    }
+
+    #[test]
+    fn areas_on_the_panel() {
+        // aligned in the frame
+        let at = [8, 2];
+        let size = [100, 50];
+        assert_eq!(
+            area_on_panel(at, size, [1872, 1404], Rotation::R0),
+            [1764, 2, 100, 50]
+        );
+        // but not on the panel's rows, which are the frame's columns
+        assert_eq!(
+            area_on_panel(at, size, [1404, 1872], Rotation::R90),
+            [1820, 1296, 50, 100]
+        );
+        // clipped to the frame
+        assert_eq!(
+            area_on_panel([1800, 0], size, [1872, 1404], Rotation::R0),
+            [0, 0, 72, 50]
+        );
+    }
+
+    #[test]
+    fn packs_rows_in_order() {
+        let img = GrayImage::from_fn(12, 3, |x, y| image::Luma([((y * 12 + x) % 16 * 16) as u8]));
+        let row = |y: u32| luma8_pxs_into_packed_u16_vec((4..12).map(|x| *img.get_pixel(x, y)));
+        let rows = [row(1), row(2)].concat();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(pack_rows(&img, [4, 1], [8, 2]), rows);
+    }
+}
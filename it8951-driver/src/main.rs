@@ -1,251 +1,399 @@
 use clap::Parser;
 use image::GrayImage;
-use it8951::WaveformMode;
+use it8951_driver::{
+    config::{Fit, Hardware, Intervals, Model},
+    dry_run, pattern,
+    protocol::{self, Command, Pattern, Request, Response, Rotation, Waveform},
+    queue::{Job, Queue},
+    waveshare, Panel,
+};
 use miette::*;
-use std::path::Path;
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long a request can take before the driver is taken to be hung, such as waiting on a busy
+/// pin which never clears, and exits to be started again. Long enough for a request's retries.
+const HUNG_AFTER: Duration = Duration::from_secs(120);
+
+/// When the request being handled started, if there is one.
+static HANDLING: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Where a client's replies are written.
+type Reply = Arc<Mutex<dyn Write + Send>>;
 
 fn main() -> Result<()> {
     let app = App::parse();
 
-    let driver = app.build_driver()?;
-    if app.test {
-        run_test(driver)
+    let hw = app.hardware()?;
+    let mut panel = match (&app.dry_run, hw.panel) {
+        (Some(dir), model) => {
+            let size = app.size.unwrap_or(match model {
+                Model::It8951 => dry_run::SIZE,
+                Model::Epd7in5V2 => waveshare::SIZE,
+            });
+            Panel::dry_run(dir, size, &hw)?
+        }
+        (None, _) => Panel::open(&hw)?,
+    };
+    if let Some(pattern) = app.test {
+        run_test(panel, pattern)
+    } else if let Some(waveform) = app.clear {
+        panel.clear(waveform)
+    } else if let Some(path) = &app.listen {
+        listen(panel, path, hw.min_interval)
     } else {
-        run(driver)
+        run(panel, hw.min_interval)
     }
 }
 
 /// Driver to display an image on `IT8951` devices, such as
 /// https://core-electronics.com.au/waveshare-10-3inch-e-paper-display-hat-for-raspberry-pi-black-white.html
+///
+/// Requests are read from stdin and answered on stdout, a line of JSON each, see the protocol
+/// module. Or from any number of clients over a Unix socket, with `--listen`.
+///
+/// The wiring defaults to the Waveshare HAT, use the flags or `--config` for others. The
+/// Waveshare 7.5" V2, which has no IT8951, is driven with `--panel epd7in5-v2`.
 #[derive(Parser)]
 struct App {
-    /// The SPI device path.
-    #[arg(long, default_value = "/dev/spidev0.0")]
-    spi: String,
+    /// The kind of panel [default: it8951].
+    #[arg(long, value_enum)]
+    panel: Option<Model>,
+
+    /// The SPI device path [default: /dev/spidev0.0].
+    #[arg(long)]
+    spi: Option<String>,
+
+    /// The GPIO device path [default: /dev/gpiochip0].
+    #[arg(long)]
+    gpio: Option<String>,
+
+    /// The GPIO line of the reset pin [default: 17].
+    #[arg(long)]
+    rst_pin: Option<u32>,
+
+    /// The GPIO line of the busy (HRDY) pin [default: 24].
+    #[arg(long)]
+    busy_pin: Option<u32>,
+
+    /// The GPIO line of the data/command pin, of panels without an IT8951 [default: 25].
+    #[arg(long)]
+    dc_pin: Option<u32>,
+
+    /// The SPI clock's maximum speed, in Hz [default: 12000000].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    spi_hz: Option<u32>,
+
+    /// The panel's VCOM voltage in millivolts, as printed on its ribbon cable without the sign,
+    /// -1.67V is 1670 [default: 1670].
+    #[arg(long)]
+    vcom: Option<u16>,
+
+    /// The most rows of an image loaded in one SPI transfer, fewer transfers refresh sooner
+    /// [default: 32].
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    chunk_rows: Option<u16>,
+
+    /// Partial refreshes allowed before one is made a full refresh, to clear the ghosting, 0 for
+    /// no limit [default: 50].
+    #[arg(long, value_name = "PUSHES")]
+    full_every_pushes: Option<u32>,
 
-    /// The GPIO device path.
-    #[arg(long, default_value = "/dev/gpiochip0")]
-    gpio: String,
+    /// The longest allowed between full refreshes, such as `30m`, `0s` for no limit
+    /// [default: 1h].
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    full_every: Option<Duration>,
 
-    /// Run a test routine for checking display is working correctly.
+    /// Attach to the display controller as it is, rather than resetting it, which flashes the
+    /// panel. Falls back to a reset if the controller isn't initialised, such as after a power
+    /// cycle.
     #[arg(long)]
-    test: bool,
+    no_reset: bool,
+
+    /// How far the panel is turned clockwise, `0`, `90`, `180`, or `270`, so frames are pushed
+    /// the way it is hung, such as portrait frames for `90`. A push can override it [default: 0].
+    #[arg(long, value_name = "DEGREES")]
+    rotate: Option<Rotation>,
+
+    /// How an image which isn't the size of a frame is fitted to it: `scale` to fit and centre
+    /// it, `center` it as it is, or `clip` it at the top left [default: scale].
+    #[arg(long, value_enum)]
+    fit: Option<Fit>,
+
+    /// Refresh only with GC16 while the panel is below this temperature in °C, as the faster
+    /// waveforms smear in the cold. Only an IT8951 reports its temperature [default: never].
+    #[arg(long, value_name = "CELSIUS", allow_negative_numbers = true)]
+    cold_below: Option<i16>,
+
+    /// Read the settings not given as flags from this TOML file, such as `vcom = 1500`
+    /// [default: ./it8951.pical.toml, if it exists].
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Run a test routine for checking display is working correctly, or show a test pattern:
+    /// `wedge`, `checkerboard`, `border`, or `crawl`, which steps dots over every pixel.
+    #[arg(long, value_name = "PATTERN", value_enum, num_args = 0..=1)]
+    test: Option<Option<Pattern>>,
+
+    /// Clear the panel to white and exit, such as before unmounting it or overnight. `du` clears
+    /// without flashing [default: gc16].
+    #[arg(long, value_name = "WAVEFORM", value_enum, num_args = 0..=1, default_missing_value = "gc16")]
+    clear: Option<Waveform>,
+
+    /// Serve requests from clients connecting to this Unix socket, such as
+    /// `/run/pical/it8951.sock`, rather than stdin.
+    #[arg(long, value_name = "SOCKET")]
+    listen: Option<PathBuf>,
+
+    /// Write what the panel would show to numbered PNGs in this directory, rather than
+    /// driving a panel, to try the whole pipeline without one.
+    #[arg(long, value_name = "DIR")]
+    dry_run: Option<PathBuf>,
+
+    /// The panel's size with `--dry-run`, such as `1404x1872` [default: 1872x1404].
+    #[arg(long, value_name = "WxH", value_parser = parse_size, requires = "dry_run")]
+    size: Option<[u16; 2]>,
+}
+
+fn parse_size(s: &str) -> Result<[u16; 2], String> {
+    let size = s
+        .split_once('x')
+        .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]));
+    match size {
+        Some([w, h]) if w > 0 && h > 0 => Ok([w, h]),
+        _ => Err(format!("expected a size such as 1872x1404, not {s}")),
+    }
 }
 
 impl App {
-    fn build_driver(&self) -> Result<DriverRun> {
-        use linux_embedded_hal::{gpio_cdev::*, spidev::*, CdevPin, Delay, Spidev};
-        let devspi = &self.spi;
-        println!("ℹ Connecting to {devspi}");
-        let mut spi = Spidev::open(devspi)
-            .into_diagnostic()
-            .wrap_err_with(|| format!("spi path: {devspi}"))?;
-        let opts = SpidevOptions::new()
-            .bits_per_word(8)
-            .max_speed_hz(12_000_000)
-            .mode(SpiModeFlags::SPI_MODE_0)
-            .build();
-        spi.configure(&opts).into_diagnostic()?;
-
-        let devgpio = &self.gpio;
-        let mut chip = Chip::new(devgpio)
-            .into_diagnostic()
-            .wrap_err_with(|| format!("gpio path: {devgpio}"))?;
-        // RST: 17
-        let rst_output = chip.get_line(17).into_diagnostic()?;
-        let rst_output_handle = rst_output
-            .request(LineRequestFlags::OUTPUT, 0, "meeting-room")
-            .into_diagnostic()?;
-        let rst = CdevPin::new(rst_output_handle).into_diagnostic()?;
-        // BUSY / HDRY: 24
-        let busy_input = chip.get_line(24).into_diagnostic()?;
-        let busy_input_handle = busy_input
-            .request(LineRequestFlags::INPUT, 0, "meeting-room")
-            .into_diagnostic()?;
-        let busy = CdevPin::new(busy_input_handle).into_diagnostic()?;
-
-        let driver = it8951::interface::IT8951SPIInterface::new(spi, busy, rst, Delay);
-        /* Disabled no reset for now
-        let x = if self.reset {
-            it8951::IT8951::new(driver).init(1670)
-        } else {
-            it8951::IT8951::attach(driver)
+    /// The settings from the flags, then the config file, then the defaults.
+    fn hardware(&self) -> Result<Hardware> {
+        let mut hw = Hardware::load(self.config.as_deref())?;
+        let Self {
+            panel,
+            spi,
+            gpio,
+            rst_pin,
+            busy_pin,
+            dc_pin,
+            spi_hz,
+            vcom,
+            chunk_rows,
+            full_every_pushes,
+            full_every,
+            no_reset,
+            rotate,
+            fit,
+            cold_below,
+            test: _,
+            clear: _,
+            listen: _,
+            config: _,
+            dry_run: _,
+            size: _,
+        } = self;
+        if let Some(x) = spi {
+            hw.spi = x.clone();
+        }
+        if let Some(x) = gpio {
+            hw.gpio = x.clone();
         }
-        */
-        let x = it8951::IT8951::new(driver)
-            .init(1670)
-            .map_err(|e| miette!("failed to build it8951 driver: {:?}", e))?;
-        println!("✅ Connected to E-Ink Display:\n{:#?}", x.get_dev_info());
-        Ok(Driver { inner: x })
+        hw.panel = panel.unwrap_or(hw.panel);
+        hw.rst_pin = rst_pin.unwrap_or(hw.rst_pin);
+        hw.dc_pin = dc_pin.unwrap_or(hw.dc_pin);
+        hw.busy_pin = busy_pin.unwrap_or(hw.busy_pin);
+        hw.spi_hz = spi_hz.unwrap_or(hw.spi_hz);
+        hw.vcom = vcom.unwrap_or(hw.vcom);
+        hw.chunk_rows = chunk_rows.unwrap_or(hw.chunk_rows);
+        hw.full_every_pushes = full_every_pushes.unwrap_or(hw.full_every_pushes);
+        hw.full_every = full_every.unwrap_or(hw.full_every);
+        hw.reset &= !no_reset;
+        hw.rotate = rotate.unwrap_or(hw.rotate);
+        hw.fit = fit.unwrap_or(hw.fit);
+        hw.cold_below = cold_below.or(hw.cold_below);
+        Ok(hw)
     }
 }
 
-fn run_test(mut driver: DriverRun) -> Result<()> {
-    let img = test_image();
-    driver.push_image(&img, None, WaveformMode::GrayscaleClearing16)?;
-    println!("✅ Display refreshed, you should see your image now!");
-    driver.shutdown()
+fn run_test(mut panel: Panel, pattern: Option<Pattern>) -> Result<()> {
+    match pattern {
+        None => {
+            panel.push(&test_image(), &[], Waveform::Gc16, None)?;
+        }
+        Some(Pattern::Crawl) => {
+            for step in 0..pattern::CRAWL_GAP * pattern::CRAWL_GAP {
+                panel.test_pattern(Pattern::Crawl, step, Waveform::Du)?;
+            }
+        }
+        Some(pattern) => panel.test_pattern(pattern, 0, Waveform::Gc16)?,
+    }
+    Ok(())
 }
 
-fn run(driver: DriverRun) -> Result<()> {
-    let stdin = std::io::stdin();
-    let mut line = String::new();
-    let mut driver = driver.sleep()?;
-
+/// Exit if a request has been handled for longer than [`HUNG_AFTER`], as retrying can't get
+/// over a call which never returns.
+fn watchdog() {
     loop {
-        line.clear();
-        println!(
-            "🔤 Please specifiy <IMAGE> [--high|--low|--reset] [<DIFF IMAGE>] path(s) to render"
-        );
-        stdin.read_line(&mut line).into_diagnostic()?;
-        let (img, quality, diff) = parse_line(line.trim())?;
-        let img = read_image(img)?;
-        let mut diff = diff.map(read_image).transpose()?;
-        let mut d = driver.wake()?;
-        let mode = match quality {
-            Quality::Reset => {
-                diff = None;
-                d.reset()?;
-                WaveformMode::GrayscaleClearing16
-            }
-            Quality::High => WaveformMode::GrayscaleClearing16,
-            Quality::Low => WaveformMode::DU4,
-        };
-        d.push_image(&img, diff.as_ref(), mode)?;
-        driver = d.sleep()?;
-        println!("✅ Display refreshed, you should see your image now!");
+        std::thread::sleep(Duration::from_secs(1));
+        let since = *HANDLING.lock().expect("watchdog lock poisoned");
+        if since.is_some_and(|x| x.elapsed() > HUNG_AFTER) {
+            eprintln!(
+                "❌ Hung handling a request for {}, exiting",
+                humantime::format_duration(HUNG_AFTER)
+            );
+            std::process::exit(1);
+        }
     }
 }
 
-enum Quality {
-    Reset,
-    High,
-    Low,
+/// Serve requests from stdin, until it closes.
+fn run(panel: Panel, intervals: Intervals) -> Result<()> {
+    std::thread::spawn(watchdog);
+    let queue = Queue::new(intervals);
+    let tx: Reply = Arc::new(Mutex::new(std::io::stdout()));
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let res = read_requests(&queue, std::io::stdin().lock(), &tx);
+            queue.close();
+            res
+        });
+        drive(panel, &queue);
+        reader.join().expect("reader thread panicked")
+    })
 }
 
-fn parse_line(line: &str) -> Result<(&Path, Quality, Option<&Path>)> {
-    let mut split = line.split_whitespace();
-    let img = split
-        .next()
-        .map(Path::new)
-        .ok_or_else(|| miette!("no image path given"))?;
-    let mut quality = Quality::High;
-    let mut diff = split.next();
-    if diff.as_deref() == Some("--reset") {
-        quality = Quality::Reset;
-        diff = split.next();
-    } else if diff.as_deref() == Some("--high") {
-        quality = Quality::High;
-        diff = split.next();
-    } else if diff.as_deref() == Some("--low") {
-        quality = Quality::Low;
-        diff = split.next();
+/// Serve requests from clients connecting to the socket at `path`, each read on a thread of its
+/// own.
+///
+/// Requests are handled one at a time, so clients take turns with the panel.
+fn listen(panel: Panel, path: &Path, intervals: Intervals) -> Result<()> {
+    std::thread::spawn(watchdog);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to create {}", dir.display()))?;
     }
+    // left behind by a driver which didn't exit cleanly
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to listen on {}", path.display()))?;
+    eprintln!("👂 Listening on {}", path.display());
 
-    Ok((img, quality, diff.map(Path::new)))
+    let queue = Queue::new(intervals);
+    std::thread::scope(|scope| {
+        let queue = &queue;
+        scope.spawn(move || {
+            for stream in listener.incoming() {
+                let (rx, tx) = match stream.and_then(|x| Ok((x.try_clone()?, x))) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("⚠ Failed to accept a client: {e}");
+                        continue;
+                    }
+                };
+                let tx: Reply = Arc::new(Mutex::new(tx));
+                scope.spawn(move || {
+                    if let Err(e) = read_requests(queue, BufReader::new(rx), &tx) {
+                        eprintln!("⚠ Client disconnected: {e:?}");
+                    }
+                });
+            }
+        });
+        drive(panel, queue);
+        Ok(())
+    })
 }
 
-struct Driver<State> {
-    inner: it8951::IT8951<
-        it8951::interface::IT8951SPIInterface<
-            linux_embedded_hal::Spidev,
-            linux_embedded_hal::CdevPin,
-            linux_embedded_hal::CdevPin,
-            linux_embedded_hal::Delay,
-        >,
-        State,
-    >,
+/// Handle the queued requests one at a time, until the queue is closed.
+///
+/// Exits if the panel is given up on, to be started again by pical or the service, resetting
+/// the panel.
+fn drive(mut panel: Panel, queue: &Queue<Reply>) {
+    while let Some(Job { id, command, reply }) = queue.next() {
+        *HANDLING.lock().expect("watchdog lock poisoned") = Some(Instant::now());
+        let mut res = panel.handle(id, command);
+        *HANDLING.lock().expect("watchdog lock poisoned") = None;
+        if let Some(status) = &mut res.status {
+            status.dropped = queue.dropped();
+        }
+        if let Err(e) = answer(&reply, &res) {
+            eprintln!("⚠ Failed to answer request {id}: {e:?}");
+        }
+        if panel.is_lost() {
+            eprintln!("❌ Gave up on the panel, exiting");
+            std::process::exit(1);
+        }
+    }
 }
 
-type DriverRun = Driver<it8951::Run>;
-
-impl Driver<it8951::Run> {
-    fn push_image(
-        &mut self,
-        img: &GrayImage,
-        diff: Option<&GrayImage>,
-        mode: WaveformMode,
-    ) -> Result<()> {
-        use it8951::memory_converter_settings::*;
-        let it8951::DevInfo {
-            panel_width,
-            panel_height,
-            memory_address,
-            ..
-        } = self.inner.get_dev_info();
-        let cnvtr = || MemoryConverterSetting {
-            endianness: MemoryConverterEndianness::LittleEndian,
-            bit_per_pixel: MemoryConverterBitPerPixel::BitsPerPixel4,
-            rotation: MemoryConverterRotation::Rotate0,
+/// Queue the requests read from `rx`, until it closes, answering on `tx`.
+///
+/// The frames a request drops are answered as soon as it is queued, whichever client sent them.
+fn read_requests(queue: &Queue<Reply>, mut rx: impl BufRead, tx: &Reply) -> Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if rx.read_line(&mut line).into_diagnostic()? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, mut command) = match serde_json::from_str::<Request>(&line) {
+            Ok(Request { id, command }) => (id, command),
+            Err(e) => {
+                read_data(&mut rx, protocol::data_len(&line))?;
+                let e = miette!("invalid request: {e}");
+                answer(tx, &Response::error(protocol::request_id(&line), &e))?;
+                continue;
+            }
         };
-
-        println!(
-            "ℹ Pushing {}x{} image to display buffer",
-            img.width(),
-            img.height()
-        );
-
-        for (i, row) in enumerate_different_rows(img, diff).take(panel_height as usize) {
-            let area = it8951::AreaImgInfo {
-                area_x: 0,
-                area_y: i as u16, // row index
-                area_w: panel_width,
-                area_h: 1,
+        let pixels = read_data(&mut rx, command.data_len())?;
+        if let Command::PushRaw { data, .. } = &mut command {
+            *data = pixels;
+        }
+        let reply = tx.clone();
+        for x in queue.push(Job { id, command, reply }) {
+            eprintln!("ℹ Dropped frame {} for a newer one", x.id);
+            let res = Response {
+                dropped: true,
+                ..Response::ok(x.id)
             };
-            let data =
-                luma8_pxs_into_packed_u16_vec(row.take(panel_width as usize).map(|(_, _, px)| *px));
-            self.inner
-                .load_image_area(memory_address, cnvtr(), &area, &data)
-                .map_err(|e| miette!("failed to write image row to memory: {:?}", e))?;
+            if let Err(e) = answer(&x.reply, &res) {
+                eprintln!("⚠ Failed to answer request {}: {e:?}", x.id);
+            }
         }
-
-        println!("✅ Buffer updated!");
-
-        self.inner
-            .display(mode)
-            .map_err(|e| miette!("failed to display image buffer: {:?}", e))
-    }
-
-    fn reset(&mut self) -> Result<()> {
-        self.inner
-            .reset()
-            .map_err(|e| miette!("failed to reset screen: {:?}", e))
-    }
-
-    fn sleep(self) -> Result<Driver<it8951::PowerDown>> {
-        self.inner
-            .sleep()
-            .map_err(|e| miette!("failed to sleep device: {:?}", e))
-            .map(|inner| Driver { inner })
-    }
-
-    fn shutdown(self) -> Result<()> {
-        self.inner
-            .sleep()
-            .map_err(|e| miette!("failed to sleep device: {:?}", e))
-            .map(|_| ())
     }
 }
 
-impl Driver<it8951::PowerDown> {
-    fn wake(self) -> Result<Driver<it8951::Run>> {
-        self.inner
-            .sys_run()
-            .map_err(|e| miette!("failed to wake device: {:?}", e))
-            .map(|inner| Driver { inner })
-    }
+/// Write the response as a line of JSON.
+fn answer(tx: &Mutex<dyn Write + Send>, res: &Response) -> Result<()> {
+    let json = serde_json::to_string(res).into_diagnostic()?;
+    let mut tx = tx.lock().expect("reply lock poisoned");
+    writeln!(tx, "{json}").into_diagnostic()?;
+    tx.flush().into_diagnostic()
 }
 
-fn enumerate_different_rows<'a>(
-    img: &'a GrayImage,
-    diff: Option<&'a GrayImage>,
-) -> impl Iterator<Item = (u32, image::buffer::EnumeratePixels<'a, image::Luma<u8>>)> {
-    let mut diff = diff.into_iter().flat_map(|x| x.rows());
-    img.enumerate_rows()
-        .filter(move |(_, r)| match diff.next() {
-            Some(d) => !r.clone().map(|(_, _, p)| p).eq(d),
-            None => true,
-        })
+/// Read the `len` bytes following a request's line.
+fn read_data(rx: &mut impl BufRead, len: usize) -> Result<Vec<u8>> {
+    if len > protocol::MAX_LEN {
+        // what follows can't be told apart from the pixels, so the client is dropped
+        return Err(miette!(
+            "a request of {len} bytes is over the most of {}",
+            protocol::MAX_LEN
+        ));
+    }
+    let mut data = vec![0; len];
+    rx.read_exact(&mut data)
+        .into_diagnostic()
+        .wrap_err("failed to read the request's pixels")?;
+    Ok(data)
 }
 
 fn test_image() -> GrayImage {
@@ -253,24 +401,3 @@ fn test_image() -> GrayImage {
         .expect("valid PNG file")
         .into_luma8()
 }
-
-fn read_image(file: impl AsRef<Path>) -> Result<GrayImage> {
-    let file = file.as_ref();
-    image::open(file)
-        .into_diagnostic()
-        .wrap_err_with(|| miette!("image path: {}", file.display()))
-        .map(|x| x.into_luma8())
-}
-
-fn luma8_pxs_into_packed_u16_vec(pxs: impl Iterator<Item = image::Luma<u8>>) -> Vec<u16> {
-    let mut pxs = pxs.collect::<Vec<_>>();
-    pxs.reverse();
-    pxs.chunks(4)
-        .map(|run| {
-            run.iter()
-                .rev()
-                .map(|x| x.0[0] / 16)
-                .fold(0u16, |d, x| d << 4 | x as u16)
-        })
-        .collect()
-}
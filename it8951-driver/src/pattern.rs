@@ -0,0 +1,90 @@
+//! Test patterns drawn by the driver, to check the panel without making images on the host.
+//!
+//! A wedge of the 16 greys shows whether the VCOM is right, washed out or crushed greys
+//! meaning it isn't. A checkerboard, a border around the edges, and a crawl of dots stepped
+//! over every pixel show up dead lines and a loose ribbon cable.
+use crate::protocol::Pattern;
+use image::{GrayImage, Luma};
+
+/// Pixels between the dots of a crawl, so it covers the panel in `CRAWL_GAP²` steps.
+pub const CRAWL_GAP: u32 = 8;
+
+/// The side of a checkerboard's squares.
+const SQUARE: u32 = 32;
+
+const BLACK: Luma<u8> = Luma([0]);
+const WHITE: Luma<u8> = Luma([255]);
+
+/// The `pattern` on a `w` by `h` frame, the `step`th of a crawl.
+pub fn draw(pattern: Pattern, [w, h]: [u16; 2], step: u32) -> GrayImage {
+    let (w, h) = (u32::from(w), u32::from(h));
+    match pattern {
+        // black on the left to white on the right
+        Pattern::Wedge => GrayImage::from_fn(w, h, |x, _| Luma([(x * 16 / w) as u8 * 17])),
+        Pattern::Checkerboard => {
+            GrayImage::from_fn(w, h, |x, y| match (x / SQUARE + y / SQUARE) % 2 {
+                0 => BLACK,
+                _ => WHITE,
+            })
+        }
+        // the outermost pixels, and a line 8 in from them
+        Pattern::Border => GrayImage::from_fn(w, h, |x, y| {
+            let edge = x.min(y).min(w - 1 - x).min(h - 1 - y);
+            match edge {
+                0 | 8 => BLACK,
+                _ => WHITE,
+            }
+        }),
+        Pattern::Crawl => {
+            let step = step % (CRAWL_GAP * CRAWL_GAP);
+            let (dx, dy) = (step % CRAWL_GAP, step / CRAWL_GAP);
+            GrayImage::from_fn(w, h, |x, y| {
+                match x % CRAWL_GAP == dx && y % CRAWL_GAP == dy {
+                    true => BLACK,
+                    false => WHITE,
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_patterns() {
+        let px = |img: &GrayImage, x, y| img.get_pixel(x, y).0[0];
+
+        let img = draw(Pattern::Wedge, [32, 4], 0);
+        let greys = (0..16).map(|i| px(&img, i * 2, 3)).collect::<Vec<_>>();
+        assert_eq!(greys, (0..16).map(|i| i * 17).collect::<Vec<u8>>());
+
+        let img = draw(Pattern::Checkerboard, [64, 64], 0);
+        assert_eq!(
+            [px(&img, 0, 0), px(&img, 32, 0), px(&img, 32, 32)],
+            [0, 255, 0]
+        );
+
+        let img = draw(Pattern::Border, [20, 20], 0);
+        assert_eq!(
+            [
+                px(&img, 0, 5),
+                px(&img, 19, 19),
+                px(&img, 8, 10),
+                px(&img, 1, 1)
+            ],
+            [0, 0, 0, 255]
+        );
+
+        // every pixel is black once over a crawl
+        let mut hits = vec![0; 16 * 16];
+        for step in 0..CRAWL_GAP * CRAWL_GAP {
+            let img = draw(Pattern::Crawl, [16, 16], step);
+            for (x, y, p) in img.enumerate_pixels() {
+                hits[(y * 16 + x) as usize] += u32::from(p.0[0] == 0);
+            }
+        }
+        assert!(hits.iter().all(|&x| x == 1));
+    }
+}
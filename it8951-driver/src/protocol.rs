@@ -0,0 +1,531 @@
+//! The requests read from stdin and the responses written to stdout, a line of JSON each.
+//!
+//! Every request has an `id` which its response repeats, so the caller knows what was done:
+//!
+//! ```text
+//! {"id":1,"cmd":"push","image":"./frame.bmp","waveform":"du4","areas":[{"x":0,"y":0,"w":200,"h":60}]}
+//! {"id":1,"ok":true}
+//! {"id":2,"cmd":"status"}
+//! {"id":2,"ok":true,"status":{"width":1872,"height":1404,"firmware":"...","lut":"...","rotate":0,"power":"asleep","pushes":1,"full_refreshes":1,"failures":0,"retries":0,"resets":0,"dropped":0,"uptime_secs":42,"temperature":24}}
+//! {"id":3,"cmd":"push-area","image":"./clock.bmp","x":1600,"y":20,"waveform":"a2"}
+//! {"id":3,"ok":true}
+//! {"id":4,"cmd":"clear"}
+//! {"id":4,"ok":false,"error":"failed 3 times driving the panel: failed to display image buffer: Spi","recovery":"fatal"}
+//! {"id":5,"cmd":"push-raw","width":1872,"height":1404,"bpp":4,"len":1314144}
+//! <1314144 bytes of pixels>
+//! {"id":5,"ok":true}
+//! {"id":6,"cmd":"push","image":"./small.bmp"}
+//! {"id":6,"ok":true,"warning":"the 800x480 image was scaled to fit the 1872x1404 frame"}
+//! {"id":7,"cmd":"test","pattern":"crawl","step":3,"waveform":"du"}
+//! {"id":7,"ok":true}
+//! ```
+//!
+//! Frames are queued for the panel, and one still waiting when a newer one covering it comes,
+//! such as another whole frame, is dropped and answered with `"dropped":true`. A refresh waits
+//! for its waveform's `min_interval` since the last, so a client pushing faster than that has
+//! only its most recent frame shown. A GC16 frame is only dropped for another GC16 one.
+//!
+//! A response has a `recovery` if driving the panel failed, `recovered` if trying again or
+//! resetting the controller got over it, or `fatal` if the panel was given up on and the driver
+//! is exiting to be started again.
+//!
+//! A line which isn't a request is answered with a `null` id, skipping its `len` bytes if it
+//! has them. Anything else the driver has to say goes to stderr.
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "kebab-case")]
+pub enum Command {
+    /// Show the image at `image`, only the `areas` of it if any are given.
+    Push {
+        image: PathBuf,
+        #[serde(default)]
+        waveform: Waveform,
+        #[serde(default)]
+        areas: Vec<Area>,
+        /// Overrides the driver's rotation for this push.
+        #[serde(default)]
+        rotate: Option<Rotation>,
+    },
+    /// Show the `len` bytes of pixels following the request's line, a `width` by `height` image
+    /// packed a row after another, so frames needn't be written to a file. With a `bpp` of 4,
+    /// two pixels to a byte, the first in the high bits, from 0 for black to 15 for white, and
+    /// rows start on a byte. With a `bpp` of 8, a byte a pixel.
+    PushRaw {
+        width: u16,
+        height: u16,
+        bpp: u8,
+        len: usize,
+        #[serde(default)]
+        waveform: Waveform,
+        #[serde(default)]
+        areas: Vec<Area>,
+        #[serde(default)]
+        rotate: Option<Rotation>,
+        /// Read after the request's line.
+        #[serde(skip)]
+        data: Vec<u8>,
+    },
+    /// Show the image at `image` with its top left at `x`, `y`, refreshing only that area, such
+    /// as just the clock. The area's x and width on the panel must be multiples of 4, which are
+    /// the frame's y and height when it is turned 90° or 270°.
+    PushArea {
+        image: PathBuf,
+        x: u16,
+        y: u16,
+        #[serde(default)]
+        waveform: Waveform,
+        #[serde(default)]
+        rotate: Option<Rotation>,
+    },
+    /// Clear the panel to white, by default with a flashing refresh, or `du` to clear text
+    /// without flashing.
+    Clear {
+        #[serde(default)]
+        waveform: Waveform,
+    },
+    /// Show a test `pattern` drawn by the driver, to check the panel and its cable. `step` picks
+    /// which of a crawl's dots are shown.
+    Test {
+        pattern: Pattern,
+        #[serde(default)]
+        step: u32,
+        #[serde(default)]
+        waveform: Waveform,
+    },
+    /// Leave the panel powered down between requests, as it is by default, such as overnight.
+    Sleep,
+    /// Leave the panel in standby between requests, quicker to wake than asleep.
+    Standby,
+    /// Keep the panel running between requests, for the quickest refreshes, until `sleep` or
+    /// `standby`.
+    Wake,
+    /// Reply with the panel's details and the driver's counters.
+    Status,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Waveform {
+    /// Black and white only, the fastest.
+    A2,
+    /// Black and white only, without flashing.
+    Du,
+    /// Four greys, without flashing.
+    Du4,
+    /// All 16 greys, without flashing.
+    Gl16,
+    /// All 16 greys, flashing to clear any ghosting.
+    #[default]
+    Gc16,
+}
+
+impl From<Waveform> for it8951::WaveformMode {
+    fn from(x: Waveform) -> Self {
+        match x {
+            Waveform::A2 => Self::A2,
+            Waveform::Du => Self::DirectUpdate,
+            Waveform::Du4 => Self::DU4,
+            Waveform::Gl16 => Self::GL16,
+            Waveform::Gc16 => Self::GrayscaleClearing16,
+        }
+    }
+}
+
+/// A test pattern the driver draws itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pattern {
+    /// The 16 greys in bands from black to white, washed out or crushed if the VCOM is wrong.
+    Wedge,
+    /// Black and white squares.
+    Checkerboard,
+    /// Lines around the edges of the panel, broken if the cable is loose.
+    Border,
+    /// Dots a few pixels apart, stepped over every pixel to find dead ones.
+    Crawl,
+}
+
+/// How far the panel is turned clockwise from its natural landscape, `0`, `90`, `180`, or `270`.
+///
+/// The display controller rotates images as it loads them, so a portrait frame can be pushed
+/// as is.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u16")]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = String;
+
+    fn try_from(x: u16) -> Result<Self, Self::Error> {
+        match x {
+            0 => Ok(Self::R0),
+            90 => Ok(Self::R90),
+            180 => Ok(Self::R180),
+            270 => Ok(Self::R270),
+            x => Err(format!("rotation must be 0, 90, 180, or 270, not {x}")),
+        }
+    }
+}
+
+impl std::str::FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u16>().map_err(|e| e.to_string())?.try_into()
+    }
+}
+
+impl Rotation {
+    pub fn degrees(self) -> u16 {
+        match self {
+            Self::R0 => 0,
+            Self::R90 => 90,
+            Self::R180 => 180,
+            Self::R270 => 270,
+        }
+    }
+
+    /// The size of a frame for a panel of `size`.
+    pub fn frame(self, [w, h]: [u16; 2]) -> [u16; 2] {
+        match self {
+            Self::R0 | Self::R180 => [w, h],
+            Self::R90 | Self::R270 => [h, w],
+        }
+    }
+
+    /// Where the `[x, y, w, h]` area of a `frame` sized frame loaded with this rotation ends up
+    /// in the panel's memory.
+    pub fn to_panel(self, [x, y, w, h]: [u16; 4], [fw, fh]: [u16; 2]) -> [u16; 4] {
+        match self {
+            Self::R0 => [x, y, w, h],
+            Self::R90 => [fh - y - h, x, h, w],
+            Self::R180 => [fw - x - w, fh - y - h, w, h],
+            Self::R270 => [y, fw - x - w, h, w],
+        }
+    }
+}
+
+impl From<Rotation> for it8951::memory_converter_settings::MemoryConverterRotation {
+    fn from(x: Rotation) -> Self {
+        match x {
+            Rotation::R0 => Self::Rotate0,
+            Rotation::R90 => Self::Rotate90,
+            Rotation::R180 => Self::Rotate180,
+            Rotation::R270 => Self::Rotate270,
+        }
+    }
+}
+
+/// An area of the image to update, in image pixels.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Area {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Response {
+    /// The request's, or `None` if the line couldn't be read as one.
+    pub id: Option<u64>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    /// Something the caller should fix though the request succeeded, such as an image which
+    /// isn't the size of a frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<Recovery>,
+    /// The frame wasn't shown, as a newer one came before the panel got to it.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dropped: bool,
+}
+
+impl Response {
+    pub fn ok(id: u64) -> Self {
+        Self {
+            id: Some(id),
+            ok: true,
+            error: None,
+            status: None,
+            warning: None,
+            recovery: None,
+            dropped: false,
+        }
+    }
+
+    /// The error and its causes, on one line.
+    pub fn error(id: Option<u64>, e: &miette::Report) -> Self {
+        let error = e.chain().map(|x| x.to_string()).collect::<Vec<_>>();
+        Self {
+            id,
+            ok: false,
+            error: Some(error.join(": ")),
+            status: None,
+            warning: None,
+            recovery: None,
+            dropped: false,
+        }
+    }
+}
+
+/// How a request got over failing to drive the panel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Recovery {
+    /// Tried again, or after resetting the controller, and handled.
+    Recovered,
+    /// The panel was given up on, and the driver exits to be started again.
+    Fatal,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Status {
+    pub width: u16,
+    pub height: u16,
+    pub firmware: String,
+    pub lut: String,
+    /// The driver's rotation, `width` and `height` are the size of a frame in it.
+    pub rotate: u16,
+    pub power: Power,
+    /// Pushes since the driver started.
+    pub pushes: u64,
+    /// Refreshes of the whole panel with GC16 since the driver started.
+    pub full_refreshes: u64,
+    /// Requests which failed since the driver started.
+    pub failures: u64,
+    /// Tries at driving the panel which failed and were tried again.
+    pub retries: u64,
+    /// Resets of the controller after repeated failures.
+    pub resets: u64,
+    /// Frames dropped for newer ones.
+    pub dropped: u64,
+    pub uptime_secs: u64,
+    /// The panel's temperature in °C when it was last read, only an IT8951 reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<i16>,
+}
+
+/// The panel's power between requests, as `sleep`, `standby`, or `wake` left it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Power {
+    Asleep,
+    Standby,
+    Awake,
+    /// Given up on after driving it failed even after a reset, so it needs opening again.
+    Lost,
+}
+
+/// The most bytes of pixels a request can have, over a 4K panel at 8 bits a pixel.
+pub const MAX_LEN: usize = 16 << 20;
+
+impl Command {
+    /// The bytes of pixels following the request's line.
+    pub fn data_len(&self) -> usize {
+        match self {
+            Command::PushRaw { len, .. } => *len,
+            _ => 0,
+        }
+    }
+}
+
+/// The id of a line which isn't a valid request, if it has one, to answer with.
+pub fn request_id(line: &str) -> Option<u64> {
+    #[derive(Deserialize)]
+    struct Id {
+        id: u64,
+    }
+    serde_json::from_str::<Id>(line).ok().map(|x| x.id)
+}
+
+/// The bytes following a line which isn't a valid request, if it says, to skip.
+pub fn data_len(line: &str) -> usize {
+    #[derive(Deserialize)]
+    struct Len {
+        len: usize,
+    }
+    serde_json::from_str::<Len>(line).map_or(0, |x| x.len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let req = serde_json::from_str::<Request>(
+            r#"{"id":4,"cmd":"push","image":"./frame.bmp","waveform":"du4","areas":[{"x":0,"y":8,"w":200,"h":60}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            req,
+            Request {
+                id: 4,
+                command: Command::Push {
+                    image: "./frame.bmp".into(),
+                    waveform: Waveform::Du4,
+                    areas: vec![Area {
+                        x: 0,
+                        y: 8,
+                        w: 200,
+                        h: 60
+                    }],
+                    rotate: None,
+                },
+            }
+        );
+        let req = serde_json::from_str::<Request>(r#"{"id":5,"cmd":"push","image":"a.bmp"}"#);
+        assert!(matches!(
+            req.unwrap().command,
+            Command::Push {
+                waveform: Waveform::Gc16,
+                ..
+            }
+        ));
+
+        let req = r#"{"id":6,"cmd":"push-area","image":"clock.bmp","x":1600,"y":20,"waveform":"a2","rotate":90}"#;
+        assert_eq!(
+            serde_json::from_str::<Request>(req).unwrap().command,
+            Command::PushArea {
+                image: "clock.bmp".into(),
+                x: 1600,
+                y: 20,
+                waveform: Waveform::A2,
+                rotate: Some(Rotation::R90),
+            }
+        );
+        let req = r#"{"id":7,"cmd":"push","image":"a.bmp","rotate":45}"#;
+        assert!(serde_json::from_str::<Request>(req).is_err());
+
+        let req = r#"{"id":8,"cmd":"clear"}"#;
+        assert_eq!(
+            serde_json::from_str::<Request>(req).unwrap().command,
+            Command::Clear {
+                waveform: Waveform::Gc16
+            }
+        );
+        let req = r#"{"id":9,"cmd":"clear","waveform":"du"}"#;
+        assert_eq!(
+            serde_json::from_str::<Request>(req).unwrap().command,
+            Command::Clear {
+                waveform: Waveform::Du
+            }
+        );
+        let req = r#"{"id":9,"cmd":"test","pattern":"wedge"}"#;
+        assert_eq!(
+            serde_json::from_str::<Request>(req).unwrap().command,
+            Command::Test {
+                pattern: Pattern::Wedge,
+                step: 0,
+                waveform: Waveform::Gc16
+            }
+        );
+
+        let req = r#"{"id":10,"cmd":"push-raw","width":3,"height":2,"bpp":4,"len":4}"#;
+        let req = serde_json::from_str::<Request>(req).unwrap();
+        assert_eq!(req.command.data_len(), 4);
+        assert!(matches!(
+            req.command,
+            Command::PushRaw {
+                width: 3,
+                height: 2,
+                bpp: 4,
+                waveform: Waveform::Gc16,
+                ref data,
+                ..
+            } if data.is_empty()
+        ));
+
+        let bad = r#"{"id":6,"cmd":"dance"}"#;
+        assert!(serde_json::from_str::<Request>(bad).is_err());
+        assert_eq!(request_id(bad), Some(6));
+        assert_eq!(data_len(bad), 0);
+        let bad = r#"{"id":11,"cmd":"push-raw","width":3,"len":4}"#;
+        assert!(serde_json::from_str::<Request>(bad).is_err());
+        assert_eq!(data_len(bad), 4);
+        assert_eq!(request_id("./frame.bmp --high"), None);
+    }
+
+    #[test]
+    fn rotated_areas() {
+        // a portrait frame on a 1872x1404 panel
+        let frame = Rotation::R90.frame([1872, 1404]);
+        assert_eq!(frame, [1404, 1872]);
+        let clock = [1204, 20, 180, 60];
+        assert_eq!(Rotation::R90.to_panel(clock, frame), [1792, 1204, 60, 180]);
+        assert_eq!(Rotation::R270.to_panel(clock, frame), [20, 20, 60, 180]);
+        let frame = Rotation::R180.frame([1872, 1404]);
+        assert_eq!(Rotation::R180.to_panel(clock, frame), [488, 1324, 180, 60]);
+        assert_eq!(Rotation::R0.to_panel(clock, frame), clock);
+        assert_eq!("270".parse(), Ok(Rotation::R270));
+        assert!("45".parse::<Rotation>().is_err());
+    }
+
+    #[test]
+    fn responses() {
+        let json = |x| serde_json::to_string(&x).unwrap();
+        assert_eq!(json(Response::ok(1)), r#"{"id":1,"ok":true}"#);
+        let status = Status {
+            width: 1404,
+            height: 1872,
+            firmware: "SWv_0.1.1".into(),
+            lut: "M841".into(),
+            rotate: 90,
+            power: Power::Lost,
+            pushes: 3,
+            full_refreshes: 1,
+            failures: 1,
+            retries: 2,
+            resets: 1,
+            dropped: 4,
+            uptime_secs: 60,
+            temperature: Some(-2),
+        };
+        assert_eq!(
+            json(Response {
+                status: Some(status),
+                ..Response::ok(2)
+            }),
+            r#"{"id":2,"ok":true,"status":{"width":1404,"height":1872,"firmware":"SWv_0.1.1","lut":"M841","rotate":90,"power":"lost","pushes":3,"full_refreshes":1,"failures":1,"retries":2,"resets":1,"dropped":4,"uptime_secs":60,"temperature":-2}}"#
+        );
+        let e = miette::miette!("Spi").wrap_err("failed to display image buffer");
+        assert_eq!(
+            json(Response::error(None, &e)),
+            r#"{"id":null,"ok":false,"error":"failed to display image buffer: Spi"}"#
+        );
+        assert_eq!(
+            json(Response {
+                recovery: Some(Recovery::Fatal),
+                ..Response::error(Some(4), &e)
+            }),
+            r#"{"id":4,"ok":false,"error":"failed to display image buffer: Spi","recovery":"fatal"}"#
+        );
+        assert_eq!(
+            json(Response {
+                dropped: true,
+                ..Response::ok(5)
+            }),
+            r#"{"id":5,"ok":true,"dropped":true}"#
+        );
+    }
+}
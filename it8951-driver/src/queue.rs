@@ -0,0 +1,300 @@
+//! The requests waiting for the panel, handled one at a time in the order they came.
+//!
+//! A client pushing faster than the panel refreshes would otherwise have its frames pile up.
+//! A frame waiting in the queue is dropped when a newer one covers it, so only the most recent
+//! is shown, and a refresh waits until the last is at least its waveform's minimum interval
+//! ago, giving newer frames the chance to replace it. Requests which don't refresh, such as
+//! `status`, go ahead of a refresh waiting for its interval.
+use crate::{
+    config::Intervals,
+    protocol::{Command, Waveform},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::Instant,
+};
+
+/// A request, with where to send its reply.
+pub struct Job<R> {
+    pub id: u64,
+    pub command: Command,
+    pub reply: R,
+}
+
+/// The requests waiting for the panel, shared between the clients and the thread driving it.
+pub struct Queue<R> {
+    jobs: Mutex<Jobs<R>>,
+    ready: Condvar,
+}
+
+impl<R> Queue<R> {
+    pub fn new(intervals: Intervals) -> Self {
+        Self {
+            jobs: Mutex::new(Jobs::new(intervals)),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Add a request, returning the frames it drops, to be answered as such.
+    pub fn push(&self, job: Job<R>) -> Vec<Job<R>> {
+        let dropped = self.lock().push(job);
+        self.ready.notify_one();
+        dropped
+    }
+
+    /// Wait for the next request the panel can handle, or `None` once the queue is closed and
+    /// empty.
+    pub fn next(&self) -> Option<Job<R>> {
+        let mut jobs = self.lock();
+        loop {
+            let next = jobs.take(Instant::now());
+            jobs = match next {
+                Next::Job(x) => return Some(x),
+                Next::Empty if jobs.closed => return None,
+                Next::Empty => self.ready.wait(jobs).expect("queue lock poisoned"),
+                // woken early by a newer frame, which may drop the one waiting
+                Next::Wait(until) => {
+                    let wait = until.saturating_duration_since(Instant::now());
+                    let jobs = self.ready.wait_timeout(jobs, wait);
+                    jobs.expect("queue lock poisoned").0
+                }
+            };
+        }
+    }
+
+    /// Take no more requests, [`Queue::next`] returning `None` once those waiting are handled.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+
+    /// Frames dropped for newer ones since the driver started.
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Jobs<R>> {
+        self.jobs.lock().expect("queue lock poisoned")
+    }
+}
+
+enum Next<R> {
+    Job(Job<R>),
+    /// The next is a refresh too soon after the last.
+    Wait(Instant),
+    Empty,
+}
+
+struct Jobs<R> {
+    waiting: VecDeque<Job<R>>,
+    intervals: Intervals,
+    last_refresh: Option<Instant>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl<R> Jobs<R> {
+    fn new(intervals: Intervals) -> Self {
+        Self {
+            waiting: VecDeque::new(),
+            intervals,
+            last_refresh: None,
+            dropped: 0,
+            closed: false,
+        }
+    }
+
+    fn push(&mut self, job: Job<R>) -> Vec<Job<R>> {
+        let (dropped, kept): (Vec<_>, VecDeque<_>) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|x| covers(&job.command, &x.command));
+        self.waiting = kept;
+        self.waiting.push_back(job);
+        self.dropped += dropped.len() as u64;
+        dropped
+    }
+
+    fn take(&mut self, now: Instant) -> Next<R> {
+        let Some(job) = self.waiting.front() else {
+            return Next::Empty;
+        };
+        if let Some(waveform) = refresh(&job.command) {
+            let due = self.last_refresh.map(|x| x + self.intervals.of(waveform));
+            if let Some(due) = due.filter(|&x| x > now) {
+                // nothing to wait for in a request which doesn't refresh
+                return self
+                    .waiting
+                    .iter()
+                    .position(|x| refresh(&x.command).is_none())
+                    .and_then(|i| self.waiting.remove(i))
+                    .map_or(Next::Wait(due), Next::Job);
+            }
+            self.last_refresh = Some(now);
+        }
+        self.waiting.pop_front().map_or(Next::Empty, Next::Job)
+    }
+}
+
+/// The waveform of a request which refreshes the panel.
+fn refresh(command: &Command) -> Option<Waveform> {
+    match command {
+        Command::Push { waveform, .. }
+        | Command::PushRaw { waveform, .. }
+        | Command::PushArea { waveform, .. }
+        | Command::Clear { waveform }
+        | Command::Test { waveform, .. } => Some(*waveform),
+        Command::Sleep | Command::Standby | Command::Wake | Command::Status => None,
+    }
+}
+
+/// Whether the frame `new` shows everything `old` would, so `old` needn't be.
+///
+/// A GC16 refresh is only covered by another, as the faster waveforms don't clear the ghosting
+/// it was pushed to.
+fn covers(new: &Command, old: &Command) -> bool {
+    use Command::*;
+    if refresh(old) == Some(Waveform::Gc16) && refresh(new) != Some(Waveform::Gc16) {
+        return false;
+    }
+    match (new, old) {
+        // a whole frame
+        (
+            Push { areas, .. } | PushRaw { areas, .. },
+            Push { .. } | PushRaw { .. } | PushArea { .. },
+        ) if areas.is_empty() => true,
+        (
+            Push { areas, rotate, .. } | PushRaw { areas, rotate, .. },
+            Push {
+                areas: old_areas,
+                rotate: old_rotate,
+                ..
+            }
+            | PushRaw {
+                areas: old_areas,
+                rotate: old_rotate,
+                ..
+            },
+        ) => areas == old_areas && rotate == old_rotate,
+        (
+            PushArea {
+                image,
+                x,
+                y,
+                rotate,
+                ..
+            },
+            PushArea {
+                image: old_image,
+                x: old_x,
+                y: old_y,
+                rotate: old_rotate,
+                ..
+            },
+        ) => image == old_image && [x, y] == [old_x, old_y] && rotate == old_rotate,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Area;
+    use std::time::Duration;
+
+    fn push(waveform: Waveform, areas: Vec<Area>) -> Command {
+        Command::Push {
+            image: "frame.bmp".into(),
+            waveform,
+            areas,
+            rotate: None,
+        }
+    }
+
+    fn ids(jobs: &[Job<()>]) -> Vec<u64> {
+        jobs.iter().map(|x| x.id).collect()
+    }
+
+    #[test]
+    fn drops_frames_covered_by_newer_ones() {
+        let mut jobs = Jobs::new(Intervals::default());
+        let job = |id, command| Job {
+            id,
+            command,
+            reply: (),
+        };
+        let clock = || {
+            vec![Area {
+                x: 0,
+                y: 0,
+                w: 200,
+                h: 60,
+            }]
+        };
+        assert!(jobs.push(job(1, push(Waveform::A2, clock()))).is_empty());
+        assert!(jobs.push(job(2, Command::Sleep)).is_empty());
+        // another area isn't covered
+        let body = vec![Area {
+            x: 0,
+            y: 60,
+            w: 200,
+            h: 60,
+        }];
+        assert!(jobs.push(job(3, push(Waveform::Du4, body))).is_empty());
+        assert_eq!(ids(&jobs.push(job(4, push(Waveform::A2, clock())))), [1]);
+        assert_eq!(
+            ids(&jobs.push(job(5, push(Waveform::Gc16, vec![])))),
+            [3, 4]
+        );
+        // not by a waveform which doesn't clear the ghosting
+        assert!(jobs.push(job(6, push(Waveform::A2, vec![]))).is_empty());
+        assert_eq!(jobs.dropped, 3);
+        // in the order they came, less those dropped
+        let order = std::iter::from_fn(|| match jobs.take(Instant::now()) {
+            Next::Job(x) => Some(x.id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(order, [2, 5, 6]);
+    }
+
+    #[test]
+    fn waits_between_refreshes() {
+        let intervals = Intervals {
+            a2: Duration::ZERO,
+            gc16: Duration::from_secs(2),
+            ..Default::default()
+        };
+        let mut jobs = Jobs::new(intervals);
+        let start = Instant::now();
+        let secs = |x| start + Duration::from_secs(x);
+        for (id, waveform) in [(1, Waveform::Gc16), (2, Waveform::A2), (3, Waveform::Gc16)] {
+            jobs.push(Job {
+                id,
+                command: push(waveform, vec![]),
+                reply: (),
+            });
+        }
+        // each whole frame drops the one before
+        assert!(matches!(jobs.take(secs(0)), Next::Job(Job { id: 3, .. })));
+        jobs.push(Job {
+            id: 4,
+            command: push(Waveform::Gc16, vec![]),
+            reply: (),
+        });
+        assert!(matches!(jobs.take(secs(1)), Next::Wait(x) if x == secs(2)));
+        assert!(matches!(jobs.take(secs(2)), Next::Job(Job { id: 4, .. })));
+        // requests which don't refresh aren't held up, even behind a refresh which is waiting
+        for (id, command) in [(5, push(Waveform::Gc16, vec![])), (6, Command::Status)] {
+            jobs.push(Job {
+                id,
+                command,
+                reply: (),
+            });
+        }
+        assert!(matches!(jobs.take(secs(3)), Next::Job(Job { id: 6, .. })));
+        assert!(matches!(jobs.take(secs(3)), Next::Wait(x) if x == secs(4)));
+        assert!(matches!(jobs.take(secs(4)), Next::Job(Job { id: 5, .. })));
+        assert!(matches!(jobs.take(secs(4)), Next::Empty));
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading the panel's temperature from the IT8951, which the it8951 crate has no command for.
+//!
+//! The crate owns the SPI device and pins it drives the controller with, so the temperature is
+//! read over a second handle to the same SPI device, sharing the busy pin with the crate.
+//! Requests are only made between the crate's, while the controller is running.
+use embedded_hal::{
+    blocking::spi::{Transfer, Write},
+    digital::v2::InputPin,
+};
+use linux_embedded_hal::{CdevPin, Spidev};
+use miette::*;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The preamble of a command word.
+const COMMAND: u16 = 0x6000;
+/// The preamble of a data word written to the controller.
+const WRITE_DATA: u16 = 0x0000;
+/// The preamble of data words read from the controller, which follow a dummy word.
+const READ_DATA: u16 = 0x1000;
+/// Gets (with a parameter of 0) or forces the temperature the controller picks waveforms by.
+const TEMPERATURE: u16 = 0x0040;
+
+/// The longest the controller is waited on to be ready.
+const READY_WITHIN: Duration = Duration::from_secs(5);
+
+/// A GPIO pin read by the it8951 crate and the [`Thermometer`].
+#[derive(Clone)]
+pub struct SharedPin(pub Arc<CdevPin>);
+
+impl InputPin for SharedPin {
+    type Error = <CdevPin as InputPin>::Error;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.0.is_high()
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+}
+
+pub struct Thermometer {
+    spi: Spidev,
+    busy: SharedPin,
+}
+
+impl Thermometer {
+    pub fn new(spi: Spidev, busy: SharedPin) -> Self {
+        Self { spi, busy }
+    }
+
+    /// The panel's temperature in °C, as the controller's sensor reads it.
+    pub fn read(&mut self) -> Result<i16> {
+        self.command(TEMPERATURE)?;
+        self.write(0)?;
+        let [real, _forced] = self.read_words()?;
+        // two's complement, below zero in the cold
+        Ok(real as i16)
+    }
+
+    fn command(&mut self, cmd: u16) -> Result<()> {
+        self.send(&[COMMAND, cmd])
+    }
+
+    fn write(&mut self, data: u16) -> Result<()> {
+        self.send(&[WRITE_DATA, data])
+    }
+
+    fn send(&mut self, words: &[u16]) -> Result<()> {
+        self.wait_ready()?;
+        let bytes = words
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect::<Vec<_>>();
+        Write::write(&mut self.spi, &bytes)
+            .into_diagnostic()
+            .wrap_err("failed to write to the display controller")
+    }
+
+    /// The words read after the preamble and dummy word.
+    fn read_words<const N: usize>(&mut self) -> Result<[u16; N]> {
+        self.wait_ready()?;
+        let mut buf = vec![0; 4 + N * 2];
+        buf[..2].copy_from_slice(&READ_DATA.to_be_bytes());
+        let rx = Transfer::transfer(&mut self.spi, &mut buf)
+            .into_diagnostic()
+            .wrap_err("failed to read from the display controller")?;
+        let mut words = [0; N];
+        for (x, b) in words.iter_mut().zip(rx[4..].chunks_exact(2)) {
+            *x = u16::from_be_bytes([b[0], b[1]]);
+        }
+        Ok(words)
+    }
+
+    /// Wait for the busy pin to go high, as the controller is ready for the next word.
+    fn wait_ready(&self) -> Result<()> {
+        let start = Instant::now();
+        while !self
+            .busy
+            .is_high()
+            .map_err(|e| miette!("failed to read the busy pin: {e:?}"))?
+        {
+            if start.elapsed() > READY_WITHIN {
+                return Err(miette!("the display controller stayed busy"));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+}
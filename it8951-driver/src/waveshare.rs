@@ -0,0 +1,216 @@
+//! The Waveshare 7.5" V2, an 800x480 black and white panel on SPI without an IT8951.
+//!
+//! Its UC8179 controller has no memory to load areas into or waveforms to choose, so images are
+//! loaded into a framebuffer as the IT8951 would, and every refresh sends the whole of it and
+//! updates with the LUT in the panel's OTP. Greys are shown as black or white, so dither to two
+//! levels for this panel.
+use crate::{config::Hardware, framebuffer::Framebuffer, DisplayBackend, Info, Rest};
+use image::GrayImage;
+use it8951::{memory_converter_settings::MemoryConverterSetting, AreaImgInfo, WaveformMode};
+use linux_embedded_hal::{
+    gpio_cdev::{Chip, LineHandle, LineRequestFlags},
+    spidev::{SpiModeFlags, Spidev, SpidevOptions},
+};
+use miette::*;
+use std::{
+    io::Write,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+pub const SIZE: [u16; 2] = [800, 480];
+/// The most spidev takes in one write, by default.
+const SPI_CHUNK: usize = 4096;
+/// A full refresh takes around 4 seconds, longer in the cold.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Epd7in5V2 {
+    spi: Spidev,
+    rst: LineHandle,
+    dc: LineHandle,
+    busy: LineHandle,
+    fb: Framebuffer,
+    /// How the panel was last left, asleep when opened so that it is reset.
+    power: Rest,
+}
+
+impl Epd7in5V2 {
+    /// Open the SPI device and GPIO pins the panel is wired to.
+    pub fn open(hw: &Hardware) -> Result<Self> {
+        let devspi = &hw.spi;
+        eprintln!("ℹ Connecting to {devspi}");
+        let mut spi = Spidev::open(devspi)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("spi path: {devspi}"))?;
+        let opts = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(hw.spi_hz)
+            .mode(SpiModeFlags::SPI_MODE_0)
+            .build();
+        spi.configure(&opts).into_diagnostic()?;
+
+        let devgpio = &hw.gpio;
+        let mut chip = Chip::new(devgpio)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("gpio path: {devgpio}"))?;
+        let mut line = |name: &str, pin: u32, flags: LineRequestFlags| {
+            chip.get_line(pin)
+                .and_then(|x| x.request(flags, 0, "meeting-room"))
+                .into_diagnostic()
+                .wrap_err_with(|| format!("{name} pin: {pin}"))
+        };
+        Ok(Self {
+            rst: line("reset", hw.rst_pin, LineRequestFlags::OUTPUT)?,
+            dc: line("data/command", hw.dc_pin, LineRequestFlags::OUTPUT)?,
+            busy: line("busy", hw.busy_pin, LineRequestFlags::INPUT)?,
+            spi,
+            fb: Framebuffer::new(SIZE),
+            power: Rest::Asleep,
+        })
+    }
+
+    /// Wake the panel from how it was left, resetting and initialising it from deep sleep.
+    pub fn wake(&mut self) -> Result<()> {
+        match self.power {
+            Rest::Asleep => self.init()?,
+            Rest::Standby => self.power_on()?,
+            Rest::Awake => (),
+        }
+        self.power = Rest::Awake;
+        Ok(())
+    }
+
+    /// Leave the awake panel in `rest`, powered off for standby, and in deep sleep too when
+    /// asleep, which only a reset wakes from.
+    pub fn rest(&mut self, rest: Rest) -> Result<()> {
+        if rest != Rest::Awake {
+            self.command(0x02, &[])?;
+            self.wait()?;
+        }
+        if rest == Rest::Asleep {
+            self.command(0x07, &[0xa5])?;
+        }
+        self.power = rest;
+        Ok(())
+    }
+
+    fn init(&mut self) -> Result<()> {
+        for (value, ms) in [(1, 20), (0, 2), (1, 20)] {
+            self.rst.set_value(value).into_diagnostic()?;
+            sleep(Duration::from_millis(ms));
+        }
+        // power setting, booster soft start
+        self.command(0x01, &[0x07, 0x07, 0x3f, 0x3f])?;
+        self.command(0x06, &[0x17, 0x17, 0x28, 0x17])?;
+        self.power_on()?;
+        // black and white with the OTP's LUT, 800x480, VCOM and data interval, TCON
+        self.command(0x00, &[0x1f])?;
+        self.command(0x61, &[0x03, 0x20, 0x01, 0xe0])?;
+        self.command(0x15, &[0x00])?;
+        self.command(0x50, &[0x10, 0x07])?;
+        self.command(0x60, &[0x22])
+    }
+
+    fn power_on(&mut self) -> Result<()> {
+        self.command(0x04, &[])?;
+        sleep(Duration::from_millis(100));
+        self.wait()
+    }
+
+    fn command(&mut self, cmd: u8, data: &[u8]) -> Result<()> {
+        let write = |spi: &mut Spidev, x: &[u8]| {
+            spi.write_all(x)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to write command {cmd:#04x}"))
+        };
+        self.dc.set_value(0).into_diagnostic()?;
+        write(&mut self.spi, &[cmd])?;
+        if !data.is_empty() {
+            self.dc.set_value(1).into_diagnostic()?;
+            for chunk in data.chunks(SPI_CHUNK) {
+                write(&mut self.spi, chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until the panel is idle, the busy pin is low while it isn't.
+    fn wait(&mut self) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            // get status, which the busy pin only updates after
+            self.command(0x71, &[])?;
+            if self.busy.get_value().into_diagnostic()? == 1 {
+                return Ok(());
+            }
+            if start.elapsed() > BUSY_TIMEOUT {
+                return Err(miette!("timed out waiting for the display to be ready"));
+            }
+            sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl DisplayBackend for Epd7in5V2 {
+    fn info(&self) -> Info {
+        let [width, height] = self.fb.size();
+        Info {
+            width,
+            height,
+            memory_address: 0,
+            firmware: "epd7in5-v2".to_string(),
+            lut: "otp".to_string(),
+        }
+    }
+
+    fn load_image_area(
+        &mut self,
+        _: u32,
+        cnvtr: MemoryConverterSetting,
+        area: &AreaImgInfo,
+        data: &[u16],
+    ) -> Result<()> {
+        self.fb.load(cnvtr, area, data)
+    }
+
+    fn display(&mut self, _: Option<&AreaImgInfo>, _: WaveformMode) -> Result<()> {
+        let white = pack_bits(&self.fb.shown());
+        let black = white.iter().map(|x| !x).collect::<Vec<_>>();
+        // the old and new frames, then refresh
+        self.command(0x10, &white)?;
+        self.command(0x13, &black)?;
+        self.command(0x12, &[])?;
+        sleep(Duration::from_millis(100));
+        self.wait()
+    }
+}
+
+/// A bit a pixel, set for white, 8 pixels to a byte with the first in the highest bit.
+fn pack_bits(img: &GrayImage) -> Vec<u8> {
+    img.rows()
+        .flat_map(|row| {
+            let row = row.map(|x| x.0[0] >= 128).collect::<Vec<_>>();
+            row.chunks(8)
+                .map(|x| {
+                    let byte = x.iter().fold(0u8, |b, &white| b << 1 | u8::from(white));
+                    byte << (8 - x.len())
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn packs_white_bits() {
+        let img = GrayImage::from_fn(10, 2, |x, y| Luma([if (x + y) % 3 == 0 { 255 } else { 0 }]));
+        assert_eq!(
+            pack_bits(&img),
+            [0b1001_0010, 0b0100_0000, 0b0010_0100, 0b1000_0000]
+        );
+    }
+}
@@ -0,0 +1,41 @@
+#!/bin/sh
+# Install pical as a systemd service on Raspberry Pi OS, run from the extracted package:
+#   tar -xzf pical-*.tar.gz && sudo pical/install.sh
+set -eu
+
+DIR=/opt/pical
+SRC=$(dirname "$0")
+
+if [ "$(id -u)" -ne 0 ]; then
+    echo "⚠ Run as root: sudo $0" >&2
+    exit 1
+fi
+
+# the panel is driven over SPI
+if command -v raspi-config >/dev/null; then
+    raspi-config nonint do_spi 0
+fi
+
+if ! id pical >/dev/null 2>&1; then
+    useradd --system --home-dir "$DIR" --shell /usr/sbin/nologin pical
+fi
+for group in spi gpio; do
+    if getent group "$group" >/dev/null; then
+        usermod -aG "$group" pical
+    fi
+done
+
+mkdir -p "$DIR"
+# stop first, a running binary can't be overwritten
+systemctl stop pical 2>/dev/null || true
+install -m 755 "$SRC/pical" "$SRC/it8951-driver" "$DIR/"
+if [ ! -e "$DIR/config.pical.toml" ]; then
+    install -m 644 "$SRC/config.pical.toml" "$DIR/"
+    echo "ℹ Edit $DIR/config.pical.toml with your calendars and location"
+fi
+chown -R pical:pical "$DIR"
+
+install -m 644 "$SRC/pical.service" /etc/systemd/system/
+systemctl daemon-reload
+systemctl enable --now pical
+echo "✅ Installed, follow the logs with: journalctl -u pical -f"
@@ -0,0 +1,37 @@
+#!/usr/bin/env -S rust-script -c
+//! Build the release binaries and package them for installing on a Raspberry Pi.
+//! You might need to chmod +x your script!
+//! ```cargo
+//! [dependencies.rust-script-ext]
+//! git = "https://github.com/kurtlawrence/rust-script-ext"
+//! rev = "47361b1a62272e6bf94ed849ec06c8df79f02362"
+//! ```
+// See <https://kurtlawrence.github.io/rust-script-ext/rust_script_ext/> for documentation
+use rust_script_ext::prelude::*;
+
+fn main() -> Result<()> {
+    let arch = "arm-unknown-linux-musleabihf";
+    cmd!(cargo: b, --release, -p pical, --target, {arch}).run()?;
+    cmd!(cargo: b, --release, -p it8951-driver, --target, {arch}).run()?;
+
+    let release = format!("/tmp/pical/{arch}/release");
+    let out = "/tmp/pical/package";
+    let stage = format!("{out}/pical");
+    cmd!(rm: -rf, {&stage}).run()?;
+    cmd!(mkdir: -p, {&stage}).run()?;
+    for file in [
+        format!("{release}/pical"),
+        format!("{release}/it8951-driver"),
+        "os/pical.service".to_string(),
+        "os/install.sh".to_string(),
+        "os/config.pical.toml".to_string(),
+    ] {
+        cmd!(cp: {file}, {&stage}).run()?;
+    }
+
+    let tarball = format!("{out}/pical-{arch}.tar.gz");
+    cmd!(tar: -czf, {&tarball}, -C, {out}, pical).run()?;
+    println!("✅ Packaged {tarball}");
+    println!("ℹ Copy it to the Pi and run: tar -xzf pical-{arch}.tar.gz && sudo pical/install.sh");
+    Ok(())
+}
@@ -0,0 +1,380 @@
+//! A soak test of the fetch and render loops of `pical::run` over simulated months, with fetches
+//! failing and the panel timing out at random, checking nothing panics, memory stays bounded, and
+//! everything recovers once the failures stop.
+//!
+//! The loops run by a simulated clock, the calendars replay iCal payloads through the real
+//! parser, and the panel is a mock which times out like the IT8951 driver can. A few simulated
+//! days run with `cargo test`, the long run is ignored by default:
+//!
+//! ```sh
+//! PICAL_SOAK_DAYS=90 cargo test --release --test soak -- --ignored --nocapture
+//! ```
+//!
+//! `PICAL_SOAK_SPEED` is how much faster than the wall clock the simulated clock runs, 1000 by
+//! default or 0 for as fast as possible, and `PICAL_SOAK_SEED` picks the failures.
+use image::GrayImage;
+use miette::*;
+use pical::{
+    data::{
+        cal::{calendar_patch, parse_ical},
+        injected::Injected,
+        source::{DataSource, FetchFuture, Registry},
+    },
+    dither::Dither,
+    driver::Command,
+    fetch::Client,
+    layout::Layout,
+    policy::{Push, Schedule},
+    rotation::{Page, Rotation},
+    run::{
+        Clock, FetchLoop, Frame, Painting, Panel, PanelFuture, Passes, Policies, RenderLoop, State,
+    },
+    state::dispatcher,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use time::{macros::datetime, macros::format_description, OffsetDateTime};
+
+/// The frame size, small to keep the simulated months quick.
+const SIZE: [u32; 2] = [240, 180];
+/// How often a frame is rendered, in simulated minutes.
+const RENDER_EVERY: u64 = 15;
+const DAY: u64 = 24 * 60;
+
+/// A seeded xorshift, so a failing run can be repeated with its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn chance(&mut self, p: f64) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64 <= p
+    }
+}
+
+/// Replays an iCal payload, moved to be around the simulated time, failing at random while
+/// `flaky`.
+struct Replay {
+    name: &'static str,
+    interval: Duration,
+    rng: Rng,
+    flaky: Arc<AtomicBool>,
+    fetches: u64,
+    /// How many fetches of all the sources failed.
+    failures: Arc<AtomicU64>,
+    /// The simulated time of the last successful fetch.
+    last_ok: Arc<Mutex<Option<OffsetDateTime>>>,
+}
+
+impl DataSource for Replay {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn fetch<'a>(&'a mut self, _: &'a Client, now: OffsetDateTime) -> FetchFuture<'a> {
+        Box::pin(async move {
+            if self.flaky.load(Ordering::Relaxed) && self.rng.chance(0.2) {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(miette!("connection reset by peer"));
+            }
+            self.fetches += 1;
+            let cal = parse_ical(
+                &payload(now, self.fetches),
+                now.offset(),
+                now + time::Duration::days(60),
+            )?;
+            *self.last_ok.lock().unwrap() = Some(now);
+            Ok(calendar_patch(self.name.to_string(), cal, Instant::now()))
+        })
+    }
+}
+
+/// A weekly series which started a month ago, and a one off tomorrow which changes with each
+/// fetch.
+fn payload(now: OffsetDateTime, n: u64) -> String {
+    let fmt = format_description!("[year][month][day]");
+    let date = |days| {
+        (now + time::Duration::days(days))
+            .format(fmt)
+            .expect("formattable date")
+    };
+    format!(
+        "BEGIN:VCALENDAR
+BEGIN:VEVENT
+DTSTART;TZID=Australia/Brisbane:{first}T083000
+DTEND;TZID=Australia/Brisbane:{first}T093000
+RRULE:FREQ=WEEKLY;WKST=SU;BYDAY=SA
+SUMMARY:Swimming
+END:VEVENT
+BEGIN:VEVENT
+DTSTART;TZID=Australia/Brisbane:{tomorrow}T190000
+DTEND;TZID=Australia/Brisbane:{tomorrow}T220000
+SUMMARY:Babysitter #{n}
+ATTENDEE;CN=Sam:mailto:sam@example.com
+END:VEVENT
+END:VCALENDAR",
+        first = date(-30),
+        tomorrow = date(1),
+    )
+}
+
+/// The simulated clock, moved on by the test.
+#[derive(Clone)]
+struct Sim {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Sim {
+    fn set(&self, elapsed: Duration) {
+        *self.elapsed.lock().unwrap() = elapsed;
+    }
+}
+
+impl Clock for Sim {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+/// The panel, which times out at random while `flaky`.
+struct Flaky {
+    rng: Rng,
+    flaky: Arc<AtomicBool>,
+    shown: Option<GrayImage>,
+    timeouts: u64,
+    /// The last push timed out.
+    timed_out: bool,
+}
+
+impl Panel for Flaky {
+    fn push<'a>(&'a mut self, frame: &'a Frame, push: &'a Push) -> PanelFuture<'a> {
+        Box::pin(async move {
+            if self.timed_out {
+                assert_eq!(
+                    *push,
+                    Push::FULL,
+                    "a timed out push is followed by a full refresh"
+                );
+            }
+            if self.flaky.load(Ordering::Relaxed) && self.rng.chance(0.05) {
+                self.timeouts += 1;
+                self.timed_out = true;
+                return Err(miette!("timed out waiting for the display to be ready"));
+            }
+            self.timed_out = false;
+            self.shown = Some(frame.image()?);
+            Ok(())
+        })
+    }
+
+    fn power(&mut self, _: Command) -> PanelFuture<'_> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// The resident memory of the process, where it can be read.
+///
+/// Read from `VmRSS`, which is in kB whatever the page size, as `statm` counts pages and they
+/// aren't 4 KiB on every kernel, such as 16 KiB ones on ARM.
+fn rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|x| x.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+fn env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Run `days` simulated days, the last without failures so everything should have recovered.
+async fn soak(days: u64, speed: f64, seed: u64) {
+    let start = datetime!(2024-06-21 0:00 +10);
+    let wall = Instant::now();
+    let clock = Sim {
+        start: Instant::now(),
+        elapsed: Default::default(),
+    };
+    let flaky = Arc::new(AtomicBool::new(true));
+
+    let (dispatch, state_loop) = dispatcher(State {
+        layout: Layout {
+            now: start,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    tokio::spawn(state_loop);
+    let modes = dispatch.run(|s| s.layout.modes.clone()).await;
+
+    let client = pical::fetch::client(Duration::from_secs(1), None).unwrap();
+    let mut sources = Registry::default();
+    let failures = Arc::new(AtomicU64::new(0));
+    let mut last_oks = Vec::new();
+    for (i, (name, mins)) in [("Family", 5), ("Work", 15)].into_iter().enumerate() {
+        let last_ok = Arc::new(Mutex::new(None));
+        last_oks.push((name, Duration::from_secs(mins * 60), last_ok.clone()));
+        sources.register(Replay {
+            name,
+            interval: Duration::from_secs(mins * 60),
+            rng: Rng(seed + i as u64 + 1),
+            flaky: flaky.clone(),
+            fetches: 0,
+            failures: failures.clone(),
+            last_ok,
+        });
+    }
+    let mut fetch = FetchLoop::new(dispatch.clone(), clock.clone(), client, sources, false);
+
+    // each mode for an hour
+    let pages = modes
+        .names()
+        .map(|mode| Page {
+            mode: mode.to_string(),
+            dwell: Duration::from_secs(60 * 60),
+        })
+        .collect::<Vec<_>>();
+    let painting = Painting {
+        rotation: Rotation::new(&pages, &modes).unwrap(),
+        size: SIZE,
+        scaling: 1.0,
+        passes: Passes {
+            contrast: None,
+            tone: None,
+            dither: Dither::Ordered,
+        },
+        sinks: Vec::new(),
+    };
+    let policies = Policies {
+        vacation: None,
+        quiet: None,
+        raw_frames: true,
+        schedule: Schedule::new(Schedule::default_cadences()),
+    };
+    let panel = Flaky {
+        rng: Rng(seed),
+        flaky: flaky.clone(),
+        shown: None,
+        timeouts: 0,
+        timed_out: false,
+    };
+    // kept for the whole run, so the renderer's textures are soaked too
+    let mut render = RenderLoop::new(dispatch.clone(), clock.clone(), panel, painting, policies);
+
+    let total = days * DAY;
+    let mut baseline = None;
+    for minute in 0..total {
+        let elapsed = Duration::from_secs(minute * 60);
+        let now = start + elapsed;
+        clock.set(elapsed);
+        flaky.store(minute < total - DAY, Ordering::Relaxed);
+        if speed > 0.0 {
+            let due = wall + elapsed.div_f64(speed);
+            tokio::time::sleep_until(due.into()).await;
+        }
+
+        // as the clock loop does
+        let inject = minute % DAY == 18 * 60;
+        dispatch
+            .run(move |s| {
+                s.layout.now = now;
+                let model = s.model.make_mut();
+                if inject {
+                    let ev = Injected {
+                        summary: "Pizza night".to_string(),
+                        start: now,
+                        end: now + time::Duration::hours(3),
+                    };
+                    model.insert_events("scripts", vec![ev.into()]).unwrap();
+                }
+                model.expire_events(now);
+            })
+            .await;
+
+        fetch.iteration().await;
+        if minute % RENDER_EVERY == 0 {
+            render.iteration().await;
+        }
+
+        if minute % DAY == 0 {
+            // fetched calendars are replaced, and injected events expire
+            let model = dispatch.run(|s| s.model.clone()).await;
+            let events = model.cals.values().map(Vec::len).sum::<usize>();
+            assert!(events < 100, "day {}: {events} events", minute / DAY);
+            assert!(model.injected.len() <= 1, "{:?}", model.injected);
+            match (baseline, rss()) {
+                // the first day warms up the font atlas and allocator
+                (None, Some(x)) if minute >= DAY => baseline = Some(x),
+                (Some(base), Some(x)) => assert!(
+                    x <= base + 64 * 1024 * 1024,
+                    "day {}: resident memory grew from {base} to {x} bytes",
+                    minute / DAY
+                ),
+                _ => (),
+            }
+        }
+    }
+
+    // recovered during the last day
+    let end = start + Duration::from_secs(total * 60);
+    let failure = dispatch.run(|s| s.layout.failure.clone()).await;
+    assert!(
+        failure.is_none(),
+        "failure still shown: {}",
+        failure.map(|x| x.message).unwrap_or_default()
+    );
+    for (name, interval, last_ok) in last_oks {
+        let last = last_ok.lock().unwrap().expect("fetched at least once");
+        assert!(end - last <= interval, "{name} last fetched at {last}");
+    }
+    let panel = render.panel();
+    assert!(!panel.timed_out, "the last push timed out");
+    assert!(panel.shown.is_some());
+    let fetch_failures = failures.load(Ordering::Relaxed);
+    assert!(
+        days < 2 || fetch_failures > 0,
+        "no fetch failures were injected"
+    );
+    assert!(
+        days < 2 || panel.timeouts > 0,
+        "no panel timeouts were injected"
+    );
+    println!(
+        "{days} simulated days: {fetch_failures} fetch failures, {} panel timeouts, {} dispatched",
+        panel.timeouts,
+        dispatch.stats().executed
+    );
+}
+
+#[tokio::test]
+async fn soak_days() {
+    soak(3, 0.0, env("PICAL_SOAK_SEED", 1)).await;
+}
+
+#[tokio::test]
+#[ignore = "runs for hours, see the module docs"]
+async fn soak_months() {
+    let days = env("PICAL_SOAK_DAYS", 90);
+    let speed = env("PICAL_SOAK_SPEED", 1000.0);
+    soak(days, speed, env("PICAL_SOAK_SEED", 1)).await;
+}